@@ -0,0 +1,367 @@
+use crate::{
+  loc::Loc,
+  tag::{
+    Tag,
+    TagValue,
+  },
+};
+use serde::{
+  Deserialize,
+  Deserializer,
+  Serialize,
+  Serializer,
+};
+use std::collections::BTreeMap;
+
+/// A span of a document marked with a value, e.g. an entity with its type or
+/// a citation with its key. Annotations may overlap each other and don't
+/// depend on how the document is chunked.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Annotation<'a> {
+  pub loc: Loc,
+  #[serde(borrow)]
+  pub value: TagValue<'a>,
+}
+
+impl<'a> Annotation<'a> {
+  pub fn new(loc: Loc, value: impl Into<TagValue<'a>>) -> Self {
+    Self {
+      loc,
+      value: value.into(),
+    }
+  }
+
+  pub fn into_owned(self) -> Annotation<'static> {
+    Annotation {
+      loc: self.loc,
+      value: self.value.into_owned(),
+    }
+  }
+}
+
+/// The annotations of one kind, e.g. `entities` or `redactions`, indexed by
+/// their `Loc`.
+///
+/// Annotations are kept sorted by `Loc` in an implicit interval tree: the
+/// middle of every range of the sorted list is the root of a subtree, and
+/// `max_end` holds the largest end of each subtree. Lookups only descend
+/// into subtrees that can reach the queried range, so they take
+/// `O(log n + k)` for `k` results.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnnotationLayer<'a> {
+  annotations: Vec<Annotation<'a>>,
+  max_end: Vec<usize>,
+}
+
+impl<'a> AnnotationLayer<'a> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds an annotation. Prefer `extend` to add many at once, which indexes
+  /// them only once.
+  pub fn insert(&mut self, annotation: Annotation<'a>) {
+    let key = sort_key(&annotation);
+    let index = self
+      .annotations
+      .partition_point(|other| sort_key(other) <= key);
+    self.annotations.insert(index, annotation);
+    self.reindex();
+  }
+
+  /// Removes and returns the annotations for which `f` returns `true`.
+  pub fn remove_where(
+    &mut self,
+    mut f: impl FnMut(&Annotation<'a>) -> bool,
+  ) -> Vec<Annotation<'a>> {
+    let (removed, kept) = std::mem::take(&mut self.annotations)
+      .into_iter()
+      .partition(|annotation| f(annotation));
+    self.annotations = kept;
+    self.reindex();
+    removed
+  }
+
+  /// All annotations intersecting `loc`, ordered by `Loc`.
+  pub fn intersecting(&self, loc: &Loc) -> Vec<&Annotation<'a>> {
+    let mut found = vec![];
+    self.search(0, self.annotations.len(), loc, &mut found);
+    found
+  }
+
+  /// All annotations lying entirely within `loc`, ordered by `Loc`.
+  pub fn within(&self, loc: &Loc) -> Vec<&Annotation<'a>> {
+    let mut found = self.intersecting(loc);
+    found.retain(|annotation| loc.contains(&annotation.loc));
+    found
+  }
+
+  /// All annotations, ordered by `Loc`.
+  pub fn iter(&self) -> std::slice::Iter<'_, Annotation<'a>> {
+    self.annotations.iter()
+  }
+
+  pub fn len(&self) -> usize {
+    self.annotations.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.annotations.is_empty()
+  }
+
+  pub fn into_owned(self) -> AnnotationLayer<'static> {
+    AnnotationLayer {
+      annotations: self
+        .annotations
+        .into_iter()
+        .map(Annotation::into_owned)
+        .collect(),
+      max_end: self.max_end,
+    }
+  }
+
+  fn reindex(&mut self) {
+    self.max_end = vec![0; self.annotations.len()];
+    self.index(0, self.annotations.len());
+  }
+
+  fn index(&mut self, lo: usize, hi: usize) -> usize {
+    if lo >= hi {
+      return 0;
+    }
+    let mid = lo + (hi - lo) / 2;
+    let max_end = self.annotations[mid]
+      .loc
+      .end
+      .max(self.index(lo, mid))
+      .max(self.index(mid + 1, hi));
+    self.max_end[mid] = max_end;
+    max_end
+  }
+
+  fn search<'s>(
+    &'s self,
+    lo: usize,
+    hi: usize,
+    loc: &Loc,
+    found: &mut Vec<&'s Annotation<'a>>,
+  ) {
+    if lo >= hi {
+      return;
+    }
+    let mid = lo + (hi - lo) / 2;
+    // Nothing in this subtree ends after the start of `loc`.
+    if self.max_end[mid] <= loc.start {
+      return;
+    }
+    self.search(lo, mid, loc, found);
+    let annotation = &self.annotations[mid];
+    // This and everything to the right starts after the end of `loc`.
+    if annotation.loc.start >= loc.end {
+      return;
+    }
+    if annotation.loc.intersects(loc) {
+      found.push(annotation);
+    }
+    self.search(mid + 1, hi, loc, found);
+  }
+}
+
+fn sort_key(annotation: &Annotation) -> (usize, usize) {
+  (annotation.loc.start, annotation.loc.end)
+}
+
+impl<'a> FromIterator<Annotation<'a>> for AnnotationLayer<'a> {
+  fn from_iter<I: IntoIterator<Item = Annotation<'a>>>(iter: I) -> Self {
+    let mut layer = Self::new();
+    layer.extend(iter);
+    layer
+  }
+}
+
+impl<'a> Extend<Annotation<'a>> for AnnotationLayer<'a> {
+  fn extend<I: IntoIterator<Item = Annotation<'a>>>(&mut self, iter: I) {
+    self.annotations.extend(iter);
+    // Stable, so annotations with equal locs keep their insertion order.
+    self.annotations.sort_by_key(sort_key);
+    self.reindex();
+  }
+}
+
+impl Serialize for AnnotationLayer<'_> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    self.annotations.serialize(serializer)
+  }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for AnnotationLayer<'a> {
+  fn deserialize<D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Self, D::Error> {
+    Vec::<Annotation<'a>>::deserialize(deserializer).map(Self::from_iter)
+  }
+}
+
+/// The annotation layers of a document, by name.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Annotations<'a> {
+  #[serde(borrow)]
+  layers: BTreeMap<String, AnnotationLayer<'a>>,
+}
+
+impl<'a> Annotations<'a> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds an annotation to the layer `name`, creating the layer if needed.
+  pub fn insert(&mut self, name: &str, annotation: Annotation<'a>) {
+    self.layer_mut(name).insert(annotation);
+  }
+
+  pub fn layer(&self, name: &str) -> Option<&AnnotationLayer<'a>> {
+    self.layers.get(name)
+  }
+
+  /// The layer `name`, created empty if it doesn't exist yet.
+  pub fn layer_mut(&mut self, name: &str) -> &mut AnnotationLayer<'a> {
+    self.layers.entry(name.to_string()).or_default()
+  }
+
+  pub fn remove_layer(&mut self, name: &str) -> Option<AnnotationLayer<'a>> {
+    self.layers.remove(name)
+  }
+
+  /// The names of all layers, sorted.
+  pub fn names(&self) -> impl Iterator<Item = &str> {
+    self.layers.keys().map(String::as_str)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &AnnotationLayer<'a>)> {
+    self
+      .layers
+      .iter()
+      .map(|(name, layer)| (name.as_str(), layer))
+  }
+
+  /// All annotations intersecting `loc` with the name of their layer, ordered
+  /// by layer name and then by `Loc`.
+  pub fn intersecting(&self, loc: &Loc) -> Vec<(&str, &Annotation<'a>)> {
+    self
+      .iter()
+      .flat_map(|(name, layer)| {
+        layer
+          .intersecting(loc)
+          .into_iter()
+          .map(move |annotation| (name, annotation))
+      })
+      .collect()
+  }
+
+  /// Tags for all annotations intersecting `loc`, keyed by their layer name.
+  /// The tags keep the full `Loc` of their annotation, which may extend past
+  /// `loc`. Only the layers in `names` are used, or all layers if it's empty.
+  pub fn tags_at<'s>(&'s self, loc: &Loc, names: &[String]) -> Vec<Tag<'s>>
+  where
+    'a: 's,
+  {
+    self
+      .intersecting(loc)
+      .into_iter()
+      .filter(|(name, _)| {
+        names.is_empty() || names.iter().any(|other| other == name)
+      })
+      .map(|(name, annotation)| {
+        Tag::new(name, annotation.value.clone(), annotation.loc.clone())
+      })
+      .collect()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.layers.values().all(AnnotationLayer::is_empty)
+  }
+
+  pub fn into_owned(self) -> Annotations<'static> {
+    Annotations {
+      layers: self
+        .layers
+        .into_iter()
+        .map(|(name, layer)| (name, layer.into_owned()))
+        .collect(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn intersecting() {
+    let spans = [(0, 100), (5, 8), (10, 20), (12, 14), (30, 40), (35, 90)];
+    let layer = spans
+      .iter()
+      .rev()
+      .map(|&(start, end)| Annotation::new(Loc::new(start, end), "x"))
+      .collect::<AnnotationLayer>();
+
+    let query = |start, end| {
+      layer
+        .intersecting(&Loc::new(start, end))
+        .iter()
+        .map(|annotation| annotation.loc.as_tuple())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(query(13, 31), [(0, 100), (10, 20), (12, 14), (30, 40)]);
+    assert_eq!(query(8, 10), [(0, 100)]);
+    assert_eq!(query(95, 120), [(0, 100)]);
+    assert_eq!(query(100, 120), []);
+    // Compare against a linear scan for every possible range.
+    for start in 0..110 {
+      for end in start..110 {
+        let loc = Loc::new(start, end);
+        let expected = layer
+          .iter()
+          .filter(|annotation| annotation.loc.intersects(&loc))
+          .collect::<Vec<_>>();
+        assert_eq!(layer.intersecting(&loc), expected);
+      }
+    }
+
+    let within = layer.within(&Loc::new(0, 20));
+    assert_eq!(within.len(), 3);
+  }
+
+  #[test]
+  fn layers() {
+    let mut annotations = Annotations::new();
+    annotations.insert("entities", Annotation::new(Loc::new(0, 5), "PERSON"));
+    annotations.insert("entities", Annotation::new(Loc::new(20, 26), "ORG"));
+    annotations.insert("redactions", Annotation::new(Loc::new(2, 30), true));
+
+    let at = annotations
+      .intersecting(&Loc::new(4, 10))
+      .into_iter()
+      .map(|(name, annotation)| format!("{name}: {}", annotation.value))
+      .collect::<Vec<_>>();
+    assert_eq!(at, ["entities: PERSON", "redactions: true"]);
+
+    let loc = Loc::new(21, 22);
+    let tags = annotations.tags_at(&loc, &["entities".to_string()]);
+    assert_eq!(tags, [Tag::new("entities", "ORG", Loc::new(20, 26))]);
+
+    let json = serde_json::to_string(&annotations).unwrap();
+    let parsed: Annotations = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, annotations);
+    let removed = annotations
+      .layer_mut("entities")
+      .remove_where(|annotation| annotation.value == "ORG");
+    assert_eq!(removed.len(), 1);
+    assert!(annotations
+      .layer("entities")
+      .unwrap()
+      .intersecting(&loc)
+      .is_empty());
+  }
+}
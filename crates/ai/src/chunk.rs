@@ -1,14 +1,15 @@
 use crate::{
   error::Error,
   loc::Loc,
-  tag::Tag,
+  tag::Tags,
 };
 use serde::{
   Deserialize,
   Serialize,
 };
-use std::collections::HashMap;
+use std::borrow::Cow;
 
+pub mod annotated;
 pub mod recursive;
 pub mod simple;
 
@@ -27,7 +28,7 @@ pub enum Chunk<'a> {
 impl<'a> Chunk<'a> {
   pub fn content(&'a self) -> &'a str {
     match self {
-      Chunk::Simple(simple) => simple.content,
+      Chunk::Simple(simple) => &simple.content,
     }
   }
 
@@ -36,17 +37,47 @@ impl<'a> Chunk<'a> {
       Chunk::Simple(simple) => &simple.loc,
     }
   }
+
+  pub fn tags(&self) -> &Tags<'a> {
+    match self {
+      Chunk::Simple(simple) => &simple.tags,
+    }
+  }
+
+  pub fn tags_mut(&mut self) -> &mut Tags<'a> {
+    match self {
+      Chunk::Simple(simple) => &mut simple.tags,
+    }
+  }
+
+  /// Copies any borrowed content, e.g. to keep chunks after the text they
+  /// were split from is dropped.
+  pub fn into_owned(self) -> Chunk<'static> {
+    match self {
+      Chunk::Simple(simple) => Chunk::Simple(simple.into_owned()),
+    }
+  }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SimpleChunk<'a> {
-  pub content: &'a str,
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
   pub loc: Loc,
-  pub tags: HashMap<&'a str, Tag<'a>>,
+  #[serde(borrow, default)]
+  pub tags: Tags<'a>,
 }
 
 impl<'a> SimpleChunk<'a> {
   pub fn as_chunk(self) -> Chunk<'a> {
     Chunk::Simple(self)
   }
+
+  pub fn into_owned(self) -> SimpleChunk<'static> {
+    SimpleChunk {
+      content: Cow::Owned(self.content.into_owned()),
+      loc: self.loc,
+      tags: self.tags.into_owned(),
+    }
+  }
 }
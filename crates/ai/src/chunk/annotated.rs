@@ -0,0 +1,147 @@
+use super::{
+  Chunk,
+  Chunker,
+};
+use crate::{
+  document::Document,
+  error::Error,
+  telemetry::{
+    self,
+    Size,
+  },
+};
+use derive_builder::Builder;
+
+/// Chunks the text of a document with another chunker and tags every chunk
+/// with the document's annotations intersecting it. Each annotation becomes a
+/// tag keyed by the name of its layer, with the annotation's value and `Loc`.
+#[derive(Builder, Debug)]
+#[builder(pattern = "owned", setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct AnnotatedChunker<C> {
+  /// The chunker used to split the text of the document.
+  #[builder(setter(into = false))]
+  chunker: C,
+
+  /// The annotation layers to project onto chunks. All layers are used when
+  /// empty.
+  #[builder(default)]
+  layers: Vec<String>,
+}
+
+impl<C> AnnotatedChunker<C> {
+  fn annotate<'a>(
+    &self,
+    input: &'a Document<'a>,
+  ) -> Result<Vec<Chunk<'a>>, Error>
+  where
+    C: Chunker<'a, Input = &'a str>,
+  {
+    let (Some(text), Some(annotations)) = (input.text(), input.annotations())
+    else {
+      return Err(Error::UnsupportedDocument(
+        "binary documents need to be loaded before chunking".to_string(),
+      ));
+    };
+
+    let mut chunks = self.chunker.chunk(text)?;
+    for chunk in &mut chunks {
+      let loc = chunk.loc().clone();
+      for tag in annotations.tags_at(&loc, &self.layers) {
+        chunk.tags_mut().insert(tag);
+      }
+    }
+    Ok(chunks)
+  }
+}
+
+impl<'a, C> Chunker<'a> for AnnotatedChunker<C>
+where
+  C: Chunker<'a, Input = &'a str>,
+{
+  type Input = &'a Document<'a>;
+
+  fn chunk(&self, input: Self::Input) -> Result<Vec<Chunk<'a>>, Error> {
+    telemetry::processor("AnnotatedChunker", Some(input.size()), || {
+      self.annotate(input)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    annotation::{
+      Annotation,
+      Annotations,
+    },
+    chunk::recursive::RecursiveChunkerBuilder,
+    document::TextDocument,
+    loc::Loc,
+  };
+
+  #[test]
+  fn projects_annotations() {
+    // Offsets:    0         1         2         3
+    //             012345678901234567890123456789012
+    let text = "Ada Lovelace met Charles Babbage.";
+    let mut annotations = Annotations::new();
+    annotations.insert("entities", Annotation::new(Loc::new(0, 12), "PERSON"));
+    annotations.insert("entities", Annotation::new(Loc::new(17, 32), "PERSON"));
+    annotations.insert("citations", Annotation::new(Loc::new(4, 20), "[1]"));
+    let doc = TextDocument::new(text)
+      .with_annotations(annotations)
+      .as_document();
+
+    let chunker = AnnotatedChunkerBuilder::default()
+      .chunker(
+        RecursiveChunkerBuilder::default()
+          .chunk_size(16u32)
+          .separators(vec![" met "])
+          .build()
+          .unwrap(),
+      )
+      .build()
+      .unwrap();
+    let chunks = chunker.chunk(&doc).unwrap();
+    let tags = chunks
+      .iter()
+      .map(|chunk| {
+        let tags = chunk
+          .tags()
+          .iter()
+          .map(|tag| format!("{}={}", tag.key, tag.value))
+          .collect::<Vec<_>>();
+        format!("{}: {}", chunk.content(), tags.join(" "))
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      tags,
+      [
+        "Ada Lovelace: citations=[1] entities=PERSON",
+        "Charles Babbage.: citations=[1] entities=PERSON",
+      ]
+    );
+    assert_eq!(
+      chunks[1].tags().get("citations").unwrap().loc,
+      Loc::new(4, 20)
+    );
+
+    let chunker = AnnotatedChunkerBuilder::default()
+      .chunker(
+        RecursiveChunkerBuilder::default()
+          .chunk_size(16u32)
+          .separators(vec![" met "])
+          .build()
+          .unwrap(),
+      )
+      .layers(vec!["entities".to_string()])
+      .build()
+      .unwrap();
+    let chunks = chunker.chunk(&doc).unwrap();
+    assert!(chunks
+      .iter()
+      .all(|chunk| chunk.tags().keys() == ["entities"]));
+  }
+}
@@ -7,6 +7,7 @@ use super::{
 use crate::{
   error::Error,
   loc::Loc,
+  telemetry,
 };
 use derive_builder::Builder;
 
@@ -22,10 +23,8 @@ pub struct RecursiveChunker<'sep> {
   separators: Vec<&'sep str>,
 }
 
-impl<'a> Chunker<'a> for RecursiveChunker<'a> {
-  type Input = &'a str;
-
-  fn chunk(&self, input: Self::Input) -> Result<Vec<Chunk<'a>>, Error> {
+impl<'a> RecursiveChunker<'a> {
+  fn split(&self, input: &'a str) -> Result<Vec<Chunk<'a>>, Error> {
     let chunk_size = self.chunk_size as usize;
     if chunk_size == 0 {
       return Err(Error::InvalidChunkSize(chunk_size as u32));
@@ -78,8 +77,8 @@ impl<'a> Chunker<'a> for RecursiveChunker<'a> {
             chunks.extend(simple_chunker.chunk(s)?)
           } else {
             chunks.push(Chunk::Simple(SimpleChunk {
-              content: &input[start..end],
-              loc: Loc { start, end },
+              content: input[start..end].into(),
+              loc: Loc::new(start, end),
               tags: Default::default(),
             }))
           }
@@ -92,6 +91,16 @@ impl<'a> Chunker<'a> for RecursiveChunker<'a> {
   }
 }
 
+impl<'a> Chunker<'a> for RecursiveChunker<'a> {
+  type Input = &'a str;
+
+  fn chunk(&self, input: Self::Input) -> Result<Vec<Chunk<'a>>, Error> {
+    telemetry::processor("RecursiveChunker", Some(input.len()), || {
+      self.split(input)
+    })
+  }
+}
+
 #[derive(Clone, Debug)]
 enum Part<'a> {
   String(&'a str),
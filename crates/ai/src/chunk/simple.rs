@@ -6,6 +6,7 @@ use super::{
 use crate::{
   error::Error,
   loc::Loc,
+  telemetry,
 };
 use derive_builder::Builder;
 
@@ -25,10 +26,8 @@ pub struct SimpleChunker {
   loc_offset: usize,
 }
 
-impl<'a> Chunker<'a> for SimpleChunker {
-  type Input = &'a str;
-
-  fn chunk(&self, input: Self::Input) -> Result<Vec<Chunk<'a>>, Error> {
+impl SimpleChunker {
+  fn split<'a>(&self, input: &'a str) -> Result<Vec<Chunk<'a>>, Error> {
     let chunk_size = self.chunk_size as usize;
     if chunk_size == 0 {
       return Err(Error::InvalidChunkSize(chunk_size as u32));
@@ -49,11 +48,8 @@ impl<'a> Chunker<'a> for SimpleChunker {
       // UTF-8 code point sequence. We have to adjust `end` accordingly.
       end = next_boundary(input, end);
       chunks.push(Chunk::Simple(SimpleChunk {
-        content: &input[start..end],
-        loc: Loc {
-          start: start + self.loc_offset,
-          end: end + self.loc_offset,
-        },
+        content: input[start..end].into(),
+        loc: Loc::new(start + self.loc_offset, end + self.loc_offset),
         tags: Default::default(),
       }));
       start = end;
@@ -63,6 +59,16 @@ impl<'a> Chunker<'a> for SimpleChunker {
   }
 }
 
+impl<'a> Chunker<'a> for SimpleChunker {
+  type Input = &'a str;
+
+  fn chunk(&self, input: Self::Input) -> Result<Vec<Chunk<'a>>, Error> {
+    telemetry::processor("SimpleChunker", Some(input.len()), || {
+      self.split(input)
+    })
+  }
+}
+
 // This finds the next valid character boundary in `string` that is >= `index`.
 // Note: it may return `string.len()` which is always considered a valid
 // character boundary.
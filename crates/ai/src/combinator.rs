@@ -0,0 +1,599 @@
+use crate::{
+  describe::Graph,
+  error::Error,
+  traits::{
+    AsyncProcessor,
+    Processor,
+  },
+};
+use futures_util::{
+  StreamExt,
+  TryStreamExt,
+};
+use rayon::prelude::*;
+use std::borrow::Cow;
+
+pub mod cache;
+pub mod dag;
+pub mod resilience;
+
+/// Runs every processor of a tuple on the same input, returning a tuple of
+/// their outputs, e.g. to embed and keyword-index the same chunks. The input
+/// is cloned for every processor but the last.
+///
+/// As a `Processor` the processors run one after the other. As an
+/// `AsyncProcessor` they run concurrently, and the first error cancels the
+/// others. Use a `Dag` to run sync processors in parallel.
+///
+/// ```
+/// use ragkit_ai::{
+///   combinator::FanOut,
+///   error::Error,
+///   traits::Processor,
+/// };
+///
+/// let words = |text: String| -> Result<usize, Error> {
+///   Ok(text.split_whitespace().count())
+/// };
+/// let chars = |text: String| -> Result<usize, Error> { Ok(text.len()) };
+///
+/// let stats = FanOut((words, chars)).run("hello world".to_string())?;
+/// assert_eq!(stats, (2, 11));
+/// # Ok::<(), Error>(())
+/// ```
+pub struct FanOut<T>(pub T);
+
+/// Runs every processor of a tuple on the same input like `FanOut`, and
+/// concatenates their outputs in order.
+pub struct Merge<T>(pub T);
+
+macro_rules! impl_fan_out {
+  ($(($processor:ident, $output:ident, $value:ident)),+; $last:ident, $last_output:ident, $last_value:ident) => {
+    impl<Input, $($processor, $output,)+ $last, $last_output>
+      Processor<Input, ($($output,)+ $last_output)>
+      for FanOut<($($processor,)+ $last)>
+    where
+      Input: Clone,
+      $($processor: Processor<Input, $output>,)+
+      $last: Processor<Input, $last_output>,
+    {
+      #[allow(non_snake_case)]
+      fn process(
+        &self,
+        input: Input,
+      ) -> Result<($($output,)+ $last_output), Error> {
+        let ($($processor,)+ $last) = &self.0;
+        $(let $value = $processor.run(input.clone())?;)+
+        let $last_value = $last.run(input)?;
+        Ok(($($value,)+ $last_value))
+      }
+
+      fn name(&self) -> Cow<'_, str> {
+        Cow::Borrowed("FanOut")
+      }
+
+      #[allow(non_snake_case)]
+      fn describe(&self) -> Graph {
+        let ($($processor,)+ $last) = &self.0;
+        Graph::parallel([$($processor.describe(),)+ $last.describe()])
+      }
+    }
+
+    impl<Input, $($processor, $output,)+ $last, $last_output>
+      AsyncProcessor<Input, ($($output,)+ $last_output)>
+      for FanOut<($($processor,)+ $last)>
+    where
+      Input: Clone + Send,
+      $($processor: AsyncProcessor<Input, $output> + Sync,)+
+      $last: AsyncProcessor<Input, $last_output> + Sync,
+      $($output: Send,)+
+      $last_output: Send,
+    {
+      #[allow(non_snake_case)]
+      async fn process(
+        &self,
+        input: Input,
+      ) -> Result<($($output,)+ $last_output), Error> {
+        let ($($processor,)+ $last) = &self.0;
+        futures_util::try_join!(
+          $($processor.process(input.clone()),)+
+          $last.process(input),
+        )
+      }
+
+      fn name(&self) -> Cow<'_, str> {
+        Cow::Borrowed("FanOut")
+      }
+
+      #[allow(non_snake_case)]
+      fn describe(&self) -> Graph {
+        let ($($processor,)+ $last) = &self.0;
+        Graph::parallel([$($processor.describe(),)+ $last.describe()])
+      }
+    }
+
+    impl<Input, Item, $($processor,)+ $last> Processor<Input, Vec<Item>>
+      for Merge<($($processor,)+ $last)>
+    where
+      Input: Clone,
+      $($processor: Processor<Input, Vec<Item>>,)+
+      $last: Processor<Input, Vec<Item>>,
+    {
+      #[allow(non_snake_case)]
+      fn process(&self, input: Input) -> Result<Vec<Item>, Error> {
+        let ($($processor,)+ $last) = &self.0;
+        let mut items = vec![];
+        $(items.extend($processor.run(input.clone())?);)+
+        items.extend($last.run(input)?);
+        Ok(items)
+      }
+
+      fn name(&self) -> Cow<'_, str> {
+        Cow::Borrowed("Merge")
+      }
+
+      #[allow(non_snake_case)]
+      fn describe(&self) -> Graph {
+        let ($($processor,)+ $last) = &self.0;
+        Graph::parallel([$($processor.describe(),)+ $last.describe()])
+      }
+    }
+
+    impl<Input, Item, $($processor,)+ $last> AsyncProcessor<Input, Vec<Item>>
+      for Merge<($($processor,)+ $last)>
+    where
+      Input: Clone + Send,
+      Item: Send,
+      $($processor: AsyncProcessor<Input, Vec<Item>> + Sync,)+
+      $last: AsyncProcessor<Input, Vec<Item>> + Sync,
+    {
+      #[allow(non_snake_case)]
+      async fn process(&self, input: Input) -> Result<Vec<Item>, Error> {
+        let ($($processor,)+ $last) = &self.0;
+        let ($($value,)+ $last_value) = futures_util::try_join!(
+          $($processor.process(input.clone()),)+
+          $last.process(input),
+        )?;
+        let mut items = vec![];
+        $(items.extend($value);)+
+        items.extend($last_value);
+        Ok(items)
+      }
+
+      fn name(&self) -> Cow<'_, str> {
+        Cow::Borrowed("Merge")
+      }
+
+      #[allow(non_snake_case)]
+      fn describe(&self) -> Graph {
+        let ($($processor,)+ $last) = &self.0;
+        Graph::parallel([$($processor.describe(),)+ $last.describe()])
+      }
+    }
+  };
+}
+
+impl_fan_out!((A, OutA, a); B, OutB, b);
+impl_fan_out!((A, OutA, a), (B, OutB, b); C, OutC, c);
+impl_fan_out!((A, OutA, a), (B, OutB, b), (C, OutC, c); D, OutD, d);
+impl_fan_out!(
+  (A, OutA, a), (B, OutB, b), (C, OutC, c), (D, OutD, d); E, OutE, e
+);
+
+/// Joins the outputs of a two-way `FanOut` item by item, e.g. chunks with
+/// their embeddings. Fails if the outputs have different lengths.
+pub struct Zip;
+
+impl<A, B> Processor<(Vec<A>, Vec<B>), Vec<(A, B)>> for Zip {
+  fn process(&self, input: (Vec<A>, Vec<B>)) -> Result<Vec<(A, B)>, Error> {
+    let (a, b) = input;
+    if a.len() != b.len() {
+      return Err(Error::InvalidInput(format!(
+        "can't zip {} items with {} items",
+        a.len(),
+        b.len()
+      )));
+    }
+    Ok(a.into_iter().zip(b).collect())
+  }
+}
+
+type Predicate<Input> = Box<dyn Fn(&Input) -> bool + Send + Sync>;
+
+type BoxedProcessor<Input, Output> =
+  Box<dyn Processor<Input, Output> + Send + Sync>;
+
+/// Sends each input to the first branch whose predicate accepts it, e.g. to
+/// chunk code and prose differently. Inputs no predicate accepts go to the
+/// fallback branch, or fail without one.
+///
+/// ```
+/// use ragkit_ai::{
+///   combinator::Router,
+///   error::Error,
+///   traits::Processor,
+/// };
+///
+/// let router = Router::new()
+///   .route(
+///     |n: &i32| *n < 0,
+///     |n: i32| -> Result<String, Error> { Ok(format!("negative {}", -n)) },
+///   )
+///   .otherwise(|n: i32| -> Result<String, Error> { Ok(n.to_string()) });
+/// assert_eq!(router.run(-4)?, "negative 4");
+/// assert_eq!(router.run(4)?, "4");
+/// # Ok::<(), Error>(())
+/// ```
+pub struct Router<Input, Output> {
+  routes: Vec<(Predicate<Input>, BoxedProcessor<Input, Output>)>,
+  fallback: Option<BoxedProcessor<Input, Output>>,
+}
+
+impl<Input, Output> Router<Input, Output> {
+  pub fn new() -> Self {
+    Self {
+      routes: vec![],
+      fallback: None,
+    }
+  }
+
+  /// Adds a branch, tried after the ones added before it.
+  pub fn route(
+    mut self,
+    predicate: impl Fn(&Input) -> bool + Send + Sync + 'static,
+    processor: impl Processor<Input, Output> + Send + Sync + 'static,
+  ) -> Self {
+    self.routes.push((Box::new(predicate), Box::new(processor)));
+    self
+  }
+
+  /// Sets the branch for inputs no predicate accepts.
+  pub fn otherwise(
+    mut self,
+    processor: impl Processor<Input, Output> + Send + Sync + 'static,
+  ) -> Self {
+    self.fallback = Some(Box::new(processor));
+    self
+  }
+}
+
+impl<Input, Output> Default for Router<Input, Output> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<Input, Output> Processor<Input, Output> for Router<Input, Output> {
+  fn process(&self, input: Input) -> Result<Output, Error> {
+    let branch = self
+      .routes
+      .iter()
+      .find(|(predicate, _)| predicate(&input))
+      .map(|(_, processor)| processor)
+      .or(self.fallback.as_ref());
+    match branch {
+      Some(processor) => processor.run(input),
+      None => Err(Error::InvalidInput(
+        "no route accepts the input".to_string(),
+      )),
+    }
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Borrowed("Router")
+  }
+
+  fn describe(&self) -> Graph {
+    let routes = self.routes.iter().map(|(_, processor)| processor);
+    let branches = routes.chain(&self.fallback);
+    Graph::parallel(branches.map(|processor| processor.describe()))
+  }
+}
+
+/// Runs a processor on every item of a list, e.g. on every element a
+/// splitter returns, keeping the order of the items. Stops at the first
+/// error.
+///
+/// Items are processed one at a time, see `ParMap` to process them
+/// concurrently.
+pub struct Map<P>(pub P);
+
+impl<Item, Output, P> Processor<Vec<Item>, Vec<Output>> for Map<P>
+where
+  P: Processor<Item, Output>,
+{
+  fn process(&self, input: Vec<Item>) -> Result<Vec<Output>, Error> {
+    input.into_iter().map(|item| self.0.run(item)).collect()
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Owned(format!("Map<{}>", self.0.name()))
+  }
+
+  fn config(&self) -> Option<String> {
+    self.0.config()
+  }
+
+  fn describe(&self) -> Graph {
+    mapped("Map", self.0.describe())
+  }
+}
+
+impl<Item, Output, P> AsyncProcessor<Vec<Item>, Vec<Output>> for Map<P>
+where
+  P: AsyncProcessor<Item, Output> + Sync,
+  Item: Send,
+  Output: Send,
+{
+  async fn process(&self, input: Vec<Item>) -> Result<Vec<Output>, Error> {
+    let mut outputs = Vec::with_capacity(input.len());
+    for item in input {
+      outputs.push(self.0.process(item).await?);
+    }
+    Ok(outputs)
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Owned(format!("Map<{}>", self.0.name()))
+  }
+
+  fn config(&self) -> Option<String> {
+    self.0.config()
+  }
+
+  fn describe(&self) -> Graph {
+    mapped("Map", self.0.describe())
+  }
+}
+
+// The stages of a processor applied to every item by `wrapper`, e.g.
+// `Map<Embed>`.
+fn mapped(wrapper: &str, mut graph: Graph) -> Graph {
+  for stage in &mut graph.stages {
+    stage.name = format!("{wrapper}<{}>", stage.name);
+  }
+  graph
+}
+
+/// Like `Map`, but processes items concurrently while keeping their order.
+///
+/// As a `Processor` the items are processed in parallel on rayon's thread
+/// pool. As an `AsyncProcessor` up to `concurrency` items are processed at
+/// once, e.g. to limit the number of requests made to an embedding API.
+///
+/// ```
+/// use ragkit_ai::{
+///   combinator::ParMap,
+///   error::Error,
+///   traits::Processor,
+/// };
+///
+/// let square = ParMap::new(|n: u64| -> Result<u64, Error> { Ok(n * n) });
+/// assert_eq!(square.run(vec![1, 2, 3])?, [1, 4, 9]);
+/// # Ok::<(), Error>(())
+/// ```
+pub struct ParMap<P> {
+  processor: P,
+  concurrency: usize,
+}
+
+impl<P> ParMap<P> {
+  /// The number of items processed at once by default by async processors.
+  pub const DEFAULT_CONCURRENCY: usize = 8;
+
+  pub fn new(processor: P) -> Self {
+    Self {
+      processor,
+      concurrency: Self::DEFAULT_CONCURRENCY,
+    }
+  }
+
+  /// Sets the number of items processed at once by async processors. Sync
+  /// processors use all of rayon's threads.
+  pub fn with_concurrency(self, concurrency: usize) -> Self {
+    Self {
+      concurrency: concurrency.max(1),
+      ..self
+    }
+  }
+}
+
+impl<Item, Output, P> Processor<Vec<Item>, Vec<Output>> for ParMap<P>
+where
+  P: Processor<Item, Output> + Sync,
+  Item: Send,
+  Output: Send,
+{
+  fn process(&self, input: Vec<Item>) -> Result<Vec<Output>, Error> {
+    input
+      .into_par_iter()
+      .map(|item| self.processor.run(item))
+      .collect()
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Owned(format!("ParMap<{}>", self.processor.name()))
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    mapped("ParMap", self.processor.describe())
+  }
+}
+
+impl<Item, Output, P> AsyncProcessor<Vec<Item>, Vec<Output>> for ParMap<P>
+where
+  P: AsyncProcessor<Item, Output> + Sync,
+  Item: Send,
+  Output: Send,
+{
+  async fn process(&self, input: Vec<Item>) -> Result<Vec<Output>, Error> {
+    futures_util::stream::iter(input)
+      .map(|item| self.processor.process(item))
+      .buffered(self.concurrency)
+      .try_collect()
+      .await
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Owned(format!("ParMap<{}>", self.processor.name()))
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    mapped("ParMap", self.processor.describe())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    element::Element,
+    process::splitter::simple::SimpleSplitterBuilder,
+    traits::{
+      AsyncPipeline,
+      Pipeline,
+    },
+  };
+  use std::{
+    sync::{
+      atomic::{
+        AtomicUsize,
+        Ordering,
+      },
+      Arc,
+    },
+    time::Duration,
+  };
+
+  fn words(text: &str) -> Result<Vec<String>, Error> {
+    Ok(text.split_whitespace().map(str::to_string).collect())
+  }
+
+  fn lengths(words: Vec<String>) -> Result<Vec<usize>, Error> {
+    Ok(words.iter().map(String::len).collect())
+  }
+
+  fn upper(words: Vec<String>) -> Result<Vec<String>, Error> {
+    Ok(words.iter().map(|word| word.to_uppercase()).collect())
+  }
+
+  #[test]
+  fn fan_out_and_join() {
+    let pipeline = Pipeline::new(words)
+      .chain(FanOut((upper, lengths)))
+      .chain(Zip);
+    let zipped: Vec<(String, usize)> = pipeline.run("a bb").unwrap();
+    assert_eq!(zipped, [("A".to_string(), 1), ("BB".to_string(), 2)]);
+
+    let merged = Pipeline::new(words).chain(Merge((upper, Ok)));
+    let words: Vec<String> = merged.run("a b").unwrap();
+    assert_eq!(words, ["A", "B", "a", "b"]);
+
+    let err = Zip.run((vec![1], vec![1, 2])).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Invalid input: can't zip 1 items with 2 items"
+    );
+  }
+
+  #[tokio::test]
+  async fn concurrent_fan_out() {
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let slow = |millis: u64| {
+      let running = Arc::clone(&running);
+      let max_running = Arc::clone(&max_running);
+      async move {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+        Ok(millis)
+      }
+    };
+    let pipeline = AsyncPipeline::new(FanOut((slow, slow, slow)));
+    assert_eq!(pipeline.run(20u64).await.unwrap(), (20, 20, 20));
+    assert_eq!(max_running.load(Ordering::SeqCst), 3);
+
+    let failing = |_: u64| async move { Err(Error::InvalidChunkSize(0)) };
+    let pipeline =
+      AsyncPipeline::new(Merge((|n: u64| async move { Ok(vec![n]) }, failing)));
+    let err = pipeline.run(1u64).await.unwrap_err();
+    assert_eq!(err.stages(), ["Merge"]);
+    assert!(matches!(err.root(), Error::InvalidChunkSize(0)));
+  }
+
+  #[test]
+  fn map() {
+    let splitter = SimpleSplitterBuilder::default()
+      .chunk_size(4u32)
+      .build()
+      .unwrap();
+    let pipeline = Pipeline::new(splitter)
+      .chain(Map(|element: Element| Ok(element.content().to_uppercase())));
+    let upper: Vec<String> = pipeline.run("abcdefghij").unwrap();
+    assert_eq!(upper, ["ABCD", "EFGH", "IJ"]);
+
+    let items = (0..1000u64).collect::<Vec<_>>();
+    let doubled = ParMap::new(|n: u64| Ok(n * 2)).run(items.clone()).unwrap();
+    assert_eq!(doubled, items.iter().map(|n| n * 2).collect::<Vec<_>>());
+
+    let failing = |n: u64| {
+      if n % 100 == 42 {
+        return Err(Error::InvalidInput(n.to_string()));
+      }
+      Ok(n)
+    };
+    let err = ParMap::new(failing).run(items.clone()).unwrap_err();
+    assert!(matches!(err, Error::InvalidInput(_)));
+    let err = Map(failing).run(items).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid input: 42");
+  }
+
+  #[tokio::test]
+  async fn bounded_par_map() {
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let fetch = |n: u64| {
+      let running = Arc::clone(&running);
+      let max_running = Arc::clone(&max_running);
+      async move {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now, Ordering::SeqCst);
+        // Later items finish first.
+        tokio::time::sleep(Duration::from_millis(50 - n * 2)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+        Ok(n * 10)
+      }
+    };
+
+    let pipeline = AsyncPipeline::new(ParMap::new(fetch).with_concurrency(4));
+    let fetched = pipeline.run((0..20).collect::<Vec<u64>>()).await.unwrap();
+    assert_eq!(fetched, (0..20).map(|n| n * 10).collect::<Vec<_>>());
+    assert_eq!(max_running.load(Ordering::SeqCst), 4);
+
+    let pipeline = AsyncPipeline::new(Map(fetch));
+    assert_eq!(pipeline.run(vec![1u64, 2]).await.unwrap(), [10, 20]);
+  }
+
+  #[test]
+  fn routing() {
+    let router = Router::new()
+      .route(|text: &&str| text.starts_with("fn "), |_| Ok("code"))
+      .route(|text: &&str| text.starts_with('#'), |_| Ok("markdown"));
+    assert_eq!(router.run("fn main() {}").unwrap(), "code");
+    assert_eq!(router.run("# Title").unwrap(), "markdown");
+    assert!(matches!(router.run("text"), Err(Error::InvalidInput(_))));
+
+    let router = router.otherwise(|_| Ok("text"));
+    assert_eq!(router.run("text").unwrap(), "text");
+  }
+}
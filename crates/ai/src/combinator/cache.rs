@@ -0,0 +1,723 @@
+use crate::{
+  describe::Graph,
+  error::Error,
+  traits::{
+    AsyncProcessor,
+    Processor,
+  },
+};
+use derive_builder::Builder;
+use serde::{
+  de::DeserializeOwned,
+  Serialize,
+};
+use std::{
+  borrow::Cow,
+  collections::{
+    BTreeMap,
+    HashMap,
+  },
+  fs,
+  io,
+  path::PathBuf,
+  sync::{
+    atomic::{
+      AtomicU64,
+      Ordering,
+    },
+    Mutex,
+  },
+  time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+  },
+};
+
+/// A cached output, serialized.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheEntry {
+  pub value: Vec<u8>,
+  /// When the entry stops being used, or `None` to keep it until it's
+  /// evicted.
+  pub expires_at: Option<SystemTime>,
+}
+
+impl CacheEntry {
+  pub fn is_expired(&self) -> bool {
+    self
+      .expires_at
+      .is_some_and(|expires_at| expires_at <= SystemTime::now())
+  }
+}
+
+/// Where `Cached` keeps its entries. Stores never return expired entries.
+pub trait CacheStore: Send + Sync {
+  fn get(&self, key: &str) -> Result<Option<CacheEntry>, Error>;
+
+  fn put(&self, key: &str, entry: CacheEntry) -> Result<(), Error>;
+
+  fn remove(&self, key: &str) -> Result<(), Error>;
+}
+
+/// An in-memory store keeping up to `capacity` entries, evicting the least
+/// recently used ones first.
+#[derive(Debug)]
+pub struct MemoryStore {
+  capacity: usize,
+  lru: Mutex<Lru>,
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+  // Every entry with the tick it was last used at.
+  entries: HashMap<String, (CacheEntry, u64)>,
+  // Keys by the tick they were last used at, least recent first.
+  order: BTreeMap<u64, String>,
+  tick: u64,
+}
+
+impl Lru {
+  fn touch(&mut self, key: &str) {
+    if let Some((_, tick)) = self.entries.get_mut(key) {
+      self.order.remove(tick);
+      self.tick += 1;
+      *tick = self.tick;
+      self.order.insert(self.tick, key.to_string());
+    }
+  }
+
+  fn remove(&mut self, key: &str) {
+    if let Some((_, tick)) = self.entries.remove(key) {
+      self.order.remove(&tick);
+    }
+  }
+}
+
+impl MemoryStore {
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      lru: Mutex::default(),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.lock().entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+    self.lru.lock().expect("cache is never poisoned")
+  }
+}
+
+impl CacheStore for MemoryStore {
+  fn get(&self, key: &str) -> Result<Option<CacheEntry>, Error> {
+    let mut lru = self.lock();
+    let Some((entry, _)) = lru.entries.get(key) else {
+      return Ok(None);
+    };
+    if entry.is_expired() {
+      lru.remove(key);
+      return Ok(None);
+    }
+    let entry = entry.clone();
+    lru.touch(key);
+    Ok(Some(entry))
+  }
+
+  fn put(&self, key: &str, entry: CacheEntry) -> Result<(), Error> {
+    if self.capacity == 0 {
+      return Ok(());
+    }
+    let mut lru = self.lock();
+    lru.remove(key);
+    while lru.entries.len() >= self.capacity {
+      let Some((_, oldest)) = lru.order.pop_first() else {
+        break;
+      };
+      lru.entries.remove(&oldest);
+    }
+    lru.tick += 1;
+    let tick = lru.tick;
+    lru.entries.insert(key.to_string(), (entry, tick));
+    lru.order.insert(tick, key.to_string());
+    Ok(())
+  }
+
+  fn remove(&self, key: &str) -> Result<(), Error> {
+    self.lock().remove(key);
+    Ok(())
+  }
+}
+
+/// A store keeping every entry in a file under `dir`, so entries outlive the
+/// process. Expired entries are deleted when they're read.
+///
+/// Keys must be lowercase hex, like the keys of `Cached`, so they're safe to
+/// use as file names.
+#[derive(Debug)]
+pub struct DiskStore {
+  dir: PathBuf,
+}
+
+impl DiskStore {
+  /// Opens a store in `dir`, creating it if needed.
+  pub fn new(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+    let dir = dir.into();
+    fs::create_dir_all(&dir).map_err(|err| Error::io(&dir, err))?;
+    Ok(Self { dir })
+  }
+
+  // Entries are spread over subdirectories by the first characters of their
+  // key, which are hashes.
+  fn path(&self, key: &str) -> Result<PathBuf, Error> {
+    let hex = |c: char| matches!(c, '0'..='9' | 'a'..='f');
+    if key.is_empty() || !key.chars().all(hex) {
+      return Err(Error::InvalidInput(format!(
+        "cache key {key:?} isn't lowercase hex"
+      )));
+    }
+    let prefix = key.get(..2).unwrap_or("_");
+    Ok(self.dir.join(prefix).join(key))
+  }
+}
+
+impl CacheStore for DiskStore {
+  fn get(&self, key: &str) -> Result<Option<CacheEntry>, Error> {
+    let path = self.path(key)?;
+    let bytes = match fs::read(&path) {
+      Ok(bytes) => bytes,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(err) => return Err(Error::io(path, err)),
+    };
+    // Entries start with their expiry in seconds since the epoch, or 0.
+    let Some((expiry, value)) = bytes.split_first_chunk::<8>() else {
+      return Ok(None);
+    };
+    let expiry = u64::from_le_bytes(*expiry);
+    let entry = CacheEntry {
+      value: value.to_vec(),
+      expires_at: (expiry > 0)
+        .then(|| UNIX_EPOCH + Duration::from_secs(expiry)),
+    };
+    if entry.is_expired() {
+      self.remove(key)?;
+      return Ok(None);
+    }
+    Ok(Some(entry))
+  }
+
+  fn put(&self, key: &str, entry: CacheEntry) -> Result<(), Error> {
+    let path = self.path(key)?;
+    let dir = path.parent().expect("entries are in a subdirectory");
+    fs::create_dir_all(dir).map_err(|err| Error::io(dir, err))?;
+
+    let expiry = entry.expires_at.map_or(0, |expires_at| {
+      let secs = expires_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+      // Rounded up, so entries never live shorter than asked.
+      secs.as_secs() + u64::from(secs.subsec_nanos() > 0)
+    });
+    let mut bytes = expiry.to_le_bytes().to_vec();
+    bytes.extend(entry.value);
+    // Written to a temporary file first so readers never see half an entry.
+    // Its name is unique, so writers of the same entry don't mix theirs up.
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let tmp = path.with_extension(format!(
+      "{}.{}.tmp",
+      std::process::id(),
+      WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp, bytes).map_err(|err| Error::io(&tmp, err))?;
+    fs::rename(&tmp, &path).map_err(|err| Error::io(&path, err))
+  }
+
+  fn remove(&self, key: &str) -> Result<(), Error> {
+    let path = self.path(key)?;
+    match fs::remove_file(&path) {
+      Err(err) if err.kind() != io::ErrorKind::NotFound => {
+        Err(Error::io(path, err))
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Two stores used together, usually a small `MemoryStore` in front of a
+/// `DiskStore`. Entries are written to both, and entries only found in the
+/// second store are copied to the first.
+#[derive(Debug)]
+pub struct TieredStore<A, B> {
+  first: A,
+  second: B,
+}
+
+impl<A: CacheStore, B: CacheStore> TieredStore<A, B> {
+  pub fn new(first: A, second: B) -> Self {
+    Self { first, second }
+  }
+}
+
+impl<A: CacheStore, B: CacheStore> CacheStore for TieredStore<A, B> {
+  fn get(&self, key: &str) -> Result<Option<CacheEntry>, Error> {
+    if let Some(entry) = self.first.get(key)? {
+      return Ok(Some(entry));
+    }
+    let entry = self.second.get(key)?;
+    if let Some(entry) = &entry {
+      self.first.put(key, entry.clone())?;
+    }
+    Ok(entry)
+  }
+
+  fn put(&self, key: &str, entry: CacheEntry) -> Result<(), Error> {
+    self.first.put(key, entry.clone())?;
+    self.second.put(key, entry)
+  }
+
+  fn remove(&self, key: &str) -> Result<(), Error> {
+    self.first.remove(key)?;
+    self.second.remove(key)
+  }
+}
+
+/// How a `Cached` processor has been used so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+  pub hits: u64,
+  pub misses: u64,
+  /// Store failures and entries that couldn't be (de)serialized. These are
+  /// treated as misses, so a broken cache never fails a pipeline.
+  pub errors: u64,
+}
+
+impl CacheStats {
+  /// The share of calls answered from the cache, between 0 and 1.
+  pub fn hit_rate(&self) -> f64 {
+    let calls = self.hits + self.misses;
+    if calls == 0 {
+      return 0.0;
+    }
+    self.hits as f64 / calls as f64
+  }
+}
+
+/// Caches the outputs of a processor, so re-running a pipeline skips
+/// expensive steps like embedding chunks that haven't changed.
+///
+/// Outputs are keyed by a hash of the input, serialized as JSON, together
+/// with `name` and `version`. Change the version whenever the processor's
+/// config changes in a way that changes its outputs, e.g. to the name of
+/// the embedding model. Outputs must be owned, since they're deserialized
+/// from the store.
+///
+/// ```
+/// use ragkit_ai::{
+///   combinator::cache::{
+///     CachedBuilder,
+///     MemoryStore,
+///   },
+///   error::Error,
+///   traits::Processor,
+/// };
+///
+/// let embed =
+///   |text: String| -> Result<Vec<f32>, Error> { Ok(vec![text.len() as f32]) };
+/// let cached = CachedBuilder::default()
+///   .processor(embed)
+///   .store(MemoryStore::new(1000))
+///   .name("embed")
+///   .version("model-v1")
+///   .build()?;
+/// cached.run("hello".to_string())?;
+/// cached.run("hello".to_string())?;
+/// assert_eq!(cached.stats().hits, 1);
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Builder, Debug)]
+#[builder(pattern = "owned", setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct Cached<P, S> {
+  #[builder(setter(into = false))]
+  processor: P,
+
+  #[builder(setter(into = false))]
+  store: S,
+
+  /// Identifies the processor, so processors can share a store. It's part
+  /// of the key, so it must stay the same for outputs to be reused.
+  name: String,
+
+  #[builder(default)]
+  version: String,
+
+  /// How long outputs are kept. Forever by default.
+  #[builder(setter(strip_option), default)]
+  ttl: Option<Duration>,
+
+  #[builder(setter(skip))]
+  hits: AtomicU64,
+
+  #[builder(setter(skip))]
+  misses: AtomicU64,
+
+  #[builder(setter(skip))]
+  errors: AtomicU64,
+}
+
+impl<P, S: CacheStore> Cached<P, S> {
+  pub fn stats(&self) -> CacheStats {
+    CacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      errors: self.errors.load(Ordering::Relaxed),
+    }
+  }
+
+  pub fn store(&self) -> &S {
+    &self.store
+  }
+
+  /// The key of the output for `input` of a processor with the given
+  /// `config`: a BLAKE3 hash of the name, version, config and input
+  /// serialized as JSON. The config is part of the key so changing a setting
+  /// of the processor doesn't reuse stale outputs. It only stays the same
+  /// across runs if the input serializes the same way, which isn't the case
+  /// for e.g. a `HashMap`, whose order changes.
+  pub fn key<Input: Serialize>(
+    &self,
+    config: Option<&str>,
+    input: &Input,
+  ) -> Result<String, Error> {
+    let input = serde_json::to_vec(input)
+      .map_err(|err| Error::serialization("json", err))?;
+    // Tell a missing config apart from an empty one.
+    let config = match config {
+      Some(config) => [b"+", config.as_bytes()].concat(),
+      None => vec![],
+    };
+    let mut hasher = blake3::Hasher::new();
+    let parts = [
+      self.name.as_bytes(),
+      self.version.as_bytes(),
+      &config,
+      &input,
+    ];
+    for part in parts {
+      // Length-prefixed, so parts can't run into each other.
+      hasher.update(&(part.len() as u64).to_le_bytes());
+      hasher.update(part);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+  }
+
+  /// The key for `input`, counting inputs that can't be serialized as errors
+  /// and misses: they are processed without the cache.
+  fn try_key<Input: Serialize>(
+    &self,
+    config: Option<String>,
+    input: &Input,
+  ) -> Option<String> {
+    let key = self.key(config.as_deref(), input).ok();
+    if key.is_none() {
+      self.errors.fetch_add(1, Ordering::Relaxed);
+      self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+    key
+  }
+
+  fn lookup<Output: DeserializeOwned>(&self, key: &str) -> Option<Output> {
+    let output = match self.store.get(key) {
+      Ok(Some(entry)) => serde_json::from_slice(&entry.value).ok(),
+      Ok(None) => {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        return None;
+      }
+      Err(_) => None,
+    };
+    match output {
+      Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+      None => {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        self.misses.fetch_add(1, Ordering::Relaxed)
+      }
+    };
+    output
+  }
+
+  fn save<Output: Serialize>(&self, key: &str, output: &Output) {
+    let entry = serde_json::to_vec(output).map(|value| CacheEntry {
+      value,
+      expires_at: self.ttl.map(|ttl| SystemTime::now() + ttl),
+    });
+    let saved = match entry {
+      Ok(entry) => self.store.put(key, entry).is_ok(),
+      Err(_) => false,
+    };
+    if !saved {
+      self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+}
+
+impl<Input, Output, P, S> Processor<Input, Output> for Cached<P, S>
+where
+  P: Processor<Input, Output>,
+  S: CacheStore,
+  Input: Serialize,
+  Output: Serialize + DeserializeOwned,
+{
+  fn process(&self, input: Input) -> Result<Output, Error> {
+    let Some(key) = self.try_key(self.processor.config(), &input) else {
+      return self.processor.run(input);
+    };
+    if let Some(output) = self.lookup(&key) {
+      return Ok(output);
+    }
+    let output = self.processor.run(input)?;
+    self.save(&key, &output);
+    Ok(output)
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.processor.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.processor.describe()
+  }
+}
+
+impl<Input, Output, P, S> AsyncProcessor<Input, Output> for Cached<P, S>
+where
+  P: AsyncProcessor<Input, Output> + Sync,
+  S: CacheStore,
+  Input: Serialize + Send,
+  Output: Serialize + DeserializeOwned + Send,
+{
+  async fn process(&self, input: Input) -> Result<Output, Error> {
+    let Some(key) = self.try_key(self.processor.config(), &input) else {
+      return self.processor.process(input).await;
+    };
+    if let Some(output) = self.lookup(&key) {
+      return Ok(output);
+    }
+    let output = self.processor.process(input).await?;
+    self.save(&key, &output);
+    Ok(output)
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.processor.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.processor.describe()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::AtomicU32;
+
+  fn entry(value: &str) -> CacheEntry {
+    CacheEntry {
+      value: value.as_bytes().to_vec(),
+      expires_at: None,
+    }
+  }
+
+  #[test]
+  fn caches_outputs() {
+    let calls = AtomicU32::new(0);
+    let embed = |text: String| -> Result<Vec<f32>, Error> {
+      calls.fetch_add(1, Ordering::SeqCst);
+      Ok(vec![text.len() as f32])
+    };
+    let cached = CachedBuilder::default()
+      .processor(embed)
+      .store(MemoryStore::new(10))
+      .name("embed")
+      .version("v1")
+      .build()
+      .unwrap();
+    assert_eq!(cached.run("abc".to_string()).unwrap(), [3.0]);
+    assert_eq!(cached.run("abc".to_string()).unwrap(), [3.0]);
+    assert_eq!(cached.run("de".to_string()).unwrap(), [2.0]);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(
+      cached.stats(),
+      CacheStats {
+        hits: 1,
+        misses: 2,
+        errors: 0,
+      }
+    );
+    assert!((cached.stats().hit_rate() - 1.0 / 3.0).abs() < 1e-9);
+
+    // Keys are stable, and depend on the version and config.
+    let key = cached.key(None, &"abc").unwrap();
+    assert_eq!(key, cached.key(None, &"abc".to_string()).unwrap());
+    assert_ne!(key, cached.key(Some(""), &"abc").unwrap());
+    assert_ne!(key, cached.key(Some("size: 2"), &"abc").unwrap());
+    let other = CachedBuilder::default()
+      .processor(embed)
+      .store(MemoryStore::new(10))
+      .name("embed")
+      .version("v2")
+      .build()
+      .unwrap();
+    assert_ne!(key, other.key(None, &"abc").unwrap());
+
+    // Undecodable entries are misses.
+    cached.store().put(&key, entry("not json")).unwrap();
+    assert_eq!(cached.run("abc".to_string()).unwrap(), [3.0]);
+    assert_eq!(cached.stats().errors, 1);
+  }
+
+  struct Truncate(usize);
+
+  impl Processor<String, String> for Truncate {
+    fn process(&self, text: String) -> Result<String, Error> {
+      Ok(text.chars().take(self.0).collect())
+    }
+
+    fn config(&self) -> Option<String> {
+      Some(format!("len: {}", self.0))
+    }
+  }
+
+  #[test]
+  fn keys_include_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let cached = |len| {
+      CachedBuilder::default()
+        .processor(Truncate(len))
+        .store(DiskStore::new(dir.path()).unwrap())
+        .name("truncate")
+        .build()
+        .unwrap()
+    };
+    assert_eq!(cached(2).run("hello".to_string()).unwrap(), "he");
+    // Same name and version, other settings.
+    assert_eq!(cached(3).run("hello".to_string()).unwrap(), "hel");
+    let again = cached(2);
+    assert_eq!(again.run("hello".to_string()).unwrap(), "he");
+    assert_eq!(again.stats().hits, 1);
+  }
+
+  #[test]
+  fn unserializable_inputs_skip_the_cache() {
+    // JSON objects need string keys.
+    let sum = |map: HashMap<(u8, u8), u8>| -> Result<u8, Error> {
+      Ok(map.values().sum())
+    };
+    let cached = CachedBuilder::default()
+      .processor(sum)
+      .store(MemoryStore::new(10))
+      .name("sum")
+      .build()
+      .unwrap();
+    let input = HashMap::from([((1, 2), 3), ((4, 5), 6)]);
+    assert_eq!(cached.run(input).unwrap(), 9);
+    assert_eq!(cached.store().len(), 0);
+    assert_eq!(
+      cached.stats(),
+      CacheStats {
+        hits: 0,
+        misses: 1,
+        errors: 1,
+      }
+    );
+  }
+
+  #[test]
+  fn lru_and_ttl() {
+    let store = MemoryStore::new(2);
+    store.put("a", entry("1")).unwrap();
+    store.put("b", entry("2")).unwrap();
+    store.get("a").unwrap();
+    store.put("c", entry("3")).unwrap();
+    assert_eq!(store.len(), 2);
+    assert!(store.get("b").unwrap().is_none());
+    assert_eq!(store.get("a").unwrap(), Some(entry("1")));
+
+    let expired = CacheEntry {
+      expires_at: Some(SystemTime::now() - Duration::from_secs(1)),
+      ..entry("4")
+    };
+    store.put("d", expired).unwrap();
+    assert!(store.get("d").unwrap().is_none());
+    assert!(store.len() == 1);
+  }
+
+  #[tokio::test]
+  async fn disk_and_tiered_stores() {
+    let dir = tempfile::tempdir().unwrap();
+    let calls = AtomicU32::new(0);
+    let summarize = |text: String| {
+      calls.fetch_add(1, Ordering::SeqCst);
+      async move { Ok(text.to_uppercase()) }
+    };
+
+    for _ in 0..2 {
+      // A new memory store every time, as if the process restarted.
+      let cached = CachedBuilder::default()
+        .processor(summarize)
+        .store(TieredStore::new(
+          MemoryStore::new(10),
+          DiskStore::new(dir.path()).unwrap(),
+        ))
+        .name("summarize")
+        .build()
+        .unwrap();
+      let summary = AsyncProcessor::process(&cached, "hi".to_string()).await;
+      assert_eq!(summary.unwrap(), "HI");
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let store = DiskStore::new(dir.path()).unwrap();
+    let expiring = CacheEntry {
+      expires_at: Some(SystemTime::now() + Duration::from_secs(60)),
+      ..entry("x")
+    };
+    store.put("abcd", expiring).unwrap();
+    assert_eq!(store.get("abcd").unwrap().unwrap().value, b"x");
+    let expired = CacheEntry {
+      expires_at: Some(SystemTime::now() - Duration::from_secs(60)),
+      ..entry("x")
+    };
+    store.put("abcd", expired).unwrap();
+    assert!(store.get("abcd").unwrap().is_none());
+    assert!(!dir.path().join("ab").join("abcd").exists());
+
+    for key in ["", "../abcd", "ABCD"] {
+      assert!(matches!(
+        store.put(key, entry("x")),
+        Err(Error::InvalidInput(_))
+      ));
+    }
+  }
+
+  #[test]
+  fn requires_a_name() {
+    let result = CachedBuilder::default()
+      .processor(|text: String| -> Result<String, Error> { Ok(text) })
+      .store(MemoryStore::new(10))
+      .build();
+    assert!(matches!(result, Err(Error::UninitializedField("name"))));
+  }
+}
@@ -0,0 +1,646 @@
+use crate::{
+  describe::{
+    Graph,
+    Stage,
+  },
+  dynamic::{
+    downcast,
+    DynValue,
+    TypeInfo,
+  },
+  error::Error,
+  telemetry,
+  traits::Processor,
+};
+use std::{
+  any::Any,
+  borrow::Cow,
+  marker::PhantomData,
+  panic::{
+    self,
+    AssertUnwindSafe,
+  },
+  sync::mpsc,
+};
+
+type Run = Box<dyn Fn(Vec<DynValue>) -> Result<DynValue, Error> + Send + Sync>;
+
+struct Node {
+  name: String,
+  config: Option<String>,
+  inputs: Vec<usize>,
+  output_type: TypeInfo,
+  // `None` for the input of the DAG.
+  run: Option<Run>,
+  // Outputs used by several nodes are cloned for all but the last one.
+  clone: fn(&DynValue) -> DynValue,
+}
+
+/// Processors connected in a directed acyclic graph, e.g. to embed and
+/// keyword-index the same chunks and then store both. Every node takes the
+/// outputs of the nodes it's connected from, and the DAG returns the output
+/// of one node.
+///
+/// Only the nodes the output depends on run. Every node starts on a scoped
+/// thread as soon as the nodes it's connected from have finished, so nodes
+/// that don't depend on each other run in parallel. Nodes can only be
+/// connected from nodes added before them, so the graph can't have cycles.
+///
+/// ```
+/// use ragkit_ai::{
+///   combinator::dag::{
+///     DagBuilder,
+///     INPUT,
+///   },
+///   error::Error,
+///   traits::Processor,
+/// };
+///
+/// let dag = DagBuilder::<String>::new()
+///   .node("words", INPUT, |text: String| {
+///     Ok(text.split_whitespace().count())
+///   })
+///   .node("chars", INPUT, |text: String| Ok(text.len()))
+///   .join(
+///     "ratio",
+///     &["chars", "words"],
+///     |(chars, words): (usize, usize)| Ok(chars / words),
+///   )
+///   .build::<usize>("ratio")?;
+/// assert_eq!(dag.run("hello there".to_string())?, 5);
+/// # Ok::<(), Error>(())
+/// ```
+pub struct Dag<Input, Output> {
+  nodes: Vec<Node>,
+  // The nodes connected from each node, among those that run.
+  dependents: Vec<Vec<usize>>,
+  // How many times the output of each node is used by the nodes that run.
+  uses: Vec<usize>,
+  output: usize,
+  phantom: PhantomData<fn(Input) -> Output>,
+}
+
+/// The name of the input of a DAG, which nodes can be connected from.
+pub const INPUT: &str = "input";
+
+/// Builds a `Dag` taking `Input`, see `Dag`.
+pub struct DagBuilder<Input> {
+  nodes: Vec<Node>,
+  // The first error is reported by `build`, so nodes can be chained.
+  error: Option<Error>,
+  phantom: PhantomData<fn(Input)>,
+}
+
+impl<Input> DagBuilder<Input>
+where
+  Input: Clone + Send + 'static,
+{
+  pub fn new() -> Self {
+    Self {
+      nodes: vec![Node {
+        name: INPUT.to_string(),
+        config: None,
+        inputs: vec![],
+        output_type: TypeInfo::of::<Input>(),
+        run: None,
+        clone: clone_value::<Input>,
+      }],
+      error: None,
+      phantom: PhantomData,
+    }
+  }
+
+  /// Adds a node running `processor` on the output of the node `from`.
+  pub fn node<In, Out>(
+    self,
+    name: &str,
+    from: &str,
+    processor: impl Processor<In, Out> + Send + Sync + 'static,
+  ) -> Self
+  where
+    In: Send + 'static,
+    Out: Clone + Send + 'static,
+  {
+    let config = processor.config();
+    let run: Run = Box::new(move |mut values| {
+      let input =
+        downcast::<In>(values.remove(0), || "node input".to_string())?;
+      Ok(Box::new(processor.run(input)?))
+    });
+    let input_types = vec![TypeInfo::of::<In>()];
+    self.add::<Out>(name, config, &[from], input_types, run)
+  }
+
+  /// Adds a node running `processor` on the outputs of the nodes `from`,
+  /// passed as a tuple in the same order.
+  pub fn join<In, Out>(
+    self,
+    name: &str,
+    from: &[&str],
+    processor: impl Processor<In, Out> + Send + Sync + 'static,
+  ) -> Self
+  where
+    In: Join,
+    Out: Clone + Send + 'static,
+  {
+    let config = processor.config();
+    let run: Run = Box::new(move |values| {
+      let input = In::join(values)?;
+      Ok(Box::new(processor.run(input)?))
+    });
+    self.add::<Out>(name, config, from, In::types(), run)
+  }
+
+  /// Builds a DAG returning the output of the node `output`.
+  pub fn build<Output: 'static>(
+    self,
+    output: &str,
+  ) -> Result<Dag<Input, Output>, Error> {
+    if let Some(err) = self.error {
+      return Err(err);
+    }
+    let output = find(&self.nodes, output)?;
+    let output_type = self.nodes[output].output_type;
+    if output_type != TypeInfo::of::<Output>() {
+      return Err(Error::TypeMismatch {
+        at: "DAG output".to_string(),
+        expected: std::any::type_name::<Output>(),
+        found: output_type.name,
+      });
+    }
+
+    // Nodes are connected from earlier nodes only, so walking back from the
+    // output finds all the nodes it depends on.
+    let mut needed = vec![false; self.nodes.len()];
+    needed[output] = true;
+    for index in (0..=output).rev() {
+      if needed[index] {
+        for &input in &self.nodes[index].inputs {
+          needed[input] = true;
+        }
+      }
+    }
+    let mut dependents = vec![vec![]; self.nodes.len()];
+    let mut uses = vec![0; self.nodes.len()];
+    for (index, node) in self.nodes.iter().enumerate() {
+      if needed[index] {
+        for &input in &node.inputs {
+          dependents[input].push(index);
+          uses[input] += 1;
+        }
+      }
+    }
+    uses[output] += 1;
+
+    Ok(Dag {
+      nodes: self.nodes,
+      dependents,
+      uses,
+      output,
+      phantom: PhantomData,
+    })
+  }
+
+  fn add<Out: Clone + Send + 'static>(
+    mut self,
+    name: &str,
+    config: Option<String>,
+    from: &[&str],
+    input_types: Vec<TypeInfo>,
+    run: Run,
+  ) -> Self {
+    if self.error.is_none() {
+      let added = self.try_add::<Out>(name, config, from, input_types, run);
+      if let Err(err) = added {
+        self.error = Some(err);
+      }
+    }
+    self
+  }
+
+  fn try_add<Out: Clone + Send + 'static>(
+    &mut self,
+    name: &str,
+    config: Option<String>,
+    from: &[&str],
+    input_types: Vec<TypeInfo>,
+    run: Run,
+  ) -> Result<(), Error> {
+    if self.nodes.iter().any(|node| node.name == name) {
+      return Err(Error::InvalidInput(format!(
+        "DAG node `{name}` is defined twice"
+      )));
+    }
+    if from.len() != input_types.len() {
+      return Err(Error::InvalidInput(format!(
+        "DAG node `{name}` takes {} inputs, but is connected from {}",
+        input_types.len(),
+        from.len()
+      )));
+    }
+
+    let mut inputs = vec![];
+    for (from, input_type) in from.iter().zip(input_types) {
+      let index = find(&self.nodes, from)?;
+      let output_type = self.nodes[index].output_type;
+      if output_type != input_type {
+        return Err(Error::TypeMismatch {
+          at: format!("DAG node `{name}` input from `{from}`"),
+          expected: input_type.name,
+          found: output_type.name,
+        });
+      }
+      inputs.push(index);
+    }
+
+    self.nodes.push(Node {
+      name: name.to_string(),
+      config,
+      inputs,
+      output_type: TypeInfo::of::<Out>(),
+      run: Some(run),
+      clone: clone_value::<Out>,
+    });
+    Ok(())
+  }
+}
+
+impl<Input> Default for DagBuilder<Input>
+where
+  Input: Clone + Send + 'static,
+{
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<Input, Output> Processor<Input, Output> for Dag<Input, Output>
+where
+  Input: Send + 'static,
+  Output: 'static,
+{
+  fn process(&self, input: Input) -> Result<Output, Error> {
+    let mut values: Vec<Option<DynValue>> =
+      self.nodes.iter().map(|_| None).collect();
+    values[0] = Some(Box::new(input));
+    let mut uses = self.uses.clone();
+    // The number of inputs each node is still waiting for.
+    let mut waiting = self
+      .nodes
+      .iter()
+      .map(|node| node.inputs.len())
+      .collect::<Vec<_>>();
+    let mut ready = vec![];
+    self.finished(0, &mut waiting, &mut ready);
+
+    std::thread::scope(|scope| -> Result<(), Error> {
+      let (sender, receiver) = mpsc::channel();
+      let mut running = 0;
+      loop {
+        // A single node with nothing else running doesn't need a thread.
+        if running == 0 && ready.len() == 1 {
+          let index = ready.pop().expect("a node is ready");
+          let inputs = self.inputs(index, &mut values, &mut uses);
+          values[index] = Some(self.run_node(index, inputs)?);
+          self.finished(index, &mut waiting, &mut ready);
+          continue;
+        }
+        for index in ready.drain(..) {
+          let inputs = self.inputs(index, &mut values, &mut uses);
+          let sender = sender.clone();
+          running += 1;
+          scope.spawn(move || {
+            let run = AssertUnwindSafe(|| self.run_node(index, inputs));
+            let output = panic::catch_unwind(run).unwrap_or_else(|_| {
+              Err(Error::Task(format!(
+                "DAG node `{}` panicked",
+                self.nodes[index].name
+              )))
+            });
+            // Nobody is listening anymore if another node failed.
+            let _ = sender.send((index, output));
+          });
+        }
+        if running == 0 {
+          return Ok(());
+        }
+        let (index, output) =
+          receiver.recv().expect("running nodes send their output");
+        running -= 1;
+        values[index] = Some(output?);
+        self.finished(index, &mut waiting, &mut ready);
+      }
+    })?;
+
+    let output = self.take(&mut values, &mut uses, self.output);
+    downcast::<Output>(output, || "DAG output".to_string())
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Borrowed("Dag")
+  }
+
+  fn describe(&self) -> Graph {
+    // The input isn't a stage, so stages are numbered from the first node.
+    let mut graph = Graph::default();
+    for (index, node) in self.nodes.iter().enumerate().skip(1) {
+      graph.stages.push(Stage {
+        name: node.name.clone(),
+        config: node.config.clone(),
+      });
+      for &input in &node.inputs {
+        match input {
+          0 => graph.inputs.push(index - 1),
+          input => graph.edges.push((input - 1, index - 1)),
+        }
+      }
+    }
+    graph.outputs.extend(self.output.checked_sub(1));
+    graph
+  }
+}
+
+impl<Input, Output> Dag<Input, Output> {
+  /// The names of the nodes, in the order they were added.
+  pub fn nodes(&self) -> impl Iterator<Item = &str> {
+    self.nodes.iter().map(|node| node.name.as_str())
+  }
+
+  fn run_node(
+    &self,
+    index: usize,
+    inputs: Vec<DynValue>,
+  ) -> Result<DynValue, Error> {
+    let node = &self.nodes[index];
+    let run = node.run.as_ref().expect("only the input has no processor");
+    // Numbered like the stages of `describe`, which leave out the input.
+    telemetry::stage(index - 1, &node.name, || run(inputs))
+  }
+
+  /// Marks the node `index` as finished, adding the nodes that were only
+  /// waiting for it to `ready`.
+  fn finished(
+    &self,
+    index: usize,
+    waiting: &mut [usize],
+    ready: &mut Vec<usize>,
+  ) {
+    for &dependent in &self.dependents[index] {
+      waiting[dependent] -= 1;
+      if waiting[dependent] == 0 {
+        ready.push(dependent);
+      }
+    }
+  }
+
+  fn inputs(
+    &self,
+    index: usize,
+    values: &mut [Option<DynValue>],
+    uses: &mut [usize],
+  ) -> Vec<DynValue> {
+    let inputs = self.nodes[index].inputs.iter();
+    inputs
+      .map(|&input| self.take(values, uses, input))
+      .collect()
+  }
+
+  /// The output of the node `index` for one of its uses: moved to the last
+  /// one and cloned for the others.
+  fn take(
+    &self,
+    values: &mut [Option<DynValue>],
+    uses: &mut [usize],
+    index: usize,
+  ) -> DynValue {
+    uses[index] -= 1;
+    let value = &mut values[index];
+    if uses[index] == 0 {
+      value.take().expect("nodes run after their inputs")
+    } else {
+      let value = value.as_ref().expect("nodes run after their inputs");
+      (self.nodes[index].clone)(value)
+    }
+  }
+}
+
+/// The input of a node joining several others: a tuple with an element for
+/// every node it's connected from.
+pub trait Join: Sized + Send + 'static {
+  fn types() -> Vec<TypeInfo>;
+
+  fn join(values: Vec<DynValue>) -> Result<Self, Error>;
+}
+
+macro_rules! impl_join {
+  ($($name:ident),+) => {
+    impl<$($name: Send + 'static),+> Join for ($($name,)+) {
+      fn types() -> Vec<TypeInfo> {
+        vec![$(TypeInfo::of::<$name>()),+]
+      }
+
+      fn join(values: Vec<DynValue>) -> Result<Self, Error> {
+        let mut values = values.into_iter();
+        Ok(($(
+          downcast::<$name>(
+            values.next().expect("inputs are checked when nodes are added"),
+            || "node input".to_string(),
+          )?,
+        )+))
+      }
+    }
+  };
+}
+
+impl_join!(A, B);
+impl_join!(A, B, C);
+impl_join!(A, B, C, D);
+impl_join!(A, B, C, D, E);
+
+fn find(nodes: &[Node], name: &str) -> Result<usize, Error> {
+  nodes
+    .iter()
+    .position(|node| node.name == name)
+    .ok_or_else(|| Error::InvalidInput(format!("unknown DAG node `{name}`")))
+}
+
+fn clone_value<T: Clone + Send + Any>(value: &DynValue) -> DynValue {
+  let value = value.downcast_ref::<T>().expect("nodes have a single type");
+  Box::new(value.clone())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    sync::{
+      atomic::{
+        AtomicUsize,
+        Ordering,
+      },
+      Arc,
+    },
+    time::Duration,
+  };
+
+  type Chunks = Vec<String>;
+
+  fn chunks(text: String) -> Result<Chunks, Error> {
+    Ok(text.split_whitespace().map(str::to_string).collect())
+  }
+
+  fn embed(chunks: Chunks) -> Result<Vec<usize>, Error> {
+    Ok(chunks.iter().map(String::len).collect())
+  }
+
+  fn keywords(chunks: Chunks) -> Result<Vec<String>, Error> {
+    Ok(chunks.into_iter().filter(|chunk| chunk.len() > 3).collect())
+  }
+
+  // Wraps a node to count how many nodes run at once.
+  fn tracked<I, O>(
+    max_running: &Arc<AtomicUsize>,
+    running: &Arc<AtomicUsize>,
+    node: fn(I) -> Result<O, Error>,
+  ) -> impl Fn(I) -> Result<O, Error> {
+    let max_running = Arc::clone(max_running);
+    let running = Arc::clone(running);
+    move |input| {
+      let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+      max_running.fetch_max(now, Ordering::SeqCst);
+      std::thread::sleep(Duration::from_millis(50));
+      running.fetch_sub(1, Ordering::SeqCst);
+      node(input)
+    }
+  }
+
+  #[test]
+  fn runs_independent_nodes_in_parallel() {
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let dag = DagBuilder::new()
+      .node("chunks", INPUT, chunks)
+      .node("embed", "chunks", tracked(&max_running, &running, embed))
+      .node(
+        "keywords",
+        "chunks",
+        tracked(&max_running, &running, keywords),
+      )
+      .join(
+        "store",
+        &["chunks", "embed", "keywords"],
+        |(chunks, embeddings, keywords): (Chunks, Vec<usize>, Chunks)| {
+          Ok(format!("{chunks:?} {embeddings:?} {keywords:?}"))
+        },
+      )
+      .build::<String>("store")
+      .unwrap();
+    assert_eq!(
+      dag.nodes().collect::<Vec<_>>(),
+      ["input", "chunks", "embed", "keywords", "store"]
+    );
+    let graph = dag.describe();
+    assert_eq!(graph.edges, [(0, 1), (0, 2), (0, 3), (1, 3), (2, 3)]);
+    assert_eq!((graph.inputs, graph.outputs), (vec![0], vec![3]));
+
+    let stored = dag.run("the quick fox".to_string()).unwrap();
+    assert_eq!(stored, r#"["the", "quick", "fox"] [3, 5, 3] ["quick"]"#);
+    assert_eq!(max_running.load(Ordering::SeqCst), 2);
+
+    // Any node can be the output.
+    let dag = DagBuilder::new()
+      .node("chunks", INPUT, chunks)
+      .node("embed", "chunks", embed)
+      .build::<Chunks>("chunks")
+      .unwrap();
+    assert_eq!(dag.run("a b".to_string()).unwrap(), ["a", "b"]);
+  }
+
+  #[test]
+  fn errors() {
+    let err = DagBuilder::<String>::new()
+      .node("chunks", INPUT, chunks)
+      .node("embed", "chunk", embed)
+      .build::<Vec<usize>>("embed")
+      .err()
+      .unwrap();
+    assert_eq!(err.to_string(), "Invalid input: unknown DAG node `chunk`");
+
+    let err = DagBuilder::<String>::new()
+      .node("chunks", INPUT, chunks)
+      .node("embed", INPUT, embed)
+      .build::<Vec<usize>>("embed")
+      .err()
+      .unwrap();
+    assert!(matches!(
+      err,
+      Error::TypeMismatch { at, .. } if at == "DAG node `embed` input from `input`"
+    ));
+
+    let failing =
+      |_: Chunks| -> Result<usize, Error> { Err(Error::InvalidChunkSize(0)) };
+    let dag = DagBuilder::new()
+      .node("chunks", INPUT, chunks)
+      .node("embed", "chunks", embed)
+      .node("fail", "chunks", failing)
+      .join(
+        "store",
+        &["embed", "fail"],
+        |(embeddings, _): (Vec<usize>, usize)| Ok(embeddings),
+      )
+      .build::<Vec<usize>>("store")
+      .unwrap();
+    let err = dag.run("a".to_string()).unwrap_err();
+    assert_eq!(err.stages(), ["fail"]);
+    assert!(matches!(err.root(), Error::InvalidChunkSize(0)));
+  }
+
+  #[test]
+  fn runs_only_what_the_output_needs() {
+    let failing =
+      |_: Chunks| -> Result<usize, Error> { Err(Error::InvalidChunkSize(0)) };
+    let dag = DagBuilder::new()
+      .node("chunks", INPUT, chunks)
+      .node("embed", "chunks", embed)
+      .node("fail", "chunks", failing)
+      .build::<Vec<usize>>("embed")
+      .unwrap();
+    assert_eq!(dag.run("a bc".to_string()).unwrap(), [1, 2]);
+  }
+
+  #[test]
+  fn starts_nodes_when_their_inputs_are_done() {
+    let slow_done = Arc::new(AtomicUsize::new(0));
+    let slow = {
+      let slow_done = Arc::clone(&slow_done);
+      move |chunks: Chunks| -> Result<Vec<usize>, Error> {
+        std::thread::sleep(Duration::from_millis(200));
+        slow_done.store(1, Ordering::SeqCst);
+        embed(chunks)
+      }
+    };
+    // Runs after `keywords`, and shouldn't wait for `embed` to finish.
+    let count = {
+      let slow_done = Arc::clone(&slow_done);
+      move |keywords: Chunks| -> Result<(usize, usize), Error> {
+        Ok((keywords.len(), slow_done.load(Ordering::SeqCst)))
+      }
+    };
+    let dag = DagBuilder::new()
+      .node("chunks", INPUT, chunks)
+      .node("embed", "chunks", slow)
+      .node("keywords", "chunks", keywords)
+      .node("count", "keywords", count)
+      .join(
+        "store",
+        &["embed", "count"],
+        |(embeddings, count): (Vec<usize>, (usize, usize))| {
+          Ok((embeddings.len(), count))
+        },
+      )
+      .build::<(usize, (usize, usize))>("store")
+      .unwrap();
+    let stored = dag.run("the quick fox".to_string()).unwrap();
+    assert_eq!(stored, (3, (1, 0)));
+  }
+}
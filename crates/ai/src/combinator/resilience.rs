@@ -0,0 +1,698 @@
+//! Middleware for processors that can fail transiently, like embedding or
+//! LLM providers. Each wraps a processor and is a processor itself, so they
+//! compose, e.g. `Fallback` over `Retry` over `Timeout`.
+
+use crate::{
+  describe::Graph,
+  error::Error,
+  traits::{
+    AsyncProcessor,
+    Processor,
+  },
+};
+use derive_builder::Builder;
+use std::{
+  borrow::Cow,
+  hash::{
+    BuildHasher,
+    RandomState,
+  },
+  sync::{
+    Mutex,
+    MutexGuard,
+  },
+  time::{
+    Duration,
+    Instant,
+  },
+};
+
+/// Retries a processor when it fails with a retryable error, waiting longer
+/// after every attempt. The input is cloned for every attempt.
+///
+/// The wait after attempt `n` is `initial_backoff * multiplier^(n - 1)`, up
+/// to `max_backoff`. With `jitter`, a random wait between zero and that is
+/// used instead, so clients that failed together don't retry together.
+///
+/// Retrying async processors needs the `tokio` feature, for its timer.
+///
+/// ```
+/// use ragkit_ai::{
+///   combinator::resilience::RetryBuilder,
+///   error::Error,
+///   traits::Processor,
+/// };
+/// use std::time::Duration;
+///
+/// let flaky = |_: u32| -> Result<u32, Error> {
+///   Err(Error::Timeout(Duration::from_secs(1)))
+/// };
+/// let retry = RetryBuilder::default()
+///   .processor(flaky)
+///   .max_attempts(3u32)
+///   .initial_backoff(Duration::from_millis(1))
+///   .build()?;
+/// assert!(matches!(retry.run(1), Err(Error::Timeout(_))));
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Builder, Debug)]
+#[builder(pattern = "owned", setter(into))]
+#[builder(build_fn(
+  error = "crate::error::Error",
+  validate = "Self::validate"
+))]
+pub struct Retry<P> {
+  #[builder(setter(into = false))]
+  processor: P,
+
+  /// The number of attempts, including the first one.
+  #[builder(default = "3")]
+  max_attempts: u32,
+
+  #[builder(default = "DEFAULT_INITIAL_BACKOFF")]
+  initial_backoff: Duration,
+
+  #[builder(default = "DEFAULT_MAX_BACKOFF")]
+  max_backoff: Duration,
+
+  /// How much longer each wait is than the one before, at least 1.
+  #[builder(default = "DEFAULT_MULTIPLIER")]
+  multiplier: f64,
+
+  #[builder(default = "true")]
+  jitter: bool,
+
+  /// Which errors are retried. Called with the `Error::root` of errors, so
+  /// errors of pipeline stages can be matched by their variant. Defaults to
+  /// `Error::is_retryable`.
+  #[builder(setter(into = false), default = "Error::is_retryable")]
+  retry_if: fn(&Error) -> bool,
+}
+
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+
+impl<P> RetryBuilder<P> {
+  fn validate(&self) -> Result<(), Error> {
+    let multiplier = self.multiplier.unwrap_or(DEFAULT_MULTIPLIER);
+    if !multiplier.is_finite() || multiplier < 1.0 {
+      return Err(Error::Validation {
+        field: "multiplier".to_string(),
+        message: format!("{multiplier} isn't a finite number of at least 1"),
+      });
+    }
+    let initial_backoff =
+      self.initial_backoff.unwrap_or(DEFAULT_INITIAL_BACKOFF);
+    let max_backoff = self.max_backoff.unwrap_or(DEFAULT_MAX_BACKOFF);
+    if initial_backoff > max_backoff {
+      return Err(Error::Validation {
+        field: "initial_backoff".to_string(),
+        message: format!(
+          "{initial_backoff:?} is longer than max_backoff {max_backoff:?}"
+        ),
+      });
+    }
+    Ok(())
+  }
+}
+
+impl<P> Retry<P> {
+  /// The wait after the failed attempt `attempt`, counting from 1.
+  fn backoff(&self, attempt: u32) -> Duration {
+    let factor = self.multiplier.powi(attempt as i32 - 1);
+    let backoff = self
+      .initial_backoff
+      .mul_f64(factor.min(u32::MAX as f64))
+      .min(self.max_backoff);
+    if self.jitter {
+      backoff.mul_f64(random())
+    } else {
+      backoff
+    }
+  }
+
+  /// The wait before the next attempt, or `None` to give up.
+  fn next_backoff(&self, attempt: u32, err: &Error) -> Option<Duration> {
+    (attempt < self.max_attempts && (self.retry_if)(err.root()))
+      .then(|| self.backoff(attempt))
+  }
+}
+
+impl<Input, Output, P> Processor<Input, Output> for Retry<P>
+where
+  P: Processor<Input, Output>,
+  Input: Clone,
+{
+  fn process(&self, input: Input) -> Result<Output, Error> {
+    let mut attempt = 1;
+    loop {
+      match self.processor.run(input.clone()) {
+        Ok(output) => return Ok(output),
+        Err(err) => match self.next_backoff(attempt, &err) {
+          Some(backoff) => std::thread::sleep(backoff),
+          None => return Err(err),
+        },
+      }
+      attempt += 1;
+    }
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.processor.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.processor.describe()
+  }
+}
+
+#[cfg(feature = "tokio")]
+impl<Input, Output, P> AsyncProcessor<Input, Output> for Retry<P>
+where
+  P: AsyncProcessor<Input, Output> + Sync,
+  Input: Clone + Send,
+  Output: Send,
+{
+  async fn process(&self, input: Input) -> Result<Output, Error> {
+    let mut attempt = 1;
+    loop {
+      match self.processor.process(input.clone()).await {
+        Ok(output) => return Ok(output),
+        Err(err) => match self.next_backoff(attempt, &err) {
+          Some(backoff) => tokio::time::sleep(backoff).await,
+          None => return Err(err),
+        },
+      }
+      attempt += 1;
+    }
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.processor.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.processor.describe()
+  }
+}
+
+// A random number in `[0, 1)`. Every `RandomState` is seeded differently,
+// which is plenty for jitter.
+fn random() -> f64 {
+  let bits = RandomState::new().hash_one(0u8) >> 11;
+  bits as f64 / (1u64 << 53) as f64
+}
+
+/// Fails with `Error::Timeout` when an async processor takes longer than
+/// `duration`, cancelling it.
+///
+/// Sync processors can't be interrupted: wrap them in `Blocking` first, which
+/// lets them finish in the background after the timeout. Needs the `tokio`
+/// feature.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct Timeout<P> {
+  processor: P,
+  duration: Duration,
+}
+
+#[cfg(feature = "tokio")]
+impl<P> Timeout<P> {
+  pub fn new(processor: P, duration: Duration) -> Self {
+    Self {
+      processor,
+      duration,
+    }
+  }
+}
+
+#[cfg(feature = "tokio")]
+impl<Input, Output, P> AsyncProcessor<Input, Output> for Timeout<P>
+where
+  P: AsyncProcessor<Input, Output> + Sync,
+  Input: Send,
+{
+  async fn process(&self, input: Input) -> Result<Output, Error> {
+    tokio::time::timeout(self.duration, self.processor.process(input))
+      .await
+      .map_err(|_| Error::Timeout(self.duration))?
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.processor.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.processor.describe()
+  }
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+  failures: u32,
+  opened_at: Option<Instant>,
+  // Whether the trial call of a half-open circuit is running.
+  trial: bool,
+}
+
+/// Stops calling a processor that keeps failing, to let it recover and to
+/// fail fast in the meantime.
+///
+/// After `failure_threshold` consecutive failures the circuit opens, and
+/// calls fail with `Error::CircuitOpen` without reaching the processor. Once
+/// `reset_timeout` has passed, the circuit is half-open: a single trial call
+/// is let through, and others are rejected until it finishes. The circuit
+/// closes again if it succeeds, and stays open for another `reset_timeout`
+/// if it fails.
+#[derive(Builder, Debug)]
+#[builder(pattern = "owned", setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct CircuitBreaker<P> {
+  #[builder(setter(into = false))]
+  processor: P,
+
+  #[builder(default = "5")]
+  failure_threshold: u32,
+
+  #[builder(default = "Duration::from_secs(30)")]
+  reset_timeout: Duration,
+
+  #[builder(setter(skip))]
+  state: Mutex<CircuitState>,
+}
+
+impl<P> CircuitBreaker<P> {
+  /// Whether calls are currently rejected.
+  pub fn is_open(&self) -> bool {
+    let state = self.lock();
+    state.trial || self.waiting(&state)
+  }
+
+  fn lock(&self) -> MutexGuard<'_, CircuitState> {
+    self.state.lock().expect("circuit state is never poisoned")
+  }
+
+  // Whether the circuit is open and `reset_timeout` hasn't passed yet.
+  fn waiting(&self, state: &CircuitState) -> bool {
+    state
+      .opened_at
+      .is_some_and(|opened_at| opened_at.elapsed() < self.reset_timeout)
+  }
+
+  fn check(&self) -> Result<Call<'_, P>, Error> {
+    let mut state = self.lock();
+    if state.trial || self.waiting(&state) {
+      return Err(Error::CircuitOpen);
+    }
+    // Any call while the circuit is open is the trial.
+    let trial = state.opened_at.is_some();
+    state.trial = trial;
+    Ok(Call {
+      breaker: self,
+      trial,
+    })
+  }
+}
+
+/// A call let through by a `CircuitBreaker`. If it's dropped without being
+/// recorded, e.g. when the future is cancelled, its trial is given up.
+struct Call<'b, P> {
+  breaker: &'b CircuitBreaker<P>,
+  trial: bool,
+}
+
+impl<P> Call<'_, P> {
+  fn record<T>(mut self, result: &Result<T, Error>) {
+    self.trial = false;
+    let mut state = self.breaker.lock();
+    match result {
+      Ok(_) => *state = CircuitState::default(),
+      Err(_) => {
+        state.trial = false;
+        state.failures += 1;
+        if state.failures >= self.breaker.failure_threshold {
+          state.opened_at = Some(Instant::now());
+        }
+      }
+    }
+  }
+}
+
+impl<P> Drop for Call<'_, P> {
+  fn drop(&mut self) {
+    if self.trial {
+      self.breaker.lock().trial = false;
+    }
+  }
+}
+
+impl<Input, Output, P> Processor<Input, Output> for CircuitBreaker<P>
+where
+  P: Processor<Input, Output>,
+{
+  fn process(&self, input: Input) -> Result<Output, Error> {
+    let call = self.check()?;
+    let result = self.processor.run(input);
+    call.record(&result);
+    result
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.processor.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.processor.describe()
+  }
+}
+
+impl<Input, Output, P> AsyncProcessor<Input, Output> for CircuitBreaker<P>
+where
+  P: AsyncProcessor<Input, Output> + Sync,
+  Input: Send,
+{
+  async fn process(&self, input: Input) -> Result<Output, Error> {
+    let call = self.check()?;
+    let result = self.processor.process(input).await;
+    call.record(&result);
+    result
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.processor.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.processor.describe()
+  }
+}
+
+/// Runs an alternate processor when the primary one fails, e.g. a smaller
+/// local model when a provider is down. The input is cloned for the primary
+/// processor.
+#[derive(Debug)]
+pub struct Fallback<P, F> {
+  primary: P,
+  fallback: F,
+}
+
+impl<P, F> Fallback<P, F> {
+  pub fn new(primary: P, fallback: F) -> Self {
+    Self { primary, fallback }
+  }
+}
+
+impl<Input, Output, P, F> Processor<Input, Output> for Fallback<P, F>
+where
+  P: Processor<Input, Output>,
+  F: Processor<Input, Output>,
+  Input: Clone,
+{
+  fn process(&self, input: Input) -> Result<Output, Error> {
+    self
+      .primary
+      .run(input.clone())
+      .or_else(|_| self.fallback.run(input))
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.primary.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.primary.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.primary.describe()
+  }
+}
+
+impl<Input, Output, P, F> AsyncProcessor<Input, Output> for Fallback<P, F>
+where
+  P: AsyncProcessor<Input, Output> + Sync,
+  F: AsyncProcessor<Input, Output> + Sync,
+  Input: Clone + Send,
+  Output: Send,
+{
+  async fn process(&self, input: Input) -> Result<Output, Error> {
+    match self.primary.process(input.clone()).await {
+      Ok(output) => Ok(output),
+      Err(_) => self.fallback.process(input).await,
+    }
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.primary.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.primary.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.primary.describe()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[cfg(feature = "tokio")]
+  use crate::traits::AsyncPipeline;
+  use crate::traits::Pipeline;
+  use std::sync::{
+    atomic::{
+      AtomicU32,
+      Ordering,
+    },
+    mpsc,
+  };
+
+  // Fails with `err` the first `failures` times it's called.
+  fn flaky(
+    calls: &AtomicU32,
+    failures: u32,
+    err: Error,
+  ) -> impl Fn(u32) -> Result<u32, Error> + '_ {
+    move |n| {
+      if calls.fetch_add(1, Ordering::SeqCst) < failures {
+        return Err(err.clone());
+      }
+      Ok(n * 2)
+    }
+  }
+
+  #[test]
+  fn retry() {
+    let timeout = Error::Timeout(Duration::from_secs(1));
+    let calls = AtomicU32::new(0);
+    let retry = RetryBuilder::default()
+      .processor(flaky(&calls, 2, timeout.clone()))
+      .initial_backoff(Duration::from_millis(1))
+      .build()
+      .unwrap();
+    assert_eq!(retry.run(4).unwrap(), 8);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    // Gives up after `max_attempts`.
+    let calls = AtomicU32::new(0);
+    let retry = RetryBuilder::default()
+      .processor(flaky(&calls, 5, timeout))
+      .max_attempts(4u32)
+      .initial_backoff(Duration::from_millis(1))
+      .build()
+      .unwrap();
+    assert!(matches!(retry.run(4), Err(Error::Timeout(_))));
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+    // Doesn't retry errors that aren't retryable.
+    let calls = AtomicU32::new(0);
+    let retry = RetryBuilder::default()
+      .processor(flaky(&calls, 1, Error::InvalidChunkSize(0)))
+      .build()
+      .unwrap();
+    assert!(retry.run(4).is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let calls = AtomicU32::new(0);
+    let retry = RetryBuilder::default()
+      .processor(flaky(&calls, 1, Error::InvalidChunkSize(0)))
+      .retry_if(|err| matches!(err, Error::InvalidChunkSize(_)))
+      .initial_backoff(Duration::from_millis(1))
+      .build()
+      .unwrap();
+    assert_eq!(retry.run(4).unwrap(), 8);
+
+    // Errors of pipeline stages are matched without their stages.
+    let calls = AtomicU32::new(0);
+    let pipeline = Pipeline::new(flaky(&calls, 2, Error::InvalidChunkSize(0)));
+    assert!(matches!(pipeline.run(4_u32), Err(Error::Stage { .. })));
+    let retry = RetryBuilder::default()
+      .processor(pipeline)
+      .retry_if(|err| matches!(err, Error::InvalidChunkSize(_)))
+      .initial_backoff(Duration::from_millis(1))
+      .build()
+      .unwrap();
+    assert_eq!(retry.run(4).unwrap(), 8);
+  }
+
+  #[test]
+  fn backoff() {
+    let retry = RetryBuilder::default()
+      .processor(|n: u32| -> Result<u32, Error> { Ok(n) })
+      .initial_backoff(Duration::from_millis(100))
+      .max_backoff(Duration::from_millis(500))
+      .jitter(false)
+      .build()
+      .unwrap();
+    let backoffs = (1..=5).map(|n| retry.backoff(n)).collect::<Vec<_>>();
+    assert_eq!(
+      backoffs,
+      [100, 200, 400, 500, 500].map(Duration::from_millis)
+    );
+
+    let retry = RetryBuilder::default()
+      .processor(|n: u32| -> Result<u32, Error> { Ok(n) })
+      .initial_backoff(Duration::from_millis(100))
+      .build()
+      .unwrap();
+    let jittered = (0..20).map(|_| retry.backoff(2)).collect::<Vec<_>>();
+    assert!(jittered.iter().all(|b| *b <= Duration::from_millis(200)));
+    assert!(jittered.iter().any(|b| *b != jittered[0]));
+
+    let invalid =
+      |builder: RetryBuilder<fn(u32) -> Result<u32, Error>>| match builder
+        .processor(Ok)
+        .build()
+      {
+        Err(Error::Validation { field, .. }) => field,
+        other => panic!("expected a validation error, got {other:?}"),
+      };
+    assert_eq!(
+      invalid(RetryBuilder::default().multiplier(0.5)),
+      "multiplier"
+    );
+    assert_eq!(
+      invalid(RetryBuilder::default().multiplier(f64::NAN)),
+      "multiplier"
+    );
+    assert_eq!(
+      invalid(RetryBuilder::default().initial_backoff(Duration::from_secs(20))),
+      "initial_backoff"
+    );
+  }
+
+  #[cfg(feature = "tokio")]
+  #[tokio::test]
+  async fn timeout_retry_and_fallback() {
+    let calls = AtomicU32::new(0);
+    let slow = |n: u32| {
+      let first = calls.fetch_add(1, Ordering::SeqCst) == 0;
+      async move {
+        if first {
+          tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+        Ok(n + 1)
+      }
+    };
+    let pipeline = AsyncPipeline::new(
+      RetryBuilder::default()
+        .processor(Timeout::new(slow, Duration::from_millis(20)))
+        .initial_backoff(Duration::from_millis(1))
+        .build()
+        .unwrap(),
+    );
+    assert_eq!(pipeline.run(1u32).await.unwrap(), 2);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    let down = |_: u32| async { Err(Error::Task("down".to_string())) };
+    let local = |n: u32| async move { Ok(n) };
+    let pipeline = AsyncPipeline::new(Fallback::new(down, local));
+    assert_eq!(pipeline.run(7u32).await.unwrap(), 7);
+  }
+
+  #[test]
+  fn circuit_breaker() {
+    let calls = AtomicU32::new(0);
+    let breaker = CircuitBreakerBuilder::default()
+      .processor(flaky(&calls, 3, Error::Task("down".to_string())))
+      .failure_threshold(2u32)
+      .reset_timeout(Duration::from_millis(50))
+      .build()
+      .unwrap();
+    assert!(matches!(breaker.run(1), Err(Error::Task(_))));
+    assert!(!breaker.is_open());
+    assert!(matches!(breaker.run(1), Err(Error::Task(_))));
+    assert!(breaker.is_open());
+    assert!(matches!(breaker.run(1), Err(Error::CircuitOpen)));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // A failed trial call opens the circuit again.
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(matches!(breaker.run(1), Err(Error::Task(_))));
+    assert!(matches!(breaker.run(1), Err(Error::CircuitOpen)));
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert_eq!(breaker.run(1).unwrap(), 2);
+    assert!(!breaker.is_open());
+
+    let fallback = Fallback::new(|n| breaker.run(n), |n: u32| Ok(n));
+    assert_eq!(fallback.run(3).unwrap(), 6);
+  }
+
+  #[test]
+  fn half_open_circuit_breaker() {
+    let calls = AtomicU32::new(0);
+    let (started, trial_started) = mpsc::channel();
+    let (finish, trial_finish) = mpsc::channel::<()>();
+    let trial_finish = Mutex::new(trial_finish);
+    let breaker = CircuitBreakerBuilder::default()
+      .processor(|n: u32| {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+          return Err(Error::Task("down".to_string()));
+        }
+        started.send(()).unwrap();
+        trial_finish.lock().unwrap().recv().unwrap();
+        Ok(n)
+      })
+      .failure_threshold(1u32)
+      .reset_timeout(Duration::ZERO)
+      .build()
+      .unwrap();
+    assert!(breaker.run(1).is_err());
+
+    // Only one trial call runs at a time.
+    std::thread::scope(|scope| {
+      let trial = scope.spawn(|| breaker.run(2));
+      trial_started.recv().unwrap();
+      assert!(breaker.is_open());
+      assert!(matches!(breaker.run(3), Err(Error::CircuitOpen)));
+      finish.send(()).unwrap();
+      assert_eq!(trial.join().unwrap().unwrap(), 2);
+    });
+    assert!(!breaker.is_open());
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+  }
+}
@@ -0,0 +1,384 @@
+use serde::Serialize;
+use serde_yaml_ng::Value;
+use std::fmt::{
+  self,
+  Write,
+};
+
+/// The stages a processor is made of and how they're connected, as returned
+/// by `Processor::describe`. Exported to Graphviz with `to_dot` or to Mermaid
+/// with `to_mermaid`, e.g. to document pipelines.
+///
+/// ```
+/// use ragkit_ai::{
+///   error::Error,
+///   process::splitter::simple::SimpleSplitterBuilder,
+///   traits::{
+///     Named,
+///     Pipeline,
+///     Processor,
+///   },
+/// };
+///
+/// let splitter = SimpleSplitterBuilder::default()
+///   .chunk_size(500_u32)
+///   .build()?;
+/// let count = |elements: Vec<_>| -> Result<usize, Error> { Ok(elements.len()) };
+/// let pipeline = Pipeline::new(splitter).chain(Named::new("count", count));
+///
+/// let graph = pipeline.describe();
+/// assert_eq!(graph.stages[0].name, "SimpleSplitter");
+/// assert_eq!(
+///   graph.stages[0].config.as_deref(),
+///   Some("chunk_size: 500, loc_offset: 0")
+/// );
+/// assert_eq!(graph.stages[1].name, "count");
+/// println!("{}", graph.to_mermaid());
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Graph {
+  pub stages: Vec<Stage>,
+  /// Connections between stages, as indices into `stages`.
+  pub edges: Vec<(usize, usize)>,
+  /// The stages taking the input of the processor.
+  pub inputs: Vec<usize>,
+  /// The stages the output of the processor comes from.
+  pub outputs: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Stage {
+  pub name: String,
+  pub config: Option<String>,
+}
+
+impl Graph {
+  /// A graph of a single stage.
+  pub fn stage(name: impl Into<String>, config: Option<String>) -> Self {
+    Self {
+      stages: vec![Stage {
+        name: name.into(),
+        config,
+      }],
+      edges: vec![],
+      inputs: vec![0],
+      outputs: vec![0],
+    }
+  }
+
+  /// Feeds the outputs of this graph to the inputs of `next`.
+  pub fn then(mut self, next: Graph) -> Self {
+    if self.stages.is_empty() {
+      return next;
+    }
+    if next.stages.is_empty() {
+      return self;
+    }
+    let offset = self.stages.len();
+    for &output in &self.outputs {
+      for &input in &next.inputs {
+        self.edges.push((output, input + offset));
+      }
+    }
+    self.outputs = next.outputs.iter().map(|i| i + offset).collect();
+    self.append(next);
+    self
+  }
+
+  /// Graphs running side by side on the same input.
+  pub fn parallel(graphs: impl IntoIterator<Item = Graph>) -> Self {
+    let mut parallel = Self::default();
+    for graph in graphs {
+      let offset = parallel.stages.len();
+      parallel
+        .inputs
+        .extend(graph.inputs.iter().map(|i| i + offset));
+      parallel
+        .outputs
+        .extend(graph.outputs.iter().map(|i| i + offset));
+      parallel.append(graph);
+    }
+    parallel
+  }
+
+  // Adds the stages and edges of `graph`, leaving inputs and outputs alone.
+  fn append(&mut self, graph: Graph) {
+    let offset = self.stages.len();
+    self.stages.extend(graph.stages);
+    let edges = graph.edges.iter().map(|(a, b)| (a + offset, b + offset));
+    self.edges.extend(edges);
+  }
+
+  /// The graph in Graphviz's DOT language, with the input and output of the
+  /// processor as extra nodes.
+  pub fn to_dot(&self) -> String {
+    let mut dot = String::from("digraph pipeline {\n");
+    dot.push_str("  rankdir=LR;\n  node [shape=box];\n");
+    dot.push_str("  input [shape=ellipse];\n  output [shape=ellipse];\n");
+    for (index, stage) in self.stages.iter().enumerate() {
+      let mut label = stage.name.clone();
+      if let Some(config) = &stage.config {
+        label.push('\n');
+        label.push_str(config);
+      }
+      let label = label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+      writeln!(dot, "  s{index} [label=\"{label}\"];").unwrap();
+    }
+    for (from, to) in self.connections() {
+      writeln!(dot, "  {from} -> {to};").unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+  }
+
+  /// The graph as a Mermaid flowchart, with the input and output of the
+  /// processor as extra nodes.
+  pub fn to_mermaid(&self) -> String {
+    let mut mermaid = String::from("flowchart LR\n");
+    mermaid.push_str("  input([input])\n  output([output])\n");
+    for (index, stage) in self.stages.iter().enumerate() {
+      let mut label = escape_mermaid(&stage.name);
+      if let Some(config) = &stage.config {
+        label.push_str("<br/>");
+        label.push_str(&escape_mermaid(config));
+      }
+      writeln!(mermaid, "  s{index}[\"{label}\"]").unwrap();
+    }
+    for (from, to) in self.connections() {
+      writeln!(mermaid, "  {from} --> {to}").unwrap();
+    }
+    mermaid
+  }
+
+  // The edges between node ids, including the ones from the input and to the
+  // output.
+  fn connections(&self) -> Vec<(String, String)> {
+    if self.stages.is_empty() {
+      return vec![("input".to_string(), "output".to_string())];
+    }
+    let id = |index: &usize| format!("s{index}");
+    let inputs = self.inputs.iter().map(|i| ("input".to_string(), id(i)));
+    let edges = self.edges.iter().map(|(a, b)| (id(a), id(b)));
+    let outputs = self.outputs.iter().map(|i| (id(i), "output".to_string()));
+    inputs.chain(edges).chain(outputs).collect()
+  }
+}
+
+fn escape_mermaid(text: &str) -> String {
+  text
+    .replace('&', "#amp;")
+    .replace('"', "#quot;")
+    .replace('<', "#lt;")
+    .replace('>', "#gt;")
+    .replace('\n', "<br/>")
+}
+
+/// The settings of a processor for `Processor::config`, from the fields it
+/// serializes: `Splitter { chunk_size: 5 }` becomes `chunk_size: 5`. `None`
+/// for values without fields. Skip fields that shouldn't show up in
+/// descriptions, e.g. credentials, with `#[serde(skip)]`.
+pub fn config(value: &impl Serialize) -> Option<String> {
+  let json = |value: &Value| serde_json::to_string(value).ok();
+  match serde_yaml_ng::to_value(value).ok()? {
+    Value::Null => None,
+    Value::Mapping(fields) => {
+      let fields = fields.iter().map(|(field, value)| {
+        let field = field
+          .as_str()
+          .map_or_else(|| json(field), |field| Some(field.to_string()))?;
+        Some(format!("{field}: {}", json(value)?))
+      });
+      let fields = fields.collect::<Option<Vec<_>>>()?;
+      (!fields.is_empty()).then(|| fields.join(", "))
+    }
+    value => json(&value),
+  }
+}
+
+/// Implements `Processor::config` or `AsyncProcessor::config` with `config`,
+/// for processors deriving `Serialize`.
+macro_rules! impl_config {
+  () => {
+    fn config(&self) -> Option<String> {
+      $crate::describe::config(self)
+    }
+  };
+}
+
+pub(crate) use impl_config;
+
+impl fmt::Display for Graph {
+  /// The stages one per line, with their configs.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (index, stage) in self.stages.iter().enumerate() {
+      write!(f, "{index}: {}", stage.name)?;
+      if let Some(config) = &stage.config {
+        write!(f, " ({config})")?;
+      }
+      let next = self.edges.iter().filter(|(from, _)| *from == index);
+      let next = next.map(|(_, to)| to.to_string()).collect::<Vec<_>>();
+      if !next.is_empty() {
+        write!(f, " -> {}", next.join(", "))?;
+      }
+      writeln!(f)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    combinator::{
+      resilience::{
+        Fallback,
+        RetryBuilder,
+      },
+      FanOut,
+      Map,
+    },
+    error::Error,
+    process::{
+      normalize::NormalizerBuilder,
+      splitter::simple::SimpleSplitterBuilder,
+    },
+    registry::SimpleSplitterConfig,
+    traits::{
+      Named,
+      Pipeline,
+      Processor,
+    },
+  };
+
+  #[test]
+  fn configs() {
+    let splitter = SimpleSplitterConfig {
+      chunk_size: 5,
+      loc_offset: 2,
+    };
+    assert_eq!(
+      config(&splitter).as_deref(),
+      Some("chunk_size: 5, loc_offset: 2")
+    );
+    assert_eq!(config(&Some(3)).as_deref(), Some("3"));
+    assert_eq!(config(&None::<u32>), None);
+
+    let normalizer = NormalizerBuilder::default()
+      .encoding("utf-16le")
+      .build()
+      .unwrap();
+    assert_eq!(
+      config(&normalizer).as_deref(),
+      Some(
+        "encoding: \"utf-16le\", form: \"Nfc\", remove_control: true, \
+         ascii_punctuation: false, collapse_whitespace: true"
+      )
+    );
+  }
+
+  #[test]
+  fn wrappers() {
+    let splitter = || {
+      SimpleSplitterBuilder::default()
+        .chunk_size(5_u32)
+        .build()
+        .unwrap()
+    };
+    let retry = RetryBuilder::default()
+      .processor(splitter())
+      .build()
+      .unwrap();
+    let fallback = Fallback::new(retry, Named::new("fallback", splitter()));
+    let graph = Processor::<&str, Vec<_>>::describe(&fallback);
+    assert_eq!(graph.stages[0].name, "SimpleSplitter");
+    assert_eq!(
+      graph.stages[0].config.as_deref(),
+      Some("chunk_size: 5, loc_offset: 0")
+    );
+
+    let count = |text: String| -> Result<usize, Error> { Ok(text.len()) };
+    let map = Map(Pipeline::new(Named::new("count", count)));
+    let graph = Processor::<Vec<String>, Vec<usize>>::describe(&map);
+    assert_eq!(graph.stages[0].name, "Map<count>");
+  }
+
+  #[test]
+  fn pipeline_graph() {
+    let splitter = SimpleSplitterBuilder::default()
+      .chunk_size(5_u32)
+      .build()
+      .unwrap();
+    let count =
+      |elements: Vec<_>| -> Result<usize, Error> { Ok(elements.len()) };
+    let double = |n: usize| -> Result<usize, Error> { Ok(n * 2) };
+    let add = |(a, b): (usize, usize)| -> Result<usize, Error> { Ok(a + b) };
+    let pipeline = Pipeline::new(splitter)
+      .chain(Named::new("count", count))
+      .chain(FanOut((Named::new("double", double), IdentityName)))
+      .chain(Named::new("add", add));
+
+    let graph = pipeline.describe();
+    let names = graph.stages.iter().map(|stage| stage.name.as_str());
+    assert_eq!(
+      names.collect::<Vec<_>>(),
+      ["SimpleSplitter", "count", "double", "IdentityName", "add"]
+    );
+    assert_eq!(graph.edges, [(0, 1), (1, 2), (1, 3), (2, 4), (3, 4)]);
+    assert_eq!((graph.inputs, graph.outputs), (vec![0], vec![4]));
+    assert_eq!(
+      pipeline.describe().to_string(),
+      "0: SimpleSplitter (chunk_size: 5, loc_offset: 0) -> 1\n\
+       1: count -> 2, 3\n\
+       2: double -> 4\n\
+       3: IdentityName -> 4\n\
+       4: add\n"
+    );
+  }
+
+  struct IdentityName;
+
+  impl Processor<usize, usize> for IdentityName {
+    fn process(&self, input: usize) -> Result<usize, Error> {
+      Ok(input)
+    }
+  }
+
+  #[test]
+  fn exports() {
+    let graph = Graph::stage("split", Some("size: \"5\"".to_string()))
+      .then(Graph::stage("Map<Embed>", None));
+    assert_eq!(
+      graph.to_dot(),
+      "digraph pipeline {\n  \
+         rankdir=LR;\n  \
+         node [shape=box];\n  \
+         input [shape=ellipse];\n  \
+         output [shape=ellipse];\n  \
+         s0 [label=\"split\\nsize: \\\"5\\\"\"];\n  \
+         s1 [label=\"Map<Embed>\"];\n  \
+         input -> s0;\n  \
+         s0 -> s1;\n  \
+         s1 -> output;\n\
+       }\n"
+    );
+    assert_eq!(
+      graph.to_mermaid(),
+      "flowchart LR\n  \
+         input([input])\n  \
+         output([output])\n  \
+         s0[\"split<br/>size: #quot;5#quot;\"]\n  \
+         s1[\"Map#lt;Embed#gt;\"]\n  \
+         input --> s0\n  \
+         s0 --> s1\n  \
+         s1 --> output\n"
+    );
+    assert!(Graph::default()
+      .to_mermaid()
+      .ends_with("input --> output\n"));
+  }
+}
@@ -1,28 +1,461 @@
+use crate::annotation::Annotations;
+use chrono::{
+  DateTime,
+  Utc,
+};
+use derive_builder::Builder;
 use serde::{
   Deserialize,
   Serialize,
 };
+use serde_json::Value;
+use std::{
+  borrow::Cow,
+  collections::BTreeMap,
+};
 
+/// A source document. Every variant carries the same `DocumentMetadata`;
+/// text-based variants hold their content as a `Cow` so documents can either
+/// borrow from an existing string or own what a loader read from disk.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Document<'a> {
   #[serde(borrow)]
   Text(TextDocument<'a>),
+  #[serde(borrow)]
+  Markdown(MarkdownDocument<'a>),
+  #[serde(borrow)]
+  Html(HtmlDocument<'a>),
+  #[serde(borrow)]
+  Code(CodeDocument<'a>),
+  Binary(BinaryDocument<'a>),
 }
 
+impl<'a> Document<'a> {
+  /// The textual content of the document, or `None` for binary documents
+  /// which need to go through a loader first.
+  pub fn text(&self) -> Option<&str> {
+    match self {
+      Document::Text(doc) => Some(&doc.content),
+      Document::Markdown(doc) => Some(&doc.content),
+      Document::Html(doc) => Some(&doc.content),
+      Document::Code(doc) => Some(&doc.content),
+      Document::Binary(_) => None,
+    }
+  }
+
+  pub fn meta(&self) -> &DocumentMetadata {
+    match self {
+      Document::Text(doc) => &doc.meta,
+      Document::Markdown(doc) => &doc.meta,
+      Document::Html(doc) => &doc.meta,
+      Document::Code(doc) => &doc.meta,
+      Document::Binary(doc) => &doc.meta,
+    }
+  }
+
+  pub fn meta_mut(&mut self) -> &mut DocumentMetadata {
+    match self {
+      Document::Text(doc) => &mut doc.meta,
+      Document::Markdown(doc) => &mut doc.meta,
+      Document::Html(doc) => &mut doc.meta,
+      Document::Code(doc) => &mut doc.meta,
+      Document::Binary(doc) => &mut doc.meta,
+    }
+  }
+
+  /// Replaces the metadata of the document.
+  pub fn with_meta(mut self, meta: DocumentMetadata) -> Self {
+    *self.meta_mut() = meta;
+    self
+  }
+
+  /// The annotation layers of the document, or `None` for binary documents.
+  pub fn annotations(&self) -> Option<&Annotations<'a>> {
+    match self {
+      Document::Text(doc) => Some(&doc.annotations),
+      Document::Markdown(doc) => Some(&doc.annotations),
+      Document::Html(doc) => Some(&doc.annotations),
+      Document::Code(doc) => Some(&doc.annotations),
+      Document::Binary(_) => None,
+    }
+  }
+
+  pub fn annotations_mut(&mut self) -> Option<&mut Annotations<'a>> {
+    match self {
+      Document::Text(doc) => Some(&mut doc.annotations),
+      Document::Markdown(doc) => Some(&mut doc.annotations),
+      Document::Html(doc) => Some(&mut doc.annotations),
+      Document::Code(doc) => Some(&mut doc.annotations),
+      Document::Binary(_) => None,
+    }
+  }
+
+  pub fn id(&self) -> Option<&str> {
+    self.meta().id.as_deref()
+  }
+
+  pub fn source(&self) -> Option<&str> {
+    self.meta().source.as_deref()
+  }
+
+  pub fn mime_type(&self) -> Option<&str> {
+    self.meta().mime_type.as_deref()
+  }
+
+  pub fn title(&self) -> Option<&str> {
+    self.meta().title.as_deref()
+  }
+
+  /// Converts any borrowed data into owned data.
+  pub fn into_owned(self) -> Document<'static> {
+    match self {
+      Document::Text(doc) => Document::Text(doc.into_owned()),
+      Document::Markdown(doc) => Document::Markdown(doc.into_owned()),
+      Document::Html(doc) => Document::Html(doc.into_owned()),
+      Document::Code(doc) => Document::Code(doc.into_owned()),
+      Document::Binary(doc) => Document::Binary(doc.into_owned()),
+    }
+  }
+}
+
+/// Information about a document that isn't part of its content. All fields
+/// are optional; loaders fill in whatever they know about the source.
+#[derive(Serialize, Deserialize, Builder, Clone, Debug, Default, PartialEq)]
+#[builder(setter(into, strip_option), default)]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct DocumentMetadata {
+  /// A stable identifier for the document.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+
+  /// Where the document came from, as a URI, e.g. `file:///docs/a.md`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub source: Option<String>,
+
+  /// The MIME type of the original source, e.g. `text/markdown`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mime_type: Option<String>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub title: Option<String>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub created_at: Option<DateTime<Utc>>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub modified_at: Option<DateTime<Utc>>,
+
+  /// Arbitrary additional metadata.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  #[builder(setter(custom))]
+  pub extra: BTreeMap<String, Value>,
+}
+
+impl DocumentMetadataBuilder {
+  /// Adds a single entry to the `extra` metadata map.
+  pub fn extra(
+    &mut self,
+    key: impl Into<String>,
+    value: impl Into<Value>,
+  ) -> &mut Self {
+    self
+      .extra
+      .get_or_insert_with(Default::default)
+      .insert(key.into(), value.into());
+    self
+  }
+}
+
+// ============================================================================
+// Text based documents
+// ============================================================================
+
+// The builder methods and conversions shared by text based documents, with
+// the fields besides `content`, `meta` and `annotations` they own.
+//
+// Documents may own their content, so they only convert to a `&str` by
+// reference. By value they convert into a `Cow<str>`, borrowed or owned like
+// their content.
+macro_rules! impl_text_document {
+  ($($name:ident => $variant:ident { $($field:ident),* }),* $(,)?) => {$(
+    impl<'a> $name<'a> {
+      pub fn with_meta(self, meta: DocumentMetadata) -> Self {
+        Self { meta, ..self }
+      }
+
+      pub fn with_annotations(self, annotations: Annotations<'a>) -> Self {
+        Self {
+          annotations,
+          ..self
+        }
+      }
+
+      pub fn into_owned(self) -> $name<'static> {
+        $name {
+          content: Cow::Owned(self.content.into_owned()),
+          $($field: self.$field,)*
+          meta: self.meta,
+          annotations: self.annotations.into_owned(),
+        }
+      }
+
+      pub fn as_document(self) -> Document<'a> {
+        Document::$variant(self)
+      }
+    }
+
+    impl<'a, 'b> From<&'b $name<'a>> for &'b str {
+      fn from(val: &'b $name<'a>) -> Self {
+        &val.content
+      }
+    }
+
+    impl<'a> From<$name<'a>> for Cow<'a, str> {
+      fn from(val: $name<'a>) -> Self {
+        val.content
+      }
+    }
+  )*};
+}
+
+impl_text_document!(
+  TextDocument => Text {},
+  MarkdownDocument => Markdown {},
+  HtmlDocument => Html {},
+  CodeDocument => Code { language },
+);
+
+/// Plain text. Unlike before documents could own their content, a
+/// `TextDocument` converts into a `&str` by reference only, and into a
+/// `Cow<str>` by value.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TextDocument<'a> {
-  content: &'a str,
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
+  #[serde(default)]
+  pub meta: DocumentMetadata,
+  #[serde(borrow, default, skip_serializing_if = "Annotations::is_empty")]
+  pub annotations: Annotations<'a>,
 }
 
 impl<'a> TextDocument<'a> {
-  pub fn new(content: &'a str) -> Self {
-    Self { content }
+  pub fn new(content: impl Into<Cow<'a, str>>) -> Self {
+    Self {
+      content: content.into(),
+      meta: Default::default(),
+      annotations: Default::default(),
+    }
   }
 }
 
-impl<'a> From<TextDocument<'a>> for &'a str {
-  fn from(val: TextDocument<'a>) -> Self {
-    val.content
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MarkdownDocument<'a> {
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
+  #[serde(default)]
+  pub meta: DocumentMetadata,
+  #[serde(borrow, default, skip_serializing_if = "Annotations::is_empty")]
+  pub annotations: Annotations<'a>,
+}
+
+impl<'a> MarkdownDocument<'a> {
+  pub fn new(content: impl Into<Cow<'a, str>>) -> Self {
+    Self {
+      content: content.into(),
+      meta: Default::default(),
+      annotations: Default::default(),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HtmlDocument<'a> {
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
+  #[serde(default)]
+  pub meta: DocumentMetadata,
+  #[serde(borrow, default, skip_serializing_if = "Annotations::is_empty")]
+  pub annotations: Annotations<'a>,
+}
+
+impl<'a> HtmlDocument<'a> {
+  pub fn new(content: impl Into<Cow<'a, str>>) -> Self {
+    Self {
+      content: content.into(),
+      meta: Default::default(),
+      annotations: Default::default(),
+    }
+  }
+}
+
+/// Source code. `language` is a lowercase language name such as `rust` or
+/// `python`, when known.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CodeDocument<'a> {
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub language: Option<String>,
+  #[serde(default)]
+  pub meta: DocumentMetadata,
+  #[serde(borrow, default, skip_serializing_if = "Annotations::is_empty")]
+  pub annotations: Annotations<'a>,
+}
+
+impl<'a> CodeDocument<'a> {
+  pub fn new(
+    content: impl Into<Cow<'a, str>>,
+    language: Option<String>,
+  ) -> Self {
+    Self {
+      content: content.into(),
+      language,
+      meta: Default::default(),
+      annotations: Default::default(),
+    }
+  }
+}
+
+// ============================================================================
+// Binary documents
+// ============================================================================
+
+/// The format of a `BinaryDocument`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryFormat {
+  Pdf,
+  Docx,
+  Pptx,
+  Xlsx,
+  Odt,
+  Epub,
+  Other,
+}
+
+impl BinaryFormat {
+  /// Guesses the format from a MIME type.
+  pub fn from_mime_type(mime_type: &str) -> Self {
+    match mime_type {
+      "application/pdf" => BinaryFormat::Pdf,
+      "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
+        BinaryFormat::Docx
+      }
+      "application/vnd.openxmlformats-officedocument.presentationml.presentation" => {
+        BinaryFormat::Pptx
+      }
+      "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => {
+        BinaryFormat::Xlsx
+      }
+      "application/vnd.oasis.opendocument.text" => BinaryFormat::Odt,
+      "application/epub+zip" => BinaryFormat::Epub,
+      _ => BinaryFormat::Other,
+    }
+  }
+}
+
+/// Raw bytes of a source that needs to be loaded before it can be processed
+/// as text, e.g. a PDF or an Office file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BinaryDocument<'a> {
+  pub data: Cow<'a, [u8]>,
+  pub format: BinaryFormat,
+  #[serde(default)]
+  pub meta: DocumentMetadata,
+}
+
+impl<'a> BinaryDocument<'a> {
+  pub fn new(data: impl Into<Cow<'a, [u8]>>, format: BinaryFormat) -> Self {
+    Self {
+      data: data.into(),
+      format,
+      meta: Default::default(),
+    }
+  }
+
+  pub fn with_meta(self, meta: DocumentMetadata) -> Self {
+    Self { meta, ..self }
+  }
+
+  pub fn into_owned(self) -> BinaryDocument<'static> {
+    BinaryDocument {
+      data: Cow::Owned(self.data.into_owned()),
+      format: self.format,
+      meta: self.meta,
+    }
+  }
+
+  pub fn as_document(self) -> Document<'a> {
+    Document::Binary(self)
+  }
+}
+
+impl<'a, 'b> From<&'b BinaryDocument<'a>> for &'b [u8] {
+  fn from(val: &'b BinaryDocument<'a>) -> Self {
+    &val.data
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let meta = DocumentMetadataBuilder::default()
+      .id("doc-1")
+      .source("file:///docs/readme.md")
+      .mime_type("text/markdown")
+      .title("Readme")
+      .modified_at(DateTime::from_timestamp(1_700_000_000, 0).unwrap())
+      .extra("lang", "en")
+      .extra("stars", 42)
+      .build()
+      .unwrap();
+    let doc = MarkdownDocument::new("# Hello")
+      .with_meta(meta)
+      .as_document();
+
+    let json = serde_json::to_string(&doc).unwrap();
+    let parsed: Document = serde_json::from_str(&json).unwrap();
+    assert_eq!(doc, parsed);
+    assert_eq!(parsed.title(), Some("Readme"));
+    assert_eq!(parsed.meta().extra["stars"], 42);
+  }
+
+  #[test]
+  fn content() {
+    let doc = TextDocument::new("hello");
+    assert_eq!(<&str>::from(&doc), "hello");
+    assert!(matches!(Cow::from(doc), Cow::Borrowed("hello")));
+
+    let doc = CodeDocument::new("fn main() {}".to_string(), None);
+    let content: Cow<str> = doc.into();
+    assert!(matches!(content, Cow::Owned(_)));
+  }
+
+  #[test]
+  fn round_trip_binary() {
+    let doc =
+      BinaryDocument::new(vec![0x25, 0x50, 0x44, 0x46], BinaryFormat::Pdf)
+        .as_document();
+
+    let json = serde_json::to_string(&doc).unwrap();
+    let parsed: Document = serde_json::from_str(&json).unwrap();
+    assert_eq!(doc, parsed);
+    assert_eq!(parsed.text(), None);
+  }
+
+  #[test]
+  fn borrowed_content() {
+    let json = r#"{"type":"text","content":"hello"}"#;
+    let doc: Document = serde_json::from_str(json).unwrap();
+    match &doc {
+      Document::Text(text) => assert!(matches!(text.content, Cow::Borrowed(_))),
+      _ => panic!("expected a text document"),
+    }
+    assert_eq!(doc.text(), Some("hello"));
+    assert_eq!(doc.meta(), &DocumentMetadata::default());
   }
 }
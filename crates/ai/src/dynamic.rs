@@ -0,0 +1,379 @@
+use crate::{
+  describe::Graph,
+  error::Error,
+  telemetry,
+  traits::Processor,
+};
+use std::{
+  any::{
+    type_name,
+    Any,
+    TypeId,
+  },
+  borrow::Cow,
+  fmt,
+  marker::PhantomData,
+};
+
+/// A value passed between the stages of a `DynPipeline`.
+pub type DynValue = Box<dyn Any + Send>;
+
+/// A type flowing in or out of a `DynProcessor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TypeInfo {
+  pub id: TypeId,
+  pub name: &'static str,
+}
+
+impl TypeInfo {
+  pub fn of<T: Any>() -> Self {
+    Self {
+      id: TypeId::of::<T>(),
+      name: type_name::<T>(),
+    }
+  }
+}
+
+impl fmt::Display for TypeInfo {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name)
+  }
+}
+
+/// A `Processor` with its input and output types erased, so processors of
+/// different types can be stored together and combined at runtime. Inputs
+/// and outputs are checked when a value is passed in, and a mismatch is an
+/// `Error::TypeMismatch` rather than a panic.
+///
+/// Since values are passed as `Any`, the types can't borrow: use owned types
+/// like `String` and `Document<'static>`.
+pub trait DynProcessor: Send + Sync {
+  fn input_type(&self) -> TypeInfo;
+
+  fn output_type(&self) -> TypeInfo;
+
+  /// The name of the processor, used in errors, traces and descriptions.
+  fn name(&self) -> &str;
+
+  /// See `Processor::config`.
+  fn config(&self) -> Option<String> {
+    None
+  }
+
+  fn process_dyn(&self, input: DynValue) -> Result<DynValue, Error>;
+}
+
+/// Erases the types of `processor`, e.g.
+/// `erase::<String, usize>(|s: String| Ok(s.len()))`.
+pub fn erase<Input, Output>(
+  processor: impl Processor<Input, Output> + Send + Sync + 'static,
+) -> Box<dyn DynProcessor>
+where
+  Input: Send + 'static,
+  Output: Send + 'static,
+{
+  Box::new(Erased::new(processor))
+}
+
+/// The `DynProcessor` for a typed processor `P`, see `erase`.
+pub struct Erased<P, Input, Output> {
+  processor: P,
+  name: String,
+  config: Option<String>,
+  phantom: PhantomData<fn(Input) -> Output>,
+}
+
+impl<P, Input, Output> Erased<P, Input, Output>
+where
+  P: Processor<Input, Output>,
+{
+  pub fn new(processor: P) -> Self {
+    Self {
+      name: processor.name().into_owned(),
+      config: processor.config(),
+      processor,
+      phantom: PhantomData,
+    }
+  }
+
+  /// Replaces the name of the processor, see `Processor::name`.
+  pub fn with_name(self, name: impl Into<String>) -> Self {
+    Self {
+      name: name.into(),
+      ..self
+    }
+  }
+
+  /// Replaces the config of the processor, see `Processor::config`.
+  pub fn with_config(self, config: impl Into<String>) -> Self {
+    Self {
+      config: Some(config.into()),
+      ..self
+    }
+  }
+}
+
+impl<P, Input, Output> DynProcessor for Erased<P, Input, Output>
+where
+  P: Processor<Input, Output> + Send + Sync,
+  Input: Send + 'static,
+  Output: Send + 'static,
+{
+  fn input_type(&self) -> TypeInfo {
+    TypeInfo::of::<Input>()
+  }
+
+  fn output_type(&self) -> TypeInfo {
+    TypeInfo::of::<Output>()
+  }
+
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn config(&self) -> Option<String> {
+    self.config.clone()
+  }
+
+  fn process_dyn(&self, input: DynValue) -> Result<DynValue, Error> {
+    let input = downcast::<Input>(input, || format!("input of {}", self.name))?;
+    let output = self.processor.run(input)?;
+    Ok(Box::new(output))
+  }
+}
+
+/// A pipeline of `DynProcessor`s assembled at runtime. Every stage must take
+/// exactly the output type of the previous one, which is checked as stages
+/// are added.
+///
+/// ```
+/// use ragkit_ai::{
+///   dynamic::{
+///     erase,
+///     DynPipeline,
+///   },
+///   error::Error,
+/// };
+///
+/// let words = |text: String| -> Result<Vec<String>, Error> {
+///   Ok(text.split_whitespace().map(str::to_string).collect())
+/// };
+/// let count = |words: Vec<String>| -> Result<usize, Error> { Ok(words.len()) };
+///
+/// let pipeline = DynPipeline::new(erase(words)).chain(erase(count))?;
+/// assert_eq!(pipeline.run_typed::<_, usize>("a b c".to_string())?, 3);
+/// # Ok::<(), Error>(())
+/// ```
+pub struct DynPipeline {
+  stages: Vec<Box<dyn DynProcessor>>,
+}
+
+impl DynPipeline {
+  pub fn new(processor: Box<dyn DynProcessor>) -> Self {
+    Self {
+      stages: vec![processor],
+    }
+  }
+
+  /// Adds a stage, failing if it doesn't take the output of the last one.
+  pub fn chain(
+    mut self,
+    processor: Box<dyn DynProcessor>,
+  ) -> Result<Self, Error> {
+    self.push(processor)?;
+    Ok(self)
+  }
+
+  /// Like `chain`, for pipelines built up in place, e.g. from a list of
+  /// stage names.
+  pub fn push(
+    &mut self,
+    processor: Box<dyn DynProcessor>,
+  ) -> Result<(), Error> {
+    let last = self.stages.last().expect("pipelines have a stage");
+    if processor.input_type() != last.output_type() {
+      return Err(Error::TypeMismatch {
+        at: format!("stage {} ({})", self.stages.len(), processor.name()),
+        expected: processor.input_type().name,
+        found: last.output_type().name,
+      });
+    }
+    self.stages.push(processor);
+    Ok(())
+  }
+
+  pub fn stages(&self) -> &[Box<dyn DynProcessor>] {
+    &self.stages
+  }
+
+  /// Runs the pipeline on a typed input, returning a typed output. Fails
+  /// without running any stage if the types don't match the pipeline's.
+  pub fn run_typed<Input, Output>(&self, input: Input) -> Result<Output, Error>
+  where
+    Input: Send + 'static,
+    Output: 'static,
+  {
+    let input_type = self.input_type();
+    if TypeInfo::of::<Input>() != input_type {
+      return Err(Error::TypeMismatch {
+        at: "pipeline input".to_string(),
+        expected: input_type.name,
+        found: type_name::<Input>(),
+      });
+    }
+    // Checked up front too, so a wrong output type doesn't cost a full run.
+    let output_type = self.output_type();
+    if TypeInfo::of::<Output>() != output_type {
+      return Err(Error::TypeMismatch {
+        at: "pipeline output".to_string(),
+        expected: type_name::<Output>(),
+        found: output_type.name,
+      });
+    }
+    let output = self.process_dyn(Box::new(input))?;
+    downcast::<Output>(output, || "pipeline output".to_string())
+  }
+}
+
+impl DynProcessor for DynPipeline {
+  fn input_type(&self) -> TypeInfo {
+    self.stages[0].input_type()
+  }
+
+  fn output_type(&self) -> TypeInfo {
+    self.stages[self.stages.len() - 1].output_type()
+  }
+
+  fn name(&self) -> &str {
+    "DynPipeline"
+  }
+
+  fn process_dyn(&self, input: DynValue) -> Result<DynValue, Error> {
+    self
+      .stages
+      .iter()
+      .enumerate()
+      .try_fold(input, |value, (index, stage)| {
+        telemetry::stage(index, stage.name(), || stage.process_dyn(value))
+      })
+  }
+}
+
+// Dynamic pipelines can be used as stages of typed pipelines.
+impl<Input, Output> Processor<Input, Output> for DynPipeline
+where
+  Input: Send + 'static,
+  Output: 'static,
+{
+  fn process(&self, input: Input) -> Result<Output, Error> {
+    self.run_typed(input)
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Borrowed("DynPipeline")
+  }
+
+  fn describe(&self) -> Graph {
+    self.stages.iter().fold(Graph::default(), |graph, stage| {
+      graph.then(Graph::stage(stage.name(), stage.config()))
+    })
+  }
+}
+
+pub(crate) fn downcast<T: 'static>(
+  value: DynValue,
+  at: impl FnOnce() -> String,
+) -> Result<T, Error> {
+  // `Box<dyn Any + Send>` can't name the type it holds, only the caller's
+  // expectation is known.
+  value
+    .downcast::<T>()
+    .map(|value| *value)
+    .map_err(|_| Error::TypeMismatch {
+      at: at(),
+      expected: type_name::<T>(),
+      found: "a different type",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::traits::Pipeline;
+  use std::sync::{
+    atomic::{
+      AtomicU32,
+      Ordering,
+    },
+    Arc,
+  };
+
+  fn words(text: String) -> Result<Vec<String>, Error> {
+    Ok(text.split_whitespace().map(str::to_string).collect())
+  }
+
+  fn count(words: Vec<String>) -> Result<usize, Error> {
+    Ok(words.len())
+  }
+
+  #[test]
+  fn runtime_stages() {
+    let mut stages: Vec<Box<dyn DynProcessor>> =
+      vec![erase(words), erase(count)];
+    let mut pipeline = DynPipeline::new(stages.remove(0));
+    for stage in stages {
+      pipeline.push(stage).unwrap();
+    }
+    assert_eq!(pipeline.stages().len(), 2);
+    assert_eq!(pipeline.input_type(), TypeInfo::of::<String>());
+    assert_eq!(pipeline.output_type(), TypeInfo::of::<usize>());
+    assert_eq!(
+      pipeline
+        .run_typed::<_, usize>("one two".to_string())
+        .unwrap(),
+      2
+    );
+
+    // Nested in a typed pipeline.
+    let typed = Pipeline::new(|s: &str| Ok(s.to_string()))
+      .chain::<String, usize, _>(pipeline);
+    let n: usize = typed.run("a b c").unwrap();
+    assert_eq!(n, 3);
+  }
+
+  #[test]
+  fn type_mismatches() {
+    let err = DynPipeline::new(erase(count))
+      .chain(erase(count))
+      .err()
+      .unwrap();
+    assert!(matches!(
+      err,
+      Error::TypeMismatch { at, .. } if at == "stage 1 (count)"
+    ));
+
+    let pipeline = DynPipeline::new(erase(words));
+    let err = pipeline.run_typed::<_, Vec<String>>(5u32).unwrap_err();
+    assert!(matches!(
+      err,
+      Error::TypeMismatch { at, .. } if at == "pipeline input"
+    ));
+
+    // The output type is checked before any stage runs.
+    let calls = Arc::new(AtomicU32::new(0));
+    let counted = {
+      let calls = Arc::clone(&calls);
+      move |text: String| -> Result<Vec<String>, Error> {
+        calls.fetch_add(1, Ordering::SeqCst);
+        words(text)
+      }
+    };
+    let pipeline = DynPipeline::new(erase(counted));
+    let err = pipeline.run_typed::<_, usize>("a".to_string()).unwrap_err();
+    assert!(matches!(
+      err,
+      Error::TypeMismatch { at, .. } if at == "pipeline output"
+    ));
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+  }
+}
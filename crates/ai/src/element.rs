@@ -1,37 +1,437 @@
 use crate::{
   loc::Loc,
-  tag::Tag,
+  tag::{
+    Tag,
+    TagValue,
+    Tags,
+  },
 };
 use serde::{
   Deserialize,
   Serialize,
 };
-use std::collections::HashMap;
+use std::borrow::Cow;
 
+/// A typed piece of a document.
+///
+/// Elements produced together, e.g. by a loader or a `Partitioner`, form a
+/// tree: `parent` is the index of the element an element belongs to in that
+/// list, i.e. the title of its section or the list item it's nested in, and
+/// `section` is the index of the title of its innermost section. Both are
+/// `None` for top level elements and page furniture like headers.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-#[serde(tag = "type", rename_all = "lowercase")]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Element<'a> {
   #[serde(borrow)]
   Simple(SimpleElement<'a>),
+  #[serde(borrow)]
+  Title(TitleElement<'a>),
+  /// A regular paragraph of prose.
+  #[serde(borrow)]
+  NarrativeText(SimpleElement<'a>),
+  #[serde(borrow)]
+  ListItem(ListItemElement<'a>),
+  #[serde(borrow)]
+  Table(TableElement<'a>),
+  #[serde(borrow)]
+  CodeBlock(CodeBlockElement<'a>),
+  #[serde(borrow)]
+  Image(ImageElement<'a>),
+  /// The end of a page. Its content is empty.
+  #[serde(borrow)]
+  PageBreak(SimpleElement<'a>),
+  /// Text repeated at the top of pages, like a running title.
+  #[serde(borrow)]
+  Header(SimpleElement<'a>),
+  /// Text repeated at the bottom of pages, like page numbers.
+  #[serde(borrow)]
+  Footer(SimpleElement<'a>),
 }
 
+/// A piece of a document. The content is usually borrowed from the document
+/// it was split from, but loaders that extract text from binary formats
+/// produce owned content.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SimpleElement<'a> {
-  pub content: &'a str,
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
+  pub loc: Loc,
+  #[serde(borrow, default)]
+  pub tags: Tags<'a>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub parent: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub section: Option<usize>,
+}
+
+impl<'a> SimpleElement<'a> {
+  pub fn new(content: impl Into<Cow<'a, str>>, loc: Loc) -> Self {
+    Self {
+      content: content.into(),
+      loc,
+      tags: Default::default(),
+      parent: None,
+      section: None,
+    }
+  }
+
+  pub fn as_element(self) -> Element<'a> {
+    Element::Simple(self)
+  }
+}
+
+/// A title or heading. `level` is `0` for the document title and `1`-`9` for
+/// headings, matching `h1`-`h6` for html-like sources.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TitleElement<'a> {
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
+  pub loc: Loc,
+  #[serde(borrow, default)]
+  pub tags: Tags<'a>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub parent: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub section: Option<usize>,
+  pub level: u8,
+}
+
+impl<'a> TitleElement<'a> {
+  pub fn new(content: impl Into<Cow<'a, str>>, loc: Loc, level: u8) -> Self {
+    Self {
+      content: content.into(),
+      loc,
+      tags: Default::default(),
+      parent: None,
+      section: None,
+      level,
+    }
+  }
+
+  pub fn as_element(self) -> Element<'a> {
+    Element::Title(self)
+  }
+}
+
+/// An item of a bulleted or numbered list. `depth` is `0` for top level
+/// items.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ListItemElement<'a> {
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
+  pub loc: Loc,
+  #[serde(borrow, default)]
+  pub tags: Tags<'a>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub parent: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub section: Option<usize>,
+  pub depth: u8,
+  pub ordered: bool,
+}
+
+impl<'a> ListItemElement<'a> {
+  pub fn new(
+    content: impl Into<Cow<'a, str>>,
+    loc: Loc,
+    depth: u8,
+    ordered: bool,
+  ) -> Self {
+    Self {
+      content: content.into(),
+      loc,
+      tags: Default::default(),
+      parent: None,
+      section: None,
+      depth,
+      ordered,
+    }
+  }
+
+  pub fn as_element(self) -> Element<'a> {
+    Element::ListItem(self)
+  }
+}
+
+/// A table. `content` is the text of the table with cells separated by tabs
+/// and rows separated by newlines; `rows` holds the text of each cell.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TableElement<'a> {
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
+  pub loc: Loc,
+  #[serde(borrow, default)]
+  pub tags: Tags<'a>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub parent: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub section: Option<usize>,
+  pub rows: Vec<Vec<String>>,
+}
+
+impl<'a> TableElement<'a> {
+  pub fn new(
+    content: impl Into<Cow<'a, str>>,
+    loc: Loc,
+    rows: Vec<Vec<String>>,
+  ) -> Self {
+    Self {
+      content: content.into(),
+      loc,
+      tags: Default::default(),
+      parent: None,
+      section: None,
+      rows,
+    }
+  }
+
+  pub fn as_element(self) -> Element<'a> {
+    Element::Table(self)
+  }
+}
+
+/// A block of source code, with the language it's written in when known.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CodeBlockElement<'a> {
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
+  pub loc: Loc,
+  #[serde(borrow, default)]
+  pub tags: Tags<'a>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub parent: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub section: Option<usize>,
+  pub language: Option<String>,
+}
+
+impl<'a> CodeBlockElement<'a> {
+  pub fn new(
+    content: impl Into<Cow<'a, str>>,
+    loc: Loc,
+    language: Option<String>,
+  ) -> Self {
+    Self {
+      content: content.into(),
+      loc,
+      tags: Default::default(),
+      parent: None,
+      section: None,
+      language,
+    }
+  }
+
+  pub fn as_element(self) -> Element<'a> {
+    Element::CodeBlock(self)
+  }
+}
+
+/// An image, represented by its alternative text. `src` is where the image
+/// is stored, when known.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImageElement<'a> {
+  #[serde(borrow)]
+  pub content: Cow<'a, str>,
   pub loc: Loc,
-  pub tags: HashMap<&'a str, Tag<'a>>,
+  #[serde(borrow, default)]
+  pub tags: Tags<'a>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub parent: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub section: Option<usize>,
+  pub src: Option<String>,
+}
+
+impl<'a> ImageElement<'a> {
+  pub fn new(
+    content: impl Into<Cow<'a, str>>,
+    loc: Loc,
+    src: Option<String>,
+  ) -> Self {
+    Self {
+      content: content.into(),
+      loc,
+      tags: Default::default(),
+      parent: None,
+      section: None,
+      src,
+    }
+  }
+
+  pub fn as_element(self) -> Element<'a> {
+    Element::Image(self)
+  }
+}
+
+// Elements only borrow through their content and tags.
+macro_rules! impl_into_owned {
+  ($($name:ident { $($field:ident),* }),* $(,)?) => {$(
+    impl $name<'_> {
+      pub fn into_owned(self) -> $name<'static> {
+        $name {
+          content: Cow::Owned(self.content.into_owned()),
+          tags: self.tags.into_owned(),
+          $($field: self.$field,)*
+        }
+      }
+    }
+  )*};
+}
+
+impl_into_owned! {
+  SimpleElement { loc, parent, section },
+  TitleElement { loc, parent, section, level },
+  ListItemElement { loc, parent, section, depth, ordered },
+  TableElement { loc, parent, section, rows },
+  CodeBlockElement { loc, parent, section, language },
+  ImageElement { loc, parent, section, src },
 }
 
 impl<'a> Element<'a> {
   pub fn content(&'a self) -> &'a str {
     match self {
-      Element::Simple(simple) => simple.content,
+      Element::Simple(el)
+      | Element::NarrativeText(el)
+      | Element::PageBreak(el)
+      | Element::Header(el)
+      | Element::Footer(el) => &el.content,
+      Element::Title(el) => &el.content,
+      Element::ListItem(el) => &el.content,
+      Element::Table(el) => &el.content,
+      Element::CodeBlock(el) => &el.content,
+      Element::Image(el) => &el.content,
     }
   }
 
   pub fn loc(&'a self) -> &'a Loc {
     match self {
-      Element::Simple(simple) => &simple.loc,
+      Element::Simple(el)
+      | Element::NarrativeText(el)
+      | Element::PageBreak(el)
+      | Element::Header(el)
+      | Element::Footer(el) => &el.loc,
+      Element::Title(el) => &el.loc,
+      Element::ListItem(el) => &el.loc,
+      Element::Table(el) => &el.loc,
+      Element::CodeBlock(el) => &el.loc,
+      Element::Image(el) => &el.loc,
+    }
+  }
+
+  pub fn tags(&self) -> &Tags<'a> {
+    match self {
+      Element::Simple(el)
+      | Element::NarrativeText(el)
+      | Element::PageBreak(el)
+      | Element::Header(el)
+      | Element::Footer(el) => &el.tags,
+      Element::Title(el) => &el.tags,
+      Element::ListItem(el) => &el.tags,
+      Element::Table(el) => &el.tags,
+      Element::CodeBlock(el) => &el.tags,
+      Element::Image(el) => &el.tags,
+    }
+  }
+
+  pub fn tags_mut(&mut self) -> &mut Tags<'a> {
+    match self {
+      Element::Simple(el)
+      | Element::NarrativeText(el)
+      | Element::PageBreak(el)
+      | Element::Header(el)
+      | Element::Footer(el) => &mut el.tags,
+      Element::Title(el) => &mut el.tags,
+      Element::ListItem(el) => &mut el.tags,
+      Element::Table(el) => &mut el.tags,
+      Element::CodeBlock(el) => &mut el.tags,
+      Element::Image(el) => &mut el.tags,
+    }
+  }
+
+  /// The index of the element this one belongs to. See `Element`.
+  pub fn parent(&self) -> Option<usize> {
+    match self {
+      Element::Simple(el)
+      | Element::NarrativeText(el)
+      | Element::PageBreak(el)
+      | Element::Header(el)
+      | Element::Footer(el) => el.parent,
+      Element::Title(el) => el.parent,
+      Element::ListItem(el) => el.parent,
+      Element::Table(el) => el.parent,
+      Element::CodeBlock(el) => el.parent,
+      Element::Image(el) => el.parent,
+    }
+  }
+
+  /// The index of the title of the section this element is in.
+  pub fn section(&self) -> Option<usize> {
+    match self {
+      Element::Simple(el)
+      | Element::NarrativeText(el)
+      | Element::PageBreak(el)
+      | Element::Header(el)
+      | Element::Footer(el) => el.section,
+      Element::Title(el) => el.section,
+      Element::ListItem(el) => el.section,
+      Element::Table(el) => el.section,
+      Element::CodeBlock(el) => el.section,
+      Element::Image(el) => el.section,
+    }
+  }
+
+  pub fn set_relations(
+    &mut self,
+    parent: Option<usize>,
+    section: Option<usize>,
+  ) {
+    let (el_parent, el_section) = match self {
+      Element::Simple(el)
+      | Element::NarrativeText(el)
+      | Element::PageBreak(el)
+      | Element::Header(el)
+      | Element::Footer(el) => (&mut el.parent, &mut el.section),
+      Element::Title(el) => (&mut el.parent, &mut el.section),
+      Element::ListItem(el) => (&mut el.parent, &mut el.section),
+      Element::Table(el) => (&mut el.parent, &mut el.section),
+      Element::CodeBlock(el) => (&mut el.parent, &mut el.section),
+      Element::Image(el) => (&mut el.parent, &mut el.section),
+    };
+    *el_parent = parent;
+    *el_section = section;
+  }
+
+  /// The first tag with `key`.
+  pub fn tag(&self, key: &str) -> Option<&Tag<'a>> {
+    self.tags().get(key)
+  }
+
+  /// Adds a tag that spans the whole element, keeping any other values of
+  /// `key`.
+  pub fn with_tag(
+    mut self,
+    key: &'a str,
+    value: impl Into<TagValue<'a>>,
+  ) -> Self {
+    let tag = Tag::new(key, value, self.loc().clone());
+    self.tags_mut().insert(tag);
+    self
+  }
+
+  /// Copies any borrowed content, e.g. to keep elements after the document
+  /// they were split from is dropped.
+  pub fn into_owned(self) -> Element<'static> {
+    match self {
+      Element::Simple(el) => Element::Simple(el.into_owned()),
+      Element::Title(el) => Element::Title(el.into_owned()),
+      Element::NarrativeText(el) => Element::NarrativeText(el.into_owned()),
+      Element::ListItem(el) => Element::ListItem(el.into_owned()),
+      Element::Table(el) => Element::Table(el.into_owned()),
+      Element::CodeBlock(el) => Element::CodeBlock(el.into_owned()),
+      Element::Image(el) => Element::Image(el.into_owned()),
+      Element::PageBreak(el) => Element::PageBreak(el.into_owned()),
+      Element::Header(el) => Element::Header(el.into_owned()),
+      Element::Footer(el) => Element::Footer(el.into_owned()),
     }
   }
 }
@@ -1,3 +1,10 @@
+use std::{
+  error::Error as StdError,
+  io,
+  path::PathBuf,
+  sync::Arc,
+  time::Duration,
+};
 use thiserror::Error;
 
 #[derive(Clone, Debug, Error)]
@@ -7,6 +14,235 @@ pub enum Error {
 
   #[error("Uninitialized field: {0}")]
   UninitializedField(&'static str),
+
+  #[error("Invalid glob pattern: {0}")]
+  InvalidGlob(String),
+
+  #[error("Unsupported document: {0}")]
+  UnsupportedDocument(String),
+
+  #[error("Invalid template: {0}")]
+  InvalidTemplate(String),
+
+  #[error("Invalid {format} record at line {line}: {message}")]
+  InvalidRecord {
+    format: &'static str,
+    line: u64,
+    message: String,
+    #[source]
+    source: Option<Arc<dyn StdError + Send + Sync>>,
+  },
+
+  #[error("Git error: {message}")]
+  Git {
+    message: String,
+    source: Arc<dyn StdError + Send + Sync>,
+  },
+
+  /// A value passed to a dynamic processor or pipeline has the wrong type.
+  #[error("Type mismatch at {at}: expected {expected}, found {found}")]
+  TypeMismatch {
+    at: String,
+    expected: &'static str,
+    found: &'static str,
+  },
+
+  /// An input a processor can't handle, e.g. one no branch of a `Router`
+  /// accepts.
+  #[error("Invalid input: {0}")]
+  InvalidInput(String),
+
+  /// A processor didn't finish in time, see `resilience::Timeout`.
+  #[error("Timed out after {0:?}")]
+  Timeout(Duration),
+
+  /// A `CircuitBreaker` rejected a call because its processor kept failing.
+  #[error("Circuit breaker is open")]
+  CircuitOpen,
+
+  /// A task running a processor panicked or was cancelled.
+  #[error("Task failed: {0}")]
+  Task(String),
+
+  /// An input couldn't be parsed as `format`. `source` is the error of the
+  /// parser, if there was one rather than e.g. a missing part.
+  #[error("Failed to parse {format}: {message}")]
+  Parse {
+    format: &'static str,
+    message: String,
+    #[source]
+    source: Option<Arc<dyn StdError + Send + Sync>>,
+  },
+
+  /// A pipeline definition is invalid. `path` leads to the invalid value,
+  /// e.g. `stages[1].recursive_chunker.chunk_size`, and `position` is its
+  /// line and column in the definition, when known.
+  #[error(
+    "Invalid config at {path}{}: {message}",
+    position.map(|(line, column)| format!(" (line {line}, column {column})")).unwrap_or_default()
+  )]
+  Config {
+    path: String,
+    position: Option<(usize, usize)>,
+    message: String,
+    source: Arc<dyn StdError + Send + Sync>,
+  },
+
+  // The io error is wrapped in an `Arc` so `Error` can stay `Clone`.
+  #[error("I/O error at {}", path.display())]
+  Io {
+    path: PathBuf,
+    source: Arc<io::Error>,
+  },
+
+  /// A value couldn't be serialized or deserialized, e.g. a cached output.
+  #[error("Failed to serialize {format}")]
+  Serialization {
+    format: &'static str,
+    source: Arc<dyn StdError + Send + Sync>,
+  },
+
+  /// A call to a remote provider, e.g. an embedding or LLM API, failed.
+  /// `status` is the HTTP status of the response, if there was one.
+  #[error(
+    "{provider} request failed{}: {message}",
+    status.map(|status| format!(" with status {status}")).unwrap_or_default()
+  )]
+  Provider {
+    provider: String,
+    status: Option<u16>,
+    message: String,
+    #[source]
+    source: Option<Arc<dyn StdError + Send + Sync>>,
+  },
+
+  /// A value a processor produced or received is invalid, e.g. an embedding
+  /// of the wrong dimension.
+  #[error("Validation failed for {field}: {message}")]
+  Validation { field: String, message: String },
+
+  /// An error of a pipeline stage, with the name of the stage. Stages of
+  /// nested pipelines add one each, see `Error::stages` and `Error::root`.
+  #[error("Stage {stage} failed")]
+  Stage { stage: String, source: Box<Error> },
+}
+
+impl Error {
+  pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+    Error::Io {
+      path: path.into(),
+      source: Arc::new(source),
+    }
+  }
+
+  pub fn serialization(
+    format: &'static str,
+    source: impl StdError + Send + Sync + 'static,
+  ) -> Self {
+    Error::Serialization {
+      format,
+      source: Arc::new(source),
+    }
+  }
+
+  /// A failed call to `provider`, with the HTTP status of the response if
+  /// there was one.
+  pub fn provider(
+    provider: impl Into<String>,
+    status: Option<u16>,
+    message: impl ToString,
+  ) -> Self {
+    Error::Provider {
+      provider: provider.into(),
+      status,
+      message: message.to_string(),
+      source: None,
+    }
+  }
+
+  pub fn validation(field: impl Into<String>, message: impl ToString) -> Self {
+    Error::Validation {
+      field: field.into(),
+      message: message.to_string(),
+    }
+  }
+
+  /// Adds the pipeline stage the error happened in.
+  pub fn in_stage(self, stage: impl Into<String>) -> Self {
+    Error::Stage {
+      stage: stage.into(),
+      source: Box::new(self),
+    }
+  }
+
+  /// The stages the error happened in, outermost first.
+  pub fn stages(&self) -> Vec<&str> {
+    let mut stages = vec![];
+    let mut error = self;
+    while let Error::Stage { stage, source } = error {
+      stages.push(stage.as_str());
+      error = source;
+    }
+    stages
+  }
+
+  /// The error without the stages it happened in.
+  pub fn root(&self) -> &Error {
+    match self {
+      Error::Stage { source, .. } => source.root(),
+      error => error,
+    }
+  }
+
+  /// Whether the failed operation may succeed if it's tried again, e.g.
+  /// after a timeout, a dropped connection or a rate limit. `Retry` only
+  /// retries these errors by default.
+  pub fn is_retryable(&self) -> bool {
+    match self {
+      Error::Timeout(_) => true,
+      Error::Stage { source, .. } => source.is_retryable(),
+      // Without a response the request didn't get through, e.g. because
+      // the connection failed.
+      Error::Provider { status: None, .. } => true,
+      Error::Provider {
+        status: Some(status),
+        ..
+      } => matches!(status, 408 | 429 | 500 | 502 | 503 | 504),
+      Error::Io { source, .. } => matches!(
+        source.kind(),
+        io::ErrorKind::Interrupted
+          | io::ErrorKind::TimedOut
+          | io::ErrorKind::WouldBlock
+          | io::ErrorKind::ConnectionRefused
+          | io::ErrorKind::ConnectionReset
+          | io::ErrorKind::ConnectionAborted
+          | io::ErrorKind::BrokenPipe
+      ),
+      _ => false,
+    }
+  }
+
+  /// An input the parser of `format` failed on, keeping its error.
+  pub fn parse(
+    format: &'static str,
+    source: impl StdError + Send + Sync + 'static,
+  ) -> Self {
+    Error::Parse {
+      format,
+      message: source.to_string(),
+      source: Some(Arc::new(source)),
+    }
+  }
+
+  /// An input that parsed but isn't a valid `format` document, e.g. because
+  /// a required part is missing.
+  pub fn malformed(format: &'static str, message: impl ToString) -> Self {
+    Error::Parse {
+      format,
+      message: message.to_string(),
+      source: None,
+    }
+  }
 }
 
 impl From<derive_builder::UninitializedFieldError> for Error {
@@ -14,3 +250,61 @@ impl From<derive_builder::UninitializedFieldError> for Error {
     Error::UninitializedField(value.field_name())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn retryable() {
+    assert!(Error::provider("openai", Some(429), "rate limited").is_retryable());
+    assert!(Error::provider("openai", Some(503), "unavailable").is_retryable());
+    assert!(Error::provider("openai", None, "connection reset").is_retryable());
+    assert!(!Error::provider("openai", Some(401), "bad key").is_retryable());
+    assert!(
+      !Error::validation("embedding", "expected 3 dimensions").is_retryable()
+    );
+
+    let io = io::Error::from(io::ErrorKind::ConnectionReset);
+    assert!(Error::io("socket", io).in_stage("embed").is_retryable());
+    let io = io::Error::from(io::ErrorKind::NotFound);
+    assert!(!Error::io("doc.txt", io).in_stage("load").is_retryable());
+  }
+
+  #[test]
+  fn sources() {
+    let json = serde_json::from_str::<u32>("x").unwrap_err();
+    let err = Error::serialization("json", json).in_stage("cache");
+    assert_eq!(err.stages(), ["cache"]);
+    assert!(matches!(
+      err.root(),
+      Error::Serialization { format: "json", .. }
+    ));
+
+    // Every error in the chain can be reached through `source`.
+    let mut chain = vec![err.to_string()];
+    let mut source = StdError::source(&err);
+    while let Some(err) = source {
+      chain.push(err.to_string());
+      source = err.source();
+    }
+    assert_eq!(
+      chain[..2],
+      ["Stage cache failed", "Failed to serialize json"]
+    );
+    assert!(chain[2].starts_with("expected value"));
+    assert_eq!(chain.len(), 3);
+
+    // Parse errors keep the error of the parser, if there was one.
+    let json = serde_json::from_str::<u32>("x").unwrap_err();
+    let err = Error::parse("json", json);
+    let source = StdError::source(&err).map(ToString::to_string);
+    assert!(source.is_some_and(|source| source.starts_with("expected value")));
+    assert!(StdError::source(&Error::malformed("json", "empty")).is_none());
+
+    assert_eq!(
+      Error::provider("voyage", Some(500), "oops").to_string(),
+      "voyage request failed with status 500: oops"
+    );
+  }
+}
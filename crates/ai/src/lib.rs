@@ -1,11 +1,23 @@
+//! Building blocks for retrieval-augmented generation: loaders, splitters
+//! and chunkers composed into pipelines of `traits::Processor`s. Pipelines
+//! wrap the errors of their stages in `Error::Stage`, so match on
+//! `Error::root` rather than on the error itself.
+
+pub mod annotation;
 pub mod chunk;
+pub mod combinator;
+pub mod describe;
 pub mod document;
+pub mod dynamic;
 pub mod element;
 pub mod error;
 pub mod loc;
 pub mod process;
+pub mod registry;
 pub mod tag;
+pub mod telemetry;
 pub mod traits;
+pub mod tree;
 
 #[cfg(test)]
 mod tests {
@@ -53,7 +65,7 @@ mod tests {
 
     let pipeline = Pipeline::new(splitter);
 
-    let elements = pipeline.run(doc).unwrap();
+    let elements = pipeline.run(&doc).unwrap();
     let strings = elements.iter().map(|el| el.content()).collect::<Vec<_>>();
 
     assert_eq!(strings, vec!["hello", " worl", "d"])
@@ -2,17 +2,442 @@ use serde::{
   Deserialize,
   Serialize,
 };
+use std::ops::Range;
 
 /// The location in a corresponding string. Inclusive of `start`, exclusive of
 /// `end`.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+///
+/// `doc_id` optionally names the document the offsets point into, so a `Loc`
+/// can be resolved without knowing where it came from. Range operations only
+/// combine locs of the same document, where a loc without `doc_id` matches
+/// any document.
+#[derive(
+  Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash,
+)]
 pub struct Loc {
   pub start: usize,
   pub end: usize,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub doc_id: Option<String>,
 }
 
 impl Loc {
+  pub fn new(start: usize, end: usize) -> Self {
+    Self {
+      start,
+      end,
+      doc_id: None,
+    }
+  }
+
+  pub fn with_doc_id(mut self, doc_id: impl Into<String>) -> Self {
+    self.doc_id = Some(doc_id.into());
+    self
+  }
+
   pub fn as_tuple(&self) -> (usize, usize) {
     (self.start, self.end)
   }
+
+  pub fn as_range(&self) -> Range<usize> {
+    self.start..self.end
+  }
+
+  pub fn len(&self) -> usize {
+    self.end.saturating_sub(self.start)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Whether both locs can point into the same document.
+  pub fn same_doc(&self, other: &Loc) -> bool {
+    match (&self.doc_id, &other.doc_id) {
+      (Some(a), Some(b)) => a == b,
+      _ => true,
+    }
+  }
+
+  pub fn contains_offset(&self, offset: usize) -> bool {
+    self.start <= offset && offset < self.end
+  }
+
+  /// Whether `other` lies entirely within this loc.
+  pub fn contains(&self, other: &Loc) -> bool {
+    self.same_doc(other) && self.start <= other.start && other.end <= self.end
+  }
+
+  /// Whether the locs share at least one byte.
+  pub fn intersects(&self, other: &Loc) -> bool {
+    self.same_doc(other) && self.start < other.end && other.start < self.end
+  }
+
+  /// The bytes both locs share, if any.
+  pub fn intersection(&self, other: &Loc) -> Option<Loc> {
+    if !self.intersects(other) {
+      return None;
+    }
+    Some(Loc {
+      start: self.start.max(other.start),
+      end: self.end.min(other.end),
+      doc_id: self.doc_id.clone().or_else(|| other.doc_id.clone()),
+    })
+  }
+
+  /// The smallest loc covering both locs, including any gap between them.
+  /// `None` if they point into different documents.
+  pub fn union(&self, other: &Loc) -> Option<Loc> {
+    if !self.same_doc(other) {
+      return None;
+    }
+    Some(Loc {
+      start: self.start.min(other.start),
+      end: self.end.max(other.end),
+      doc_id: self.doc_id.clone().or_else(|| other.doc_id.clone()),
+    })
+  }
+
+  /// Whether one loc ends where the other starts.
+  pub fn is_adjacent(&self, other: &Loc) -> bool {
+    self.same_doc(other) && (self.end == other.start || other.end == self.start)
+  }
+
+  /// Merges overlapping and adjacent locs. The result is sorted by document
+  /// and start offset.
+  ///
+  /// Like for `same_doc`, locs without a `doc_id` can point into any
+  /// document: when all other locs point into the same one, they are merged
+  /// with those and take their `doc_id`.
+  pub fn merge(locs: impl IntoIterator<Item = Loc>) -> Vec<Loc> {
+    let mut locs = locs.into_iter().collect::<Vec<_>>();
+    let mut doc_ids = locs.iter().filter_map(|loc| loc.doc_id.as_ref());
+    if let Some(doc_id) = doc_ids.next() {
+      if doc_ids.all(|other| other == doc_id) {
+        let doc_id = doc_id.clone();
+        for loc in &mut locs {
+          loc.doc_id.get_or_insert_with(|| doc_id.clone());
+        }
+      }
+    }
+    locs.sort_by(|a, b| {
+      (&a.doc_id, a.start, a.end).cmp(&(&b.doc_id, b.start, b.end))
+    });
+
+    let mut merged: Vec<Loc> = Vec::with_capacity(locs.len());
+    for loc in locs {
+      match merged.last_mut() {
+        Some(last) if last.doc_id == loc.doc_id && loc.start <= last.end => {
+          last.end = last.end.max(loc.end);
+        }
+        _ => merged.push(loc),
+      }
+    }
+    merged
+  }
+
+  /// The text at this loc, `None` if it's out of bounds or not on character
+  /// boundaries.
+  pub fn slice<'s>(&self, text: &'s str) -> Option<&'s str> {
+    text.get(self.as_range())
+  }
+}
+
+/// A 0-based line and column.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineCol {
+  pub line: usize,
+  pub col: usize,
+}
+
+/// Converts between the byte offsets used by `Loc` and other ways to address
+/// text: lines and columns, char indices and UTF-16 code units, as used by
+/// editors and JavaScript.
+///
+/// Lines are separated by `\n`. Columns count chars, or UTF-16 code units for
+/// the `_utf16` variants. Conversions return `None` for positions outside the
+/// text or inside a character.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineIndex {
+  len: usize,
+  /// The byte offset of the start of every line.
+  line_starts: Vec<usize>,
+  /// Characters longer than one byte, in order.
+  wide_chars: Vec<WideChar>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct WideChar {
+  offset: usize,
+  len_utf8: usize,
+  /// The char index of this character.
+  char_index: usize,
+  /// The UTF-16 index of this character.
+  utf16_index: usize,
+  /// Bytes minus chars up to the end of this character.
+  char_shrink: usize,
+  /// Bytes minus UTF-16 code units up to the end of this character.
+  utf16_shrink: usize,
+}
+
+impl LineIndex {
+  pub fn new(text: &str) -> Self {
+    let mut line_starts = vec![0];
+    let mut wide_chars = vec![];
+    let (mut char_shrink, mut utf16_shrink) = (0, 0);
+    for (offset, c) in text.char_indices() {
+      if c == '\n' {
+        line_starts.push(offset + 1);
+      }
+      let len_utf8 = c.len_utf8();
+      if len_utf8 > 1 {
+        let char_index = offset - char_shrink;
+        let utf16_index = offset - utf16_shrink;
+        char_shrink += len_utf8 - 1;
+        utf16_shrink += len_utf8 - c.len_utf16();
+        wide_chars.push(WideChar {
+          offset,
+          len_utf8,
+          char_index,
+          utf16_index,
+          char_shrink,
+          utf16_shrink,
+        });
+      }
+    }
+    Self {
+      len: text.len(),
+      line_starts,
+      wide_chars,
+    }
+  }
+
+  pub fn line_count(&self) -> usize {
+    self.line_starts.len()
+  }
+
+  /// The byte range of `line`, without its line break.
+  pub fn line(&self, line: usize) -> Option<Loc> {
+    let start = *self.line_starts.get(line)?;
+    let end = match self.line_starts.get(line + 1) {
+      Some(next) => next - 1,
+      None => self.len,
+    };
+    Some(Loc::new(start, end))
+  }
+
+  pub fn char_index(&self, offset: usize) -> Option<usize> {
+    let shrink = self.shrink_at(offset)?;
+    Some(offset - shrink.map_or(0, |wide| wide.char_shrink))
+  }
+
+  pub fn utf16_index(&self, offset: usize) -> Option<usize> {
+    let shrink = self.shrink_at(offset)?;
+    Some(offset - shrink.map_or(0, |wide| wide.utf16_shrink))
+  }
+
+  pub fn offset_of_char(&self, index: usize) -> Option<usize> {
+    self.offset_of(index, |wide| (wide.char_index, 1, wide.char_shrink))
+  }
+
+  pub fn offset_of_utf16(&self, index: usize) -> Option<usize> {
+    self.offset_of(index, |wide| {
+      // Characters outside the BMP are surrogate pairs in UTF-16.
+      let units = if wide.len_utf8 == 4 { 2 } else { 1 };
+      (wide.utf16_index, units, wide.utf16_shrink)
+    })
+  }
+
+  pub fn line_col(&self, offset: usize) -> Option<LineCol> {
+    self.line_col_with(offset, Self::char_index)
+  }
+
+  pub fn line_col_utf16(&self, offset: usize) -> Option<LineCol> {
+    self.line_col_with(offset, Self::utf16_index)
+  }
+
+  pub fn offset(&self, line_col: LineCol) -> Option<usize> {
+    self.offset_with(line_col, Self::char_index, Self::offset_of_char)
+  }
+
+  pub fn offset_utf16(&self, line_col: LineCol) -> Option<usize> {
+    self.offset_with(line_col, Self::utf16_index, Self::offset_of_utf16)
+  }
+
+  /// The last wide character ending at or before `offset`. `None` if
+  /// `offset` is inside a character or out of bounds.
+  fn shrink_at(&self, offset: usize) -> Option<Option<&WideChar>> {
+    if offset > self.len {
+      return None;
+    }
+    let count = self.wide_chars.partition_point(|wide| wide.offset < offset);
+    let Some(wide) = count.checked_sub(1).map(|i| &self.wide_chars[i]) else {
+      return Some(None);
+    };
+    if offset < wide.offset + wide.len_utf8 {
+      return None;
+    }
+    Some(Some(wide))
+  }
+
+  /// Converts an `index` in another unit to a byte offset. `unit` gives the
+  /// index, length and shrink of a wide char in that unit.
+  fn offset_of(
+    &self,
+    index: usize,
+    unit: impl Fn(&WideChar) -> (usize, usize, usize),
+  ) -> Option<usize> {
+    let count = self.wide_chars.partition_point(|wide| unit(wide).0 < index);
+    let offset = match count.checked_sub(1) {
+      Some(i) => {
+        let (start, len, shrink) = unit(&self.wide_chars[i]);
+        if index < start + len {
+          return None;
+        }
+        index + shrink
+      }
+      None => index,
+    };
+    (offset <= self.len).then_some(offset)
+  }
+
+  fn line_col_with(
+    &self,
+    offset: usize,
+    index: impl Fn(&Self, usize) -> Option<usize>,
+  ) -> Option<LineCol> {
+    let col = index(self, offset)?;
+    let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+    let line_start = index(self, self.line_starts[line])?;
+    Some(LineCol {
+      line,
+      col: col - line_start,
+    })
+  }
+
+  fn offset_with(
+    &self,
+    line_col: LineCol,
+    index: impl Fn(&Self, usize) -> Option<usize>,
+    offset_of: impl Fn(&Self, usize) -> Option<usize>,
+  ) -> Option<usize> {
+    let line = self.line(line_col.line)?;
+    let offset = offset_of(self, index(self, line.start)? + line_col.col)?;
+    (offset <= line.end).then_some(offset)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn range_operations() {
+    let a = Loc::new(0, 5);
+    let b = Loc::new(3, 8);
+    let c = Loc::new(8, 10);
+
+    assert!(a.intersects(&b));
+    assert!(!b.intersects(&c));
+    assert!(b.is_adjacent(&c));
+    assert!(a.contains(&Loc::new(1, 5)));
+    assert!(!a.contains(&b));
+    assert_eq!(a.intersection(&b), Some(Loc::new(3, 5)));
+    assert_eq!(a.union(&c), Some(Loc::new(0, 10)));
+    assert_eq!(Loc::new(2, 6).slice("hello world"), Some("llo "));
+    assert_eq!(Loc::new(2, 20).slice("hello"), None);
+
+    let x = Loc::new(0, 5).with_doc_id("x");
+    let y = Loc::new(0, 5).with_doc_id("y");
+    assert!(!x.intersects(&y));
+    assert!(x.intersects(&a));
+    assert_eq!(x.union(&y), None);
+    assert_eq!(x.intersection(&b), Some(Loc::new(3, 5).with_doc_id("x")));
+
+    let merged = Loc::merge([
+      Loc::new(8, 10),
+      Loc::new(0, 5),
+      Loc::new(12, 14),
+      Loc::new(3, 8),
+    ]);
+    assert_eq!(merged, [Loc::new(0, 10), Loc::new(12, 14)]);
+  }
+
+  #[test]
+  fn merge_without_doc_ids() {
+    // Locs without a doc id belong to the only document.
+    let merged = Loc::merge([
+      Loc::new(8, 10),
+      Loc::new(0, 5).with_doc_id("y"),
+      Loc::new(3, 8),
+      Loc::new(12, 14).with_doc_id("y"),
+    ]);
+    assert_eq!(
+      merged,
+      [
+        Loc::new(0, 10).with_doc_id("y"),
+        Loc::new(12, 14).with_doc_id("y"),
+      ]
+    );
+
+    // With several documents it's ambiguous, so they are kept apart.
+    let merged = Loc::merge([
+      Loc::new(3, 8),
+      Loc::new(0, 5).with_doc_id("x"),
+      Loc::new(4, 6).with_doc_id("y"),
+    ]);
+    assert_eq!(
+      merged,
+      [
+        Loc::new(3, 8),
+        Loc::new(0, 5).with_doc_id("x"),
+        Loc::new(4, 6).with_doc_id("y"),
+      ]
+    );
+  }
+
+  #[test]
+  fn serde_omits_missing_doc_id() {
+    let json = serde_json::to_string(&Loc::new(1, 2)).unwrap();
+    assert_eq!(json, r#"{"start":1,"end":2}"#);
+    let loc: Loc =
+      serde_json::from_str(r#"{"start":1,"end":2,"doc_id":"a"}"#).unwrap();
+    assert_eq!(loc, Loc::new(1, 2).with_doc_id("a"));
+  }
+
+  #[test]
+  fn line_index() {
+    // "é" is 2 bytes and 1 UTF-16 unit, "😀" 4 bytes and 2 units.
+    let text = "ab\ncé😀d\n\nend";
+    let index = LineIndex::new(text);
+    assert_eq!(index.line_count(), 4);
+    assert_eq!(index.line(1).unwrap().slice(text), Some("cé😀d"));
+
+    let d = text.find('d').unwrap();
+    assert_eq!(d, 10);
+    assert_eq!(index.char_index(d), Some(6));
+    assert_eq!(index.utf16_index(d), Some(7));
+    assert_eq!(index.line_col(d), Some(LineCol { line: 1, col: 3 }));
+    assert_eq!(index.line_col_utf16(d), Some(LineCol { line: 1, col: 4 }));
+    assert_eq!(index.offset(LineCol { line: 1, col: 3 }), Some(d));
+    assert_eq!(index.offset_utf16(LineCol { line: 1, col: 4 }), Some(d));
+    assert_eq!(index.offset_of_char(6), Some(d));
+    assert_eq!(index.offset_of_utf16(7), Some(d));
+
+    // Inside characters.
+    assert_eq!(index.char_index(5), None);
+    assert_eq!(index.utf16_index(8), None);
+    assert_eq!(index.offset_of_utf16(6), None);
+
+    // Line ends and out of bounds.
+    assert_eq!(
+      index.line_col(text.len()),
+      Some(LineCol { line: 3, col: 3 })
+    );
+    assert_eq!(index.offset(LineCol { line: 0, col: 2 }), Some(2));
+    assert_eq!(index.offset(LineCol { line: 0, col: 3 }), None);
+    assert_eq!(index.offset(LineCol { line: 4, col: 0 }), None);
+    assert_eq!(index.offset_of_char(100), None);
+    assert_eq!(index.offset(LineCol { line: 2, col: 0 }), Some(12));
+  }
 }
@@ -0,0 +1,21 @@
+//! Detection of the encoding of text in unknown encodings, shared by loaders
+//! and the `Normalizer`.
+
+use encoding_rs::{
+  Encoding,
+  UTF_8,
+};
+
+/// The encoding of `bytes`: the one of their byte order mark if they have
+/// one, else UTF-8 if they're valid UTF-8 and a statistical guess if not.
+pub(crate) fn detect(bytes: &[u8]) -> &'static Encoding {
+  if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+    return encoding;
+  }
+  if std::str::from_utf8(bytes).is_ok() {
+    return UTF_8;
+  }
+  let mut detector = chardetng::EncodingDetector::new();
+  detector.feed(bytes, true);
+  detector.guess(None, true)
+}
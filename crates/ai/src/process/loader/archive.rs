@@ -0,0 +1,145 @@
+//! Helpers for zip based formats such as docx and epub.
+
+use super::xml::{
+  self,
+  Node,
+};
+use crate::error::Error;
+use std::{
+  error::Error as StdError,
+  io::{
+    Cursor,
+    Read,
+  },
+  sync::Arc,
+};
+use zip::{
+  result::ZipError,
+  ZipArchive,
+};
+
+/// The default limit on the decompressed size of an entry.
+pub(crate) const DEFAULT_MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+pub(crate) struct Archive<'a> {
+  format: &'static str,
+  zip: ZipArchive<Cursor<&'a [u8]>>,
+  /// Entries decompressing to more bytes are rejected, so a small zip bomb
+  /// can't exhaust memory.
+  max_entry_size: u64,
+}
+
+impl<'a> Archive<'a> {
+  pub fn new(
+    format: &'static str,
+    data: &'a [u8],
+    max_entry_size: u64,
+  ) -> Result<Self, Error> {
+    let zip = ZipArchive::new(Cursor::new(data))
+      .map_err(|err| Error::parse(format, err))?;
+    Ok(Self {
+      format,
+      zip,
+      max_entry_size,
+    })
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.zip.index_for_name(name).is_some()
+  }
+
+  /// Reads an entry as UTF-8 text. Returns `None` if there is no such entry.
+  pub fn read_string(&mut self, name: &str) -> Result<Option<String>, Error> {
+    let file = match self.zip.by_name(name) {
+      Ok(file) => file,
+      Err(ZipError::FileNotFound) => return Ok(None),
+      Err(err) => return Err(Error::parse(self.format, err)),
+    };
+    // Reading one byte past the limit tells entries at the limit from
+    // larger ones.
+    let mut content = vec![];
+    file
+      .take(self.max_entry_size + 1)
+      .read_to_end(&mut content)
+      .map_err(|err| entry_error(self.format, name, err))?;
+    if content.len() as u64 > self.max_entry_size {
+      return Err(Error::malformed(
+        self.format,
+        format!("{name} is larger than {} bytes", self.max_entry_size),
+      ));
+    }
+    String::from_utf8(content)
+      .map(Some)
+      .map_err(|err| entry_error(self.format, name, err))
+  }
+
+  /// Reads and parses an XML entry. Returns `None` if there is no such entry.
+  pub fn read_xml(&mut self, name: &str) -> Result<Option<Node>, Error> {
+    match self.read_string(name)? {
+      Some(content) => xml::parse(self.format, &content).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  /// Like `read_xml`, but fails if the entry doesn't exist.
+  pub fn require_xml(&mut self, name: &str) -> Result<Node, Error> {
+    self
+      .read_xml(name)?
+      .ok_or_else(|| Error::malformed(self.format, format!("missing {name}")))
+  }
+}
+
+// An entry `name` of the archive that couldn't be read.
+fn entry_error(
+  format: &'static str,
+  name: &str,
+  err: impl StdError + Send + Sync + 'static,
+) -> Error {
+  Error::Parse {
+    format,
+    message: format!("{name}: {err}"),
+    source: Some(Arc::new(err)),
+  }
+}
+
+/// Resolves `target` relative to the directory of the zip entry `base`, the
+/// way relationship targets and hrefs are resolved.
+pub(crate) fn resolve(base: &str, target: &str) -> String {
+  let target = target.split('#').next().unwrap_or_default();
+  if let Some(absolute) = target.strip_prefix('/') {
+    return absolute.to_string();
+  }
+
+  let mut parts = base.split('/').collect::<Vec<_>>();
+  // Drop the file name of `base`.
+  parts.pop();
+  for part in target.split('/') {
+    match part {
+      "." | "" => {}
+      ".." => {
+        parts.pop();
+      }
+      part => parts.push(part),
+    }
+  }
+  parts.join("/")
+}
+
+/// Builds an in-memory zip archive from `(name, content)` pairs.
+#[cfg(test)]
+pub(crate) fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+  use std::io::Write;
+  use zip::{
+    write::SimpleFileOptions,
+    ZipWriter,
+  };
+
+  let mut writer = ZipWriter::new(Cursor::new(vec![]));
+  for (name, content) in files {
+    writer
+      .start_file(*name, SimpleFileOptions::default())
+      .unwrap();
+    writer.write_all(content.as_bytes()).unwrap();
+  }
+  writer.finish().unwrap().into_inner()
+}
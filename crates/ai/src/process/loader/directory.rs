@@ -0,0 +1,466 @@
+use crate::{
+  describe,
+  document::{
+    BinaryDocument,
+    BinaryFormat,
+    CodeDocument,
+    Document,
+    DocumentMetadata,
+    HtmlDocument,
+    MarkdownDocument,
+    TextDocument,
+  },
+  error::Error,
+  process::{
+    encoding,
+    loader::file_uri,
+  },
+  telemetry,
+  traits::Processor,
+};
+use chrono::{
+  DateTime,
+  Utc,
+};
+use derive_builder::Builder;
+use encoding_rs::Encoding;
+use globset::{
+  Glob,
+  GlobSet,
+  GlobSetBuilder,
+};
+use ignore::WalkBuilder;
+use serde::Serialize;
+use std::{
+  fs,
+  path::Path,
+};
+
+/// How many bytes are inspected when deciding whether a file is binary.
+const SNIFF_LEN: usize = 8 * 1024;
+
+/// Walks a directory tree and loads every matching file as a `Document`.
+///
+/// Include and exclude patterns are globs matched against the path relative
+/// to the root, e.g. `docs/**/*.md`. When no include patterns are given every
+/// file is included. Excludes always win over includes.
+///
+/// The loaded documents are ordered by path and have their `id` set to the
+/// relative path, their `source` set to a `file://` URI and the `path`,
+/// `size` and `encoding` (for text files) available in `extra` metadata.
+///
+/// Like rows of `CsvLoader`, every file is loaded on its own: a file or
+/// directory that can't be read, e.g. a broken symlink, is an `Err` in the
+/// output and doesn't stop the others from loading.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct DirectoryLoader {
+  /// Glob patterns of files to include.
+  #[builder(default)]
+  include: Vec<String>,
+
+  /// Glob patterns of files to exclude.
+  #[builder(default)]
+  exclude: Vec<String>,
+
+  /// Whether to skip files ignored by `.gitignore`, `.ignore` and git's
+  /// exclude files.
+  #[builder(default = "true")]
+  respect_gitignore: bool,
+
+  /// Whether to load hidden files and descend into hidden directories.
+  #[builder(default = "false")]
+  include_hidden: bool,
+
+  #[builder(default = "false")]
+  follow_links: bool,
+
+  /// Files larger than this many bytes are skipped.
+  #[builder(default, setter(strip_option))]
+  max_file_size: Option<u64>,
+}
+
+impl<'p> Processor<&'p Path, Vec<Result<Document<'static>, Error>>>
+  for DirectoryLoader
+{
+  fn process(
+    &self,
+    root: &'p Path,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    telemetry::processor("DirectoryLoader", None, || self.walk(root))
+  }
+
+  describe::impl_config!();
+}
+
+impl DirectoryLoader {
+  fn walk(
+    &self,
+    root: &Path,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    let include = build_glob_set(&self.include)?;
+    let exclude = build_glob_set(&self.exclude)?;
+
+    let walker = WalkBuilder::new(root)
+      .hidden(!self.include_hidden)
+      .follow_links(self.follow_links)
+      .max_filesize(self.max_file_size)
+      .git_ignore(self.respect_gitignore)
+      .git_global(self.respect_gitignore)
+      .git_exclude(self.respect_gitignore)
+      .ignore(self.respect_gitignore)
+      .parents(self.respect_gitignore)
+      // Respect `.gitignore` files even if `root` isn't inside a git repo.
+      .require_git(false)
+      .sort_by_file_name(|a, b| a.cmp(b))
+      .build();
+
+    let mut documents = vec![];
+    for entry in walker {
+      let entry = match entry {
+        Ok(entry) => entry,
+        Err(err) => {
+          documents.push(Err(walk_error(root, err)));
+          continue;
+        }
+      };
+      if !entry.file_type().is_some_and(|t| t.is_file()) {
+        continue;
+      }
+
+      let path = entry.path();
+      let relative = path.strip_prefix(root).unwrap_or(path);
+      if !include.is_empty() && !include.is_match(relative) {
+        continue;
+      }
+      if exclude.is_match(relative) {
+        continue;
+      }
+
+      documents.push(load_file(path, relative));
+    }
+
+    Ok(documents)
+  }
+}
+
+pub(crate) fn build_glob_set(patterns: &[String]) -> Result<GlobSet, Error> {
+  let mut builder = GlobSetBuilder::new();
+  for pattern in patterns {
+    let glob = Glob::new(pattern).map_err(|err| {
+      Error::InvalidGlob(format!("{pattern}: {}", err.kind()))
+    })?;
+    builder.add(glob);
+  }
+  builder
+    .build()
+    .map_err(|err| Error::InvalidGlob(err.to_string()))
+}
+
+fn walk_error(root: &Path, err: ignore::Error) -> Error {
+  let message = err.to_string();
+  match err.into_io_error() {
+    Some(io) => Error::io(root, io),
+    None => Error::io(root, std::io::Error::other(message)),
+  }
+}
+
+fn load_file(path: &Path, relative: &Path) -> Result<Document<'static>, Error> {
+  let bytes = fs::read(path).map_err(|err| Error::io(path, err))?;
+  let fs_meta = fs::metadata(path).map_err(|err| Error::io(path, err))?;
+  let absolute = fs::canonicalize(path).map_err(|err| Error::io(path, err))?;
+  let relative = relative.to_string_lossy().replace('\\', "/");
+
+  let mut meta = DocumentMetadata {
+    id: Some(relative.clone()),
+    source: Some(file_uri(&absolute)),
+    created_at: fs_meta.created().ok().map(DateTime::<Utc>::from),
+    modified_at: fs_meta.modified().ok().map(DateTime::<Utc>::from),
+    ..Default::default()
+  };
+  meta.extra.insert("path".into(), relative.into());
+  meta.extra.insert("size".into(), fs_meta.len().into());
+
+  Ok(typed_document(path, bytes, meta))
+}
+
+/// Creates a document of the type matching the file at `path` from its
+/// `bytes`. Sets the `mime_type` and, for text files, the `encoding` in
+/// `meta`.
+pub(crate) fn typed_document(
+  path: &Path,
+  bytes: Vec<u8>,
+  mut meta: DocumentMetadata,
+) -> Document<'static> {
+  let extension = path
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .map(str::to_lowercase)
+    .unwrap_or_default();
+  // Code extensions can clash with other formats, e.g. `mime_guess` takes
+  // `.ts` files for MPEG transport streams, so they're checked first.
+  let language = code_language(&extension).filter(|_| !is_binary(&bytes));
+
+  let mime_type = language
+    .map(code_mime_type)
+    .or_else(|| mime_guess::from_path(path).first_raw())
+    .map(str::to_string)
+    .unwrap_or_else(|| {
+      if is_binary(&bytes) {
+        "application/octet-stream".to_string()
+      } else {
+        "text/plain".to_string()
+      }
+    });
+  meta.mime_type = Some(mime_type.clone());
+
+  let format = BinaryFormat::from_mime_type(&mime_type);
+  if format != BinaryFormat::Other || is_binary(&bytes) {
+    return BinaryDocument::new(bytes, format)
+      .with_meta(meta)
+      .as_document();
+  }
+
+  let encoding = encoding::detect(&bytes);
+  let (content, _, _) = encoding.decode(&bytes);
+  let content = content.into_owned();
+  meta
+    .extra
+    .insert("encoding".into(), encoding.name().to_lowercase().into());
+
+  match mime_type.as_str() {
+    "text/markdown" | "text/x-markdown" => {
+      MarkdownDocument::new(content).with_meta(meta).as_document()
+    }
+    "text/html" | "application/xhtml+xml" => {
+      HtmlDocument::new(content).with_meta(meta).as_document()
+    }
+    _ => match language {
+      Some(language) => CodeDocument::new(content, Some(language.to_string()))
+        .with_meta(meta)
+        .as_document(),
+      None => TextDocument::new(content).with_meta(meta).as_document(),
+    },
+  }
+}
+
+// A file is considered binary if it contains a NUL byte near the start and
+// doesn't begin with a UTF-16 byte order mark.
+fn is_binary(bytes: &[u8]) -> bool {
+  let head = &bytes[..bytes.len().min(SNIFF_LEN)];
+  Encoding::for_bom(head).is_none() && head.contains(&0)
+}
+
+fn code_language(extension: &str) -> Option<&'static str> {
+  let language = match extension {
+    "rs" => "rust",
+    "py" => "python",
+    "js" | "mjs" | "cjs" | "jsx" => "javascript",
+    "ts" | "tsx" => "typescript",
+    "go" => "go",
+    "java" => "java",
+    "kt" | "kts" => "kotlin",
+    "c" | "h" => "c",
+    "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+    "cs" => "csharp",
+    "rb" => "ruby",
+    "php" => "php",
+    "swift" => "swift",
+    "scala" => "scala",
+    "sh" | "bash" | "zsh" => "shell",
+    "sql" => "sql",
+    "lua" => "lua",
+    _ => return None,
+  };
+  Some(language)
+}
+
+fn code_mime_type(language: &str) -> &'static str {
+  match language {
+    "rust" => "text/x-rust",
+    "python" => "text/x-python",
+    "javascript" => "text/javascript",
+    "typescript" => "text/x-typescript",
+    "go" => "text/x-go",
+    "java" => "text/x-java",
+    "kotlin" => "text/x-kotlin",
+    "c" => "text/x-c",
+    "cpp" => "text/x-c++",
+    "csharp" => "text/x-csharp",
+    "ruby" => "text/x-ruby",
+    "php" => "text/x-php",
+    "swift" => "text/x-swift",
+    "scala" => "text/x-scala",
+    "shell" => "text/x-shellscript",
+    "sql" => "text/x-sql",
+    "lua" => "text/x-lua",
+    _ => "text/plain",
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::traits::Pipeline;
+
+  fn write(root: &Path, path: &str, content: &[u8]) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+  }
+
+  fn load(loader: &DirectoryLoader, root: &Path) -> Vec<Document<'static>> {
+    let documents = loader.process(root).unwrap();
+    documents.into_iter().map(Result::unwrap).collect()
+  }
+
+  fn ids(documents: &[Document]) -> Vec<String> {
+    documents
+      .iter()
+      .map(|doc| doc.id().unwrap().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn loads_typed_documents() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "README.md", b"# Hello");
+    write(dir.path(), "src/main.rs", b"fn main() {}");
+    write(dir.path(), "notes.txt", b"plain");
+    write(dir.path(), "report.pdf", b"%PDF-1.7");
+
+    let loader = DirectoryLoaderBuilder::default().build().unwrap();
+    let documents = Pipeline::new(loader).run(dir.path()).unwrap();
+    let documents = documents
+      .into_iter()
+      .map(Result::unwrap)
+      .collect::<Vec<_>>();
+
+    assert_eq!(
+      ids(&documents),
+      vec!["README.md", "notes.txt", "report.pdf", "src/main.rs"]
+    );
+    assert!(matches!(documents[0], Document::Markdown(_)));
+    assert!(matches!(documents[1], Document::Text(_)));
+    assert!(matches!(
+      &documents[2],
+      Document::Binary(doc) if doc.format == BinaryFormat::Pdf
+    ));
+    assert!(matches!(
+      &documents[3],
+      Document::Code(doc) if doc.language.as_deref() == Some("rust")
+    ));
+
+    let meta = documents[0].meta();
+    assert_eq!(meta.mime_type.as_deref(), Some("text/markdown"));
+    assert_eq!(meta.extra["size"], 7);
+    assert_eq!(meta.extra["encoding"], "utf-8");
+    assert!(meta.modified_at.is_some());
+    assert!(documents[0].source().unwrap().starts_with("file://"));
+    assert_eq!(documents[3].mime_type(), Some("text/x-rust"));
+  }
+
+  #[test]
+  fn file_uris_and_code_mime_types() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "my notes #1.md", b"# Notes");
+    write(dir.path(), "app.ts", b"const a: number = 1;");
+
+    let loader = DirectoryLoaderBuilder::default().build().unwrap();
+    let documents = load(&loader, dir.path());
+
+    let source = documents[1].source().unwrap();
+    assert!(source.ends_with("/my%20notes%20%231.md"), "{source}");
+    assert!(!source.contains(' '));
+    assert_eq!(documents[0].mime_type(), Some("text/x-typescript"));
+    assert!(matches!(
+      &documents[0],
+      Document::Code(doc) if doc.language.as_deref() == Some("typescript")
+    ));
+  }
+
+  #[test]
+  fn include_and_exclude() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "docs/a.md", b"a");
+    write(dir.path(), "docs/drafts/b.md", b"b");
+    write(dir.path(), "docs/c.txt", b"c");
+
+    let loader = DirectoryLoaderBuilder::default()
+      .include(vec!["docs/**/*.md".to_string()])
+      .exclude(vec!["**/drafts/**".to_string()])
+      .build()
+      .unwrap();
+
+    assert_eq!(ids(&load(&loader, dir.path())), vec!["docs/a.md"]);
+  }
+
+  #[test]
+  fn respects_gitignore() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), ".gitignore", b"target/\n*.log\n");
+    write(dir.path(), "target/out.txt", b"out");
+    write(dir.path(), "debug.log", b"log");
+    write(dir.path(), "kept.txt", b"kept");
+
+    let loader = DirectoryLoaderBuilder::default().build().unwrap();
+    assert_eq!(ids(&load(&loader, dir.path())), vec!["kept.txt"]);
+
+    let loader = DirectoryLoaderBuilder::default()
+      .respect_gitignore(false)
+      .build()
+      .unwrap();
+    assert_eq!(
+      ids(&load(&loader, dir.path())),
+      vec!["debug.log", "kept.txt", "target/out.txt"]
+    );
+  }
+
+  #[test]
+  fn decodes_legacy_encodings() {
+    let dir = tempfile::tempdir().unwrap();
+    // "café" in UTF-16LE with a byte order mark.
+    write(dir.path(), "utf16.txt", b"\xFF\xFEc\0a\0f\0\xE9\0");
+
+    let loader = DirectoryLoaderBuilder::default().build().unwrap();
+    let documents = load(&loader, dir.path());
+    assert_eq!(documents[0].text(), Some("café"));
+    assert_eq!(documents[0].meta().extra["encoding"], "utf-16le");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn unreadable_files() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "a.txt", b"a");
+    std::os::unix::fs::symlink(
+      dir.path().join("missing"),
+      dir.path().join("b"),
+    )
+    .unwrap();
+    write(dir.path(), "c.txt", b"c");
+
+    let loader = DirectoryLoaderBuilder::default()
+      .follow_links(true)
+      .build()
+      .unwrap();
+    let documents = loader.process(dir.path()).unwrap();
+    assert_eq!(documents.len(), 3);
+    assert_eq!(documents[0].as_ref().unwrap().id(), Some("a.txt"));
+    assert!(matches!(documents[1], Err(Error::Io { .. })));
+    assert_eq!(documents[2].as_ref().unwrap().id(), Some("c.txt"));
+  }
+
+  #[test]
+  fn invalid_glob() {
+    let loader = DirectoryLoaderBuilder::default()
+      .include(vec!["a[".to_string()])
+      .build()
+      .unwrap();
+    assert!(matches!(
+      loader.process(Path::new(".")),
+      Err(Error::InvalidGlob(_))
+    ));
+  }
+}
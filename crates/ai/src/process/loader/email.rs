@@ -0,0 +1,660 @@
+use crate::{
+  describe,
+  document::{
+    BinaryDocument,
+    BinaryFormat,
+    Document,
+    DocumentMetadata,
+    HtmlDocument,
+    TextDocument,
+  },
+  error::Error,
+  telemetry,
+  traits::Processor,
+};
+use chrono::{
+  DateTime,
+  Utc,
+};
+use derive_builder::Builder;
+use mail_parser::{
+  mailbox::mbox::MessageIterator,
+  Address,
+  HeaderValue,
+  Message,
+  MessageParser,
+  MimeHeaders,
+  PartType,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+  fs::File,
+  io::{
+    BufRead,
+    BufReader,
+    Read,
+  },
+  path::Path,
+};
+
+/// An email loaded by `EmailLoader`: the message body as a document and its
+/// attachments as child documents.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Email<'a> {
+  pub document: Document<'a>,
+  pub attachments: Vec<Document<'a>>,
+}
+
+/// Loads emails from `.eml` files (RFC 5322 messages) and mbox mailboxes.
+/// Input starting with an mbox `From ` line is read as a mailbox, anything
+/// else as a single message. Like rows of `CsvLoader`, every message is
+/// loaded on its own: a malformed message of a mailbox is an `Err` in the
+/// output and doesn't stop the others from loading.
+///
+/// The body is the `text/plain` part of the message, falling back to the
+/// text of the `text/html` part. Transfer encodings and charsets are decoded.
+/// The document has its `id` set to the `Message-ID`, its `title` to the
+/// subject and `created_at` to the `Date`. The `extra` metadata holds:
+/// - `from`, `to` and `cc`: the addresses, as `Name <address>`.
+/// - `message_id`, `in_reply_to` and `references`.
+/// - `thread_id`: the id of the first message of the thread, taken from
+///   `References`, `In-Reply-To` or the message's own id.
+/// - `thread`: the subject without `Re:`/`Fwd:` prefixes.
+///
+/// Attachments keep their own format, e.g. a pdf attachment is loaded as a
+/// binary pdf document, and have `parent_id` and `filename` set in their
+/// `extra` metadata. Attached messages are loaded as text documents and are
+/// followed by their own attachments, which have the attached message as
+/// their parent.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct EmailLoader {
+  /// Whether to remove quoted replies (`> ` lines, the `On ... wrote:` line
+  /// introducing them and everything after an `Original Message` marker)
+  /// from the body.
+  #[builder(default)]
+  strip_quoted: bool,
+
+  #[builder(default = "true")]
+  attachments: bool,
+}
+
+impl EmailLoader {
+  /// Streams the messages of an mbox mailbox.
+  pub fn mbox<R: BufRead>(&self, reader: R) -> Mbox<R> {
+    Mbox {
+      loader: self.clone(),
+      messages: MessageIterator::new(reader),
+    }
+  }
+
+  /// Streams the messages of the mbox file at `path`.
+  pub fn mbox_file(&self, path: &Path) -> Result<Mbox<BufReader<File>>, Error> {
+    let file = File::open(path).map_err(|err| Error::io(path, err))?;
+    Ok(self.mbox(BufReader::new(file)))
+  }
+
+  /// Loads a single message.
+  pub fn message(&self, data: &[u8]) -> Result<Email<'static>, Error> {
+    let message = MessageParser::default()
+      .parse(data)
+      .ok_or_else(|| Error::malformed("email", "not a valid message"))?;
+    Ok(self.load(&message, message.message_id().map(str::to_string)))
+  }
+
+  fn load_all(
+    &self,
+    data: &[u8],
+  ) -> Result<Vec<Result<Email<'static>, Error>>, Error> {
+    telemetry::processor("EmailLoader", Some(data.len()), || {
+      if is_mbox(data) {
+        Ok(self.mbox(data).collect())
+      } else {
+        Ok(vec![Ok(self.message(data)?)])
+      }
+    })
+  }
+
+  /// Loads `message` as the document with the given `id`, attachments get ids
+  /// below it.
+  fn load(&self, message: &Message, id: Option<String>) -> Email<'static> {
+    let mut meta = message_meta(message);
+    meta.id = id;
+    meta.mime_type = Some("message/rfc822".to_string());
+
+    let mut body = (0..message.text_body_count())
+      .filter_map(|index| message.body_text(index))
+      .map(|text| text.replace("\r\n", "\n").trim_end().to_string())
+      .filter(|text| !text.is_empty())
+      .collect::<Vec<_>>()
+      .join("\n\n");
+    if self.strip_quoted {
+      body = strip_quoted(&body);
+    }
+
+    let mut attachments = vec![];
+    if self.attachments {
+      for (index, part) in message.attachments().enumerate() {
+        let filename = part.attachment_name().map(str::to_string);
+        let id = meta.id.as_ref().map(|parent| {
+          let name = filename.clone().unwrap_or_else(|| index.to_string());
+          format!("{parent}/{name}")
+        });
+        let mime_type = part.content_type().map(|content_type| {
+          match content_type.subtype() {
+            Some(subtype) => format!("{}/{subtype}", content_type.ctype()),
+            None => content_type.ctype().to_string(),
+          }
+        });
+
+        let mut nested_attachments = vec![];
+        let document = match &part.body {
+          PartType::Text(text) => {
+            TextDocument::new(text.to_string()).as_document()
+          }
+          PartType::Html(html) => {
+            HtmlDocument::new(html.to_string()).as_document()
+          }
+          PartType::Binary(data) | PartType::InlineBinary(data) => {
+            let format =
+              attachment_format(mime_type.as_deref(), filename.as_deref());
+            BinaryDocument::new(data.to_vec(), format).as_document()
+          }
+          PartType::Message(attached) => {
+            // Attached messages get their own metadata, with the subject as
+            // the title.
+            let id = id.clone().or(attached.message_id().map(str::to_string));
+            let nested = self.load(attached, id);
+            nested_attachments = nested.attachments;
+            nested.document
+          }
+          PartType::Multipart(_) => continue,
+        };
+
+        let mut attachment_meta = document.meta().clone();
+        attachment_meta.mime_type = mime_type.or(attachment_meta.mime_type);
+        if let Some(parent) = &meta.id {
+          attachment_meta.id = id;
+          attachment_meta
+            .extra
+            .insert("parent_id".into(), parent.clone().into());
+        }
+        if let Some(filename) = filename {
+          attachment_meta
+            .title
+            .get_or_insert_with(|| filename.clone());
+          attachment_meta
+            .extra
+            .insert("filename".into(), filename.into());
+        }
+        attachments.push(document.with_meta(attachment_meta));
+        attachments.append(&mut nested_attachments);
+      }
+    }
+
+    Email {
+      document: TextDocument::new(body).with_meta(meta).as_document(),
+      attachments,
+    }
+  }
+}
+
+impl<'p> Processor<&'p Path, Vec<Result<Email<'static>, Error>>>
+  for EmailLoader
+{
+  fn process(
+    &self,
+    path: &'p Path,
+  ) -> Result<Vec<Result<Email<'static>, Error>>, Error> {
+    let io_error = |err| Error::io(path, err);
+    let file = File::open(path).map_err(io_error)?;
+    let size = file.metadata().map_err(io_error)?.len();
+    let mut reader = BufReader::new(file);
+    telemetry::processor("EmailLoader", Some(size as usize), || {
+      // Mailboxes can be large, stream them instead of reading them whole.
+      if is_mbox(reader.fill_buf().map_err(io_error)?) {
+        return Ok(self.mbox(reader).collect());
+      }
+      let mut data = vec![];
+      reader.read_to_end(&mut data).map_err(io_error)?;
+      Ok(vec![Ok(self.message(&data)?)])
+    })
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p> Processor<&'p [u8], Vec<Result<Email<'static>, Error>>>
+  for EmailLoader
+{
+  fn process(
+    &self,
+    input: &'p [u8],
+  ) -> Result<Vec<Result<Email<'static>, Error>>, Error> {
+    self.load_all(input)
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p Document<'d>, Vec<Result<Email<'static>, Error>>>
+  for EmailLoader
+{
+  fn process(
+    &self,
+    input: &'p Document<'d>,
+  ) -> Result<Vec<Result<Email<'static>, Error>>, Error> {
+    match input {
+      Document::Binary(binary) => self.load_all(&binary.data),
+      document => match document.text() {
+        Some(text) => self.load_all(text.as_bytes()),
+        None => Err(Error::UnsupportedDocument(
+          "expected an email document".to_string(),
+        )),
+      },
+    }
+  }
+
+  describe::impl_config!();
+}
+
+/// An iterator over the messages of an mbox mailbox, see `EmailLoader::mbox`.
+pub struct Mbox<R> {
+  loader: EmailLoader,
+  messages: MessageIterator<R>,
+}
+
+impl<R: BufRead> Iterator for Mbox<R> {
+  type Item = Result<Email<'static>, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let message = match self.messages.next()? {
+      Ok(message) => message,
+      Err(err) => return Some(Err(Error::parse("mbox", err))),
+    };
+    Some(self.loader.message(message.contents()))
+  }
+}
+
+fn is_mbox(data: &[u8]) -> bool {
+  data.starts_with(b"From ")
+}
+
+fn message_meta(message: &Message) -> DocumentMetadata {
+  let mut meta = DocumentMetadata {
+    id: message.message_id().map(str::to_string),
+    title: message.subject().map(str::to_string),
+    created_at: message
+      .date()
+      .and_then(|date| DateTime::<Utc>::from_timestamp(date.to_timestamp(), 0)),
+    ..Default::default()
+  };
+
+  let extra = &mut meta.extra;
+  if let Some(from) = message
+    .from()
+    .and_then(|from| addresses(from).into_iter().next())
+  {
+    extra.insert("from".into(), from.into());
+  }
+  for (key, address) in [("to", message.to()), ("cc", message.cc())] {
+    if let Some(address) = address {
+      extra.insert(key.into(), addresses(address).into());
+    }
+  }
+  if let Some(id) = message.message_id() {
+    extra.insert("message_id".into(), id.into());
+  }
+  let in_reply_to = ids(message.in_reply_to());
+  let references = ids(message.references());
+  let thread_id = references
+    .first()
+    .or(in_reply_to.first())
+    .map(String::as_str)
+    .or(message.message_id());
+  if let Some(thread_id) = thread_id {
+    extra.insert("thread_id".into(), thread_id.into());
+  }
+  if let Some(in_reply_to) = in_reply_to.first() {
+    extra.insert("in_reply_to".into(), in_reply_to.clone().into());
+  }
+  if !references.is_empty() {
+    extra.insert("references".into(), Value::from(references));
+  }
+  if let Some(thread) = message.thread_name() {
+    extra.insert("thread".into(), thread.into());
+  }
+  meta
+}
+
+/// Formats addresses as `Name <address>`, or just the address when there's
+/// no name.
+fn addresses(address: &Address) -> Vec<String> {
+  address
+    .iter()
+    .filter_map(|addr| match (addr.name(), addr.address()) {
+      (Some(name), Some(address)) => Some(format!("{name} <{address}>")),
+      (None, Some(address)) => Some(address.to_string()),
+      (Some(name), None) => Some(name.to_string()),
+      (None, None) => None,
+    })
+    .collect()
+}
+
+fn ids(value: &HeaderValue) -> Vec<String> {
+  match value {
+    HeaderValue::Text(id) => vec![id.to_string()],
+    HeaderValue::TextList(ids) => ids.iter().map(|id| id.to_string()).collect(),
+    _ => vec![],
+  }
+}
+
+fn attachment_format(
+  mime_type: Option<&str>,
+  filename: Option<&str>,
+) -> BinaryFormat {
+  let format = mime_type
+    .map(BinaryFormat::from_mime_type)
+    .unwrap_or(BinaryFormat::Other);
+  if format != BinaryFormat::Other {
+    return format;
+  }
+  // Attachments are often sent as `application/octet-stream`.
+  filename
+    .and_then(|filename| mime_guess::from_path(filename).first())
+    .map(|mime| BinaryFormat::from_mime_type(mime.essence_str()))
+    .unwrap_or(BinaryFormat::Other)
+}
+
+/// Removes quoted replies from a message body.
+fn strip_quoted(body: &str) -> String {
+  let lines = body.lines().collect::<Vec<_>>();
+  let is_quoted = |line: &str| line.trim_start().starts_with('>');
+  let mut kept = vec![];
+  for (index, line) in lines.iter().enumerate() {
+    let trimmed = line.trim();
+    if is_original_message(trimmed) {
+      break;
+    }
+    if is_quoted(line) {
+      continue;
+    }
+    // The attribution line, e.g. `On Mon, 1 Jan 2024, Ann wrote:`.
+    if trimmed.ends_with("wrote:") {
+      let next = lines[index + 1..]
+        .iter()
+        .find(|line| !line.trim().is_empty());
+      if next.is_some_and(|line| is_quoted(line)) {
+        continue;
+      }
+    }
+    kept.push(*line);
+  }
+
+  // Collapse the blank lines left behind by removed quotes.
+  let mut stripped = String::new();
+  let mut blank = false;
+  for line in kept {
+    if line.trim().is_empty() {
+      blank = !stripped.is_empty();
+      continue;
+    }
+    if blank {
+      stripped.push('\n');
+      blank = false;
+    }
+    stripped.push_str(line);
+    stripped.push('\n');
+  }
+  stripped.truncate(stripped.trim_end().len());
+  stripped
+}
+
+fn is_original_message(line: &str) -> bool {
+  let marker = line.trim_matches('-').trim();
+  (line.starts_with("--") && marker.eq_ignore_ascii_case("original message"))
+    || (line.len() >= 16 && line.chars().all(|c| c == '_'))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const REPLY: &str = "From: Ann Smith <ann@example.com>\r\n\
+To: bob@example.com, Carl <carl@example.com>\r\n\
+Subject: Re: Lunch\r\n\
+Date: Mon, 1 Jan 2024 12:30:00 +0000\r\n\
+Message-ID: <2@example.com>\r\n\
+In-Reply-To: <1@example.com>\r\n\
+References: <1@example.com>\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=\"inner\"\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=iso-8859-1\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Sounds good, caf=E9 at noon?\r\n\
+\r\n\
+On Sun, 31 Dec 2023, Bob wrote:\r\n\
+> Lunch tomorrow?\r\n\
+--inner\r\n\
+Content-Type: text/html; charset=utf-8\r\n\
+\r\n\
+<p>Sounds good, caf&eacute; at noon?</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/octet-stream; name=\"menu.pdf\"\r\n\
+Content-Disposition: attachment; filename=\"menu.pdf\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0xLjQ=\r\n\
+--outer--\r\n";
+
+  #[test]
+  fn loads_message() {
+    let loader = EmailLoaderBuilder::default().build().unwrap();
+    let emails = loader.process(REPLY.as_bytes()).unwrap();
+    assert_eq!(emails.len(), 1);
+    let emails = emails.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+
+    let Email {
+      document,
+      attachments,
+    } = &emails[0];
+    assert_eq!(
+      document.text(),
+      Some("Sounds good, café at noon?\n\nOn Sun, 31 Dec 2023, Bob wrote:\n> Lunch tomorrow?")
+    );
+    assert_eq!(document.id(), Some("2@example.com"));
+    assert_eq!(document.title(), Some("Re: Lunch"));
+    assert_eq!(
+      document.meta().created_at.unwrap().to_rfc3339(),
+      "2024-01-01T12:30:00+00:00"
+    );
+
+    let extra = &document.meta().extra;
+    assert_eq!(extra["from"], "Ann Smith <ann@example.com>");
+    assert_eq!(
+      extra["to"],
+      serde_json::json!(["bob@example.com", "Carl <carl@example.com>"])
+    );
+    assert_eq!(extra["in_reply_to"], "1@example.com");
+    assert_eq!(extra["thread_id"], "1@example.com");
+    assert_eq!(extra["thread"], "Lunch");
+
+    assert_eq!(attachments.len(), 1);
+    let Document::Binary(pdf) = &attachments[0] else {
+      panic!("expected a binary attachment");
+    };
+    assert_eq!(pdf.format, BinaryFormat::Pdf);
+    assert_eq!(&pdf.data[..], b"%PDF-1.4");
+    assert_eq!(pdf.meta.id.as_deref(), Some("2@example.com/menu.pdf"));
+    assert_eq!(pdf.meta.extra["parent_id"], "2@example.com");
+  }
+
+  #[test]
+  fn strips_quoted_replies() {
+    let loader = EmailLoaderBuilder::default()
+      .strip_quoted(true)
+      .attachments(false)
+      .build()
+      .unwrap();
+    let email = loader.message(REPLY.as_bytes()).unwrap();
+    assert_eq!(email.document.text(), Some("Sounds good, café at noon?"));
+    assert!(email.attachments.is_empty());
+
+    let body = "Yes.\n\n-----Original Message-----\nFrom: Bob\n\nLunch?";
+    assert_eq!(strip_quoted(body), "Yes.");
+  }
+
+  #[test]
+  fn falls_back_to_html() {
+    let html = "From: ann@example.com\r\n\
+Subject: News\r\n\
+Content-Type: text/html; charset=utf-8\r\n\
+\r\n\
+<html><body><h1>Hello</h1><p>World</p></body></html>\r\n";
+    let loader = EmailLoaderBuilder::default().build().unwrap();
+    let email = loader.message(html.as_bytes()).unwrap();
+    let text = email.document.text().unwrap();
+    assert!(text.contains("Hello"));
+    assert!(text.contains("World"));
+    assert!(!text.contains('<'));
+  }
+
+  #[test]
+  fn loads_mbox() {
+    let mbox = "From ann@example.com Mon Jan  1 12:00:00 2024\n\
+From: ann@example.com\n\
+Subject: First\n\
+Message-ID: <1@example.com>\n\
+\n\
+One\n\
+>From here on\n\
+\n\
+From bob@example.com Mon Jan  1 13:00:00 2024\n\
+From: bob@example.com\n\
+Subject: Re: First\n\
+Message-ID: <2@example.com>\n\
+In-Reply-To: <1@example.com>\n\
+\n\
+Two\n";
+    let loader = EmailLoaderBuilder::default().build().unwrap();
+    let emails = loader.process(mbox.as_bytes()).unwrap();
+    let emails = emails.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(emails.len(), 2);
+    assert_eq!(emails[0].document.text(), Some("One\nFrom here on"));
+    assert_eq!(emails[1].document.text(), Some("Two"));
+    assert_eq!(
+      emails[1].document.meta().extra["thread_id"],
+      "1@example.com"
+    );
+  }
+
+  #[test]
+  fn nested_attachments() {
+    let forward = format!(
+      "From: carl@example.com\r\n\
+Subject: Fwd: Lunch\r\n\
+Message-ID: <3@example.com>\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed; boundary=\"fwd\"\r\n\
+\r\n\
+--fwd\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+See below.\r\n\
+--fwd\r\n\
+Content-Type: message/rfc822\r\n\
+Content-Disposition: attachment; filename=\"lunch.eml\"\r\n\
+\r\n\
+{REPLY}\r\n\
+--fwd--\r\n"
+    );
+    let loader = EmailLoaderBuilder::default().build().unwrap();
+    let email = loader.message(forward.as_bytes()).unwrap();
+
+    let ids = email
+      .attachments
+      .iter()
+      .map(|attachment| {
+        let parent = &attachment.meta().extra["parent_id"];
+        (attachment.id().unwrap(), parent.as_str().unwrap())
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      ids,
+      vec![
+        ("3@example.com/lunch.eml", "3@example.com"),
+        (
+          "3@example.com/lunch.eml/menu.pdf",
+          "3@example.com/lunch.eml"
+        ),
+      ]
+    );
+    assert_eq!(email.attachments[0].title(), Some("Re: Lunch"));
+    assert_eq!(
+      email.attachments[0].meta().extra["message_id"],
+      "2@example.com"
+    );
+  }
+
+  #[test]
+  fn streams_mbox_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("inbox.mbox");
+    std::fs::write(
+      &path,
+      "From ann@example.com Mon Jan  1 12:00:00 2024\n\
+From: ann@example.com\n\
+\n\
+One\n\
+\n\
+From bob@example.com Mon Jan  1 13:00:00 2024\n\
+From: bob@example.com\n\
+\n\
+Two\n",
+    )
+    .unwrap();
+    let loader = EmailLoaderBuilder::default().build().unwrap();
+    let emails = loader.process(path.as_path()).unwrap();
+    let emails = emails.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+    let texts = emails
+      .iter()
+      .map(|email| email.document.text().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(texts, vec!["One", "Two"]);
+
+    let path = dir.path().join("reply.eml");
+    std::fs::write(&path, REPLY).unwrap();
+    let emails = loader.process(path.as_path()).unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(
+      emails[0].as_ref().unwrap().document.id(),
+      Some("2@example.com")
+    );
+  }
+
+  #[test]
+  fn skips_malformed_messages() {
+    let mbox = "From ann@example.com Mon Jan  1 12:00:00 2024\n\
+From bob@example.com Mon Jan  1 13:00:00 2024\n\
+From: bob@example.com\n\
+Subject: Second\n\
+\n\
+Two\n";
+    let loader = EmailLoaderBuilder::default().build().unwrap();
+    let emails = loader.process(mbox.as_bytes()).unwrap();
+    // The first message is empty.
+    assert_eq!(emails.len(), 2);
+    assert!(matches!(emails[0], Err(Error::Parse { .. })));
+    let email = emails[1].as_ref().unwrap();
+    assert_eq!(email.document.text(), Some("Two"));
+  }
+}
@@ -0,0 +1,458 @@
+use super::{
+  archive::{
+    resolve,
+    Archive,
+    DEFAULT_MAX_ENTRY_SIZE,
+  },
+  html,
+  xml::Node,
+  Loaded,
+  LoadedBuilder,
+};
+use crate::{
+  describe,
+  document::{
+    BinaryDocument,
+    Document,
+    DocumentMetadata,
+  },
+  element::Element,
+  error::Error,
+  telemetry,
+  traits::Processor,
+};
+use derive_builder::Builder;
+use serde::Serialize;
+use std::collections::HashMap;
+
+const EPUB_MIME_TYPE: &str = "application/epub+zip";
+
+/// Loads EPUB 2 and 3 e-books. Chapters are read in spine order and their
+/// XHTML is converted into typed elements (titles, paragraphs, list items,
+/// tables and code blocks).
+///
+/// Every element is tagged with:
+/// - `chapter`: the title of its chapter, taken from the table of contents,
+///   falling back to the first heading of the chapter.
+/// - `toc_path`: the titles of the chapter and its ancestors in the table of
+///   contents, joined with ` > `, e.g. `Part I > Getting Started`.
+/// - `section`: the 1-based position of the chapter in the spine.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct EpubLoader {
+  /// Inserted between the text of consecutive blocks.
+  #[builder(default = "\"\\n\\n\".to_string()")]
+  separator: String,
+
+  /// Entries of the archive decompressing to more bytes than this are
+  /// rejected, to guard against zip bombs.
+  #[builder(default = "DEFAULT_MAX_ENTRY_SIZE")]
+  max_entry_size: u64,
+}
+
+impl<'p> Processor<&'p [u8], Loaded<'static>> for EpubLoader {
+  fn process(&self, input: &'p [u8]) -> Result<Loaded<'static>, Error> {
+    self.load(input, DocumentMetadata::default())
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p BinaryDocument<'d>, Loaded<'static>> for EpubLoader {
+  fn process(
+    &self,
+    input: &'p BinaryDocument<'d>,
+  ) -> Result<Loaded<'static>, Error> {
+    self.load(&input.data, input.meta.clone())
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p Document<'d>, Loaded<'static>> for EpubLoader {
+  fn process(&self, input: &'p Document<'d>) -> Result<Loaded<'static>, Error> {
+    match input {
+      Document::Binary(binary) => self.process(binary),
+      _ => Err(Error::UnsupportedDocument(
+        "expected a binary epub document".to_string(),
+      )),
+    }
+  }
+
+  describe::impl_config!();
+}
+
+/// An entry of the table of contents.
+#[derive(Debug)]
+struct TocEntry {
+  /// Titles from the outermost entry down to this one.
+  path: Vec<String>,
+  /// The zip entry the toc entry points to, without fragment.
+  file: String,
+}
+
+impl EpubLoader {
+  fn load(
+    &self,
+    data: &[u8],
+    meta: DocumentMetadata,
+  ) -> Result<Loaded<'static>, Error> {
+    telemetry::processor("EpubLoader", Some(data.len()), || {
+      self.read(data, meta)
+    })
+  }
+
+  fn read(
+    &self,
+    data: &[u8],
+    mut meta: DocumentMetadata,
+  ) -> Result<Loaded<'static>, Error> {
+    let mut archive = Archive::new("epub", data, self.max_entry_size)?;
+
+    let container = archive.require_xml("META-INF/container.xml")?;
+    let opf_path = container
+      .find("rootfile")
+      .and_then(|rootfile| rootfile.attr("full-path"))
+      .ok_or_else(|| Error::malformed("epub", "missing rootfile"))?
+      .to_string();
+    let opf = archive.require_xml(&opf_path)?;
+
+    // Manifest id -> (path, media type, properties).
+    let manifest: HashMap<&str, (String, &str, &str)> = opf
+      .find("manifest")
+      .map(|manifest| manifest.find_all("item"))
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|item| {
+        let href = percent_decode(item.attr("href")?);
+        Some((
+          item.attr("id")?,
+          (
+            resolve(&opf_path, &href),
+            item.attr("media-type").unwrap_or_default(),
+            item.attr("properties").unwrap_or_default(),
+          ),
+        ))
+      })
+      .collect();
+
+    let spine = opf.find("spine");
+    let toc = self.toc(&mut archive, &manifest, spine)?;
+
+    let mut builder = LoadedBuilder::new(&self.separator);
+    let itemrefs = spine
+      .map(|spine| spine.find_all("itemref"))
+      .unwrap_or_default();
+    for (index, itemref) in itemrefs.into_iter().enumerate() {
+      let Some((path, _, _)) =
+        itemref.attr("idref").and_then(|id| manifest.get(id))
+      else {
+        continue;
+      };
+      let Some(xhtml) = archive.read_string(path)? else {
+        continue;
+      };
+      let chapter = html::parse("epub", &xhtml)?;
+      let body = chapter.find("body").unwrap_or(&chapter);
+
+      let toc_path = toc
+        .iter()
+        .find(|entry| &entry.file == path)
+        .map(|entry| entry.path.clone())
+        .or_else(|| {
+          let heading = ["h1", "h2", "h3"]
+            .iter()
+            .find_map(|h| body.find(h))
+            .or_else(|| chapter.find("title"))
+            .map(html::text)
+            .filter(|title| !title.is_empty());
+          heading.map(|title| vec![title])
+        })
+        .unwrap_or_default();
+
+      let section = index + 1;
+      let tag = |element: Element<'static>| {
+        let mut element = element.with_tag("section", section);
+        if let Some(title) = toc_path.last() {
+          element = element
+            .with_tag("chapter", title.clone())
+            .with_tag("toc_path", toc_path.join(" > "));
+        }
+        element
+      };
+      html::push_blocks(body, &mut builder, &tag);
+    }
+
+    meta.mime_type = Some(EPUB_MIME_TYPE.to_string());
+    if let Some(metadata) = opf.find("metadata") {
+      let text = |name: &str| {
+        metadata
+          .find(name)
+          .map(|node| node.text().trim().to_string())
+          .filter(|text| !text.is_empty())
+      };
+      if meta.title.is_none() {
+        meta.title = text("title");
+      }
+      if let Some(creator) = text("creator") {
+        meta.extra.insert("creator".into(), creator.into());
+      }
+      if let Some(language) = text("language") {
+        meta.extra.insert("language".into(), language.into());
+      }
+    }
+    Ok(builder.finish(meta))
+  }
+
+  /// Reads the EPUB 3 navigation document, falling back to the EPUB 2 NCX.
+  fn toc(
+    &self,
+    archive: &mut Archive,
+    manifest: &HashMap<&str, (String, &str, &str)>,
+    spine: Option<&Node>,
+  ) -> Result<Vec<TocEntry>, Error> {
+    let nav = manifest.values().find(|(_, _, properties)| {
+      properties.split_whitespace().any(|p| p == "nav")
+    });
+    if let Some((path, _, _)) = nav {
+      if let Some(xhtml) = archive.read_string(path)? {
+        let nav = html::parse("epub", &xhtml)?;
+        let navs = nav.find_all("nav");
+        let toc = navs
+          .iter()
+          .find(|nav| nav.attr("type") == Some("toc"))
+          .or(navs.first());
+        if let Some(list) = toc.and_then(|toc| toc.find("ol")) {
+          let mut entries = vec![];
+          nav_entries(list, path, &[], &mut entries);
+          return Ok(entries);
+        }
+      }
+    }
+
+    let ncx = spine
+      .and_then(|spine| spine.attr("toc"))
+      .and_then(|id| manifest.get(id))
+      .or_else(|| {
+        let mut items = manifest.values();
+        items
+          .find(|(_, media_type, _)| *media_type == "application/x-dtbncx+xml")
+      });
+    if let Some((path, _, _)) = ncx {
+      if let Some(ncx) = archive.read_xml(path)? {
+        let mut entries = vec![];
+        if let Some(map) = ncx.find("navMap") {
+          ncx_entries(map, path, &[], &mut entries);
+        }
+        return Ok(entries);
+      }
+    }
+
+    Ok(vec![])
+  }
+}
+
+fn nav_entries(
+  list: &Node,
+  base: &str,
+  parents: &[String],
+  entries: &mut Vec<TocEntry>,
+) {
+  for item in list.nodes().filter(|item| item.name == "li") {
+    let label = item
+      .nodes()
+      .find(|node| node.name == "a" || node.name == "span");
+    let title = label.map(html::text).unwrap_or_default();
+    let mut path = parents.to_vec();
+    path.push(title);
+
+    if let Some(href) = label.and_then(|label| label.attr("href")) {
+      entries.push(TocEntry {
+        path: path.clone(),
+        file: resolve(base, &percent_decode(href)),
+      });
+    }
+    if let Some(nested) = item.child("ol") {
+      nav_entries(nested, base, &path, entries);
+    }
+  }
+}
+
+fn ncx_entries(
+  map: &Node,
+  base: &str,
+  parents: &[String],
+  entries: &mut Vec<TocEntry>,
+) {
+  for point in map.nodes().filter(|point| point.name == "navPoint") {
+    let title = point
+      .child("navLabel")
+      .map(|label| label.text().trim().to_string())
+      .unwrap_or_default();
+    let mut path = parents.to_vec();
+    path.push(title);
+
+    if let Some(src) = point.child("content").and_then(|c| c.attr("src")) {
+      entries.push(TocEntry {
+        path: path.clone(),
+        file: resolve(base, &percent_decode(src)),
+      });
+    }
+    ncx_entries(point, base, &path, entries);
+  }
+}
+
+/// Decodes `%XX` escapes in hrefs. Invalid escapes are kept as they are.
+fn percent_decode(href: &str) -> String {
+  let bytes = href.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let hex = bytes
+      .get(i + 1..i + 3)
+      .and_then(|hex| std::str::from_utf8(hex).ok())
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match (bytes[i], hex) {
+      (b'%', Some(byte)) => {
+        decoded.push(byte);
+        i += 3;
+      }
+      (byte, _) => {
+        decoded.push(byte);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::process::loader::archive::zip;
+
+  const CONTAINER: &str = r#"<container><rootfiles>
+    <rootfile full-path="OEBPS/content.opf"/>
+  </rootfiles></container>"#;
+
+  fn chapter(body: &str) -> String {
+    format!(
+      r#"<?xml version="1.0"?><!DOCTYPE html>
+      <html xmlns="http://www.w3.org/1999/xhtml"><head><title>t</title></head>
+      <body>{body}</body></html>"#
+    )
+  }
+
+  fn tags(loaded: &Loaded, key: &str) -> Vec<String> {
+    loaded
+      .elements
+      .iter()
+      .map(|el| el.tag(key).unwrap().value.to_string())
+      .collect()
+  }
+
+  #[test]
+  fn epub3() {
+    let opf = r#"<package xmlns:dc="dc"><metadata>
+        <dc:title>Rust in Action</dc:title><dc:creator>Tim</dc:creator>
+      </metadata>
+      <manifest>
+        <item id="nav" href="nav.xhtml" properties="nav"/>
+        <item id="c1" href="text/intro.xhtml"/>
+        <item id="c2" href="text/setup%20guide.xhtml"/>
+      </manifest>
+      <spine><itemref idref="c2"/><itemref idref="c1"/></spine>
+    </package>"#;
+    let nav = chapter(
+      r#"<nav epub:type="toc" xmlns:epub="e"><ol>
+        <li><span>Part I</span><ol>
+          <li><a href="text/setup%20guide.xhtml#top">Setup</a></li>
+        </ol></li>
+        <li><a href="text/intro.xhtml">Intro</a></li>
+      </ol></nav>"#,
+    );
+    let setup = chapter(
+      r#"<h1>Setup</h1><p>Install   the
+        <em>toolchain</em>&nbsp;first.</p>
+        <ol><li>Download<ul><li>Linux</li></ul></li></ol>"#,
+    );
+    let intro = chapter(
+      r#"<div>Hello<br/>world</div>
+        <pre><code class="hljs language-rust">fn main() {}</code></pre>"#,
+    );
+    let data = zip(&[
+      ("META-INF/container.xml", CONTAINER),
+      ("OEBPS/content.opf", opf),
+      ("OEBPS/nav.xhtml", &nav),
+      ("OEBPS/text/setup guide.xhtml", &setup),
+      ("OEBPS/text/intro.xhtml", &intro),
+    ]);
+
+    let loader = EpubLoaderBuilder::default().build().unwrap();
+    let loaded = loader.process(data.as_slice()).unwrap();
+
+    assert_eq!(
+      loaded.document.text(),
+      Some(
+        "Setup\n\nInstall the toolchain first.\n\nDownload\n\nLinux\n\n\
+         Hello\nworld\n\nfn main() {}"
+      )
+    );
+    assert!(matches!(loaded.elements[0], Element::Title(_)));
+    assert!(matches!(
+      &loaded.elements[3],
+      Element::ListItem(item) if item.depth == 1 && !item.ordered
+    ));
+    assert!(matches!(
+      &loaded.elements[5],
+      Element::CodeBlock(code) if code.language.as_deref() == Some("rust")
+    ));
+    assert_eq!(tags(&loaded, "section"), vec!["1", "1", "1", "1", "2", "2"]);
+    assert_eq!(
+      tags(&loaded, "toc_path"),
+      vec![
+        "Part I > Setup",
+        "Part I > Setup",
+        "Part I > Setup",
+        "Part I > Setup",
+        "Intro",
+        "Intro"
+      ]
+    );
+    assert_eq!(tags(&loaded, "chapter")[4], "Intro");
+    assert_eq!(loaded.document.title(), Some("Rust in Action"));
+    assert_eq!(loaded.document.meta().extra["creator"], "Tim");
+  }
+
+  #[test]
+  fn epub2_ncx() {
+    let opf = r#"<package><metadata/>
+      <manifest>
+        <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+        <item id="c1" href="one.html"/>
+        <item id="c2" href="two.html"/>
+      </manifest>
+      <spine toc="ncx"><itemref idref="c1"/><itemref idref="c2"/></spine>
+    </package>"#;
+    let ncx = r#"<ncx><navMap>
+      <navPoint><navLabel><text>Chapter 1</text></navLabel>
+        <content src="one.html"/></navPoint>
+    </navMap></ncx>"#;
+    let data = zip(&[
+      ("META-INF/container.xml", CONTAINER),
+      ("OEBPS/content.opf", opf),
+      ("OEBPS/toc.ncx", ncx),
+      ("OEBPS/one.html", &chapter("<p>first</p>")),
+      ("OEBPS/two.html", &chapter("<h2>Appendix</h2><p>second</p>")),
+    ]);
+
+    let loader = EpubLoaderBuilder::default().build().unwrap();
+    let loaded = loader.process(data.as_slice()).unwrap();
+    assert_eq!(
+      tags(&loaded, "chapter"),
+      vec!["Chapter 1", "Appendix", "Appendix"]
+    );
+    assert_eq!(loaded.document.mime_type(), Some(EPUB_MIME_TYPE));
+  }
+}
@@ -0,0 +1,576 @@
+use super::{
+  directory::{
+    build_glob_set,
+    typed_document,
+  },
+  file_uri,
+};
+use crate::{
+  describe,
+  document::{
+    Document,
+    DocumentMetadata,
+  },
+  error::Error,
+  telemetry,
+  traits::Processor,
+};
+use chrono::{
+  DateTime,
+  Utc,
+};
+use derive_builder::Builder;
+use git2::{
+  Commit,
+  Delta,
+  DiffFile,
+  FileMode,
+  ObjectType,
+  Oid,
+  Repository,
+  Sort,
+  Tree,
+  TreeWalkMode,
+  TreeWalkResult,
+};
+use globset::GlobSet;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+  collections::HashMap,
+  path::Path,
+  sync::Arc,
+};
+
+/// Loads the files of a local git repository as they are at `revision`,
+/// reading them from the object database rather than the working tree.
+///
+/// Include and exclude patterns are globs matched against the path in the
+/// repository, like for `DirectoryLoader`. Symlinks and submodules are
+/// skipped. Documents are ordered by path and have their `id` set to that
+/// path and `modified_at` to the time of the last commit that changed the
+/// file. The `extra` metadata holds:
+/// - `path` and `size`.
+/// - `blob_id`: the id of the file's blob, which changes whenever the content
+///   does.
+/// - `revision`: the id of the commit the file was read from.
+/// - `last_commit`: the `id`, `author`, `email`, `time` and `summary` of the
+///   last commit that changed the file.
+///
+/// `changes` lists the documents added, modified and deleted between two
+/// revisions, to update an index without reloading the whole repository.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct GitLoader {
+  /// The revision to load, anything `git rev-parse` understands.
+  #[builder(default = "\"HEAD\".to_string()")]
+  revision: String,
+
+  /// Glob patterns of files to include.
+  #[builder(default)]
+  include: Vec<String>,
+
+  /// Glob patterns of files to exclude.
+  #[builder(default)]
+  exclude: Vec<String>,
+
+  /// Files larger than this many bytes are skipped.
+  #[builder(default, setter(strip_option))]
+  max_file_size: Option<u64>,
+
+  /// Whether to look up the last commit of every file. This walks the
+  /// history of the repository, which can be slow for large repositories.
+  #[builder(default = "true")]
+  last_commit: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+  Added,
+  Modified,
+  Deleted,
+}
+
+/// A change to a document between two revisions. Deleted documents have no
+/// `document`, the others have it as it is at the newer revision.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+  pub kind: ChangeKind,
+  pub path: String,
+  pub document: Option<Document<'static>>,
+}
+
+impl<'p> Processor<&'p Path, Vec<Document<'static>>> for GitLoader {
+  fn process(&self, repo: &'p Path) -> Result<Vec<Document<'static>>, Error> {
+    telemetry::processor("GitLoader", None, || self.load(repo))
+  }
+
+  describe::impl_config!();
+}
+
+impl GitLoader {
+  fn load(&self, repo: &Path) -> Result<Vec<Document<'static>>, Error> {
+    let repo = Repository::open(repo).map_err(git_error)?;
+    let filter = self.filter()?;
+    let commit = resolve(&repo, &self.revision)?;
+    let tree = commit.tree().map_err(git_error)?;
+
+    let mut files = vec![];
+    let mut walk_error = None;
+    tree
+      .walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() != Some(ObjectType::Blob) {
+          return TreeWalkResult::Ok;
+        }
+        let Some(name) = entry.name() else {
+          return TreeWalkResult::Ok;
+        };
+        let path = format!("{dir}{name}");
+        match filter.accepts(&repo, &path, entry.filemode(), entry.id()) {
+          Ok(true) => files.push((path, entry.id())),
+          Ok(false) => {}
+          Err(err) => {
+            walk_error = Some(err);
+            return TreeWalkResult::Abort;
+          }
+        }
+        TreeWalkResult::Ok
+      })
+      .map_err(|err| walk_error.take().unwrap_or_else(|| git_error(err)))?;
+    files.sort();
+
+    let paths = files.iter().map(|(path, _)| path.as_str());
+    let last_commits = self.last_commits(&repo, &commit, paths)?;
+    files
+      .iter()
+      .map(|(path, id)| {
+        load_blob(&repo, &commit, path, *id, last_commits.get(path.as_str()))
+      })
+      .collect()
+  }
+}
+
+impl GitLoader {
+  /// The documents added, modified and deleted in the repository at `repo`
+  /// between the revisions `from` and `to`, ordered by path. Renamed files
+  /// show up as a deletion and an addition.
+  pub fn changes(
+    &self,
+    repo: &Path,
+    from: &str,
+    to: &str,
+  ) -> Result<Vec<Change>, Error> {
+    let repo = Repository::open(repo).map_err(git_error)?;
+    let filter = self.filter()?;
+    let from_tree = resolve(&repo, from)?.tree().map_err(git_error)?;
+    let to_commit = resolve(&repo, to)?;
+    let to_tree = to_commit.tree().map_err(git_error)?;
+    let diff = repo
+      .diff_tree_to_tree(Some(&from_tree), Some(&to_tree), None)
+      .map_err(git_error)?;
+
+    let mut deleted = vec![];
+    let mut changed = vec![];
+    for delta in diff.deltas() {
+      let old = tracked_path(&repo, &filter, &delta.old_file())?;
+      let new = tracked_path(&repo, &filter, &delta.new_file())?;
+      match (old, new) {
+        (Some(old), None) => deleted.push(old),
+        (None, Some(new)) => changed.push((ChangeKind::Added, new)),
+        (Some(_), Some(new)) if delta.status() != Delta::Unmodified => {
+          changed.push((ChangeKind::Modified, new))
+        }
+        _ => {}
+      }
+    }
+
+    let paths = changed.iter().map(|(_, (path, _))| path.as_str());
+    let last_commits = self.last_commits(&repo, &to_commit, paths)?;
+    let mut changes = deleted
+      .into_iter()
+      .map(|(path, _)| Change {
+        kind: ChangeKind::Deleted,
+        path,
+        document: None,
+      })
+      .collect::<Vec<_>>();
+    for (kind, (path, id)) in changed {
+      let last_commit = last_commits.get(path.as_str());
+      let document = load_blob(&repo, &to_commit, &path, id, last_commit)?;
+      changes.push(Change {
+        kind,
+        path,
+        document: Some(document),
+      });
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+  }
+
+  fn filter(&self) -> Result<Filter, Error> {
+    Ok(Filter {
+      include: build_glob_set(&self.include)?,
+      exclude: build_glob_set(&self.exclude)?,
+      max_file_size: self.max_file_size,
+    })
+  }
+
+  /// Finds the last commit that changed each of `paths`, walking back from
+  /// `head`. A commit changed a path if it has the file as it is in `head`
+  /// and its parents all have another version. Commits with other versions
+  /// are skipped, e.g. a mainline commit whose change a merge discarded.
+  fn last_commits<'r, 'p>(
+    &self,
+    repo: &'r Repository,
+    head: &Commit<'r>,
+    paths: impl Iterator<Item = &'p str>,
+  ) -> Result<HashMap<String, Commit<'r>>, Error> {
+    let mut found = HashMap::new();
+    if !self.last_commit {
+      return Ok(found);
+    }
+    let head_tree = head.tree().map_err(git_error)?;
+    let mut remaining = paths
+      .filter_map(|path| Some((path, entry_id(&head_tree, path)?)))
+      .collect::<Vec<_>>();
+    if remaining.is_empty() {
+      return Ok(found);
+    }
+
+    let mut revwalk = repo.revwalk().map_err(git_error)?;
+    revwalk
+      .set_sorting(Sort::TOPOLOGICAL | Sort::TIME)
+      .map_err(git_error)?;
+    revwalk.push(head.id()).map_err(git_error)?;
+    for id in revwalk {
+      let commit = repo
+        .find_commit(id.map_err(git_error)?)
+        .map_err(git_error)?;
+      let tree = commit.tree().map_err(git_error)?;
+      let parents = commit
+        .parents()
+        .map(|parent| parent.tree())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(git_error)?;
+
+      remaining.retain(|(path, target)| {
+        let changed = entry_id(&tree, path) == Some(*target)
+          && parents
+            .iter()
+            .all(|parent| entry_id(parent, path) != Some(*target));
+        if changed {
+          found.insert(path.to_string(), commit.clone());
+        }
+        !changed
+      });
+      if remaining.is_empty() {
+        break;
+      }
+    }
+    Ok(found)
+  }
+}
+
+struct Filter {
+  include: GlobSet,
+  exclude: GlobSet,
+  max_file_size: Option<u64>,
+}
+
+impl Filter {
+  fn accepts(
+    &self,
+    repo: &Repository,
+    path: &str,
+    mode: i32,
+    id: Oid,
+  ) -> Result<bool, Error> {
+    let is_file = mode == i32::from(FileMode::Blob)
+      || mode == i32::from(FileMode::BlobExecutable);
+    if !is_file
+      || (!self.include.is_empty() && !self.include.is_match(path))
+      || self.exclude.is_match(path)
+    {
+      return Ok(false);
+    }
+    if let Some(max) = self.max_file_size {
+      let size = repo.find_blob(id).map_err(git_error)?.size() as u64;
+      return Ok(size <= max);
+    }
+    Ok(true)
+  }
+}
+
+/// The path and blob id of one side of a diff, if it's a file the loader
+/// loads.
+fn tracked_path(
+  repo: &Repository,
+  filter: &Filter,
+  file: &DiffFile,
+) -> Result<Option<(String, Oid)>, Error> {
+  let Some(path) = file.path() else {
+    return Ok(None);
+  };
+  let path = path.to_string_lossy().into_owned();
+  if file.id().is_zero()
+    || !filter.accepts(repo, &path, i32::from(file.mode()), file.id())?
+  {
+    return Ok(None);
+  }
+  Ok(Some((path, file.id())))
+}
+
+fn load_blob(
+  repo: &Repository,
+  revision: &Commit,
+  path: &str,
+  id: Oid,
+  last_commit: Option<&Commit>,
+) -> Result<Document<'static>, Error> {
+  let blob = repo.find_blob(id).map_err(git_error)?;
+  let mut meta = DocumentMetadata {
+    id: Some(path.to_string()),
+    // Files of bare repositories only exist in the object database.
+    source: repo.workdir().map(|root| file_uri(&root.join(path))),
+    ..Default::default()
+  };
+  meta.extra.insert("path".into(), path.into());
+  meta.extra.insert("size".into(), blob.size().into());
+  meta.extra.insert("blob_id".into(), id.to_string().into());
+  meta
+    .extra
+    .insert("revision".into(), revision.id().to_string().into());
+  if let Some(commit) = last_commit {
+    let time = DateTime::<Utc>::from_timestamp(commit.time().seconds(), 0);
+    meta.modified_at = time;
+    let author = commit.author();
+    meta.extra.insert(
+      "last_commit".into(),
+      json!({
+        "id": commit.id().to_string(),
+        "author": author.name(),
+        "email": author.email(),
+        "time": time.map(|time| time.to_rfc3339()),
+        "summary": commit.summary(),
+      }),
+    );
+  }
+
+  Ok(typed_document(
+    Path::new(path),
+    blob.content().to_vec(),
+    meta,
+  ))
+}
+
+fn resolve<'r>(
+  repo: &'r Repository,
+  revision: &str,
+) -> Result<Commit<'r>, Error> {
+  repo
+    .revparse_single(revision)
+    .and_then(|object| object.peel_to_commit())
+    .map_err(|err| Error::Git {
+      message: format!("{revision}: {}", err.message()),
+      source: Arc::new(err),
+    })
+}
+
+fn entry_id(tree: &Tree, path: &str) -> Option<Oid> {
+  tree.get_path(Path::new(path)).ok().map(|entry| entry.id())
+}
+
+fn git_error(err: git2::Error) -> Error {
+  Error::Git {
+    message: err.message().to_string(),
+    source: Arc::new(err),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use git2::Signature;
+  use std::fs;
+
+  fn commit(repo: &Repository, files: &[(&str, Option<&str>)], message: &str) {
+    let root = repo.workdir().unwrap();
+    let mut index = repo.index().unwrap();
+    for (path, content) in files {
+      match content {
+        Some(content) => {
+          let file = root.join(path);
+          fs::create_dir_all(file.parent().unwrap()).unwrap();
+          fs::write(file, content).unwrap();
+          index.add_path(Path::new(path)).unwrap();
+        }
+        None => {
+          fs::remove_file(root.join(path)).unwrap();
+          index.remove_path(Path::new(path)).unwrap();
+        }
+      }
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("Ann", "ann@example.com").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    let parents = parent.iter().collect::<Vec<_>>();
+    repo
+      .commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+      )
+      .unwrap();
+  }
+
+  fn repo() -> (tempfile::TempDir, Repository) {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    commit(
+      &repo,
+      &[
+        ("README.md", Some("# Hello")),
+        ("src/main.rs", Some("fn main() {}")),
+        ("notes.txt", Some("first")),
+      ],
+      "Initial commit",
+    );
+    commit(&repo, &[("notes.txt", Some("second"))], "Update notes");
+    (dir, repo)
+  }
+
+  #[test]
+  fn loads_revision() {
+    let (dir, repo) = repo();
+    let loader = GitLoaderBuilder::default().build().unwrap();
+    let documents = loader.process(dir.path()).unwrap();
+
+    let ids = documents
+      .iter()
+      .map(|doc| doc.id().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(ids, ["README.md", "notes.txt", "src/main.rs"]);
+    assert!(matches!(documents[0], Document::Markdown(_)));
+    assert!(matches!(documents[2], Document::Code(_)));
+    assert_eq!(documents[1].text(), Some("second"));
+    let source = documents[2].meta().source.as_deref().unwrap();
+    assert!(source.starts_with("file://"), "{source}");
+    assert!(source.ends_with("/src/main.rs"), "{source}");
+
+    let extra = &documents[1].meta().extra;
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(extra["revision"], head.id().to_string());
+    assert_eq!(extra["last_commit"]["summary"], "Update notes");
+    assert_eq!(extra["last_commit"]["author"], "Ann");
+    let readme = &documents[0].meta().extra;
+    assert_eq!(readme["last_commit"]["summary"], "Initial commit");
+    let blob = repo.revparse_single("HEAD:README.md").unwrap().id();
+    assert_eq!(readme["blob_id"], blob.to_string());
+
+    let loader = GitLoaderBuilder::default()
+      .revision("HEAD~1")
+      .include(vec!["*.txt".to_string()])
+      .last_commit(false)
+      .build()
+      .unwrap();
+    let documents = loader.process(dir.path()).unwrap();
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0].text(), Some("first"));
+    assert!(!documents[0].meta().extra.contains_key("last_commit"));
+  }
+
+  #[test]
+  fn bare_repositories() {
+    let (dir, _) = repo();
+    let bare = tempfile::tempdir().unwrap();
+    git2::build::RepoBuilder::new()
+      .bare(true)
+      .clone(dir.path().to_str().unwrap(), bare.path())
+      .unwrap();
+
+    let loader = GitLoaderBuilder::default().build().unwrap();
+    let documents = loader.process(bare.path()).unwrap();
+    assert_eq!(documents.len(), 3);
+    assert!(documents.iter().all(|doc| doc.meta().source.is_none()));
+  }
+
+  #[test]
+  fn lists_changes() {
+    let (dir, repo) = repo();
+    commit(
+      &repo,
+      &[
+        ("README.md", Some("# Hello, world")),
+        ("notes.txt", None),
+        ("docs/guide.md", Some("Guide")),
+      ],
+      "Reorganize",
+    );
+
+    let loader = GitLoaderBuilder::default().build().unwrap();
+    let changes = loader.changes(dir.path(), "HEAD~1", "HEAD").unwrap();
+    let kinds = changes
+      .iter()
+      .map(|change| (change.kind, change.path.as_str()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      kinds,
+      [
+        (ChangeKind::Modified, "README.md"),
+        (ChangeKind::Added, "docs/guide.md"),
+        (ChangeKind::Deleted, "notes.txt"),
+      ]
+    );
+    let readme = changes[0].document.as_ref().unwrap();
+    assert_eq!(readme.text(), Some("# Hello, world"));
+    assert_eq!(readme.meta().extra["last_commit"]["summary"], "Reorganize");
+    assert!(changes[2].document.is_none());
+
+    assert!(matches!(
+      loader.changes(dir.path(), "nope", "HEAD"),
+      Err(Error::Git { .. })
+    ));
+  }
+
+  #[test]
+  fn last_commit_through_merges() {
+    let (dir, repo) = repo();
+    let base = repo.head().unwrap().peel_to_commit().unwrap();
+    let root = repo.workdir().unwrap().to_path_buf();
+    let commit_at = |notes: &str, parents: &[&Commit], offset: i64| {
+      fs::write(root.join("notes.txt"), notes).unwrap();
+      let mut index = repo.index().unwrap();
+      index.add_path(Path::new("notes.txt")).unwrap();
+      index.write().unwrap();
+      let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+      let time = git2::Time::new(base.time().seconds() + offset, 0);
+      let signature = Signature::new("Ann", "ann@example.com", &time).unwrap();
+      let message = format!("{} {notes}", parents.len());
+      let id = repo
+        .commit(None, &signature, &signature, &message, &tree, parents)
+        .unwrap();
+      repo.find_commit(id).unwrap()
+    };
+
+    // Both branches change the file, and the merge keeps the side branch's
+    // version. The mainline commit is newer, so it's visited first.
+    let side = commit_at("side", &[&base], 100);
+    let main = commit_at("main", &[&base], 200);
+    let merge = commit_at("side", &[&main, &side], 300);
+    repo
+      .reference("refs/heads/master", merge.id(), true, "merge")
+      .unwrap();
+    repo.set_head("refs/heads/master").unwrap();
+
+    let loader = GitLoaderBuilder::default().build().unwrap();
+    let documents = loader.process(dir.path()).unwrap();
+    let notes = &documents[1];
+    assert_eq!(notes.text(), Some("side"));
+    assert_eq!(notes.meta().extra["last_commit"]["summary"], "1 side");
+  }
+}
@@ -0,0 +1,275 @@
+//! Converts (X)HTML trees into typed elements.
+
+use super::{
+  xml::{
+    self,
+    Child,
+    Node,
+  },
+  LoadedBuilder,
+};
+use crate::{
+  element::{
+    CodeBlockElement,
+    Element,
+    ListItemElement,
+    SimpleElement,
+    TableElement,
+    TitleElement,
+  },
+  error::Error,
+};
+
+/// Parses XHTML, resolving the common named HTML entities XML doesn't know
+/// about.
+pub(crate) fn parse(format: &'static str, xhtml: &str) -> Result<Node, Error> {
+  xml::parse_with(format, xhtml, entity)
+}
+
+fn entity(name: &str) -> Option<&'static str> {
+  let value = match name {
+    "nbsp" => "\u{a0}",
+    "shy" => "\u{ad}",
+    "ndash" => "–",
+    "mdash" => "—",
+    "hellip" => "…",
+    "lsquo" => "‘",
+    "rsquo" => "’",
+    "sbquo" => "‚",
+    "ldquo" => "“",
+    "rdquo" => "”",
+    "bdquo" => "„",
+    "laquo" => "«",
+    "raquo" => "»",
+    "bull" => "•",
+    "middot" => "·",
+    "copy" => "©",
+    "reg" => "®",
+    "trade" => "™",
+    "deg" => "°",
+    "times" => "×",
+    "divide" => "÷",
+    "euro" => "€",
+    "pound" => "£",
+    "sect" => "§",
+    "para" => "¶",
+    "dagger" => "†",
+    "Dagger" => "‡",
+    "thinsp" => "\u{2009}",
+    "ensp" => "\u{2002}",
+    "emsp" => "\u{2003}",
+    "zwnj" => "\u{200c}",
+    "zwj" => "\u{200d}",
+    _ => return None,
+  };
+  Some(value)
+}
+
+/// Walks the html `node`, pushing one element per block to `builder`. `tag`
+/// is applied to every element, e.g. to add tags.
+pub(crate) fn push_blocks(
+  node: &Node,
+  builder: &mut LoadedBuilder,
+  tag: &dyn Fn(Element<'static>) -> Element<'static>,
+) {
+  Blocks { builder, tag }.walk(node, 0);
+}
+
+struct Blocks<'a> {
+  builder: &'a mut LoadedBuilder,
+  tag: &'a dyn Fn(Element<'static>) -> Element<'static>,
+}
+
+impl Blocks<'_> {
+  fn walk(&mut self, node: &Node, depth: u8) {
+    // Inline content between blocks forms an anonymous paragraph.
+    let mut inline = String::new();
+    for child in &node.children {
+      let child = match child {
+        Child::Text(text) => {
+          inline.push_str(text);
+          continue;
+        }
+        Child::Node(child) => child,
+      };
+      if !is_block(&child.name) {
+        inline_text(child, &mut inline);
+        continue;
+      }
+
+      self.paragraph(&inline);
+      inline.clear();
+      self.block(child, depth);
+    }
+    self.paragraph(&inline);
+  }
+
+  fn block(&mut self, node: &Node, depth: u8) {
+    let tag = self.tag;
+    match node.name.as_str() {
+      "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+        let level = node.name[1..].parse().unwrap_or(1);
+        self.builder.push(&text(node), |text, loc| {
+          tag(TitleElement::new(text, loc, level).as_element())
+        });
+      }
+      "ul" | "ol" => {
+        let ordered = node.name == "ol";
+        for item in node.nodes() {
+          self.list_item(item, depth, ordered);
+        }
+      }
+      "table" => {
+        let rows = node
+          .find_all("tr")
+          .into_iter()
+          .map(|row| {
+            row
+              .nodes()
+              .filter(|cell| cell.name == "td" || cell.name == "th")
+              .map(text)
+              .collect::<Vec<_>>()
+          })
+          .filter(|row| !row.is_empty())
+          .collect::<Vec<_>>();
+        let content = rows
+          .iter()
+          .map(|row| row.join("\t"))
+          .collect::<Vec<_>>()
+          .join("\n");
+        self.builder.push(&content, |text, loc| {
+          tag(TableElement::new(text, loc, rows).as_element())
+        });
+      }
+      "pre" => {
+        let content = node.text();
+        let content = content.trim_matches('\n');
+        let language = code_language(node);
+        self.builder.push(content, |text, loc| {
+          tag(CodeBlockElement::new(text, loc, language).as_element())
+        });
+      }
+      "head" | "script" | "style" | "hr" => {}
+      _ => self.walk(node, depth),
+    }
+  }
+
+  fn list_item(&mut self, item: &Node, depth: u8, ordered: bool) {
+    // The text of the item itself, without nested lists.
+    let mut inline = String::new();
+    for child in &item.children {
+      match child {
+        Child::Text(text) => inline.push_str(text),
+        Child::Node(node) if node.name == "ul" || node.name == "ol" => {}
+        Child::Node(node) => inline_text(node, &mut inline),
+      }
+    }
+    let tag = self.tag;
+    self.builder.push(&collapse(&inline), |text, loc| {
+      tag(ListItemElement::new(text, loc, depth, ordered).as_element())
+    });
+
+    for nested in item.nodes() {
+      if nested.name == "ul" || nested.name == "ol" {
+        let ordered = nested.name == "ol";
+        for item in nested.nodes() {
+          self.list_item(item, depth + 1, ordered);
+        }
+      }
+    }
+  }
+
+  fn paragraph(&mut self, inline: &str) {
+    let tag = self.tag;
+    self.builder.push(&collapse(inline), |text, loc| {
+      tag(Element::NarrativeText(SimpleElement::new(text, loc)))
+    });
+  }
+}
+
+// The language of a `<pre>` block, from a `language-*` class on its `<code>`
+// child as used by most syntax highlighters.
+fn code_language(pre: &Node) -> Option<String> {
+  pre
+    .child("code")?
+    .attr("class")?
+    .split_whitespace()
+    .find_map(|class| class.strip_prefix("language-"))
+    .filter(|language| !language.is_empty())
+    .map(str::to_lowercase)
+}
+
+fn is_block(name: &str) -> bool {
+  matches!(
+    name,
+    "html"
+      | "head"
+      | "body"
+      | "p"
+      | "div"
+      | "section"
+      | "article"
+      | "header"
+      | "footer"
+      | "aside"
+      | "main"
+      | "nav"
+      | "blockquote"
+      | "figure"
+      | "figcaption"
+      | "h1"
+      | "h2"
+      | "h3"
+      | "h4"
+      | "h5"
+      | "h6"
+      | "ul"
+      | "ol"
+      | "li"
+      | "dl"
+      | "dt"
+      | "dd"
+      | "table"
+      | "pre"
+      | "hr"
+      | "script"
+      | "style"
+  )
+}
+
+/// The text of `node` with whitespace collapsed like a browser would.
+pub(crate) fn text(node: &Node) -> String {
+  let mut text = String::new();
+  inline_text(node, &mut text);
+  collapse(&text)
+}
+
+fn inline_text(node: &Node, text: &mut String) {
+  match node.name.as_str() {
+    "br" => text.push(LINE_BREAK),
+    "script" | "style" => {}
+    _ => {
+      for child in &node.children {
+        match child {
+          Child::Text(t) => text.push_str(t),
+          Child::Node(node) => inline_text(node, text),
+        }
+      }
+    }
+  }
+}
+
+/// Marks `<br>`s while collecting text. NUL can't appear in XML, so it can't
+/// be confused with content.
+const LINE_BREAK: char = '\0';
+
+/// Collapses runs of whitespace into single spaces, turning `<br>`s into line
+/// breaks.
+fn collapse(text: &str) -> String {
+  text
+    .split(LINE_BREAK)
+    .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+    .filter(|line| !line.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n")
+}
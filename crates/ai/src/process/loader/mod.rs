@@ -0,0 +1,155 @@
+use crate::{
+  document::{
+    Document,
+    DocumentMetadata,
+    TextDocument,
+  },
+  element::Element,
+  loc::Loc,
+  process::partition,
+  tree::DocumentTree,
+};
+use std::{
+  fmt::Write,
+  path::Path,
+};
+
+mod archive;
+pub mod directory;
+pub mod email;
+pub mod epub;
+#[cfg(feature = "git")]
+pub mod git;
+mod html;
+pub mod notebook;
+pub mod office;
+pub mod pdf;
+pub mod records;
+mod xml;
+
+/// The output of loaders that extract structure from a source: the extracted
+/// text as a `Document`, plus `Element`s whose `Loc`s point into that text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loaded<'a> {
+  pub document: Document<'a>,
+  pub elements: Vec<Element<'a>>,
+}
+
+impl<'a> Loaded<'a> {
+  /// The sections of the document, built from its elements.
+  pub fn tree(&self) -> DocumentTree<'_, 'a> {
+    DocumentTree::new(&self.elements)
+  }
+
+  /// All elements overlapping `loc`. Useful to map a chunk back to e.g. the
+  /// page it came from.
+  pub fn elements_at<'b>(
+    &'b self,
+    loc: &'b Loc,
+  ) -> impl Iterator<Item = &'b Element<'a>> + 'b {
+    self.elements.iter().filter(move |el| {
+      let el_loc = el.loc();
+      // Empty locs are treated as points so they still map to an element.
+      el_loc.start < loc.end.max(loc.start + 1) && loc.start < el_loc.end
+    })
+  }
+}
+
+/// Builds up the text of a `Loaded` document one block at a time, keeping
+/// track of the `Loc` of each block.
+pub(crate) struct LoadedBuilder {
+  separator: String,
+  content: String,
+  elements: Vec<Element<'static>>,
+}
+
+impl LoadedBuilder {
+  pub fn new(separator: impl Into<String>) -> Self {
+    Self {
+      separator: separator.into(),
+      content: String::new(),
+      elements: vec![],
+    }
+  }
+
+  /// Appends `text` to the document and adds the element created by
+  /// `element` from the text and its `Loc`. Blank text is skipped.
+  pub fn push(
+    &mut self,
+    text: &str,
+    element: impl FnOnce(String, Loc) -> Element<'static>,
+  ) {
+    if text.trim().is_empty() {
+      return;
+    }
+    if !self.content.is_empty() {
+      self.content.push_str(&self.separator);
+    }
+    let start = self.content.len();
+    self.content.push_str(text);
+    let loc = Loc::new(start, self.content.len());
+    self.elements.push(element(text.to_string(), loc));
+  }
+
+  pub fn finish(mut self, meta: DocumentMetadata) -> Loaded<'static> {
+    partition::link(&mut self.elements);
+    Loaded {
+      document: TextDocument::new(self.content)
+        .with_meta(meta)
+        .as_document(),
+      elements: self.elements,
+    }
+  }
+}
+
+/// A `file://` URI for an absolute path, percent-encoding everything but
+/// unreserved characters and separators. Windows paths get forward slashes
+/// and lose the `\\?\` prefix `fs::canonicalize` gives them.
+pub(crate) fn file_uri(path: &Path) -> String {
+  let path = path.to_string_lossy();
+  let path = match path.strip_prefix(r"\\?\") {
+    Some(unc) if unc.starts_with(r"UNC\") => format!(r"\\{}", &unc[4..]),
+    Some(path) => path.to_string(),
+    None => path.into_owned(),
+  };
+  let path = path.replace('\\', "/");
+  // Paths of network shares start with the host, other Windows paths with a
+  // drive letter.
+  let mut uri = String::from("file:");
+  if !path.starts_with("//") {
+    uri.push_str("//");
+  }
+  if !path.starts_with('/') {
+    uri.push('/');
+  }
+  for byte in path.bytes() {
+    match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+        uri.push(byte as char)
+      }
+      b'/' | b':' => uri.push(byte as char),
+      _ => write!(uri, "%{byte:02X}").unwrap(),
+    }
+  }
+  uri
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn file_uris() {
+    let uri = |path: &str| file_uri(Path::new(path));
+    assert_eq!(
+      uri("/docs/my notes #1.md"),
+      "file:///docs/my%20notes%20%231.md"
+    );
+    assert_eq!(uri(r"\\?\C:\docs\a.md"), "file:///C:/docs/a.md");
+    assert_eq!(uri(r"C:\docs\100%.md"), "file:///C:/docs/100%25.md");
+    assert_eq!(
+      uri(r"\\?\UNC\server\share\a.md"),
+      "file://server/share/a.md"
+    );
+  }
+}
@@ -0,0 +1,413 @@
+use super::{
+  Loaded,
+  LoadedBuilder,
+};
+use crate::{
+  describe,
+  document::{
+    Document,
+    DocumentMetadata,
+  },
+  element::{
+    CodeBlockElement,
+    Element,
+    SimpleElement,
+  },
+  error::Error,
+  telemetry,
+  traits::Processor,
+};
+use derive_builder::Builder;
+use regex::Regex;
+use serde::{
+  Deserialize,
+  Serialize,
+};
+use serde_json::{
+  Map,
+  Value,
+};
+use std::sync::OnceLock;
+
+const NOTEBOOK_MIME_TYPE: &str = "application/x-ipynb+json";
+
+/// Loads Jupyter notebooks (nbformat 4). Every cell becomes an element:
+/// markdown cells are narrative text, code cells are code blocks in the
+/// kernel's language and raw cells are simple elements. The text outputs of
+/// code cells (streams, plain text results and errors) follow their cell as
+/// simple elements; rich outputs like images are skipped.
+///
+/// Every element is tagged with:
+/// - `cell`: the 1-based index of its cell.
+/// - `cell_type`: `markdown`, `code`, `raw` or `output`.
+/// - `execution_count`: for code cells and their outputs, when executed.
+/// - `output_type`: for outputs, e.g. `stream` or `execute_result`.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct NotebookLoader {
+  /// Inserted between the text of consecutive elements.
+  #[builder(default = "\"\\n\\n\".to_string()")]
+  separator: String,
+
+  /// Whether to load the outputs of code cells.
+  #[builder(default = "true")]
+  outputs: bool,
+}
+
+impl<'p> Processor<&'p [u8], Loaded<'static>> for NotebookLoader {
+  fn process(&self, input: &'p [u8]) -> Result<Loaded<'static>, Error> {
+    self.load(input, DocumentMetadata::default())
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p Document<'d>, Loaded<'static>> for NotebookLoader {
+  fn process(&self, input: &'p Document<'d>) -> Result<Loaded<'static>, Error> {
+    match input {
+      Document::Binary(binary) => self.load(&binary.data, input.meta().clone()),
+      document => match document.text() {
+        Some(text) => self.load(text.as_bytes(), input.meta().clone()),
+        None => Err(Error::UnsupportedDocument(
+          "expected a notebook document".to_string(),
+        )),
+      },
+    }
+  }
+
+  describe::impl_config!();
+}
+
+#[derive(Deserialize)]
+struct Notebook {
+  nbformat: u32,
+  #[serde(default)]
+  metadata: NotebookMetadata,
+  #[serde(default)]
+  cells: Vec<Cell>,
+}
+
+#[derive(Default, Deserialize)]
+struct NotebookMetadata {
+  kernelspec: Option<KernelSpec>,
+  language_info: Option<LanguageInfo>,
+  title: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct KernelSpec {
+  name: Option<String>,
+  language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LanguageInfo {
+  name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "cell_type", rename_all = "lowercase")]
+enum Cell {
+  Markdown {
+    source: Text,
+  },
+  Code {
+    source: Text,
+    execution_count: Option<u64>,
+    #[serde(default)]
+    outputs: Vec<Output>,
+  },
+  Raw {
+    source: Text,
+  },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "output_type", rename_all = "snake_case")]
+enum Output {
+  Stream {
+    text: Text,
+  },
+  ExecuteResult {
+    #[serde(default)]
+    data: Map<String, Value>,
+  },
+  DisplayData {
+    #[serde(default)]
+    data: Map<String, Value>,
+  },
+  Error {
+    ename: String,
+    evalue: String,
+    #[serde(default)]
+    traceback: Vec<String>,
+  },
+}
+
+/// Multiline strings are stored either as a string or as a list of lines.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Text {
+  String(String),
+  Lines(Vec<String>),
+}
+
+impl Text {
+  fn into_string(self) -> String {
+    match self {
+      Text::String(text) => text,
+      Text::Lines(lines) => lines.concat(),
+    }
+  }
+}
+
+impl NotebookLoader {
+  fn load(
+    &self,
+    data: &[u8],
+    meta: DocumentMetadata,
+  ) -> Result<Loaded<'static>, Error> {
+    telemetry::processor("NotebookLoader", Some(data.len()), || {
+      self.read(data, meta)
+    })
+  }
+
+  fn read(
+    &self,
+    data: &[u8],
+    mut meta: DocumentMetadata,
+  ) -> Result<Loaded<'static>, Error> {
+    let notebook: Notebook = serde_json::from_slice(data)
+      .map_err(|err| Error::parse("notebook", err))?;
+    if notebook.nbformat < 4 {
+      return Err(Error::UnsupportedDocument(format!(
+        "nbformat {} notebooks are not supported",
+        notebook.nbformat
+      )));
+    }
+
+    let metadata = notebook.metadata;
+    let kernel = metadata.kernelspec.as_ref().and_then(|k| k.name.clone());
+    let language = metadata
+      .kernelspec
+      .and_then(|kernel| kernel.language)
+      .or(metadata.language_info.and_then(|info| info.name));
+
+    let mut builder = LoadedBuilder::new(&self.separator);
+    for (index, cell) in notebook.cells.into_iter().enumerate() {
+      let cell_number = index + 1;
+      let tag = |element: Element<'static>, cell_type: &'static str| {
+        element
+          .with_tag("cell", cell_number)
+          .with_tag("cell_type", cell_type)
+      };
+
+      match cell {
+        Cell::Markdown { source } => {
+          builder.push(source.into_string().trim_end(), |text, loc| {
+            tag(
+              Element::NarrativeText(SimpleElement::new(text, loc)),
+              "markdown",
+            )
+          });
+        }
+        Cell::Raw { source } => {
+          builder.push(source.into_string().trim_end(), |text, loc| {
+            tag(SimpleElement::new(text, loc).as_element(), "raw")
+          });
+        }
+        Cell::Code {
+          source,
+          execution_count,
+          outputs,
+        } => {
+          let with_count = |element: Element<'static>| match execution_count {
+            Some(count) => element.with_tag("execution_count", count as i64),
+            None => element,
+          };
+          builder.push(source.into_string().trim_end(), |text, loc| {
+            let element = CodeBlockElement::new(text, loc, language.clone());
+            with_count(tag(element.as_element(), "code"))
+          });
+          if !self.outputs {
+            continue;
+          }
+          for output in outputs {
+            let (output_type, text) = match output {
+              Output::Stream { text } => ("stream", text.into_string()),
+              Output::ExecuteResult { data } => {
+                ("execute_result", plain_text(data))
+              }
+              Output::DisplayData { data } => {
+                ("display_data", plain_text(data))
+              }
+              Output::Error {
+                ename,
+                evalue,
+                traceback,
+              } => {
+                let text = if traceback.is_empty() {
+                  format!("{ename}: {evalue}")
+                } else {
+                  strip_ansi(&traceback.join("\n"))
+                };
+                ("error", text)
+              }
+            };
+            builder.push(text.trim_end(), |text, loc| {
+              let element =
+                tag(SimpleElement::new(text, loc).as_element(), "output");
+              with_count(element.with_tag("output_type", output_type))
+            });
+          }
+        }
+      }
+    }
+
+    meta.mime_type = Some(NOTEBOOK_MIME_TYPE.to_string());
+    if meta.title.is_none() {
+      meta.title = metadata.title;
+    }
+    if let Some(language) = language {
+      meta.extra.insert("language".into(), language.into());
+    }
+    if let Some(kernel) = kernel {
+      meta.extra.insert("kernel".into(), kernel.into());
+    }
+    Ok(builder.finish(meta))
+  }
+}
+
+/// The `text/plain` representation of a rich output, if it has one.
+fn plain_text(mut data: Map<String, Value>) -> String {
+  match data.remove("text/plain") {
+    Some(value) => serde_json::from_value::<Text>(value)
+      .map(Text::into_string)
+      .unwrap_or_default(),
+    None => String::new(),
+  }
+}
+
+/// Removes the terminal color codes IPython puts in tracebacks.
+fn strip_ansi(text: &str) -> String {
+  static ANSI: OnceLock<Regex> = OnceLock::new();
+  let ansi = ANSI.get_or_init(|| {
+    Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").expect("valid ansi regex")
+  });
+  ansi.replace_all(text, "").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NOTEBOOK: &str = r##"{
+    "nbformat": 4,
+    "nbformat_minor": 5,
+    "metadata": {
+      "kernelspec": {"name": "python3", "language": "python", "display_name": "Python 3"}
+    },
+    "cells": [
+      {"cell_type": "markdown", "metadata": {}, "source": ["# Analysis\n", "Some notes."]},
+      {
+        "cell_type": "code",
+        "execution_count": 3,
+        "metadata": {},
+        "source": "print('hi')\n1 + 1",
+        "outputs": [
+          {"output_type": "stream", "name": "stdout", "text": ["hi\n"]},
+          {"output_type": "execute_result", "execution_count": 3, "metadata": {},
+           "data": {"text/plain": ["2"], "text/html": ["<b>2</b>"]}},
+          {"output_type": "display_data", "metadata": {}, "data": {"image/png": "iVBOR"}}
+        ]
+      },
+      {
+        "cell_type": "code",
+        "execution_count": null,
+        "metadata": {},
+        "source": "1 / 0",
+        "outputs": [
+          {"output_type": "error", "ename": "ZeroDivisionError", "evalue": "division by zero",
+           "traceback": ["\u001b[0;31mZeroDivisionError\u001b[0m: division by zero"]}
+        ]
+      },
+      {"cell_type": "raw", "metadata": {}, "source": ""}
+    ]
+  }"##;
+
+  #[test]
+  fn loads_cells_and_outputs() {
+    let loader = NotebookLoaderBuilder::default().build().unwrap();
+    let loaded = loader.process(NOTEBOOK.as_bytes()).unwrap();
+
+    let elements = loaded
+      .elements
+      .iter()
+      .map(|el| {
+        let tag = |key| el.tag(key).map(|tag| tag.value.to_string());
+        let count = tag("execution_count")
+          .map(|count| format!(" [{count}]"))
+          .unwrap_or_default();
+        let output = tag("output_type")
+          .map(|output| format!(" ({output})"))
+          .unwrap_or_default();
+        format!(
+          "{} {}{count}{output}: {}",
+          tag("cell").unwrap(),
+          tag("cell_type").unwrap(),
+          el.content()
+        )
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      elements,
+      [
+        "1 markdown: # Analysis\nSome notes.",
+        "2 code [3]: print('hi')\n1 + 1",
+        "2 output [3] (stream): hi",
+        "2 output [3] (execute_result): 2",
+        "3 code: 1 / 0",
+        "3 output (error): ZeroDivisionError: division by zero",
+      ]
+    );
+
+    let Element::CodeBlock(code) = &loaded.elements[1] else {
+      panic!("expected a code block");
+    };
+    assert_eq!(code.language.as_deref(), Some("python"));
+    assert_eq!(loaded.document.meta().extra["kernel"], "python3");
+    assert_eq!(
+      loaded.document.mime_type(),
+      Some("application/x-ipynb+json")
+    );
+  }
+
+  #[test]
+  fn skips_outputs() {
+    let loader = NotebookLoaderBuilder::default()
+      .outputs(false)
+      .build()
+      .unwrap();
+    let loaded = loader.process(NOTEBOOK.as_bytes()).unwrap();
+    assert_eq!(loaded.elements.len(), 3);
+    assert_eq!(
+      loaded.document.text(),
+      Some("# Analysis\nSome notes.\n\nprint('hi')\n1 + 1\n\n1 / 0")
+    );
+  }
+
+  #[test]
+  fn rejects_old_notebooks() {
+    let loader = NotebookLoaderBuilder::default().build().unwrap();
+    let old = br#"{"nbformat": 3, "worksheets": []}"#;
+    assert!(matches!(
+      loader.process(&old[..]),
+      Err(Error::UnsupportedDocument(_))
+    ));
+    assert!(matches!(
+      loader.process(&b"{"[..]),
+      Err(Error::Parse { .. })
+    ));
+  }
+}
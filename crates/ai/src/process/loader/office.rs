@@ -0,0 +1,1003 @@
+use super::{
+  archive::{
+    resolve,
+    Archive,
+    DEFAULT_MAX_ENTRY_SIZE,
+  },
+  xml::{
+    Child,
+    Node,
+  },
+  Loaded,
+  LoadedBuilder,
+};
+use crate::{
+  describe,
+  document::{
+    BinaryDocument,
+    Document,
+    DocumentMetadata,
+  },
+  element::{
+    Element,
+    ListItemElement,
+    SimpleElement,
+    TableElement,
+    TitleElement,
+  },
+  error::Error,
+  loc::Loc,
+  telemetry,
+  traits::Processor,
+};
+use derive_builder::Builder;
+use serde::Serialize;
+use std::collections::HashMap;
+
+const DOCX_MIME_TYPE: &str =
+  "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+const PPTX_MIME_TYPE: &str =
+  "application/vnd.openxmlformats-officedocument.presentationml.presentation";
+const ODT_MIME_TYPE: &str = "application/vnd.oasis.opendocument.text";
+
+/// Loads word processing documents and presentations: docx, pptx and odt.
+/// The format is detected from the contents of the file.
+///
+/// Produces a `TextDocument` with the text of every block, and one element
+/// per block in document order:
+/// - Headings become `Title`s with their outline level. Slide titles are level
+///   `1`, or `0` for the title slide.
+/// - List paragraphs and bulleted slide text become `ListItem`s.
+/// - Tables become `Table`s.
+/// - Everything else becomes `NarrativeText`.
+///
+/// Elements are tagged with `style` (the paragraph style or, for slides, the
+/// placeholder type) when known, and with the 1-based `slide` number for
+/// presentations.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct OfficeLoader {
+  /// Inserted between the text of consecutive blocks.
+  #[builder(default = "\"\\n\\n\".to_string()")]
+  separator: String,
+
+  /// Entries of the archive decompressing to more bytes than this are
+  /// rejected, to guard against zip bombs.
+  #[builder(default = "DEFAULT_MAX_ENTRY_SIZE")]
+  max_entry_size: u64,
+}
+
+impl<'p> Processor<&'p [u8], Loaded<'static>> for OfficeLoader {
+  fn process(&self, input: &'p [u8]) -> Result<Loaded<'static>, Error> {
+    self.load(input, DocumentMetadata::default())
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p BinaryDocument<'d>, Loaded<'static>>
+  for OfficeLoader
+{
+  fn process(
+    &self,
+    input: &'p BinaryDocument<'d>,
+  ) -> Result<Loaded<'static>, Error> {
+    self.load(&input.data, input.meta.clone())
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p Document<'d>, Loaded<'static>> for OfficeLoader {
+  fn process(&self, input: &'p Document<'d>) -> Result<Loaded<'static>, Error> {
+    match input {
+      Document::Binary(binary) => self.process(binary),
+      _ => Err(Error::UnsupportedDocument(
+        "expected a binary office document".to_string(),
+      )),
+    }
+  }
+
+  describe::impl_config!();
+}
+
+impl OfficeLoader {
+  fn load(
+    &self,
+    data: &[u8],
+    meta: DocumentMetadata,
+  ) -> Result<Loaded<'static>, Error> {
+    telemetry::processor("OfficeLoader", Some(data.len()), || {
+      self.read(data, meta)
+    })
+  }
+
+  fn read(
+    &self,
+    data: &[u8],
+    mut meta: DocumentMetadata,
+  ) -> Result<Loaded<'static>, Error> {
+    let mut archive =
+      Archive::new("office document", data, self.max_entry_size)?;
+    let mut builder = LoadedBuilder::new(&self.separator);
+
+    let (mime_type, title) = if archive.contains("word/document.xml") {
+      load_docx(&mut archive, &mut builder)?;
+      (
+        DOCX_MIME_TYPE,
+        core_title(&mut archive, "docProps/core.xml")?,
+      )
+    } else if archive.contains("ppt/presentation.xml") {
+      load_pptx(&mut archive, &mut builder)?;
+      (
+        PPTX_MIME_TYPE,
+        core_title(&mut archive, "docProps/core.xml")?,
+      )
+    } else if archive.read_string("mimetype")?.as_deref() == Some(ODT_MIME_TYPE)
+    {
+      load_odt(&mut archive, &mut builder)?;
+      (ODT_MIME_TYPE, core_title(&mut archive, "meta.xml")?)
+    } else {
+      return Err(Error::UnsupportedDocument(
+        "not a docx, pptx or odt file".to_string(),
+      ));
+    };
+
+    meta.mime_type = Some(mime_type.to_string());
+    if meta.title.is_none() {
+      meta.title = title;
+    }
+    Ok(builder.finish(meta))
+  }
+}
+
+/// The `title` from docx/pptx `core.xml` or odt `meta.xml`.
+fn core_title(
+  archive: &mut Archive,
+  name: &str,
+) -> Result<Option<String>, Error> {
+  let title = archive
+    .read_xml(name)?
+    .and_then(|core| core.find("title").map(|title| title.text()))
+    .map(|title| title.trim().to_string())
+    .filter(|title| !title.is_empty());
+  Ok(title)
+}
+
+fn title(text: String, loc: Loc, level: u8) -> Element<'static> {
+  TitleElement::new(text, loc, level).as_element()
+}
+
+fn narrative(text: String, loc: Loc) -> Element<'static> {
+  Element::NarrativeText(SimpleElement::new(text, loc))
+}
+
+fn list_item(
+  text: String,
+  loc: Loc,
+  depth: u8,
+  ordered: bool,
+) -> Element<'static> {
+  ListItemElement::new(text, loc, depth, ordered).as_element()
+}
+
+fn with_style(
+  element: Element<'static>,
+  style: Option<&str>,
+) -> Element<'static> {
+  match style {
+    Some(style) => element.with_tag("style", style.to_string()),
+    None => element,
+  }
+}
+
+/// Adds a table from the text of its cells. `tag` can add tags to the table
+/// element.
+fn push_table(
+  builder: &mut LoadedBuilder,
+  rows: Vec<Vec<String>>,
+  tag: impl FnOnce(Element<'static>) -> Element<'static>,
+) {
+  let rows = rows
+    .into_iter()
+    .filter(|row| !row.is_empty())
+    .collect::<Vec<_>>();
+  let content = rows
+    .iter()
+    .map(|row| {
+      row
+        .iter()
+        .map(|cell| cell.replace(['\t', '\n'], " "))
+        .collect::<Vec<_>>()
+        .join("\t")
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+  builder.push(&content, |text, loc| {
+    tag(TableElement::new(text, loc, rows).as_element())
+  });
+}
+
+fn parse_level(value: Option<&str>) -> Option<u8> {
+  value.and_then(|value| value.parse().ok())
+}
+
+// ============================================================================
+// docx
+// ============================================================================
+
+#[derive(Debug, Default)]
+struct DocxStyle {
+  name: String,
+  heading: Option<u8>,
+  numbering: Option<(String, u8)>,
+}
+
+#[derive(Debug, Default)]
+struct Docx {
+  styles: HashMap<String, DocxStyle>,
+  /// `numId` -> number format of each level.
+  numbering: HashMap<String, Vec<String>>,
+}
+
+impl Docx {
+  fn new(styles: Option<Node>, numbering: Option<Node>) -> Self {
+    let mut docx = Docx::default();
+
+    for style in styles.iter().flat_map(|styles| styles.find_all("style")) {
+      let Some(id) = style.attr("styleId") else {
+        continue;
+      };
+      let name = style
+        .child("name")
+        .and_then(|name| name.attr("val"))
+        .unwrap_or(id)
+        .to_string();
+      let ppr = style.child("pPr");
+      let heading = heading_level(&name).or_else(|| outline_level(ppr));
+      let numbering = ppr.and_then(num_pr);
+      docx.styles.insert(
+        id.to_string(),
+        DocxStyle {
+          name,
+          heading,
+          numbering,
+        },
+      );
+    }
+
+    if let Some(numbering) = numbering {
+      let abstracts: HashMap<&str, Vec<String>> = numbering
+        .find_all("abstractNum")
+        .into_iter()
+        .filter_map(|abs| {
+          let formats = abs
+            .find_all("lvl")
+            .into_iter()
+            .map(|lvl| {
+              let format = lvl.child("numFmt").and_then(|f| f.attr("val"));
+              format.unwrap_or("bullet").to_string()
+            })
+            .collect();
+          Some((abs.attr("abstractNumId")?, formats))
+        })
+        .collect();
+
+      for num in numbering.find_all("num") {
+        let abstract_id =
+          num.child("abstractNumId").and_then(|a| a.attr("val"));
+        if let (Some(id), Some(formats)) = (
+          num.attr("numId"),
+          abstract_id.and_then(|id| abstracts.get(id)),
+        ) {
+          docx.numbering.insert(id.to_string(), formats.clone());
+        }
+      }
+    }
+
+    docx
+  }
+
+  fn is_ordered(&self, num_id: &str, level: u8) -> bool {
+    self
+      .numbering
+      .get(num_id)
+      .and_then(|formats| formats.get(level as usize))
+      .is_some_and(|format| format != "bullet" && format != "none")
+  }
+}
+
+fn heading_level(style_name: &str) -> Option<u8> {
+  let name = style_name.to_lowercase();
+  if name == "title" {
+    return Some(0);
+  }
+  name.strip_prefix("heading ")?.trim().parse().ok()
+}
+
+fn outline_level(ppr: Option<&Node>) -> Option<u8> {
+  let level = parse_level(ppr?.child("outlineLvl")?.attr("val"))?;
+  // Level 9 is body text.
+  (level < 9).then_some(level + 1)
+}
+
+fn num_pr(ppr: &Node) -> Option<(String, u8)> {
+  let num_pr = ppr.child("numPr")?;
+  let num_id = num_pr.child("numId")?.attr("val")?;
+  let level = parse_level(num_pr.child("ilvl").and_then(|l| l.attr("val")));
+  // `numId` 0 removes numbering inherited from the style.
+  (num_id != "0").then(|| (num_id.to_string(), level.unwrap_or(0)))
+}
+
+fn load_docx(
+  archive: &mut Archive,
+  builder: &mut LoadedBuilder,
+) -> Result<(), Error> {
+  let document = archive.require_xml("word/document.xml")?;
+  let docx = Docx::new(
+    archive.read_xml("word/styles.xml")?,
+    archive.read_xml("word/numbering.xml")?,
+  );
+  let body = document
+    .find("body")
+    .ok_or_else(|| Error::malformed("docx", "missing document body"))?;
+  docx_blocks(body, &docx, builder);
+  Ok(())
+}
+
+fn docx_blocks(node: &Node, docx: &Docx, builder: &mut LoadedBuilder) {
+  for child in node.nodes() {
+    match child.name.as_str() {
+      "p" => docx_paragraph(child, docx, builder),
+      "tbl" => {
+        let rows = child
+          .nodes()
+          .filter(|row| row.name == "tr")
+          .map(|row| {
+            row
+              .nodes()
+              .filter(|cell| cell.name == "tc")
+              .map(|cell| {
+                let paragraphs = cell.find_all("p");
+                let text = paragraphs.iter().map(|p| docx_text(p));
+                text.collect::<Vec<_>>().join("\n").trim().to_string()
+              })
+              .collect()
+          })
+          .collect();
+        push_table(builder, rows, |table| table);
+      }
+      // Content controls and custom xml wrap regular blocks.
+      "sdt" | "sdtContent" | "customXml" => docx_blocks(child, docx, builder),
+      _ => {}
+    }
+  }
+}
+
+fn docx_paragraph(p: &Node, docx: &Docx, builder: &mut LoadedBuilder) {
+  let ppr = p.child("pPr");
+  let style = ppr
+    .and_then(|ppr| ppr.child("pStyle"))
+    .and_then(|style| style.attr("val"))
+    .and_then(|id| docx.styles.get(id));
+  let heading =
+    outline_level(ppr).or_else(|| style.and_then(|style| style.heading));
+  let numbering = ppr
+    .and_then(num_pr)
+    .or_else(|| style.and_then(|style| style.numbering.clone()));
+  let style = style.map(|style| style.name.as_str());
+
+  let text = docx_text(p);
+  let text = text.trim();
+  match (heading, numbering) {
+    (Some(level), _) => {
+      builder.push(text, |text, loc| with_style(title(text, loc, level), style))
+    }
+    (None, Some((num_id, depth))) => {
+      let ordered = docx.is_ordered(&num_id, depth);
+      builder.push(text, |text, loc| {
+        with_style(list_item(text, loc, depth, ordered), style)
+      })
+    }
+    (None, None) => {
+      builder.push(text, |text, loc| with_style(narrative(text, loc), style))
+    }
+  }
+}
+
+fn docx_text(node: &Node) -> String {
+  let mut text = String::new();
+  for child in node.nodes() {
+    match child.name.as_str() {
+      "t" => text.push_str(&child.text()),
+      "tab" => text.push('\t'),
+      "br" | "cr" => text.push('\n'),
+      // Properties, deleted text and field instructions aren't content.
+      "pPr" | "rPr" | "delText" | "instrText" => {}
+      _ => text.push_str(&docx_text(child)),
+    }
+  }
+  text
+}
+
+// ============================================================================
+// pptx
+// ============================================================================
+
+fn load_pptx(
+  archive: &mut Archive,
+  builder: &mut LoadedBuilder,
+) -> Result<(), Error> {
+  let presentation = archive.require_xml("ppt/presentation.xml")?;
+  let rels = archive
+    .read_xml("ppt/_rels/presentation.xml.rels")?
+    .map(|rels| relationships(&rels))
+    .unwrap_or_default();
+
+  let slides = presentation
+    .find("sldIdLst")
+    .map(|list| list.find_all("sldId"))
+    .unwrap_or_default();
+  for (index, slide) in slides.into_iter().enumerate() {
+    // The relationship id is the namespaced `r:id`, not the plain `id`.
+    let target = slide
+      .attrs
+      .iter()
+      .find(|(key, _)| key.ends_with(":id"))
+      .and_then(|(_, id)| rels.get(id));
+    let Some(target) = target else {
+      continue;
+    };
+    let slide =
+      archive.require_xml(&resolve("ppt/presentation.xml", target))?;
+    if let Some(tree) = slide.find("spTree") {
+      pptx_shapes(tree, index + 1, builder);
+    }
+  }
+  Ok(())
+}
+
+/// Relationship id -> target from a `.rels` file.
+fn relationships(rels: &Node) -> HashMap<String, String> {
+  rels
+    .find_all("Relationship")
+    .into_iter()
+    .filter_map(|rel| {
+      Some((rel.attr("Id")?.to_string(), rel.attr("Target")?.to_string()))
+    })
+    .collect()
+}
+
+fn pptx_shapes(tree: &Node, slide: usize, builder: &mut LoadedBuilder) {
+  for shape in tree.nodes() {
+    match shape.name.as_str() {
+      "sp" => pptx_shape(shape, slide, builder),
+      "grpSp" => pptx_shapes(shape, slide, builder),
+      "graphicFrame" => {
+        let Some(table) = shape.find("tbl") else {
+          continue;
+        };
+        let rows = table
+          .find_all("tr")
+          .into_iter()
+          .map(|row| {
+            row
+              .find_all("tc")
+              .into_iter()
+              .map(|cell| {
+                let paragraphs = cell.find_all("p");
+                let text = paragraphs.iter().map(|p| pptx_text(p));
+                text.collect::<Vec<_>>().join("\n").trim().to_string()
+              })
+              .collect()
+          })
+          .collect();
+        push_table(builder, rows, |table| table.with_tag("slide", slide));
+      }
+      _ => {}
+    }
+  }
+}
+
+fn pptx_shape(shape: &Node, slide: usize, builder: &mut LoadedBuilder) {
+  let placeholder = shape
+    .find("nvSpPr")
+    .and_then(|props| props.find("ph"))
+    // Placeholders without a type are content placeholders.
+    .map(|ph| ph.attr("type").unwrap_or("obj"));
+  let Some(body) = shape.child("txBody") else {
+    return;
+  };
+  let paragraphs = body.nodes().filter(|p| p.name == "p");
+  let tag = |element: Element<'static>| {
+    with_style(element, placeholder).with_tag("slide", slide)
+  };
+
+  if let Some(kind @ ("title" | "ctrTitle")) = placeholder {
+    let text = paragraphs.map(pptx_text).collect::<Vec<_>>().join(" ");
+    let level = if kind == "ctrTitle" { 0 } else { 1 };
+    builder.push(text.trim(), |text, loc| tag(title(text, loc, level)));
+    return;
+  }
+
+  for p in paragraphs {
+    let ppr = p.child("pPr");
+    let depth = parse_level(ppr.and_then(|ppr| ppr.attr("lvl"))).unwrap_or(0);
+    let bullet = ppr.and_then(|ppr| {
+      ppr.nodes().find_map(|node| match node.name.as_str() {
+        "buAutoNum" => Some(Some(true)),
+        "buChar" | "buBlip" => Some(Some(false)),
+        "buNone" => Some(None),
+        _ => None,
+      })
+    });
+    // Body placeholders are bulleted by the slide master unless overridden.
+    let bullet = bullet.unwrap_or_else(|| {
+      matches!(placeholder, Some("body" | "obj")).then_some(false)
+    });
+
+    let text = pptx_text(p);
+    let text = text.trim();
+    match bullet {
+      Some(ordered) => builder
+        .push(text, |text, loc| tag(list_item(text, loc, depth, ordered))),
+      None => builder.push(text, |text, loc| tag(narrative(text, loc))),
+    }
+  }
+}
+
+fn pptx_text(node: &Node) -> String {
+  let mut text = String::new();
+  for child in node.nodes() {
+    match child.name.as_str() {
+      "t" => text.push_str(&child.text()),
+      "br" => text.push('\n'),
+      "pPr" | "rPr" | "endParaRPr" => {}
+      _ => text.push_str(&pptx_text(child)),
+    }
+  }
+  text
+}
+
+// ============================================================================
+// odt
+// ============================================================================
+
+#[derive(Debug, Default)]
+struct Odt {
+  /// Style name -> parent style name, for automatic styles.
+  parents: HashMap<String, String>,
+  /// Style name -> display name.
+  display_names: HashMap<String, String>,
+  /// List style name -> whether each level is numbered.
+  lists: HashMap<String, Vec<bool>>,
+}
+
+impl Odt {
+  fn new(content: &Node, styles: Option<&Node>) -> Self {
+    let mut odt = Odt::default();
+    for root in std::iter::once(content).chain(styles) {
+      for style in root.find_all("style") {
+        let Some(name) = style.attr("name") else {
+          continue;
+        };
+        if let Some(parent) = style.attr("parent-style-name") {
+          odt.parents.insert(name.to_string(), parent.to_string());
+        }
+        if let Some(display) = style.attr("display-name") {
+          odt
+            .display_names
+            .insert(name.to_string(), display.to_string());
+        }
+      }
+
+      for list in root.find_all("list-style") {
+        let Some(name) = list.attr("name") else {
+          continue;
+        };
+        let mut levels = vec![];
+        for level in list.nodes() {
+          let index = parse_level(level.attr("level")).unwrap_or(1) as usize;
+          if levels.len() < index {
+            levels.resize(index, false);
+          }
+          if index > 0 {
+            levels[index - 1] = level.name == "list-level-style-number";
+          }
+        }
+        odt.lists.insert(name.to_string(), levels);
+      }
+    }
+    odt
+  }
+
+  /// Resolves automatic styles (like `P1`) to the user visible style they
+  /// are based on.
+  fn style_name(&self, name: &str) -> String {
+    let mut name = name;
+    // Guard against cycles in malformed files.
+    for _ in 0..8 {
+      match self.parents.get(name) {
+        Some(parent) => name = parent,
+        None => break,
+      }
+    }
+    self
+      .display_names
+      .get(name)
+      .cloned()
+      .unwrap_or_else(|| name.to_string())
+  }
+
+  fn is_ordered(&self, list_style: Option<&str>, depth: u8) -> bool {
+    list_style
+      .and_then(|style| self.lists.get(style))
+      .and_then(|levels| levels.get(depth as usize))
+      .copied()
+      .unwrap_or(false)
+  }
+}
+
+fn load_odt(
+  archive: &mut Archive,
+  builder: &mut LoadedBuilder,
+) -> Result<(), Error> {
+  let content = archive.require_xml("content.xml")?;
+  let styles = archive.read_xml("styles.xml")?;
+  let odt = Odt::new(&content, styles.as_ref());
+  let text = content
+    .child("body")
+    .and_then(|body| body.child("text"))
+    .ok_or_else(|| Error::malformed("odt", "missing text body"))?;
+  odt_blocks(text, &odt, builder);
+  Ok(())
+}
+
+fn odt_blocks(node: &Node, odt: &Odt, builder: &mut LoadedBuilder) {
+  for child in node.nodes() {
+    let style = child.attr("style-name").map(|name| odt.style_name(name));
+    let style = style.as_deref();
+    match child.name.as_str() {
+      "h" => {
+        let level = parse_level(child.attr("outline-level")).unwrap_or(1);
+        let text = odt_text(child);
+        builder.push(text.trim(), |text, loc| {
+          with_style(title(text, loc, level), style)
+        });
+      }
+      "p" => {
+        let text = odt_text(child);
+        builder.push(text.trim(), |text, loc| {
+          with_style(narrative(text, loc), style)
+        });
+      }
+      "list" => odt_list(child, child.attr("style-name"), 0, odt, builder),
+      "table" => {
+        let rows = child
+          .find_all("table-row")
+          .into_iter()
+          .map(|row| {
+            row
+              .nodes()
+              .filter(|cell| cell.name == "table-cell")
+              .map(|cell| {
+                let paragraphs = cell.find_all("p");
+                let text = paragraphs.iter().map(|p| odt_text(p));
+                text.collect::<Vec<_>>().join("\n").trim().to_string()
+              })
+              .collect()
+          })
+          .collect();
+        push_table(builder, rows, |table| table);
+      }
+      "section" => odt_blocks(child, odt, builder),
+      _ => {}
+    }
+  }
+}
+
+fn odt_list(
+  list: &Node,
+  list_style: Option<&str>,
+  depth: u8,
+  odt: &Odt,
+  builder: &mut LoadedBuilder,
+) {
+  // Nested lists inherit the style of the outermost list.
+  let list_style = list.attr("style-name").or(list_style);
+  let ordered = odt.is_ordered(list_style, depth);
+  for item in list.nodes() {
+    for child in item.nodes() {
+      match child.name.as_str() {
+        "p" | "h" => {
+          let style = child.attr("style-name").map(|name| odt.style_name(name));
+          let text = odt_text(child);
+          builder.push(text.trim(), |text, loc| {
+            with_style(list_item(text, loc, depth, ordered), style.as_deref())
+          });
+        }
+        "list" => odt_list(child, list_style, depth + 1, odt, builder),
+        _ => {}
+      }
+    }
+  }
+}
+
+fn odt_text(node: &Node) -> String {
+  let mut text = String::new();
+  for child in &node.children {
+    let node = match child {
+      Child::Text(t) => {
+        text.push_str(t);
+        continue;
+      }
+      Child::Node(node) => node,
+    };
+    match node.name.as_str() {
+      "s" => {
+        let count = node.attr("c").and_then(|c| c.parse().ok()).unwrap_or(1);
+        text.push_str(&" ".repeat(count));
+      }
+      "tab" => text.push('\t'),
+      "line-break" => text.push('\n'),
+      // Footnotes and comments aren't part of the running text.
+      "note" | "annotation" => {}
+      _ => text.push_str(&odt_text(node)),
+    }
+  }
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::process::loader::archive::zip;
+
+  fn summary(loaded: &Loaded) -> Vec<String> {
+    loaded
+      .elements
+      .iter()
+      .map(|el| {
+        let style = el
+          .tag("style")
+          .map(|tag| format!(" [{}]", tag.value))
+          .unwrap_or_default();
+        let kind = match el {
+          Element::Title(title) => format!("title {}", title.level),
+          Element::ListItem(item) => {
+            format!("item {} {}", item.depth, item.ordered)
+          }
+          Element::Table(_) => "table".to_string(),
+          Element::NarrativeText(_) => "text".to_string(),
+          Element::Simple(_) => "simple".to_string(),
+          Element::CodeBlock(_) => "code".to_string(),
+          Element::Image(_) => "image".to_string(),
+          Element::PageBreak(_) => "page".to_string(),
+          Element::Header(_) => "header".to_string(),
+          Element::Footer(_) => "footer".to_string(),
+        };
+        format!("{kind}: {}{style}", el.content())
+      })
+      .collect()
+  }
+
+  fn loader() -> OfficeLoader {
+    OfficeLoaderBuilder::default().build().unwrap()
+  }
+
+  const W: &str =
+    r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main""#;
+
+  #[test]
+  fn docx() {
+    let document = format!(
+      r#"<w:document {W}><w:body>
+        <w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr>
+          <w:r><w:t>Intro</w:t></w:r></w:p>
+        <w:p><w:r><w:t xml:space="preserve">Some </w:t></w:r>
+          <w:r><w:rPr><w:b/></w:rPr><w:t>text</w:t></w:r></w:p>
+        <w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr>
+          <w:r><w:t>first</w:t></w:r></w:p>
+        <w:p><w:pPr><w:numPr><w:ilvl w:val="1"/><w:numId w:val="1"/></w:numPr></w:pPr>
+          <w:r><w:t>nested</w:t></w:r></w:p>
+        <w:tbl>
+          <w:tr><w:tc><w:p><w:r><w:t>a</w:t></w:r></w:p></w:tc>
+                <w:tc><w:p><w:r><w:t>b</w:t></w:r></w:p></w:tc></w:tr>
+          <w:tr><w:tc><w:p><w:r><w:t>1</w:t></w:r></w:p></w:tc>
+                <w:tc><w:p><w:r><w:t>2</w:t></w:r></w:p></w:tc></w:tr>
+        </w:tbl>
+        <w:p><w:pPr><w:pStyle w:val="Titel"/></w:pPr><w:r><w:t>Custom</w:t></w:r></w:p>
+      </w:body></w:document>"#
+    );
+    let styles = format!(
+      r#"<w:styles {W}>
+        <w:style w:styleId="Heading1"><w:name w:val="heading 1"/></w:style>
+        <w:style w:styleId="Titel"><w:name w:val="Chapter"/>
+          <w:pPr><w:outlineLvl w:val="1"/></w:pPr></w:style>
+      </w:styles>"#
+    );
+    let numbering = format!(
+      r#"<w:numbering {W}>
+        <w:abstractNum w:abstractNumId="7">
+          <w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/></w:lvl>
+          <w:lvl w:ilvl="1"><w:numFmt w:val="bullet"/></w:lvl>
+        </w:abstractNum>
+        <w:num w:numId="1"><w:abstractNumId w:val="7"/></w:num>
+      </w:numbering>"#
+    );
+    let core = r#"<cp:coreProperties xmlns:cp="cp" xmlns:dc="dc">
+      <dc:title>Handbook</dc:title></cp:coreProperties>"#;
+    let data = zip(&[
+      ("word/document.xml", &document),
+      ("word/styles.xml", &styles),
+      ("word/numbering.xml", &numbering),
+      ("docProps/core.xml", core),
+    ]);
+
+    let loaded = loader().process(data.as_slice()).unwrap();
+    assert_eq!(
+      summary(&loaded),
+      vec![
+        "title 1: Intro [heading 1]",
+        "text: Some text",
+        "item 0 true: first",
+        "item 1 false: nested",
+        "table: a\tb\n1\t2",
+        "title 2: Custom [Chapter]",
+      ]
+    );
+    assert_eq!(loaded.document.title(), Some("Handbook"));
+    assert_eq!(loaded.document.mime_type(), Some(DOCX_MIME_TYPE));
+
+    let text = loaded.document.text().unwrap();
+    for element in &loaded.elements {
+      let (start, end) = element.loc().as_tuple();
+      assert_eq!(&text[start..end], element.content());
+    }
+    match &loaded.elements[4] {
+      Element::Table(table) => assert_eq!(table.rows[1], vec!["1", "2"]),
+      _ => panic!("expected a table"),
+    }
+  }
+
+  #[test]
+  fn pptx() {
+    let ns = r#"xmlns:a="a" xmlns:p="p" xmlns:r="r""#;
+    let presentation = format!(
+      r#"<p:presentation {ns}><p:sldIdLst>
+        <p:sldId id="257" r:id="rId3"/><p:sldId id="256" r:id="rId2"/>
+      </p:sldIdLst></p:presentation>"#
+    );
+    let rels = r#"<Relationships>
+      <Relationship Id="rId2" Target="slides/slide1.xml"/>
+      <Relationship Id="rId3" Target="/ppt/slides/slide2.xml"/>
+    </Relationships>"#;
+    let slide = |title: &str, body: &str| {
+      format!(
+        r#"<p:sld {ns}><p:cSld><p:spTree>
+          <p:sp><p:nvSpPr><p:nvPr><p:ph type="title"/></p:nvPr></p:nvSpPr>
+            <p:txBody><a:p><a:r><a:t>{title}</a:t></a:r></a:p></p:txBody></p:sp>
+          <p:sp><p:nvSpPr><p:nvPr><p:ph idx="1"/></p:nvPr></p:nvSpPr>
+            <p:txBody>{body}</p:txBody></p:sp>
+          <p:sp><p:nvSpPr><p:nvPr><p:ph type="ftr"/></p:nvPr></p:nvSpPr>
+            <p:txBody><a:p><a:r><a:t>ACME</a:t></a:r></a:p></p:txBody></p:sp>
+        </p:spTree></p:cSld></p:sld>"#
+      )
+    };
+    let slide1 = slide("Agenda", "<a:p><a:r><a:t>Hello</a:t></a:r></a:p>");
+    let slide2 = slide(
+      "Plan",
+      r#"<a:p><a:pPr lvl="1"><a:buAutoNum type="arabicPeriod"/></a:pPr>
+        <a:r><a:t>Step</a:t></a:r></a:p>
+        <a:p><a:pPr><a:buNone/></a:pPr><a:r><a:t>Note</a:t></a:r></a:p>"#,
+    );
+    let data = zip(&[
+      ("ppt/presentation.xml", &presentation),
+      ("ppt/_rels/presentation.xml.rels", rels),
+      ("ppt/slides/slide1.xml", &slide1),
+      ("ppt/slides/slide2.xml", &slide2),
+    ]);
+
+    let loaded = loader().process(data.as_slice()).unwrap();
+    assert_eq!(
+      summary(&loaded),
+      vec![
+        "title 1: Plan [title]",
+        "item 1 true: Step [obj]",
+        "text: Note [obj]",
+        "text: ACME [ftr]",
+        "title 1: Agenda [title]",
+        "item 0 false: Hello [obj]",
+        "text: ACME [ftr]",
+      ]
+    );
+    let slides = loaded
+      .elements
+      .iter()
+      .map(|el| el.tag("slide").unwrap().value.to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(slides, vec!["1", "1", "1", "1", "2", "2", "2"]);
+  }
+
+  #[test]
+  fn odt() {
+    let content = r#"<office:document-content xmlns:office="o" xmlns:text="t"
+        xmlns:style="s" xmlns:table="tb">
+      <office:automatic-styles>
+        <style:style style:name="P1" style:parent-style-name="Text_20_body"/>
+        <text:list-style style:name="L1">
+          <text:list-level-style-bullet text:level="1"/>
+          <text:list-level-style-number text:level="2"/>
+        </text:list-style>
+      </office:automatic-styles>
+      <office:body><office:text>
+        <text:h text:outline-level="2">Setup</text:h>
+        <text:p text:style-name="P1">Run<text:s text:c="2"/>it<text:tab/>now</text:p>
+        <text:list text:style-name="L1">
+          <text:list-item><text:p>one</text:p>
+            <text:list><text:list-item><text:p>two</text:p></text:list-item></text:list>
+          </text:list-item>
+        </text:list>
+        <table:table><table:table-row>
+          <table:table-cell><text:p>x</text:p></table:table-cell>
+          <table:table-cell><text:p>y</text:p></table:table-cell>
+        </table:table-row></table:table>
+      </office:text></office:body>
+    </office:document-content>"#;
+    let styles = r#"<office:document-styles xmlns:office="o" xmlns:style="s">
+      <style:style style:name="Text_20_body" style:display-name="Text body"/>
+    </office:document-styles>"#;
+    let data = zip(&[
+      ("mimetype", ODT_MIME_TYPE),
+      ("content.xml", content),
+      ("styles.xml", styles),
+    ]);
+
+    let loaded = loader().process(data.as_slice()).unwrap();
+    assert_eq!(
+      summary(&loaded),
+      vec![
+        "title 2: Setup",
+        "text: Run  it\tnow [Text body]",
+        "item 0 false: one",
+        "item 1 true: two",
+        "table: x\ty",
+      ]
+    );
+    assert_eq!(loaded.document.mime_type(), Some(ODT_MIME_TYPE));
+  }
+
+  #[test]
+  fn unsupported() {
+    let data = zip(&[("hello.txt", "hello")]);
+    assert!(matches!(
+      loader().process(data.as_slice()),
+      Err(Error::UnsupportedDocument(_))
+    ));
+    assert!(matches!(
+      loader().process(b"not a zip".as_slice()),
+      Err(Error::Parse { .. })
+    ));
+  }
+
+  #[test]
+  fn large_entries() {
+    // Compresses to a few hundred bytes.
+    let body = "a".repeat(100_000);
+    let document = format!(
+      r#"<w:document {W}><w:body><w:p><w:r><w:t>{body}</w:t></w:r></w:p></w:body></w:document>"#
+    );
+    let data = zip(&[("word/document.xml", &document)]);
+    assert!(data.len() < 1_000);
+
+    let loader = OfficeLoaderBuilder::default()
+      .max_entry_size(10_000_u64)
+      .build()
+      .unwrap();
+    let err = loader.process(data.as_slice()).unwrap_err();
+    assert!(matches!(err, Error::Parse { .. }));
+    assert!(err.to_string().contains("larger than 10000 bytes"), "{err}");
+    assert!(self::loader().process(data.as_slice()).is_ok());
+  }
+}
@@ -0,0 +1,933 @@
+use super::Loaded;
+use crate::{
+  describe,
+  document::{
+    BinaryDocument,
+    BinaryFormat,
+    Document,
+    DocumentMetadata,
+    TextDocument,
+  },
+  element::SimpleElement,
+  error::Error,
+  loc::Loc,
+  telemetry,
+  traits::Processor,
+};
+use derive_builder::Builder;
+use lopdf::{
+  content::Content,
+  Dictionary,
+  Encoding,
+  Object,
+  Stream,
+};
+use serde::Serialize;
+use std::{
+  collections::BTreeMap,
+  rc::Rc,
+};
+
+/// Glyph width used when a font doesn't specify one, in text space units.
+const DEFAULT_GLYPH_WIDTH: f32 = 0.5;
+
+/// How deeply form XObjects are followed into each other, which also stops
+/// forms drawing themselves.
+const MAX_FORM_DEPTH: usize = 8;
+
+/// Extracts the text of a PDF page by page. Produces a `TextDocument` with
+/// the text of all pages and one element per page, in page order.
+///
+/// Each page element is tagged with:
+/// - `page`: the 1-based page number.
+/// - `bbox`: the bounding box of the text on the page as a list of floats `[x0,
+///   y0, x1, y1]` in PDF user space units (origin at the bottom left of the
+///   page). Glyph widths are taken from the font when available and estimated
+///   otherwise, so the box is approximate.
+///
+/// Text is put into reading order by grouping text runs into lines from top
+/// to bottom and ordering each line from left to right. Text drawn by form
+/// XObjects is included where the form is placed on the page.
+///
+/// Pages that can't be read are skipped rather than failing the document,
+/// and their numbers listed in the `unreadable_pages` extra metadata.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct PdfLoader {
+  /// Inserted between the text of consecutive pages.
+  #[builder(default = "\"\\n\\n\".to_string()")]
+  page_separator: String,
+}
+
+impl<'p> Processor<&'p [u8], Loaded<'static>> for PdfLoader {
+  fn process(&self, input: &'p [u8]) -> Result<Loaded<'static>, Error> {
+    self.load(input, DocumentMetadata::default())
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p BinaryDocument<'d>, Loaded<'static>> for PdfLoader {
+  fn process(
+    &self,
+    input: &'p BinaryDocument<'d>,
+  ) -> Result<Loaded<'static>, Error> {
+    if input.format != BinaryFormat::Pdf {
+      return Err(Error::UnsupportedDocument(format!(
+        "expected a pdf, found {:?}",
+        input.format
+      )));
+    }
+    self.load(&input.data, input.meta.clone())
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p Document<'d>, Loaded<'static>> for PdfLoader {
+  fn process(&self, input: &'p Document<'d>) -> Result<Loaded<'static>, Error> {
+    match input {
+      Document::Binary(binary) => self.process(binary),
+      _ => Err(Error::UnsupportedDocument(
+        "expected a binary pdf document".to_string(),
+      )),
+    }
+  }
+
+  describe::impl_config!();
+}
+
+impl PdfLoader {
+  fn load(
+    &self,
+    data: &[u8],
+    meta: DocumentMetadata,
+  ) -> Result<Loaded<'static>, Error> {
+    telemetry::processor("PdfLoader", Some(data.len()), || {
+      self.read(data, meta)
+    })
+  }
+
+  fn read(
+    &self,
+    data: &[u8],
+    mut meta: DocumentMetadata,
+  ) -> Result<Loaded<'static>, Error> {
+    let pdf = lopdf::Document::load_mem(data).map_err(pdf_error)?;
+
+    let mut content = String::new();
+    let mut elements = vec![];
+    let pages = pdf.get_pages();
+    let mut unreadable = vec![];
+    for (number, page_id) in &pages {
+      // A page that can't be read doesn't fail the whole document.
+      let Ok((text, bbox)) = extract_page(&pdf, *page_id) else {
+        unreadable.push(*number);
+        continue;
+      };
+      if !content.is_empty() {
+        content.push_str(&self.page_separator);
+      }
+
+      let start = content.len();
+      content.push_str(&text);
+      let loc = Loc::new(start, content.len());
+
+      let mut element = SimpleElement::new(text, loc)
+        .as_element()
+        .with_tag("page", *number);
+      if let Some([x0, y0, x1, y1]) = bbox {
+        element = element.with_tag("bbox", vec![x0, y0, x1, y1]);
+      }
+      elements.push(element);
+    }
+
+    meta.mime_type = Some("application/pdf".to_string());
+    if meta.title.is_none() {
+      meta.title = info_title(&pdf);
+    }
+    meta.extra.insert("pages".into(), pages.len().into());
+    if !unreadable.is_empty() {
+      meta
+        .extra
+        .insert("unreadable_pages".into(), unreadable.into());
+    }
+
+    Ok(Loaded {
+      document: TextDocument::new(content).with_meta(meta).as_document(),
+      elements,
+    })
+  }
+}
+
+fn pdf_error(err: lopdf::Error) -> Error {
+  Error::parse("pdf", err)
+}
+
+fn info_title(pdf: &lopdf::Document) -> Option<String> {
+  let info = pdf.trailer.get_deref(b"Info", pdf).ok()?.as_dict().ok()?;
+  let title = lopdf::decode_text_string(info.get_deref(b"Title", pdf).ok()?);
+  title.ok().filter(|title| !title.trim().is_empty())
+}
+
+// ============================================================================
+// Content stream interpretation
+// ============================================================================
+
+/// A 2D affine transform `[a b c d e f]` as used by PDF.
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+  [
+    m[0] * n[0] + m[1] * n[2],
+    m[0] * n[1] + m[1] * n[3],
+    m[2] * n[0] + m[3] * n[2],
+    m[2] * n[1] + m[3] * n[3],
+    m[4] * n[0] + m[5] * n[2] + n[4],
+    m[4] * n[1] + m[5] * n[3] + n[5],
+  ]
+}
+
+fn translate(tx: f32, ty: f32) -> Matrix {
+  [1.0, 0.0, 0.0, 1.0, tx, ty]
+}
+
+fn apply(m: &Matrix, x: f32, y: f32) -> (f32, f32) {
+  (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+/// A string shown on the page, positioned in user space.
+#[derive(Debug)]
+struct TextRun {
+  text: String,
+  x0: f32,
+  x1: f32,
+  /// The baseline.
+  y: f32,
+  height: f32,
+}
+
+struct Font<'a> {
+  encoding: Option<Encoding<'a>>,
+  first_char: i64,
+  widths: Vec<f32>,
+}
+
+impl<'a> Font<'a> {
+  fn new(pdf: &'a lopdf::Document, dict: &'a Dictionary) -> Self {
+    let widths = dict
+      .get_deref(b"Widths", pdf)
+      .and_then(Object::as_array)
+      .map(|widths| {
+        widths
+          .iter()
+          .map(|w| pdf.dereference(w).and_then(|(_, w)| w.as_float()))
+          .map(|w| w.unwrap_or(0.0) / 1000.0)
+          .collect()
+      })
+      .unwrap_or_default();
+
+    Font {
+      encoding: dict.get_font_encoding(pdf).ok(),
+      first_char: dict
+        .get_deref(b"FirstChar", pdf)
+        .and_then(Object::as_i64)
+        .unwrap_or(0),
+      widths,
+    }
+  }
+
+  fn is_multi_byte(&self) -> bool {
+    matches!(self.encoding, Some(Encoding::UnicodeMapEncoding(_)))
+  }
+
+  fn decode(&self, bytes: &[u8]) -> String {
+    self
+      .encoding
+      .as_ref()
+      .and_then(|encoding| encoding.bytes_to_string(bytes).ok())
+      .unwrap_or_else(|| bytes.iter().map(|&b| b as char).collect())
+  }
+
+  fn glyph_width(&self, code: u8) -> f32 {
+    let index = code as i64 - self.first_char;
+    usize::try_from(index)
+      .ok()
+      .and_then(|index| self.widths.get(index))
+      .copied()
+      .filter(|w| *w > 0.0)
+      .unwrap_or(DEFAULT_GLYPH_WIDTH)
+  }
+}
+
+/// The fonts and form XObjects a content stream can refer to by name.
+#[derive(Default)]
+struct Resources<'a> {
+  fonts: BTreeMap<Vec<u8>, Rc<Font<'a>>>,
+  forms: BTreeMap<Vec<u8>, &'a Stream>,
+}
+
+impl<'a> Resources<'a> {
+  /// Adds the fonts and forms of a resource dictionary, keeping the ones
+  /// already added under the same names.
+  fn add(&mut self, pdf: &'a lopdf::Document, resources: &'a Dictionary) {
+    let entries = |key: &[u8]| {
+      resources
+        .get_deref(key, pdf)
+        .and_then(Object::as_dict)
+        .into_iter()
+        .flat_map(|dict| dict.iter())
+        .filter_map(|(name, value)| {
+          Some((name, pdf.dereference(value).ok()?.1))
+        })
+    };
+    for (name, font) in entries(b"Font") {
+      if let Ok(font) = font.as_dict() {
+        self
+          .fonts
+          .entry(name.clone())
+          .or_insert_with(|| Rc::new(Font::new(pdf, font)));
+      }
+    }
+    for (name, xobject) in entries(b"XObject") {
+      let Ok(stream) = xobject.as_stream() else {
+        continue;
+      };
+      let subtype = stream.dict.get(b"Subtype").and_then(Object::as_name);
+      if subtype.is_ok_and(|subtype| subtype == b"Form") {
+        self.forms.entry(name.clone()).or_insert(stream);
+      }
+    }
+  }
+
+  /// Adds the fonts and forms of `parent` missing from these resources.
+  fn inherit(&mut self, parent: &Resources<'a>) {
+    for (name, font) in &parent.fonts {
+      self
+        .fonts
+        .entry(name.clone())
+        .or_insert_with(|| Rc::clone(font));
+    }
+    for (name, form) in &parent.forms {
+      self.forms.entry(name.clone()).or_insert(form);
+    }
+  }
+}
+
+/// The parts of the PDF graphics state relevant to text positioning, saved
+/// and restored as a whole by `q` and `Q`.
+#[derive(Clone)]
+struct GraphicsState<'a> {
+  ctm: Matrix,
+  font: Option<Rc<Font<'a>>>,
+  size: f32,
+  char_spacing: f32,
+  word_spacing: f32,
+  scale: f32,
+  leading: f32,
+  rise: f32,
+}
+
+impl Default for GraphicsState<'_> {
+  fn default() -> Self {
+    GraphicsState {
+      ctm: IDENTITY,
+      font: None,
+      size: 0.0,
+      char_spacing: 0.0,
+      word_spacing: 0.0,
+      scale: 1.0,
+      leading: 0.0,
+      rise: 0.0,
+    }
+  }
+}
+
+/// The graphics state, the states saved by `q`, and the text matrices, which
+/// aren't part of the graphics state.
+#[derive(Default)]
+struct TextState<'a> {
+  gs: GraphicsState<'a>,
+  stack: Vec<GraphicsState<'a>>,
+  tm: Matrix,
+  tlm: Matrix,
+}
+
+impl<'a> TextState<'a> {
+  fn new() -> Self {
+    TextState {
+      tm: IDENTITY,
+      tlm: IDENTITY,
+      ..Default::default()
+    }
+  }
+
+  fn save(&mut self) {
+    self.stack.push(self.gs.clone());
+  }
+
+  fn restore(&mut self) {
+    self.gs = self.stack.pop().unwrap_or_default();
+  }
+
+  fn move_line(&mut self, tx: f32, ty: f32) {
+    self.tlm = multiply(&translate(tx, ty), &self.tlm);
+    self.tm = self.tlm;
+  }
+
+  fn show(&mut self, bytes: &[u8], runs: &mut Vec<TextRun>) {
+    let Some(font) = self.gs.font.clone() else {
+      return;
+    };
+
+    let text = font.decode(bytes);
+    let width = if font.is_multi_byte() {
+      text
+        .chars()
+        .map(|c| {
+          let spacing = if c == ' ' { self.gs.word_spacing } else { 0.0 };
+          DEFAULT_GLYPH_WIDTH * self.gs.size + self.gs.char_spacing + spacing
+        })
+        .sum::<f32>()
+    } else {
+      bytes
+        .iter()
+        .map(|&b| {
+          let spacing = if b == b' ' { self.gs.word_spacing } else { 0.0 };
+          font.glyph_width(b) * self.gs.size + self.gs.char_spacing + spacing
+        })
+        .sum::<f32>()
+    } * self.gs.scale;
+
+    let trm = multiply(&self.tm, &self.gs.ctm);
+    let (x0, y) = apply(&trm, 0.0, self.gs.rise);
+    let (x1, _) = apply(&trm, width, self.gs.rise);
+    let (_, top) = apply(&trm, 0.0, self.gs.rise + self.gs.size);
+
+    if !text.trim().is_empty() {
+      runs.push(TextRun {
+        text,
+        x0: x0.min(x1),
+        x1: x0.max(x1),
+        y,
+        height: (top - y).abs(),
+      });
+    }
+
+    self.tm = multiply(&translate(width, 0.0), &self.tm);
+  }
+}
+
+fn operand(operands: &[Object], index: usize) -> f32 {
+  operands
+    .get(index)
+    .and_then(|o| o.as_float().ok())
+    .unwrap_or(0.0)
+}
+
+fn matrix(operands: &[Object]) -> Matrix {
+  std::array::from_fn(|i| operand(operands, i))
+}
+
+/// Returns the text of the page in reading order, and the bounding box of all
+/// text on the page.
+fn extract_page(
+  pdf: &lopdf::Document,
+  page_id: lopdf::ObjectId,
+) -> Result<(String, Option<[f32; 4]>), Error> {
+  let (page_resources, inherited) =
+    pdf.get_page_resources(page_id).map_err(pdf_error)?;
+  let mut resources = Resources::default();
+  for dict in page_resources.into_iter().chain(
+    inherited
+      .into_iter()
+      .filter_map(|id| pdf.get_dictionary(id).ok()),
+  ) {
+    resources.add(pdf, dict);
+  }
+  let data = pdf.get_page_content(page_id).map_err(pdf_error)?;
+
+  let mut state = TextState::new();
+  let mut runs = vec![];
+  interpret(pdf, &data, &resources, &mut state, &mut runs, 0)?;
+
+  Ok((reading_order(&mut runs), bounding_box(&runs)))
+}
+
+/// Collects the text runs shown by a content stream, following form XObjects
+/// drawn with `Do`.
+fn interpret<'a>(
+  pdf: &'a lopdf::Document,
+  data: &[u8],
+  resources: &Resources<'a>,
+  state: &mut TextState<'a>,
+  runs: &mut Vec<TextRun>,
+  depth: usize,
+) -> Result<(), Error> {
+  let content = Content::decode(data).map_err(pdf_error)?;
+  for op in &content.operations {
+    let operands = &op.operands;
+    match op.operator.as_str() {
+      "q" => state.save(),
+      "Q" => state.restore(),
+      "cm" => state.gs.ctm = multiply(&matrix(operands), &state.gs.ctm),
+      "Do" if depth < MAX_FORM_DEPTH => {
+        let form = operands
+          .first()
+          .and_then(|name| name.as_name().ok())
+          .and_then(|name| resources.forms.get(name));
+        if let Some(form) = form {
+          interpret_form(pdf, form, resources, state, runs, depth + 1)?;
+        }
+      }
+      "BT" => {
+        state.tm = IDENTITY;
+        state.tlm = IDENTITY;
+      }
+      "Tf" => {
+        state.gs.font = operands
+          .first()
+          .and_then(|name| name.as_name().ok())
+          .and_then(|name| resources.fonts.get(name))
+          .cloned();
+        state.gs.size = operand(operands, 1);
+      }
+      "Tc" => state.gs.char_spacing = operand(operands, 0),
+      "Tw" => state.gs.word_spacing = operand(operands, 0),
+      "Tz" => state.gs.scale = operand(operands, 0) / 100.0,
+      "TL" => state.gs.leading = operand(operands, 0),
+      "Ts" => state.gs.rise = operand(operands, 0),
+      "Td" => state.move_line(operand(operands, 0), operand(operands, 1)),
+      "TD" => {
+        state.gs.leading = -operand(operands, 1);
+        state.move_line(operand(operands, 0), operand(operands, 1));
+      }
+      "Tm" => {
+        state.tlm = matrix(operands);
+        state.tm = state.tlm;
+      }
+      "T*" => state.move_line(0.0, -state.gs.leading),
+      "Tj" => {
+        if let Some(Ok(bytes)) = operands.first().map(Object::as_str) {
+          state.show(bytes, runs);
+        }
+      }
+      "'" => {
+        state.move_line(0.0, -state.gs.leading);
+        if let Some(Ok(bytes)) = operands.first().map(Object::as_str) {
+          state.show(bytes, runs);
+        }
+      }
+      "\"" => {
+        state.gs.word_spacing = operand(operands, 0);
+        state.gs.char_spacing = operand(operands, 1);
+        state.move_line(0.0, -state.gs.leading);
+        if let Some(Ok(bytes)) = operands.get(2).map(Object::as_str) {
+          state.show(bytes, runs);
+        }
+      }
+      "TJ" => {
+        let items = operands.first().and_then(|o| o.as_array().ok());
+        for item in items.into_iter().flatten() {
+          match item {
+            Object::String(bytes, _) => state.show(bytes, runs),
+            item => {
+              let adjust = item.as_float().unwrap_or(0.0);
+              let tx = -adjust / 1000.0 * state.gs.size * state.gs.scale;
+              state.tm = multiply(&translate(tx, 0.0), &state.tm);
+            }
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+  Ok(())
+}
+
+/// Draws a form XObject: its content runs with its own resources, falling
+/// back to the ones of the stream drawing it, and its `Matrix` applied to the
+/// CTM. Like for `q` and `Q`, the graphics state is restored afterwards.
+fn interpret_form<'a>(
+  pdf: &'a lopdf::Document,
+  form: &'a Stream,
+  parent: &Resources<'a>,
+  state: &mut TextState<'a>,
+  runs: &mut Vec<TextRun>,
+  depth: usize,
+) -> Result<(), Error> {
+  let data = form
+    .decompressed_content()
+    .unwrap_or_else(|_| form.content.clone());
+  let mut resources = Resources::default();
+  if let Ok(dict) = form
+    .dict
+    .get_deref(b"Resources", pdf)
+    .and_then(Object::as_dict)
+  {
+    resources.add(pdf, dict);
+  }
+  resources.inherit(parent);
+  let form_matrix = form
+    .dict
+    .get_deref(b"Matrix", pdf)
+    .and_then(Object::as_array)
+    .map_or(IDENTITY, |operands| matrix(operands));
+
+  let depth_before = state.stack.len();
+  state.save();
+  state.gs.ctm = multiply(&form_matrix, &state.gs.ctm);
+  let result = interpret(pdf, &data, &resources, state, runs, depth);
+  // Drop states the form saved without restoring them.
+  state.stack.truncate(depth_before + 1);
+  state.restore();
+  result
+}
+
+fn bounding_box(runs: &[TextRun]) -> Option<[f32; 4]> {
+  runs.iter().fold(None, |bbox, run| {
+    let [x0, y0, x1, y1] =
+      bbox.unwrap_or([f32::MAX, f32::MAX, f32::MIN, f32::MIN]);
+    Some([
+      x0.min(run.x0),
+      y0.min(run.y),
+      x1.max(run.x1),
+      y1.max(run.y + run.height),
+    ])
+  })
+}
+
+fn reading_order(runs: &mut [TextRun]) -> String {
+  // Top to bottom, then left to right.
+  runs.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x0.total_cmp(&b.x0)));
+
+  let mut lines: Vec<Vec<&TextRun>> = vec![];
+  for run in runs.iter() {
+    match lines.last_mut() {
+      // Runs whose baselines are within half a line of each other are on the
+      // same line.
+      Some(line)
+        if (line[0].y - run.y).abs()
+          < 0.5 * line[0].height.min(run.height).max(1.0) =>
+      {
+        line.push(run)
+      }
+      _ => lines.push(vec![run]),
+    }
+  }
+
+  let mut text = String::new();
+  for (i, line) in lines.iter_mut().enumerate() {
+    if i > 0 {
+      text.push('\n');
+    }
+    line.sort_by(|a, b| a.x0.total_cmp(&b.x0));
+    let mut prev: Option<&TextRun> = None;
+    for run in line.iter() {
+      if let Some(prev) = prev {
+        // Insert a space if there is a visible gap between the runs.
+        let gap = run.x0 - prev.x1;
+        if gap > 0.15 * run.height
+          && !prev.text.ends_with(char::is_whitespace)
+          && !run.text.starts_with(char::is_whitespace)
+        {
+          text.push(' ');
+        }
+      }
+      text.push_str(&run.text);
+      prev = Some(run);
+    }
+  }
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    chunk::{
+      simple::SimpleChunkerBuilder,
+      Chunker,
+    },
+    element::Element,
+    tag::TagValue,
+  };
+  use lopdf::{
+    content::Operation,
+    dictionary,
+    Stream,
+  };
+
+  fn text_op(x: i64, y: i64, text: &str) -> Vec<Operation> {
+    vec![
+      Operation::new("BT", vec![]),
+      Operation::new("Tf", vec!["F1".into(), 10.into()]),
+      Operation::new("Td", vec![x.into(), y.into()]),
+      Operation::new("Tj", vec![Object::string_literal(text)]),
+      Operation::new("ET", vec![]),
+    ]
+  }
+
+  /// Builds a PDF with one page per entry, each page showing `(x, y, text)`
+  /// runs in the given (not necessarily reading) order.
+  fn build_pdf(pages: &[Vec<(i64, i64, &str)>]) -> Vec<u8> {
+    let contents = pages.iter().map(|runs| {
+      let operations: Vec<Operation> = runs
+        .iter()
+        .flat_map(|(x, y, text)| text_op(*x, *y, text))
+        .collect();
+      Content { operations }.encode().unwrap()
+    });
+    build_pdf_from(contents.collect())
+  }
+
+  /// Builds a PDF with one page per content stream.
+  fn build_pdf_from(contents: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut doc = lopdf::Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let font_id = doc.add_object(dictionary! {
+      "Type" => "Font",
+      "Subtype" => "Type1",
+      "BaseFont" => "Courier",
+      "Encoding" => "WinAnsiEncoding",
+    });
+    let resources_id = doc.add_object(dictionary! {
+      "Font" => dictionary! { "F1" => font_id },
+    });
+
+    let mut kids = vec![];
+    for content in contents {
+      let content_id = doc.add_object(Stream::new(dictionary! {}, content));
+      let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "Contents" => content_id,
+      });
+      kids.push(page_id.into());
+    }
+
+    let count = kids.len() as i64;
+    doc.objects.insert(
+      pages_id,
+      Object::Dictionary(dictionary! {
+        "Type" => "Pages",
+        "Kids" => kids,
+        "Count" => count,
+        "Resources" => resources_id,
+        "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+      }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+      "Type" => "Catalog",
+      "Pages" => pages_id,
+    });
+    let info_id = doc.add_object(dictionary! {
+      "Title" => Object::string_literal("Annual Report"),
+    });
+    doc.trailer.set("Root", catalog_id);
+    doc.trailer.set("Info", info_id);
+
+    let mut bytes = vec![];
+    doc.save_to(&mut bytes).unwrap();
+    bytes
+  }
+
+  fn page(element: &Element) -> String {
+    element.tag("page").unwrap().value.to_string()
+  }
+
+  #[test]
+  fn pages_in_reading_order() {
+    let pdf = build_pdf(&[
+      vec![(72, 700, "world"), (72, 720, "Hello")],
+      vec![(150, 720, "right"), (72, 720, "left")],
+    ]);
+
+    let loader = PdfLoaderBuilder::default().build().unwrap();
+    let loaded = loader.process(pdf.as_slice()).unwrap();
+
+    assert_eq!(loaded.document.text(), Some("Hello\nworld\n\nleft right"));
+    assert_eq!(loaded.document.title(), Some("Annual Report"));
+    assert_eq!(loaded.document.mime_type(), Some("application/pdf"));
+
+    let contents = loaded
+      .elements
+      .iter()
+      .map(|el| el.content())
+      .collect::<Vec<_>>();
+    assert_eq!(contents, vec!["Hello\nworld", "left right"]);
+    assert_eq!(loaded.elements[1].loc().as_tuple(), (13, 23));
+    assert_eq!(page(&loaded.elements[1]), "2");
+
+    // The font has no `Widths`, so glyphs are estimated at 0.5em: "world" is
+    // 5 glyphs at 10pt.
+    let bbox = &loaded.elements[0].tag("bbox").unwrap().value;
+    assert_eq!(bbox, &TagValue::from(vec![72.0, 700.0, 97.0, 730.0]));
+  }
+
+  #[test]
+  fn text_in_form_xobjects() {
+    let mut doc = lopdf::Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let font_id = doc.add_object(dictionary! {
+      "Type" => "Font",
+      "Subtype" => "Type1",
+      "BaseFont" => "Courier",
+    });
+    // The form has its own resources and moves its content up 20 units.
+    let form = Content {
+      operations: text_op(0, 700, "In a form"),
+    };
+    let form_id = doc.add_object(Stream::new(
+      dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        "Matrix" => vec![1.into(), 0.into(), 0.into(), 1.into(), 0.into(), 20.into()],
+        "Resources" => dictionary! {
+          "Font" => dictionary! { "F1" => font_id },
+        },
+      },
+      form.encode().unwrap(),
+    ));
+    let mut operations = text_op(72, 760, "Top");
+    operations.extend([
+      Operation::new("q", vec![]),
+      Operation::new(
+        "cm",
+        vec![1.into(), 0.into(), 0.into(), 1.into(), 100.into(), 0.into()],
+      ),
+      Operation::new("Do", vec!["Fm1".into()]),
+      Operation::new("Q", vec![]),
+    ]);
+    let content_id = doc.add_object(Stream::new(
+      dictionary! {},
+      Content { operations }.encode().unwrap(),
+    ));
+    let page_id = doc.add_object(dictionary! {
+      "Type" => "Page",
+      "Parent" => pages_id,
+      "Contents" => content_id,
+      "Resources" => dictionary! {
+        "Font" => dictionary! { "F1" => font_id },
+        "XObject" => dictionary! { "Fm1" => form_id },
+      },
+    });
+    doc.objects.insert(
+      pages_id,
+      Object::Dictionary(dictionary! {
+        "Type" => "Pages",
+        "Kids" => vec![page_id.into()],
+        "Count" => 1,
+      }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+      "Type" => "Catalog",
+      "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+    let mut pdf = vec![];
+    doc.save_to(&mut pdf).unwrap();
+
+    let loader = PdfLoaderBuilder::default().build().unwrap();
+    let loaded = loader.process(pdf.as_slice()).unwrap();
+    assert_eq!(loaded.document.text(), Some("Top\nIn a form"));
+
+    // The form's text is placed with both the CTM and the form's matrix.
+    let bbox = &loaded.elements[0].tag("bbox").unwrap().value;
+    assert_eq!(bbox, &TagValue::from(vec![72.0, 720.0, 145.0, 770.0]));
+  }
+
+  #[test]
+  fn restores_text_state() {
+    // The font size set between `q` and `Q` doesn't apply after `Q`.
+    let mut operations = vec![
+      Operation::new("Tf", vec!["F1".into(), 10.into()]),
+      Operation::new("q", vec![]),
+      Operation::new("Tf", vec!["F1".into(), 40.into()]),
+      Operation::new("Q", vec![]),
+    ];
+    operations.extend([
+      Operation::new("BT", vec![]),
+      Operation::new("Td", vec![72.into(), 700.into()]),
+      Operation::new("Tj", vec![Object::string_literal("after")]),
+      Operation::new("ET", vec![]),
+    ]);
+    let pdf = build_pdf_from(vec![Content { operations }.encode().unwrap()]);
+
+    let loader = PdfLoaderBuilder::default().build().unwrap();
+    let loaded = loader.process(pdf.as_slice()).unwrap();
+    let bbox = &loaded.elements[0].tag("bbox").unwrap().value;
+    assert_eq!(bbox, &TagValue::from(vec![72.0, 700.0, 97.0, 710.0]));
+  }
+
+  #[test]
+  fn skips_unreadable_pages() {
+    let pdf =
+      build_pdf(&[vec![(72, 720, "readable")], vec![(72, 720, "unreadable")]]);
+    // Break the second page: its parent, where resources are inherited
+    // from, isn't a dictionary.
+    let mut doc = lopdf::Document::load_mem(&pdf).unwrap();
+    let broken = doc.add_object(1);
+    let page_id = doc.get_pages()[&2];
+    let page = doc.get_dictionary_mut(page_id).unwrap();
+    page.set("Parent", broken);
+    let mut pdf = vec![];
+    doc.save_to(&mut pdf).unwrap();
+
+    let loader = PdfLoaderBuilder::default().build().unwrap();
+    let loaded = loader.process(pdf.as_slice()).unwrap();
+    assert_eq!(loaded.document.text(), Some("readable"));
+    assert_eq!(loaded.elements.len(), 1);
+    let meta = loaded.document.meta();
+    assert_eq!(meta.extra["pages"], 2);
+    assert_eq!(meta.extra["unreadable_pages"], serde_json::json!([2]));
+  }
+
+  #[test]
+  fn chunks_map_back_to_pages() {
+    let pdf = build_pdf(&[
+      vec![(72, 720, "first page")],
+      vec![(72, 720, "second page")],
+    ]);
+    let document = BinaryDocument::new(pdf, BinaryFormat::Pdf).as_document();
+
+    let loader = PdfLoaderBuilder::default().build().unwrap();
+    let loaded = loader.process(&document).unwrap();
+
+    let chunker = SimpleChunkerBuilder::default()
+      .chunk_size(10u32)
+      .build()
+      .unwrap();
+    let chunks = chunker.chunk(loaded.document.text().unwrap()).unwrap();
+    let pages = chunks
+      .iter()
+      .map(|chunk| {
+        loaded
+          .elements_at(chunk.loc())
+          .map(page)
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+
+    // "first page" | "\n\nsecond " | "page"
+    assert_eq!(pages, vec![vec!["1"], vec!["2"], vec!["2"]]);
+  }
+
+  #[test]
+  fn rejects_other_documents() {
+    let loader = PdfLoaderBuilder::default().build().unwrap();
+    let document = TextDocument::new("not a pdf").as_document();
+    assert!(matches!(
+      loader.process(&document),
+      Err(Error::UnsupportedDocument(_))
+    ));
+    assert!(matches!(
+      loader.process(b"garbage".as_slice()),
+      Err(Error::Parse { .. })
+    ));
+  }
+}
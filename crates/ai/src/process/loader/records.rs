@@ -0,0 +1,803 @@
+//! Loaders for record based formats, CSV/TSV and JSON Lines, turning every
+//! record into its own `Document`.
+//!
+//! Both loaders stream their input. `records` returns an iterator that reads
+//! one record at a time, so large files never have to be held in memory. A
+//! record that can't be read yields an `Error::InvalidRecord` with its line
+//! number and the iterator moves on to the next record; only errors that make
+//! the rest of the input unreadable (I/O errors, unreadable headers) end the
+//! stream.
+
+use crate::{
+  describe,
+  document::{
+    Document,
+    DocumentMetadata,
+    TextDocument,
+  },
+  error::Error,
+  process::loader::file_uri,
+  telemetry::{
+    self,
+    Size,
+  },
+  traits::Processor,
+};
+use derive_builder::Builder;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+  error::Error as StdError,
+  fs::{
+    self,
+    File,
+  },
+  io::{
+    BufRead,
+    BufReader,
+    Read,
+  },
+  path::Path,
+  sync::Arc,
+};
+
+/// Loads every row of a CSV file as a `TextDocument`.
+///
+/// The content of a document is made from its text columns: rendered with
+/// `template` when one is set, otherwise the values of `text_columns` joined
+/// by newlines. Without either every column is used, as `column: value`
+/// lines. The remaining columns are added to the `extra` metadata, as
+/// strings. `id_column` and `title_column` populate the `id` and `title`
+/// metadata instead.
+///
+/// Without headers, columns are named by their 0-based index.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct CsvLoader {
+  /// The field delimiter, `\t` for TSV.
+  #[builder(default = "b','")]
+  delimiter: u8,
+
+  /// Whether fields may be quoted. Quoted fields can contain delimiters and
+  /// line breaks.
+  #[builder(default = "true")]
+  quoting: bool,
+
+  /// Whether the first row names the columns.
+  #[builder(default = "true")]
+  has_headers: bool,
+
+  /// Whether rows may have a different number of fields than the header.
+  /// Fields without a header are named by their index.
+  #[builder(default)]
+  flexible: bool,
+
+  #[builder(default)]
+  text_columns: Vec<String>,
+
+  /// A template for the content, referring to columns as `{column}`. Use
+  /// `{{` and `}}` for literal braces.
+  #[builder(default, setter(strip_option))]
+  template: Option<String>,
+
+  #[builder(default, setter(strip_option))]
+  id_column: Option<String>,
+
+  #[builder(default, setter(strip_option))]
+  title_column: Option<String>,
+}
+
+impl CsvLoader {
+  /// A builder preconfigured for tab separated values.
+  pub fn tsv() -> CsvLoaderBuilder {
+    let mut builder = CsvLoaderBuilder::default();
+    builder.delimiter(b'\t');
+    builder
+  }
+
+  /// Streams the rows of `reader` as documents. Fails if the template is
+  /// invalid or the headers can't be read.
+  pub fn records<R: Read>(&self, reader: R) -> Result<CsvRecords<R>, Error> {
+    self.records_with_meta(reader, DocumentMetadata::default())
+  }
+
+  fn load_file(
+    &self,
+    path: &Path,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    let file = File::open(path).map_err(|err| Error::io(path, err))?;
+    let records = self.records_with_meta(file, file_meta(path))?;
+    Ok(records.collect())
+  }
+
+  fn load_document(
+    &self,
+    input: &Document,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    let text = text_of(input, "csv")?;
+    let records =
+      self.records_with_meta(text.as_bytes(), source_meta(input))?;
+    Ok(records.collect())
+  }
+
+  fn records_with_meta<R: Read>(
+    &self,
+    reader: R,
+    meta: DocumentMetadata,
+  ) -> Result<CsvRecords<R>, Error> {
+    let fields = RecordFields::new(
+      &self.text_columns,
+      self.template.as_deref(),
+      &self.id_column,
+      &self.title_column,
+    )?;
+    let mut reader = csv::ReaderBuilder::new()
+      .delimiter(self.delimiter)
+      .quoting(self.quoting)
+      .has_headers(self.has_headers)
+      .flexible(self.flexible)
+      .from_reader(reader);
+    let headers = if self.has_headers {
+      let headers = reader.headers().map_err(|err| csv_error(err, 1))?;
+      headers.iter().map(str::to_string).collect()
+    } else {
+      vec![]
+    };
+    Ok(CsvRecords {
+      reader,
+      headers,
+      fields,
+      meta,
+      record: csv::StringRecord::new(),
+      done: false,
+    })
+  }
+}
+
+impl<'p> Processor<&'p Path, Vec<Result<Document<'static>, Error>>>
+  for CsvLoader
+{
+  fn process(
+    &self,
+    path: &'p Path,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    telemetry::processor("CsvLoader", None, || self.load_file(path))
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p> Processor<&'p [u8], Vec<Result<Document<'static>, Error>>>
+  for CsvLoader
+{
+  fn process(
+    &self,
+    input: &'p [u8],
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    telemetry::processor("CsvLoader", Some(input.len()), || {
+      Ok(self.records(input)?.collect())
+    })
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p Document<'d>, Vec<Result<Document<'static>, Error>>>
+  for CsvLoader
+{
+  fn process(
+    &self,
+    input: &'p Document<'d>,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    telemetry::processor("CsvLoader", Some(input.size()), || {
+      self.load_document(input)
+    })
+  }
+
+  describe::impl_config!();
+}
+
+/// An iterator over the rows of a CSV input, see `CsvLoader::records`.
+pub struct CsvRecords<R> {
+  reader: csv::Reader<R>,
+  headers: Vec<String>,
+  fields: RecordFields,
+  meta: DocumentMetadata,
+  record: csv::StringRecord,
+  done: bool,
+}
+
+impl<R: Read> Iterator for CsvRecords<R> {
+  type Item = Result<Document<'static>, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    match self.reader.read_record(&mut self.record) {
+      Ok(true) => {}
+      Ok(false) => {
+        self.done = true;
+        return None;
+      }
+      Err(err) => {
+        // The reader can't recover from I/O errors, but it does skip past
+        // malformed records.
+        if let csv::ErrorKind::Io(_) = err.kind() {
+          self.done = true;
+        }
+        let line = err
+          .position()
+          .unwrap_or_else(|| self.reader.position())
+          .line();
+        return Some(Err(csv_error(err, line)));
+      }
+    }
+
+    let fields = self
+      .record
+      .iter()
+      .enumerate()
+      .map(|(index, value)| {
+        let name = match self.headers.get(index) {
+          Some(header) => header.clone(),
+          None => index.to_string(),
+        };
+        (name, Value::String(value.to_string()))
+      })
+      .collect::<Vec<_>>();
+    Some(Ok(self.fields.document(&fields, self.meta.clone())))
+  }
+}
+
+/// Loads every line of a JSON Lines file as a `TextDocument`. Each line must
+/// hold a JSON object, blank lines are skipped.
+///
+/// Content and metadata are built from the top-level fields of the object
+/// like `CsvLoader` does with columns. The remaining fields are added to the
+/// `extra` metadata as their JSON values.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct JsonlLoader {
+  #[builder(default)]
+  text_fields: Vec<String>,
+
+  /// A template for the content, referring to fields as `{field}`. Use `{{`
+  /// and `}}` for literal braces. Missing fields render as empty text.
+  #[builder(default, setter(strip_option))]
+  template: Option<String>,
+
+  #[builder(default, setter(strip_option))]
+  id_field: Option<String>,
+
+  #[builder(default, setter(strip_option))]
+  title_field: Option<String>,
+}
+
+impl JsonlLoader {
+  /// Streams the records of `reader` as documents. Fails if the template is
+  /// invalid.
+  pub fn records<R: BufRead>(
+    &self,
+    reader: R,
+  ) -> Result<JsonlRecords<R>, Error> {
+    self.records_with_meta(reader, DocumentMetadata::default())
+  }
+
+  fn load_file(
+    &self,
+    path: &Path,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    let file = File::open(path).map_err(|err| Error::io(path, err))?;
+    let records =
+      self.records_with_meta(BufReader::new(file), file_meta(path))?;
+    Ok(records.collect())
+  }
+
+  fn load_document(
+    &self,
+    input: &Document,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    let text = text_of(input, "jsonl")?;
+    let records =
+      self.records_with_meta(text.as_bytes(), source_meta(input))?;
+    Ok(records.collect())
+  }
+
+  fn records_with_meta<R: BufRead>(
+    &self,
+    reader: R,
+    meta: DocumentMetadata,
+  ) -> Result<JsonlRecords<R>, Error> {
+    let fields = RecordFields::new(
+      &self.text_fields,
+      self.template.as_deref(),
+      &self.id_field,
+      &self.title_field,
+    )?;
+    Ok(JsonlRecords {
+      reader,
+      fields,
+      meta,
+      line: 0,
+      buffer: vec![],
+      done: false,
+    })
+  }
+}
+
+impl<'p> Processor<&'p Path, Vec<Result<Document<'static>, Error>>>
+  for JsonlLoader
+{
+  fn process(
+    &self,
+    path: &'p Path,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    telemetry::processor("JsonlLoader", None, || self.load_file(path))
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p> Processor<&'p [u8], Vec<Result<Document<'static>, Error>>>
+  for JsonlLoader
+{
+  fn process(
+    &self,
+    input: &'p [u8],
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    telemetry::processor("JsonlLoader", Some(input.len()), || {
+      Ok(self.records(input)?.collect())
+    })
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p Document<'d>, Vec<Result<Document<'static>, Error>>>
+  for JsonlLoader
+{
+  fn process(
+    &self,
+    input: &'p Document<'d>,
+  ) -> Result<Vec<Result<Document<'static>, Error>>, Error> {
+    telemetry::processor("JsonlLoader", Some(input.size()), || {
+      self.load_document(input)
+    })
+  }
+
+  describe::impl_config!();
+}
+
+/// An iterator over the records of a JSON Lines input, see
+/// `JsonlLoader::records`.
+pub struct JsonlRecords<R> {
+  reader: R,
+  fields: RecordFields,
+  meta: DocumentMetadata,
+  line: u64,
+  buffer: Vec<u8>,
+  done: bool,
+}
+
+impl<R: BufRead> Iterator for JsonlRecords<R> {
+  type Item = Result<Document<'static>, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if self.done {
+        return None;
+      }
+      self.buffer.clear();
+      match self.reader.read_until(b'\n', &mut self.buffer) {
+        Ok(0) => {
+          self.done = true;
+          return None;
+        }
+        Ok(_) => self.line += 1,
+        Err(err) => {
+          self.done = true;
+          return Some(Err(jsonl_error(self.line + 1, err)));
+        }
+      }
+      if self.buffer.iter().all(u8::is_ascii_whitespace) {
+        continue;
+      }
+
+      let fields = match serde_json::from_slice::<Value>(&self.buffer) {
+        Ok(Value::Object(object)) => object.into_iter().collect::<Vec<_>>(),
+        Ok(_) => {
+          return Some(Err(Error::InvalidRecord {
+            format: "jsonl",
+            line: self.line,
+            message: "expected a JSON object".to_string(),
+            source: None,
+          }));
+        }
+        Err(err) => return Some(Err(jsonl_error(self.line, err))),
+      };
+      return Some(Ok(self.fields.document(&fields, self.meta.clone())));
+    }
+  }
+}
+
+/// Decides which fields of a record make up the content of its document and
+/// which become metadata.
+#[derive(Clone, Debug)]
+struct RecordFields {
+  template: Option<Template>,
+  text_fields: Vec<String>,
+  id_field: Option<String>,
+  title_field: Option<String>,
+}
+
+impl RecordFields {
+  fn new(
+    text_fields: &[String],
+    template: Option<&str>,
+    id_field: &Option<String>,
+    title_field: &Option<String>,
+  ) -> Result<Self, Error> {
+    Ok(Self {
+      template: template.map(Template::parse).transpose()?,
+      text_fields: text_fields.to_vec(),
+      id_field: id_field.clone(),
+      title_field: title_field.clone(),
+    })
+  }
+
+  fn is_text(&self, name: &str) -> bool {
+    match &self.template {
+      Some(template) => {
+        template.fields().any(|field| field == name)
+          || self.text_fields.iter().any(|field| field == name)
+      }
+      None if self.text_fields.is_empty() => !self.is_meta(name),
+      None => self.text_fields.iter().any(|field| field == name),
+    }
+  }
+
+  fn is_meta(&self, name: &str) -> bool {
+    self.id_field.as_deref() == Some(name)
+      || self.title_field.as_deref() == Some(name)
+  }
+
+  fn document(
+    &self,
+    fields: &[(String, Value)],
+    mut meta: DocumentMetadata,
+  ) -> Document<'static> {
+    let get = |name: &str| {
+      fields
+        .iter()
+        .find(|(field, _)| field == name)
+        .map(|(_, value)| value_text(value))
+    };
+
+    let content = if let Some(template) = &self.template {
+      template.render(|name| get(name).unwrap_or_default())
+    } else if !self.text_fields.is_empty() {
+      self
+        .text_fields
+        .iter()
+        .filter_map(|name| get(name))
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+    } else {
+      fields
+        .iter()
+        .filter(|(name, _)| self.is_text(name))
+        .map(|(name, value)| (name, value_text(value)))
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| format!("{name}: {value}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+
+    if let Some(id) = self.id_field.as_deref().and_then(get) {
+      meta.id = Some(id);
+    }
+    if let Some(title) = self.title_field.as_deref().and_then(get) {
+      meta.title = Some(title);
+    }
+    for (name, value) in fields {
+      if !self.is_text(name) && !self.is_meta(name) {
+        meta.extra.insert(name.clone(), value.clone());
+      }
+    }
+
+    TextDocument::new(content).with_meta(meta).as_document()
+  }
+}
+
+/// The text of a field value. Strings are used as is, without quotes.
+fn value_text(value: &Value) -> String {
+  match value {
+    Value::Null => String::new(),
+    Value::String(value) => value.clone(),
+    value => value.to_string(),
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+  Text(String),
+  Field(String),
+}
+
+/// A content template with `{field}` placeholders.
+#[derive(Clone, Debug, PartialEq)]
+struct Template {
+  parts: Vec<Part>,
+}
+
+impl Template {
+  fn parse(template: &str) -> Result<Self, Error> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+      match c {
+        '{' if chars.peek() == Some(&'{') => {
+          chars.next();
+          text.push('{');
+        }
+        '}' if chars.peek() == Some(&'}') => {
+          chars.next();
+          text.push('}');
+        }
+        '{' => {
+          let mut field = String::new();
+          loop {
+            match chars.next() {
+              Some('}') => break,
+              Some(c) => field.push(c),
+              None => {
+                return Err(Error::InvalidTemplate(format!(
+                  "unclosed `{{` in {template:?}"
+                )))
+              }
+            }
+          }
+          let field = field.trim();
+          if field.is_empty() {
+            return Err(Error::InvalidTemplate(format!(
+              "empty field name in {template:?}"
+            )));
+          }
+          if !text.is_empty() {
+            parts.push(Part::Text(std::mem::take(&mut text)));
+          }
+          parts.push(Part::Field(field.to_string()));
+        }
+        '}' => {
+          return Err(Error::InvalidTemplate(format!(
+            "unmatched `}}` in {template:?}, use `}}}}` for a literal brace"
+          )))
+        }
+        c => text.push(c),
+      }
+    }
+    if !text.is_empty() {
+      parts.push(Part::Text(text));
+    }
+    Ok(Self { parts })
+  }
+
+  fn fields(&self) -> impl Iterator<Item = &str> {
+    self.parts.iter().filter_map(|part| match part {
+      Part::Field(field) => Some(field.as_str()),
+      Part::Text(_) => None,
+    })
+  }
+
+  fn render(&self, value: impl Fn(&str) -> String) -> String {
+    let mut rendered = String::new();
+    for part in &self.parts {
+      match part {
+        Part::Text(text) => rendered.push_str(text),
+        Part::Field(field) => rendered.push_str(&value(field)),
+      }
+    }
+    rendered
+  }
+}
+
+fn text_of<'d>(
+  input: &'d Document<'_>,
+  format: &str,
+) -> Result<&'d str, Error> {
+  input.text().ok_or_else(|| {
+    Error::UnsupportedDocument(format!("expected a {format} text document"))
+  })
+}
+
+/// Metadata shared by all records loaded from `input`.
+fn source_meta(input: &Document) -> DocumentMetadata {
+  DocumentMetadata {
+    source: input.source().map(str::to_string),
+    ..Default::default()
+  }
+}
+
+/// Metadata shared by all records loaded from the file at `path`.
+fn file_meta(path: &Path) -> DocumentMetadata {
+  let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+  DocumentMetadata {
+    source: Some(file_uri(&absolute)),
+    ..Default::default()
+  }
+}
+
+fn csv_error(err: csv::Error, line: u64) -> Error {
+  // Use the underlying messages, the position is reported separately.
+  let message = match err.kind() {
+    csv::ErrorKind::Io(err) => err.to_string(),
+    csv::ErrorKind::Utf8 { err, .. } => err.to_string(),
+    csv::ErrorKind::UnequalLengths {
+      expected_len, len, ..
+    } => format!("expected {expected_len} fields, found {len}"),
+    _ => err.to_string(),
+  };
+  Error::InvalidRecord {
+    format: "csv",
+    line,
+    message,
+    source: Some(Arc::new(err)),
+  }
+}
+
+fn jsonl_error(line: u64, err: impl StdError + Send + Sync + 'static) -> Error {
+  Error::InvalidRecord {
+    format: "jsonl",
+    line,
+    message: err.to_string(),
+    source: Some(Arc::new(err)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn csv_with_template() {
+    let csv = "id,title,body,author\n\
+               1,Hello,\"Hi, there\",ann\n\
+               2,Quotes,\"She said \"\"hi\"\"\nand left\",bob\n";
+    let loader = CsvLoaderBuilder::default()
+      .template("{title}\n\n{body}")
+      .id_column("id")
+      .build()
+      .unwrap();
+
+    let documents = loader
+      .records(csv.as_bytes())
+      .unwrap()
+      .collect::<Result<Vec<_>, _>>()
+      .unwrap();
+    assert_eq!(documents.len(), 2);
+    assert_eq!(documents[0].text(), Some("Hello\n\nHi, there"));
+    assert_eq!(
+      documents[1].text(),
+      Some("Quotes\n\nShe said \"hi\"\nand left")
+    );
+    assert_eq!(documents[0].id(), Some("1"));
+
+    let extra = &documents[1].meta().extra;
+    assert_eq!(extra.len(), 1);
+    assert_eq!(extra["author"], "bob");
+  }
+
+  #[test]
+  fn tsv_without_headers() {
+    let tsv = "a\tb\tc\nd\te\tf\n";
+    let loader = CsvLoader::tsv()
+      .has_headers(false)
+      .text_columns(vec!["1".to_string()])
+      .build()
+      .unwrap();
+
+    let documents = loader.process(tsv.as_bytes()).unwrap();
+    let documents = documents.into_iter().collect::<Result<Vec<_>, _>>();
+    let documents = documents.unwrap();
+    assert_eq!(documents[1].text(), Some("e"));
+    assert_eq!(documents[1].meta().extra["0"], "d");
+    assert_eq!(documents[1].meta().extra["2"], "f");
+  }
+
+  #[test]
+  fn csv_reports_bad_rows() {
+    let csv = "a,b\n1,2\n3\n4,5\n";
+    let loader = CsvLoaderBuilder::default().build().unwrap();
+
+    let records = loader.records(csv.as_bytes()).unwrap().collect::<Vec<_>>();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].as_ref().unwrap().text(), Some("a: 1\nb: 2"));
+    assert!(matches!(
+      records[1],
+      Err(Error::InvalidRecord {
+        format: "csv",
+        line: 3,
+        ..
+      })
+    ));
+    assert_eq!(records[2].as_ref().unwrap().text(), Some("a: 4\nb: 5"));
+  }
+
+  #[test]
+  fn jsonl_records() {
+    let jsonl = concat!(
+      r#"{"id": "a", "text": "first", "score": 0.5, "tags": ["x"]}"#,
+      "\n\n",
+      "not json\n",
+      "[1, 2]\n",
+      r#"{"id": "b", "text": "second", "score": null}"#,
+      "\n",
+    );
+    let loader = JsonlLoaderBuilder::default()
+      .text_fields(vec!["text".to_string()])
+      .id_field("id")
+      .build()
+      .unwrap();
+
+    let records = loader
+      .records(jsonl.as_bytes())
+      .unwrap()
+      .collect::<Vec<_>>();
+    assert_eq!(records.len(), 4);
+
+    let first = records[0].as_ref().unwrap();
+    assert_eq!(first.text(), Some("first"));
+    assert_eq!(first.id(), Some("a"));
+    assert_eq!(first.meta().extra["score"], 0.5);
+    assert_eq!(first.meta().extra["tags"], serde_json::json!(["x"]));
+
+    assert!(matches!(
+      records[1],
+      Err(Error::InvalidRecord { line: 3, .. })
+    ));
+    assert!(matches!(
+      records[2],
+      Err(Error::InvalidRecord { line: 4, .. })
+    ));
+    assert_eq!(records[3].as_ref().unwrap().id(), Some("b"));
+  }
+
+  #[test]
+  fn file_sources() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("my notes.jsonl");
+    fs::write(&path, "{\"text\": \"hi\"}\n").unwrap();
+    let loader = JsonlLoaderBuilder::default().build().unwrap();
+
+    let documents = loader.process(path.as_path()).unwrap();
+    let source = documents[0].as_ref().unwrap().meta().source.clone();
+    let source = source.unwrap();
+    assert!(source.starts_with("file:///"), "{source}");
+    assert!(source.ends_with("/my%20notes.jsonl"), "{source}");
+  }
+
+  #[test]
+  fn invalid_templates() {
+    for template in ["{title", "title}", "{}"] {
+      let loader = JsonlLoaderBuilder::default()
+        .template(template)
+        .build()
+        .unwrap();
+      assert!(matches!(
+        loader.records(&b""[..]),
+        Err(Error::InvalidTemplate(_))
+      ));
+    }
+
+    let template = Template::parse("{{{a}}} {b}").unwrap();
+    assert_eq!(
+      template.render(|field| field.to_uppercase()),
+      "{A} B".to_string()
+    );
+  }
+}
@@ -0,0 +1,197 @@
+//! A small in-memory XML tree for loaders of XML based formats. The files
+//! these loaders read comfortably fit in memory, and walking a tree is a lot
+//! simpler than tracking state across a stream of events.
+
+use crate::error::Error;
+use quick_xml::{
+  events::{
+    BytesStart,
+    Event,
+  },
+  Reader,
+};
+use std::sync::Arc;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Node {
+  /// The local name, without a namespace prefix.
+  pub name: String,
+  /// Attributes keyed by their qualified name, e.g. `r:id`.
+  pub attrs: Vec<(String, String)>,
+  pub children: Vec<Child>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Child {
+  Node(Node),
+  Text(String),
+}
+
+impl Node {
+  /// Looks up an attribute. Qualified names (`r:id`) must match exactly,
+  /// unqualified names match the local name of any attribute.
+  pub fn attr(&self, name: &str) -> Option<&str> {
+    let matches = |key: &str| {
+      if name.contains(':') {
+        key == name
+      } else {
+        key.rsplit(':').next() == Some(name)
+      }
+    };
+    self
+      .attrs
+      .iter()
+      .find(|(key, _)| matches(key))
+      .map(|(_, value)| value.as_str())
+  }
+
+  /// Direct child nodes.
+  pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+    self.children.iter().filter_map(|child| match child {
+      Child::Node(node) => Some(node),
+      Child::Text(_) => None,
+    })
+  }
+
+  /// The first direct child named `name`.
+  pub fn child(&self, name: &str) -> Option<&Node> {
+    self.nodes().find(|node| node.name == name)
+  }
+
+  /// The first descendant named `name`, in document order.
+  pub fn find(&self, name: &str) -> Option<&Node> {
+    self.nodes().find_map(|node| {
+      if node.name == name {
+        Some(node)
+      } else {
+        node.find(name)
+      }
+    })
+  }
+
+  /// All descendants named `name`, in document order. Does not descend into
+  /// matching nodes.
+  pub fn find_all<'a>(&'a self, name: &str) -> Vec<&'a Node> {
+    let mut found = vec![];
+    for node in self.nodes() {
+      if node.name == name {
+        found.push(node);
+      } else {
+        found.extend(node.find_all(name));
+      }
+    }
+    found
+  }
+
+  /// All text in this node and its descendants.
+  pub fn text(&self) -> String {
+    let mut text = String::new();
+    self.collect_text(&mut text);
+    text
+  }
+
+  fn collect_text(&self, text: &mut String) {
+    for child in &self.children {
+      match child {
+        Child::Node(node) => node.collect_text(text),
+        Child::Text(t) => text.push_str(t),
+      }
+    }
+  }
+}
+
+/// Parses `xml` into a tree and returns the root element. `format` is only
+/// used for error messages. Entities other than the predefined XML ones are
+/// resolved with `entity`.
+pub(crate) fn parse_with(
+  format: &'static str,
+  xml: &str,
+  entity: impl Fn(&str) -> Option<&'static str>,
+) -> Result<Node, Error> {
+  let mut reader = Reader::from_str(xml);
+
+  // The bottom of the stack is a synthetic document node.
+  let mut stack = vec![Node::default()];
+  loop {
+    let position = reader.buffer_position();
+    let event = match reader.read_event() {
+      Ok(event) => event,
+      Err(err) => return Err(error(format, err, position)),
+    };
+    match event {
+      Event::Start(start) => {
+        stack.push(node(&start).map_err(|e| error(format, e, position))?)
+      }
+      Event::Empty(start) => {
+        let node = node(&start).map_err(|e| error(format, e, position))?;
+        push(&mut stack, Child::Node(node));
+      }
+      Event::End(_) => {
+        if stack.len() > 1 {
+          let node = stack.pop().unwrap_or_default();
+          push(&mut stack, Child::Node(node));
+        }
+      }
+      Event::Text(text) => {
+        let text = text
+          .unescape_with(&entity)
+          .map_err(|e| error(format, e, position))?;
+        push(&mut stack, Child::Text(text.into_owned()));
+      }
+      Event::CData(data) => {
+        let text = String::from_utf8_lossy(&data).into_owned();
+        push(&mut stack, Child::Text(text));
+      }
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+
+  // Close anything left open by malformed input.
+  while stack.len() > 1 {
+    let node = stack.pop().unwrap_or_default();
+    push(&mut stack, Child::Node(node));
+  }
+  let document = stack.pop().unwrap_or_default();
+  document
+    .children
+    .into_iter()
+    .find_map(|child| match child {
+      Child::Node(node) => Some(node),
+      Child::Text(_) => None,
+    })
+    .ok_or_else(|| Error::malformed(format, "missing root element"))
+}
+
+fn error(format: &'static str, err: quick_xml::Error, position: u64) -> Error {
+  Error::Parse {
+    format,
+    message: format!("{err} at position {position}"),
+    source: Some(Arc::new(err)),
+  }
+}
+
+pub(crate) fn parse(format: &'static str, xml: &str) -> Result<Node, Error> {
+  parse_with(format, xml, |_| None)
+}
+
+fn node(start: &BytesStart) -> Result<Node, quick_xml::Error> {
+  let name = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+  let mut attrs = vec![];
+  for attr in start.attributes().with_checks(false) {
+    let attr = attr?;
+    let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+    attrs.push((key, attr.unescape_value()?.into_owned()));
+  }
+  Ok(Node {
+    name,
+    attrs,
+    children: vec![],
+  })
+}
+
+fn push(stack: &mut [Node], child: Child) {
+  if let Some(parent) = stack.last_mut() {
+    parent.children.push(child);
+  }
+}
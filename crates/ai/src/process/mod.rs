@@ -1 +1,5 @@
+pub(crate) mod encoding;
+pub mod loader;
+pub mod normalize;
+pub mod partition;
 pub mod splitter;
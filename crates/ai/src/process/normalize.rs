@@ -0,0 +1,615 @@
+use crate::{
+  describe,
+  document::Document,
+  error::Error,
+  loc::Loc,
+  process::encoding,
+  telemetry,
+  traits::Processor,
+};
+use derive_builder::Builder;
+use encoding_rs::{
+  CoderResult,
+  Encoding,
+  UTF_8,
+};
+use serde::Serialize;
+use std::ops::Range;
+use unicode_normalization::{
+  char::{
+    canonical_combining_class,
+    compose,
+  },
+  UnicodeNormalization,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum NormalizationForm {
+  /// Canonical composition: e.g. `e` followed by a combining acute accent
+  /// becomes `é`.
+  Nfc,
+  /// Compatibility composition: like `Nfc`, but also folds compatibility
+  /// characters, e.g. `ﬁ` becomes `fi` and `²` becomes `2`.
+  Nfkc,
+}
+
+/// Decodes and cleans up text. In order:
+/// 1. Bytes are decoded, using the byte order mark if there is one. Otherwise
+///    the `encoding` is used if given, else UTF-8 if they're valid UTF-8 and a
+///    statistical guess if not.
+/// 2. Text is normalized to `form`.
+/// 3. Control characters other than tabs and line breaks are removed, as are
+///    zero-width characters and byte order marks.
+/// 4. Smart quotes, dashes and ellipses become their ASCII counterparts, if
+///    `ascii_punctuation` is set.
+/// 5. Whitespace is collapsed: runs of whitespace without line breaks become a
+///    single space, runs with one line break a newline and runs with more a
+///    blank line. Leading and trailing whitespace is removed.
+///
+/// The result keeps an `OffsetMap` from the normalized text back to the
+/// input, so `Loc`s into the normalized text can be translated to byte
+/// offsets in the original source.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct Normalizer {
+  /// The label of the encoding of byte input, e.g. `utf-16le` or
+  /// `windows-1252`. Detected when not set.
+  #[builder(default, setter(strip_option))]
+  encoding: Option<String>,
+
+  #[builder(default = "Some(NormalizationForm::Nfc)")]
+  form: Option<NormalizationForm>,
+
+  #[builder(default = "true")]
+  remove_control: bool,
+
+  #[builder(default)]
+  ascii_punctuation: bool,
+
+  #[builder(default = "true")]
+  collapse_whitespace: bool,
+}
+
+/// Normalized text along with the encoding it was decoded from and a map
+/// back to the original offsets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Normalized {
+  pub text: String,
+  /// The name of the encoding the input was decoded from.
+  pub encoding: &'static str,
+  pub offsets: OffsetMap,
+}
+
+/// Maps byte offsets in normalized text to byte offsets in the original
+/// input.
+///
+/// The map is made of segments pairing a range of normalized text with the
+/// range of input it came from. Within segments of equal length offsets map
+/// one to one. Other segments, e.g. a collapsed run of whitespace or a
+/// character composed from several code points, are atomic: offsets inside
+/// them map to the start or end of their original range.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OffsetMap {
+  segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Segment {
+  normalized: Range<usize>,
+  original: Range<usize>,
+}
+
+impl Segment {
+  fn is_linear(&self) -> bool {
+    self.normalized.len() == self.original.len()
+  }
+}
+
+impl OffsetMap {
+  /// The original offset of the start of whatever is at `offset` in the
+  /// normalized text. Offsets at or past the end map to the end of the last
+  /// segment.
+  pub fn to_original(&self, offset: usize) -> usize {
+    let index = self
+      .segments
+      .partition_point(|segment| segment.normalized.start <= offset);
+    let Some(segment) = index.checked_sub(1).map(|i| &self.segments[i]) else {
+      return 0;
+    };
+    if offset >= segment.normalized.end {
+      segment.original.end
+    } else if segment.is_linear() {
+      segment.original.start + (offset - segment.normalized.start)
+    } else {
+      segment.original.start
+    }
+  }
+
+  /// Like `to_original`, but for the exclusive end of a range: offsets
+  /// inside atomic segments map to the end of the segment's original range.
+  pub fn to_original_end(&self, offset: usize) -> usize {
+    if offset == 0 {
+      return self.to_original(0);
+    }
+    let index = self
+      .segments
+      .partition_point(|segment| segment.normalized.start < offset);
+    let Some(segment) = index.checked_sub(1).map(|i| &self.segments[i]) else {
+      return 0;
+    };
+    if offset >= segment.normalized.end || !segment.is_linear() {
+      segment.original.end
+    } else {
+      segment.original.start + (offset - segment.normalized.start)
+    }
+  }
+
+  /// Translates a `Loc` in the normalized text to the smallest range of the
+  /// original input covering it.
+  pub fn to_original_loc(&self, loc: &Loc) -> Loc {
+    let start = self.to_original(loc.start);
+    Loc {
+      start,
+      end: self.to_original_end(loc.end).max(start),
+      doc_id: loc.doc_id.clone(),
+    }
+  }
+
+  fn push(&mut self, normalized: Range<usize>, original: Range<usize>) {
+    let segment = Segment {
+      normalized,
+      original,
+    };
+    // Merge runs of one-to-one segments to keep the map small.
+    if let Some(last) = self.segments.last_mut() {
+      if last.is_linear()
+        && segment.is_linear()
+        && last.normalized.end == segment.normalized.start
+        && last.original.end == segment.original.start
+      {
+        last.normalized.end = segment.normalized.end;
+        last.original.end = segment.original.end;
+        return;
+      }
+    }
+    self.segments.push(segment);
+  }
+}
+
+/// Text in the making: pieces of `text` and the ranges of the input they came
+/// from, in order and covering all of `text`.
+#[derive(Debug)]
+struct Pieces {
+  text: String,
+  pieces: Vec<Piece>,
+}
+
+#[derive(Debug)]
+struct Piece {
+  text: Range<usize>,
+  original: Range<usize>,
+}
+
+impl Pieces {
+  fn with_capacity(len: usize) -> Self {
+    Pieces {
+      text: String::with_capacity(len),
+      pieces: Vec::with_capacity(len),
+    }
+  }
+
+  fn push(&mut self, text: &str, original: Range<usize>) {
+    let start = self.text.len();
+    self.text.push_str(text);
+    self.end_piece(start, original);
+  }
+
+  /// Ends the piece of whatever was appended to `text` since `start`, unless
+  /// nothing was.
+  fn end_piece(&mut self, start: usize, original: Range<usize>) {
+    if self.text.len() > start {
+      self.pieces.push(Piece {
+        text: start..self.text.len(),
+        original,
+      });
+    }
+  }
+
+  fn iter(&self) -> impl Iterator<Item = (&str, &Range<usize>)> {
+    self
+      .pieces
+      .iter()
+      .map(|piece| (&self.text[piece.text.clone()], &piece.original))
+  }
+}
+
+impl<'p> Processor<&'p [u8], Normalized> for Normalizer {
+  fn process(&self, input: &'p [u8]) -> Result<Normalized, Error> {
+    telemetry::processor("Normalizer", Some(input.len()), || {
+      self.normalize_bytes(input)
+    })
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p> Processor<&'p str, Normalized> for Normalizer {
+  fn process(&self, input: &'p str) -> Result<Normalized, Error> {
+    telemetry::processor("Normalizer", Some(input.len()), || {
+      Ok(self.normalize_str(input))
+    })
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p Document<'d>, Normalized> for Normalizer {
+  fn process(&self, input: &'p Document<'d>) -> Result<Normalized, Error> {
+    match input {
+      Document::Binary(binary) => self.process(&binary.data[..]),
+      document => match document.text() {
+        Some(text) => self.process(text),
+        None => Err(Error::UnsupportedDocument(
+          "expected a text document".to_string(),
+        )),
+      },
+    }
+  }
+
+  describe::impl_config!();
+}
+
+impl Normalizer {
+  fn normalize_bytes(&self, input: &[u8]) -> Result<Normalized, Error> {
+    let encoding = match &self.encoding {
+      Some(label) => {
+        Encoding::for_label(label.as_bytes()).ok_or_else(|| {
+          Error::UnsupportedDocument(format!("unknown encoding {label}"))
+        })?
+      }
+      None => encoding::detect(input),
+    };
+    let (pieces, encoding) = decode(input, encoding);
+    Ok(self.normalize(pieces, encoding))
+  }
+
+  fn normalize_str(&self, input: &str) -> Normalized {
+    let pieces = input
+      .char_indices()
+      .map(|(start, c)| {
+        let range = start..start + c.len_utf8();
+        Piece {
+          text: range.clone(),
+          original: range,
+        }
+      })
+      .collect();
+    let pieces = Pieces {
+      text: input.to_string(),
+      pieces,
+    };
+    self.normalize(pieces, UTF_8)
+  }
+
+  fn normalize(
+    &self,
+    pieces: Pieces,
+    encoding: &'static Encoding,
+  ) -> Normalized {
+    let mut pieces = match self.form {
+      Some(form) => normalize_form(pieces, form),
+      None => pieces,
+    };
+    if self.remove_control || self.ascii_punctuation {
+      let mut cleaned = Pieces::with_capacity(pieces.text.len());
+      for (text, original) in pieces.iter() {
+        let start = cleaned.text.len();
+        for c in text.chars() {
+          if self.remove_control && is_removed(c) {
+            continue;
+          }
+          match self.ascii_punctuation {
+            true => push_ascii_punctuation(&mut cleaned.text, c),
+            false => cleaned.text.push(c),
+          }
+        }
+        cleaned.end_piece(start, original.clone());
+      }
+      pieces = cleaned;
+    }
+    if self.collapse_whitespace {
+      pieces = collapse_whitespace(pieces);
+    }
+
+    let mut offsets = OffsetMap::default();
+    for piece in pieces.pieces {
+      offsets.push(piece.text, piece.original);
+    }
+    Normalized {
+      text: pieces.text,
+      encoding: encoding.name(),
+      offsets,
+    }
+  }
+}
+
+/// Decodes `bytes` one piece per character, with `encoding` unless they start
+/// with a byte order mark. Returns the encoding actually used.
+fn decode(
+  bytes: &[u8],
+  encoding: &'static Encoding,
+) -> (Pieces, &'static Encoding) {
+  let (encoding, bom) = Encoding::for_bom(bytes).unwrap_or((encoding, 0));
+  let input = &bytes[bom..];
+  let mut pieces = Pieces::with_capacity(input.len());
+
+  if encoding == UTF_8 {
+    // Invalid sequences become a replacement character each, like they do
+    // with encoding_rs.
+    let mut offset = bom;
+    for chunk in input.utf8_chunks() {
+      for (start, c) in chunk.valid().char_indices() {
+        let start = offset + start;
+        pieces.push(c.encode_utf8(&mut [0; 4]), start..start + c.len_utf8());
+      }
+      offset += chunk.valid().len();
+      if !chunk.invalid().is_empty() {
+        let end = offset + chunk.invalid().len();
+        pieces.push("\u{fffd}", offset..end);
+        offset = end;
+      }
+    }
+  } else if encoding.is_single_byte() {
+    // Every byte decodes to exactly one character.
+    let (text, _) = encoding.decode_without_bom_handling(input);
+    for (index, c) in text.chars().enumerate() {
+      let start = bom + index;
+      pieces.push(c.encode_utf8(&mut [0; 4]), start..start + 1);
+    }
+  } else {
+    // Feed multi-byte encodings one byte at a time to find out which bytes
+    // each character was decoded from.
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let capacity = decoder.max_utf8_buffer_length(1).unwrap_or(16);
+    let mut start = bom;
+    for end in bom + 1..=bytes.len() {
+      let text_start = pieces.text.len();
+      pieces.text.reserve(capacity);
+      let (result, _, _) =
+        decoder.decode_to_string(&bytes[end - 1..end], &mut pieces.text, false);
+      debug_assert_eq!(result, CoderResult::InputEmpty);
+      if pieces.text.len() > text_start {
+        pieces.end_piece(text_start, start..end);
+        start = end;
+      }
+    }
+    // Flush incomplete sequences at the end of the input.
+    let text_start = pieces.text.len();
+    pieces.text.reserve(capacity);
+    let (_, _, _) = decoder.decode_to_string(&[], &mut pieces.text, true);
+    pieces.end_piece(text_start, start..bytes.len());
+  }
+  (pieces, encoding)
+}
+
+/// Normalizes clusters of characters that can interact under normalization,
+/// a starter and the characters that combine with it. Clusters that come out
+/// unchanged keep their per character pieces, others become a single piece.
+fn normalize_form(pieces: Pieces, form: NormalizationForm) -> Pieces {
+  let mut normalized = Pieces::with_capacity(pieces.text.len());
+  // The indices of the pieces of the cluster.
+  let mut cluster = 0..0;
+  // The cluster so far, composed, to see if the next starter composes with it.
+  let mut composed: Option<char> = None;
+
+  let flush = |cluster: Range<usize>, normalized: &mut Pieces| {
+    let cluster = &pieces.pieces[cluster];
+    let (Some(first), Some(last)) = (cluster.first(), cluster.last()) else {
+      return;
+    };
+    let text = &pieces.text[first.text.start..last.text.end];
+    let unchanged = match form {
+      NormalizationForm::Nfc => text.nfc().eq(text.chars()),
+      NormalizationForm::Nfkc => text.nfkc().eq(text.chars()),
+    };
+    if unchanged {
+      for piece in cluster {
+        normalized
+          .push(&pieces.text[piece.text.clone()], piece.original.clone());
+      }
+      return;
+    }
+    let start = normalized.text.len();
+    match form {
+      NormalizationForm::Nfc => normalized.text.extend(text.nfc()),
+      NormalizationForm::Nfkc => normalized.text.extend(text.nfkc()),
+    }
+    normalized.end_piece(start, first.original.start..last.original.end);
+  };
+
+  for (index, (text, _)) in pieces.iter().enumerate() {
+    let c = text.chars().next().unwrap_or_default();
+    let joins = canonical_combining_class(c) != 0
+      || composed.and_then(|prev| compose(prev, c)).is_some();
+    if joins && !cluster.is_empty() {
+      composed = composed.map(|prev| compose(prev, c).unwrap_or(prev));
+    } else {
+      flush(cluster, &mut normalized);
+      cluster = index..index;
+      composed = Some(c);
+    }
+    cluster.end = index + 1;
+  }
+  flush(cluster, &mut normalized);
+  normalized
+}
+
+/// Control and invisible characters removed by `remove_control`.
+fn is_removed(c: char) -> bool {
+  (c.is_control() && c != '\n' && c != '\t' && c != '\r')
+    || matches!(c, '\u{200b}' | '\u{2060}' | '\u{feff}' | '\u{ad}')
+}
+
+fn push_ascii_punctuation(text: &mut String, c: char) {
+  match c {
+    '‘' | '’' | '‚' | '‛' | '′' => text.push('\''),
+    '“' | '”' | '„' | '‟' | '″' => text.push('"'),
+    '‐' | '‑' | '‒' | '–' | '—' | '―' | '−' => text.push('-'),
+    '…' => text.push_str("..."),
+    c => text.push(c),
+  }
+}
+
+fn collapse_whitespace(pieces: Pieces) -> Pieces {
+  let mut collapsed = Pieces::with_capacity(pieces.text.len());
+  let mut run: Option<(Range<usize>, usize)> = None;
+  for (text, original) in pieces.iter() {
+    if text.chars().all(char::is_whitespace) {
+      let breaks = line_breaks(text);
+      run = Some(match run {
+        Some((run, count)) => (run.start..original.end, count + breaks),
+        None => (original.clone(), breaks),
+      });
+      continue;
+    }
+    // Leading whitespace is dropped.
+    if let Some((original, breaks)) = run.take() {
+      if !collapsed.pieces.is_empty() {
+        let text = match breaks {
+          0 => " ",
+          1 => "\n",
+          _ => "\n\n",
+        };
+        collapsed.push(text, original);
+      }
+    }
+    collapsed.push(text, original.clone());
+  }
+  collapsed
+}
+
+/// Counts line breaks, treating `\r\n` as one.
+fn line_breaks(text: &str) -> usize {
+  text.matches('\n').count() + text.matches('\r').count()
+    - text.matches("\r\n").count()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn normalizer() -> Normalizer {
+    NormalizerBuilder::default().build().unwrap()
+  }
+
+  #[test]
+  fn composes_and_collapses() {
+    let input =
+      "  Cafe\u{301}\u{200b} au\t\u{a0} lait\r\n\r\n\r\nsecond\u{7} line  ";
+    let normalized = normalizer().process(input).unwrap();
+    assert_eq!(normalized.text, "Café au lait\n\nsecond line");
+    assert_eq!(normalized.encoding, "UTF-8");
+
+    // Every word maps back to the same word in the input.
+    for word in ["Café", "au", "lait", "second", "line"] {
+      let start = normalized.text.find(word).unwrap();
+      let loc = Loc::new(start, start + word.len());
+      let original = normalized.offsets.to_original_loc(&loc);
+      let expected = if word == "Café" { "Cafe\u{301}" } else { word };
+      assert_eq!(&input[original.start..original.end], expected);
+    }
+
+    // The collapsed whitespace maps to the whole run.
+    let start = normalized.text.find("\n\n").unwrap();
+    let loc = Loc::new(start, start + 2);
+    let original = normalized.offsets.to_original_loc(&loc);
+    assert_eq!(&input[original.start..original.end], "\r\n\r\n\r\n");
+  }
+
+  #[test]
+  fn nfkc_and_punctuation() {
+    let normalizer = NormalizerBuilder::default()
+      .form(Some(NormalizationForm::Nfkc))
+      .ascii_punctuation(true)
+      .build()
+      .unwrap();
+    let input = "“ﬁne” — x² …";
+    let normalized = normalizer.process(input).unwrap();
+    assert_eq!(normalized.text, "\"fine\" - x2 ...");
+
+    let loc = Loc::new(1, 3);
+    let original = normalized.offsets.to_original_loc(&loc);
+    assert_eq!(&input[original.start..original.end], "ﬁ");
+  }
+
+  #[test]
+  fn decodes_with_offsets() {
+    // "Grüße" in UTF-16LE with a byte order mark.
+    let mut utf16 = vec![0xff, 0xfe];
+    for unit in "Grüße".encode_utf16() {
+      utf16.extend(unit.to_le_bytes());
+    }
+    let normalized = normalizer().process(&utf16[..]).unwrap();
+    assert_eq!(normalized.text, "Grüße");
+    assert_eq!(normalized.encoding, "UTF-16LE");
+    // "ü" is 2 bytes in UTF-8 and in UTF-16.
+    let loc = normalized.offsets.to_original_loc(&Loc::new(2, 6));
+    assert_eq!(loc, Loc::new(6, 10));
+
+    // Invalid UTF-8 sequences are replaced and keep their offsets.
+    let utf8 = NormalizerBuilder::default().encoding("utf-8").build();
+    let input = b"a\xff\xe2\x82b";
+    let normalized = utf8.unwrap().process(&input[..]).unwrap();
+    assert_eq!(normalized.text, "a\u{fffd}\u{fffd}b");
+    let loc = normalized.offsets.to_original_loc(&Loc::new(4, 7));
+    assert_eq!(loc, Loc::new(2, 4));
+
+    let normalizer = NormalizerBuilder::default()
+      .encoding("windows-1252")
+      .build()
+      .unwrap();
+    let input = b"na\xefve \x93quote\x94";
+    let normalized = normalizer.process(&input[..]).unwrap();
+    assert_eq!(normalized.text, "naïve “quote”");
+    let start = normalized.text.find("quote").unwrap();
+    let loc = normalized
+      .offsets
+      .to_original_loc(&Loc::new(start, start + 5));
+    assert_eq!(&input[loc.start..loc.end], b"quote");
+    assert_eq!(
+      normalized.offsets.to_original(normalized.text.len()),
+      input.len()
+    );
+
+    // "日本語" in Shift_JIS, two bytes per character.
+    let shift_jis = NormalizerBuilder::default()
+      .encoding("shift_jis")
+      .build()
+      .unwrap();
+    let input = b"\x93\xfa\x96\x7b\x8c\xea";
+    let normalized = shift_jis.process(&input[..]).unwrap();
+    assert_eq!(normalized.text, "日本語");
+    let loc = normalized.offsets.to_original_loc(&Loc::new(3, 6));
+    assert_eq!(loc, Loc::new(2, 4));
+
+    // Byte order marks win over the configured encoding.
+    let normalized = normalizer
+      .process(&b"\xef\xbb\xbfna\xc3\xafve"[..])
+      .unwrap();
+    assert_eq!(normalized.text, "naïve");
+    assert_eq!(normalized.encoding, "UTF-8");
+  }
+
+  #[test]
+  fn unknown_encoding() {
+    let normalizer = NormalizerBuilder::default()
+      .encoding("nope")
+      .build()
+      .unwrap();
+    assert!(matches!(
+      normalizer.process(&b"text"[..]),
+      Err(Error::UnsupportedDocument(_))
+    ));
+  }
+}
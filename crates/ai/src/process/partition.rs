@@ -0,0 +1,830 @@
+use crate::{
+  describe,
+  document::Document,
+  element::{
+    CodeBlockElement,
+    Element,
+    ImageElement,
+    ListItemElement,
+    SimpleElement,
+    TableElement,
+    TitleElement,
+  },
+  error::Error,
+  loc::Loc,
+  telemetry::{
+    self,
+    Size,
+  },
+  traits::Processor,
+};
+use derive_builder::Builder;
+use regex::Regex;
+use serde::Serialize;
+use std::{
+  collections::HashMap,
+  ops::Range,
+  sync::OnceLock,
+};
+
+/// Splits documents into typed elements whose `Loc`s point into the text of
+/// the document, and links them into sections (see `Element`).
+///
+/// Markdown is parsed for headings, lists, fenced code, pipe tables and
+/// images; every other block is narrative text. Plain text is split into
+/// blocks at blank lines and classified heuristically:
+/// - Blocks where every line starts with a bullet or a number are list items,
+///   one per line.
+/// - Blocks of at least two lines with the same number of cells, separated by
+///   tabs or runs of spaces, are tables.
+/// - Blocks indented by at least four spaces are code blocks.
+/// - Single lines of up to `max_title_len` characters that start with an
+///   uppercase letter or a digit and don't end in punctuation are titles.
+///
+/// Form feeds start a new page in both formats. In plain text, lines
+/// repeated at the top or bottom of at least half the pages (ignoring
+/// digits, so page numbers match) become headers and footers. Code
+/// documents become a single code block. Html and binary documents need to
+/// go through a loader first.
+///
+/// The content of elements is borrowed from the document, except for tables.
+/// The `Loc` of a table or an image spans its whole source, e.g.
+/// `![alt](src)`; for other elements it spans their content.
+#[derive(Builder, Clone, Debug, Serialize)]
+#[builder(setter(into))]
+#[builder(build_fn(error = "crate::error::Error"))]
+pub struct Partitioner {
+  /// The longest line of plain text, in characters, considered a title.
+  #[builder(default = "80")]
+  max_title_len: usize,
+
+  /// Whether to detect headers and footers in plain text.
+  #[builder(default = "true")]
+  headers_footers: bool,
+}
+
+/// Plain text.
+impl<'p> Processor<&'p str, Vec<Element<'p>>> for Partitioner {
+  fn process(&self, input: &'p str) -> Result<Vec<Element<'p>>, Error> {
+    telemetry::processor("Partitioner", Some(input.len()), || {
+      Ok(self.plain(input))
+    })
+  }
+
+  describe::impl_config!();
+}
+
+impl<'p, 'd> Processor<&'p Document<'d>, Vec<Element<'p>>> for Partitioner {
+  fn process(
+    &self,
+    input: &'p Document<'d>,
+  ) -> Result<Vec<Element<'p>>, Error> {
+    telemetry::processor("Partitioner", Some(input.size()), || {
+      self.document(input)
+    })
+  }
+
+  describe::impl_config!();
+}
+
+/// Sets the `parent` and `section` of `elements` from their order. A title
+/// opens a section that lasts until the next title of the same or a lower
+/// level, and a list item belongs to the closest preceding item of a lower
+/// depth in the same list. Page breaks, headers and footers are left out of
+/// the tree.
+pub fn link(elements: &mut [Element]) {
+  let mut titles: Vec<(u8, usize)> = vec![];
+  let mut items: Vec<(u8, usize)> = vec![];
+  for (index, element) in elements.iter_mut().enumerate() {
+    let section = titles.last().map(|&(_, title)| title);
+    match element {
+      Element::PageBreak(_) | Element::Header(_) | Element::Footer(_) => {
+        element.set_relations(None, None);
+      }
+      Element::Title(title) => {
+        let level = title.level;
+        while titles.last().is_some_and(|&(other, _)| other >= level) {
+          titles.pop();
+        }
+        let parent = titles.last().map(|&(_, title)| title);
+        element.set_relations(parent, parent);
+        titles.push((level, index));
+        items.clear();
+      }
+      Element::ListItem(item) => {
+        let depth = item.depth;
+        while items.last().is_some_and(|&(other, _)| other >= depth) {
+          items.pop();
+        }
+        let parent = items.last().map(|&(_, item)| item).or(section);
+        element.set_relations(parent, section);
+        items.push((depth, index));
+      }
+      _ => {
+        element.set_relations(section, section);
+        items.clear();
+      }
+    }
+  }
+}
+
+impl Partitioner {
+  fn plain<'p>(&self, input: &'p str) -> Vec<Element<'p>> {
+    let mut elements = self.text(input);
+    link(&mut elements);
+    elements
+  }
+
+  fn document<'p>(
+    &self,
+    input: &'p Document<'_>,
+  ) -> Result<Vec<Element<'p>>, Error> {
+    let mut elements = match input {
+      Document::Text(doc) => self.text(&doc.content),
+      Document::Markdown(doc) => markdown(&doc.content),
+      Document::Code(doc) => {
+        let (start, end) = trim(&doc.content, 0..doc.content.len());
+        if start == end {
+          vec![]
+        } else {
+          let content = &doc.content[start..end];
+          let loc = Loc::new(start, end);
+          vec![CodeBlockElement::new(content, loc, doc.language.clone())
+            .as_element()]
+        }
+      }
+      Document::Html(_) | Document::Binary(_) => {
+        return Err(Error::UnsupportedDocument(
+          "html and binary documents need to be loaded before partitioning"
+            .to_string(),
+        ))
+      }
+    };
+    link(&mut elements);
+    Ok(elements)
+  }
+
+  fn text<'p>(&self, text: &'p str) -> Vec<Element<'p>> {
+    let pages = pages(text);
+    let (headers, footers) = if self.headers_footers {
+      furniture(text, &pages)
+    } else {
+      Default::default()
+    };
+
+    let mut elements = vec![];
+    for (n, page) in pages.into_iter().enumerate() {
+      if n > 0 {
+        // The form feed right before the page.
+        let loc = Loc::new(page.start - 1, page.start);
+        elements.push(Element::PageBreak(SimpleElement::new("", loc)));
+      }
+
+      let mut lines = lines(text, page);
+      let first = lines.iter().position(|line| !line.is_blank());
+      let last = lines.iter().rposition(|line| !line.is_blank());
+      let mut footer = None;
+      if let (Some(first), Some(last)) = (first, last) {
+        if headers.contains(&furniture_key(lines[first].text)) {
+          let (content, loc) = lines[first].trimmed();
+          elements.push(Element::Header(SimpleElement::new(content, loc)));
+          lines[first].text = "";
+        }
+        if last != first && footers.contains(&furniture_key(lines[last].text)) {
+          footer = Some(lines[last].trimmed());
+          lines[last].text = "";
+        }
+      }
+
+      for block in lines.split(Line::is_blank) {
+        if !block.is_empty() {
+          self.text_block(text, block, &mut elements);
+        }
+      }
+      if let Some((content, loc)) = footer {
+        elements.push(Element::Footer(SimpleElement::new(content, loc)));
+      }
+    }
+    elements
+  }
+
+  fn text_block<'p>(
+    &self,
+    text: &'p str,
+    block: &[Line<'p>],
+    elements: &mut Vec<Element<'p>>,
+  ) {
+    let span = trim(text, block[0].start..block[block.len() - 1].end());
+
+    let items = block
+      .iter()
+      .map(|line| list_marker(line.text))
+      .collect::<Option<Vec<_>>>();
+    if let Some(items) = items {
+      let mut indents = vec![];
+      for (line, (indent, ordered, offset)) in block.iter().zip(items) {
+        let depth = depth(&mut indents, indent);
+        let (start, end) = trim(text, line.start + offset..line.end());
+        let loc = Loc::new(start, end);
+        elements.push(
+          ListItemElement::new(&text[start..end], loc, depth, ordered)
+            .as_element(),
+        );
+      }
+      return;
+    }
+
+    if let Some(rows) = text_table(block) {
+      let loc = Loc::new(span.0, span.1);
+      elements
+        .push(TableElement::new(table_content(&rows), loc, rows).as_element());
+      return;
+    }
+
+    let loc = Loc::new(span.0, span.1);
+    if block.iter().all(|line| indent_width(line.text) >= 4) {
+      // Keep the indentation of the first line, it's part of the code.
+      let start = block[0].start;
+      let loc = Loc::new(start, span.1);
+      let content = &text[start..span.1];
+      elements.push(CodeBlockElement::new(content, loc, None).as_element());
+    } else if block.len() == 1 && self.is_title(&text[span.0..span.1]) {
+      let content = &text[span.0..span.1];
+      elements.push(TitleElement::new(content, loc, 1).as_element());
+    } else {
+      let content = &text[span.0..span.1];
+      elements.push(Element::NarrativeText(SimpleElement::new(content, loc)));
+    }
+  }
+
+  fn is_title(&self, line: &str) -> bool {
+    let starts_upper = line
+      .chars()
+      .next()
+      .is_some_and(|c| c.is_uppercase() || c.is_ascii_digit());
+    starts_upper
+      && line.chars().count() <= self.max_title_len
+      && line.chars().any(char::is_alphabetic)
+      && !line.ends_with(['.', ',', ';', '!', '?'])
+  }
+}
+
+fn markdown(text: &str) -> Vec<Element<'_>> {
+  let lines = lines(text, 0..text.len());
+  let mut elements = vec![];
+  // The indents of the open list items, to compute the depth of nested ones.
+  let mut indents = vec![];
+  let mut i = front_matter(&lines);
+
+  while i < lines.len() {
+    let line = &lines[i];
+    let trimmed = line.text.trim();
+    if !is_list_item(line.text) && !trimmed.is_empty() {
+      indents.clear();
+    }
+
+    if trimmed.is_empty() {
+      if trimmed.len() < line.text.len() && line.text.contains('\x0c') {
+        let offset = line.start + line.text.find('\x0c').unwrap_or(0);
+        let loc = Loc::new(offset, offset + 1);
+        elements.push(Element::PageBreak(SimpleElement::new("", loc)));
+      }
+      i += 1;
+    } else if let Some(fence) = fence(line.text) {
+      i = code_block(text, &lines, i, fence, &mut elements);
+    } else if let Some((level, start, end)) = heading(line.text) {
+      let (start, end) = (line.start + start, line.start + end);
+      let loc = Loc::new(start, end);
+      elements
+        .push(TitleElement::new(&text[start..end], loc, level).as_element());
+      i += 1;
+    } else if is_thematic_break(line.text) {
+      i += 1;
+    } else if let Some((indent, ordered, offset)) = list_marker(line.text) {
+      let last = continuation(&lines, i);
+      let depth = depth(&mut indents, indent);
+      let (start, end) = trim(text, line.start + offset..lines[last].end());
+      let loc = Loc::new(start, end);
+      elements.push(
+        ListItemElement::new(&text[start..end], loc, depth, ordered)
+          .as_element(),
+      );
+      i = last + 1;
+    } else if lines.get(i + 1).is_some_and(|next| is_table(line, next)) {
+      let mut rows = vec![cells(line.text)];
+      let mut last = i + 1;
+      while lines
+        .get(last + 1)
+        .is_some_and(|row| !row.is_blank() && row.text.contains('|'))
+      {
+        last += 1;
+        rows.push(cells(lines[last].text));
+      }
+      let (start, end) = trim(text, line.start..lines[last].end());
+      let loc = Loc::new(start, end);
+      elements
+        .push(TableElement::new(table_content(&rows), loc, rows).as_element());
+      i = last + 1;
+    } else if let Some((alt, src)) = image(line.text) {
+      let (start, end) = trim(text, line.start..line.end());
+      let alt = line.start + alt.start..line.start + alt.end;
+      let loc = Loc::new(start, end);
+      elements.push(ImageElement::new(&text[alt], loc, Some(src)).as_element());
+      i += 1;
+    } else {
+      let last = continuation(&lines, i);
+      let (start, end) = trim(text, line.start..lines[last].end());
+      let loc = Loc::new(start, end);
+      let underline = lines.get(last + 1).and_then(|line| setext(line.text));
+      match underline {
+        Some(level) => {
+          elements.push(
+            TitleElement::new(&text[start..end], loc, level).as_element(),
+          );
+          i = last + 2;
+        }
+        None => {
+          let content = &text[start..end];
+          elements
+            .push(Element::NarrativeText(SimpleElement::new(content, loc)));
+          i = last + 1;
+        }
+      }
+    }
+  }
+  elements
+}
+
+/// Pushes the fenced code block starting at line `i` and returns the index
+/// of the line after it.
+fn code_block<'p>(
+  text: &'p str,
+  lines: &[Line<'p>],
+  i: usize,
+  fence: Fence,
+  elements: &mut Vec<Element<'p>>,
+) -> usize {
+  let close = (i + 1..lines.len()).find(|&j| {
+    let trimmed = lines[j].text.trim();
+    trimmed.len() >= fence.len
+      && trimmed.chars().all(|c| c == fence.marker)
+      && indent_width(lines[j].text) < 4
+  });
+  let body_end = close.unwrap_or(lines.len());
+  if body_end > i + 1 {
+    let start = lines[i + 1].start;
+    let end = lines[body_end - 1].end();
+    let loc = Loc::new(start, end);
+    let element = CodeBlockElement::new(&text[start..end], loc, fence.language);
+    elements.push(element.as_element());
+  }
+  close.map_or(lines.len(), |close| close + 1)
+}
+
+/// The index of the last line of the paragraph or list item starting at line
+/// `i`: following lines belong to it until a blank line or another block.
+fn continuation(lines: &[Line], i: usize) -> usize {
+  let mut last = i;
+  while let Some(line) = lines.get(last + 1) {
+    let text = line.text;
+    let block = line.is_blank()
+      || fence(text).is_some()
+      || heading(text).is_some()
+      || is_list_item(text)
+      || is_thematic_break(text)
+      || setext(text).is_some()
+      || image(text).is_some();
+    if block {
+      break;
+    }
+    last += 1;
+  }
+  last
+}
+
+/// The index of the first line after a YAML front matter block, if there is
+/// one.
+fn front_matter(lines: &[Line]) -> usize {
+  if lines
+    .first()
+    .is_none_or(|line| line.text.trim_end() != "---")
+  {
+    return 0;
+  }
+  lines
+    .iter()
+    .skip(1)
+    .position(|line| matches!(line.text.trim_end(), "---" | "..."))
+    .map_or(0, |end| end + 2)
+}
+
+#[derive(Clone, Copy)]
+struct Line<'a> {
+  text: &'a str,
+  start: usize,
+}
+
+impl<'a> Line<'a> {
+  fn end(&self) -> usize {
+    self.start + self.text.len()
+  }
+
+  fn is_blank(&self) -> bool {
+    self.text.trim().is_empty()
+  }
+
+  fn trimmed(&self) -> (&'a str, Loc) {
+    let text = self.text;
+    let start = leading_space(text);
+    let end = text.trim_end().len().max(start);
+    (
+      &text[start..end],
+      Loc::new(self.start + start, self.start + end),
+    )
+  }
+}
+
+/// The lines of `text` in `range`, without line endings.
+fn lines(text: &str, range: Range<usize>) -> Vec<Line<'_>> {
+  let mut start = range.start;
+  text[range]
+    .split_inclusive('\n')
+    .map(|line| {
+      let line_start = start;
+      start += line.len();
+      let line = line.strip_suffix('\n').unwrap_or(line);
+      Line {
+        text: line.strip_suffix('\r').unwrap_or(line),
+        start: line_start,
+      }
+    })
+    .collect()
+}
+
+/// The ranges of the pages of `text`, which are separated by form feeds.
+fn pages(text: &str) -> Vec<Range<usize>> {
+  let mut pages = vec![];
+  let mut start = 0;
+  for (offset, _) in text.match_indices('\x0c') {
+    pages.push(start..offset);
+    start = offset + 1;
+  }
+  pages.push(start..text.len());
+  pages
+}
+
+/// The keys of the lines repeated at the top and at the bottom of at least
+/// half the pages.
+fn furniture(text: &str, pages: &[Range<usize>]) -> (Vec<String>, Vec<String>) {
+  if pages.len() < 2 {
+    return Default::default();
+  }
+  let mut firsts = HashMap::<String, usize>::new();
+  let mut lasts = HashMap::<String, usize>::new();
+  for page in pages {
+    let lines = lines(text, page.clone());
+    let mut content = lines.iter().filter(|line| !line.is_blank());
+    if let Some(first) = content.next() {
+      *firsts.entry(furniture_key(first.text)).or_default() += 1;
+    }
+    if let Some(last) = content.next_back() {
+      *lasts.entry(furniture_key(last.text)).or_default() += 1;
+    }
+  }
+  let threshold = pages.len().div_ceil(2).max(2);
+  let repeated = |counts: HashMap<String, usize>| {
+    counts
+      .into_iter()
+      .filter(|(_, count)| *count >= threshold)
+      .map(|(key, _)| key)
+      .collect()
+  };
+  (repeated(firsts), repeated(lasts))
+}
+
+/// Lines are compared without digits, so `Page 1` matches `Page 2`.
+fn furniture_key(line: &str) -> String {
+  line
+    .trim()
+    .chars()
+    .map(|c| if c.is_ascii_digit() { '#' } else { c })
+    .collect()
+}
+
+/// The range of `range` without surrounding whitespace.
+fn trim(text: &str, range: Range<usize>) -> (usize, usize) {
+  let slice = &text[range.clone()];
+  let trimmed = slice.trim();
+  if trimmed.is_empty() {
+    return (range.start, range.start);
+  }
+  let start = range.start + leading_space(slice);
+  (start, start + trimmed.len())
+}
+
+fn leading_space(text: &str) -> usize {
+  text.len() - text.trim_start().len()
+}
+
+/// The width of the indentation of `line`, counting tabs as four spaces.
+fn indent_width(line: &str) -> usize {
+  line
+    .chars()
+    .take_while(|c| c.is_whitespace())
+    .map(|c| if c == '\t' { 4 } else { 1 })
+    .sum()
+}
+
+/// The depth of a list item indented by `indent`, given the indents of the
+/// open items before it.
+fn depth(indents: &mut Vec<usize>, indent: usize) -> u8 {
+  while indents.last().is_some_and(|&other| other >= indent) {
+    indents.pop();
+  }
+  let depth = indents.len();
+  indents.push(indent);
+  depth.min(u8::MAX as usize) as u8
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+  cell.get_or_init(|| Regex::new(pattern).expect("valid partition regex"))
+}
+
+/// The indent width of a list item, whether it's ordered and the offset of
+/// its content.
+fn list_marker(line: &str) -> Option<(usize, bool, usize)> {
+  static MARKER: OnceLock<Regex> = OnceLock::new();
+  let marker = regex(&MARKER, r"^[ \t]*([-*+•‣◦]|\d{1,9}[.)])(?:[ \t]+|$)");
+  if is_thematic_break(line) {
+    return None;
+  }
+  let captures = marker.captures(line)?;
+  let ordered = captures[1].starts_with(|c: char| c.is_ascii_digit());
+  Some((indent_width(line), ordered, captures[0].len()))
+}
+
+fn is_list_item(line: &str) -> bool {
+  list_marker(line).is_some()
+}
+
+fn is_thematic_break(line: &str) -> bool {
+  static BREAK: OnceLock<Regex> = OnceLock::new();
+  regex(
+    &BREAK,
+    r"^ {0,3}(?:(?:-[ \t]*){3,}|(?:\*[ \t]*){3,}|(?:_[ \t]*){3,})$",
+  )
+  .is_match(line)
+}
+
+/// The level and the range of the text of an ATX heading like `## Title ##`.
+fn heading(line: &str) -> Option<(u8, usize, usize)> {
+  static HEADING: OnceLock<Regex> = OnceLock::new();
+  let heading = regex(
+    &HEADING,
+    r"^ {0,3}(#{1,6})(?:[ \t]+(.*?))?(?:[ \t]+#+)?[ \t]*$",
+  );
+  let captures = heading.captures(line)?;
+  let text = captures.get(2)?;
+  if text.as_str().is_empty() {
+    return None;
+  }
+  Some((captures[1].len() as u8, text.start(), text.end()))
+}
+
+/// The level of the heading a setext underline (`===` or `---`) makes.
+fn setext(line: &str) -> Option<u8> {
+  let trimmed = line.trim();
+  if indent_width(line) >= 4 || trimmed.is_empty() {
+    None
+  } else if trimmed.chars().all(|c| c == '=') {
+    Some(1)
+  } else if trimmed.chars().all(|c| c == '-') {
+    Some(2)
+  } else {
+    None
+  }
+}
+
+struct Fence {
+  marker: char,
+  len: usize,
+  language: Option<String>,
+}
+
+fn fence(line: &str) -> Option<Fence> {
+  static FENCE: OnceLock<Regex> = OnceLock::new();
+  let fence = regex(&FENCE, r"^ {0,3}(`{3,}|~{3,})[ \t]*([^`\s]*)");
+  let captures = fence.captures(line)?;
+  let language = &captures[2];
+  Some(Fence {
+    marker: captures[1].chars().next()?,
+    len: captures[1].len(),
+    language: (!language.is_empty()).then(|| language.to_lowercase()),
+  })
+}
+
+/// The range of the alt text and the source of an image on a line of its own,
+/// like `![alt](src "title")`.
+fn image(line: &str) -> Option<(Range<usize>, String)> {
+  static IMAGE: OnceLock<Regex> = OnceLock::new();
+  let image = regex(
+    &IMAGE,
+    r#"^[ \t]*!\[([^\]]*)\]\([ \t]*<?([^)\s>]*)>?(?:[ \t]+"[^"]*")?[ \t]*\)[ \t]*$"#,
+  );
+  let captures = image.captures(line)?;
+  let alt = captures.get(1)?.range();
+  Some((alt, captures[2].to_string()))
+}
+
+/// Whether `line` is the header row of a pipe table, i.e. is followed by a
+/// delimiter row like `| --- | :-: |`.
+fn is_table(line: &Line, next: &Line) -> bool {
+  static DELIMITER: OnceLock<Regex> = OnceLock::new();
+  let delimiter = regex(
+    &DELIMITER,
+    r"^[ \t]*\|?(?:[ \t]*:?-+:?[ \t]*\|)*[ \t]*:?-+:?[ \t]*\|?[ \t]*$",
+  );
+  line.text.contains('|')
+    && next.text.contains(['|', ':'])
+    && delimiter.is_match(next.text)
+    && cells(line.text).len() == cells(next.text).len()
+}
+
+/// The cells of a row of a pipe table.
+fn cells(line: &str) -> Vec<String> {
+  let line = line.trim();
+  let line = line.strip_prefix('|').unwrap_or(line);
+  let line = match line.strip_suffix('|') {
+    Some(rest) if !rest.ends_with('\\') => rest,
+    _ => line,
+  };
+  let mut cells = vec![String::new()];
+  let mut chars = line.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => match chars.next() {
+        Some('|') => cells.last_mut().unwrap().push('|'),
+        Some(c) => cells.last_mut().unwrap().extend(['\\', c]),
+        None => cells.last_mut().unwrap().push('\\'),
+      },
+      '|' => cells.push(String::new()),
+      c => cells.last_mut().unwrap().push(c),
+    }
+  }
+  cells.iter().map(|cell| cell.trim().to_string()).collect()
+}
+
+/// The rows of a block of plain text whose lines all have the same number of
+/// cells, separated by tabs or by runs of at least two spaces.
+fn text_table(block: &[Line]) -> Option<Vec<Vec<String>>> {
+  static COLUMNS: OnceLock<Regex> = OnceLock::new();
+  let columns = regex(&COLUMNS, r"\t+|  +");
+  if block.len() < 2 {
+    return None;
+  }
+  let rows = block
+    .iter()
+    .map(|line| {
+      columns
+        .split(line.text.trim())
+        .map(str::to_string)
+        .collect::<Vec<_>>()
+    })
+    .collect::<Vec<_>>();
+  let width = rows[0].len();
+  (width >= 2 && rows.iter().all(|row| row.len() == width)).then_some(rows)
+}
+
+fn table_content(rows: &[Vec<String>]) -> String {
+  rows
+    .iter()
+    .map(|row| row.join("\t"))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::document::{
+    CodeDocument,
+    MarkdownDocument,
+  };
+
+  fn partitioner() -> Partitioner {
+    PartitionerBuilder::default().build().unwrap()
+  }
+
+  /// One line per element: its kind, its parent and its content.
+  fn summary(elements: &[Element]) -> Vec<String> {
+    elements
+      .iter()
+      .enumerate()
+      .map(|(index, el)| {
+        let kind = match el {
+          Element::Title(title) => format!("title {}", title.level),
+          Element::ListItem(item) => format!("item {}", item.depth),
+          Element::Table(table) => format!("table {}", table.rows.len()),
+          Element::CodeBlock(code) => {
+            format!("code {}", code.language.as_deref().unwrap_or("-"))
+          }
+          Element::Image(image) => {
+            format!("image {}", image.src.as_deref().unwrap_or("-"))
+          }
+          Element::NarrativeText(_) => "text".to_string(),
+          Element::PageBreak(_) => "page".to_string(),
+          Element::Header(_) => "header".to_string(),
+          Element::Footer(_) => "footer".to_string(),
+          Element::Simple(_) => "simple".to_string(),
+        };
+        let parent = el.parent().map_or("-".to_string(), |p| p.to_string());
+        format!("{index} {kind} <{parent}>: {}", el.content())
+      })
+      .collect()
+  }
+
+  #[test]
+  fn markdown() {
+    let text =
+      "---\ntitle: Guide\n---\n# Guide\n\nIntro text\nover two lines.\n\n\
+      ## Install\n\n- one\n  - nested\n- two\ncontinued\n\n\
+      ```Rust\nfn main() {}\n```\n\n\
+      | a | b |\n|---|:-:|\n| 1 | 2 \\| 3 |\n\n\
+      ![A diagram](img/flow.png \"Flow\")\n\n\
+      Usage\n-----\n\n***\n\nDone.\n";
+    let doc = MarkdownDocument::new(text).as_document();
+    let elements = partitioner().process(&doc).unwrap();
+    assert_eq!(
+      summary(&elements),
+      [
+        "0 title 1 <->: Guide",
+        "1 text <0>: Intro text\nover two lines.",
+        "2 title 2 <0>: Install",
+        "3 item 0 <2>: one",
+        "4 item 1 <3>: nested",
+        "5 item 0 <2>: two\ncontinued",
+        "6 code rust <2>: fn main() {}",
+        "7 table 2 <2>: a\tb\n1\t2 | 3",
+        "8 image img/flow.png <2>: A diagram",
+        "9 title 2 <0>: Usage",
+        "10 text <9>: Done.",
+      ]
+    );
+    assert!(elements
+      .iter()
+      .filter(|el| !matches!(el, Element::Table(_) | Element::Image(_)))
+      .all(|el| el.loc().slice(text) == Some(el.content())));
+    assert_eq!(elements[4].section(), Some(2));
+    assert_eq!(
+      elements[8].loc().slice(text),
+      Some("![A diagram](img/flow.png \"Flow\")")
+    );
+  }
+
+  #[test]
+  fn plain_text() {
+    let text = "ACME Report\n\nOverview\n\nSales grew, mostly abroad.\n\
+      Costs fell.\n\n- first\n- second\n\nPage 1\n\x0c\
+      ACME Report\n\nName\tQty\nBolt\t3\n\n    let x = 1;\n    x + 1\n\n\
+      Page 2\n";
+    let elements = partitioner().process(text).unwrap();
+    assert_eq!(
+      summary(&elements),
+      [
+        "0 header <->: ACME Report",
+        "1 title 1 <->: Overview",
+        "2 text <1>: Sales grew, mostly abroad.\nCosts fell.",
+        "3 item 0 <1>: first",
+        "4 item 0 <1>: second",
+        "5 footer <->: Page 1",
+        "6 page <->: ",
+        "7 header <->: ACME Report",
+        "8 table 2 <1>: Name\tQty\nBolt\t3",
+        "9 code - <1>:     let x = 1;\n    x + 1",
+        "10 footer <->: Page 2",
+      ]
+    );
+    assert_eq!(elements[6].loc().slice(text), Some("\x0c"));
+
+    let partitioner = PartitionerBuilder::default()
+      .headers_footers(false)
+      .build()
+      .unwrap();
+    let elements = partitioner.process(text).unwrap();
+    assert!(matches!(elements[0], Element::Title(_)));
+  }
+
+  #[test]
+  fn code_and_unsupported() {
+    let doc = CodeDocument::new("\nfn main() {}\n", Some("rust".to_string()))
+      .as_document();
+    let elements = partitioner().process(&doc).unwrap();
+    assert_eq!(summary(&elements), ["0 code rust <->: fn main() {}"]);
+
+    let html = crate::document::HtmlDocument::new("<p>hi</p>").as_document();
+    assert!(matches!(
+      partitioner().process(&html),
+      Err(Error::UnsupportedDocument(_))
+    ));
+  }
+}
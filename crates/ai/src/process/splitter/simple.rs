@@ -1,18 +1,21 @@
 use crate::{
+  describe,
   element::{
     Element,
     SimpleElement,
   },
   error::Error,
   loc::Loc,
+  telemetry,
   traits::Processor,
 };
 use derive_builder::Builder;
+use serde::Serialize;
 
 /// Simple chunking algorithm. Splits a string along character boundaries
 /// according to the `chunk_size``. This should not be used on its own. It
 /// serves as a building block for more advanced chunking algorithms.
-#[derive(Default, Builder, Debug)]
+#[derive(Default, Builder, Debug, Serialize)]
 #[builder(setter(into))]
 #[builder(build_fn(error = "crate::error::Error"))]
 pub struct SimpleSplitter {
@@ -25,8 +28,8 @@ pub struct SimpleSplitter {
   loc_offset: usize,
 }
 
-impl<'a> Processor<&'a str, Vec<Element<'a>>> for SimpleSplitter {
-  fn process(&self, input: &'a str) -> Result<Vec<Element<'a>>, Error> {
+impl SimpleSplitter {
+  fn split<'a>(&self, input: &'a str) -> Result<Vec<Element<'a>>, Error> {
     let chunk_size = self.chunk_size as usize;
     if chunk_size == 0 {
       return Err(Error::InvalidChunkSize(chunk_size as u32));
@@ -47,14 +50,13 @@ impl<'a> Processor<&'a str, Vec<Element<'a>>> for SimpleSplitter {
       // Naively incrementing by `chunk_size` could put us in the middle of
       // a UTF-8 code point sequence. We have to adjust `end` accordingly.
       end = next_boundary(input, end);
-      chunks.push(Element::Simple(SimpleElement {
-        content: &input[start..end],
-        loc: Loc {
-          start: start + self.loc_offset,
-          end: end + self.loc_offset,
-        },
-        tags: Default::default(),
-      }));
+      chunks.push(
+        SimpleElement::new(
+          &input[start..end],
+          Loc::new(start + self.loc_offset, end + self.loc_offset),
+        )
+        .as_element(),
+      );
       start = end;
     }
 
@@ -62,6 +64,16 @@ impl<'a> Processor<&'a str, Vec<Element<'a>>> for SimpleSplitter {
   }
 }
 
+impl<'a> Processor<&'a str, Vec<Element<'a>>> for SimpleSplitter {
+  fn process(&self, input: &'a str) -> Result<Vec<Element<'a>>, Error> {
+    telemetry::processor("SimpleSplitter", Some(input.len()), || {
+      self.split(input)
+    })
+  }
+
+  describe::impl_config!();
+}
+
 // This finds the next valid character boundary in `string` that is >= `index`.
 // Note: it may return `string.len()` which is always considered a valid
 // character boundary.
@@ -0,0 +1,707 @@
+use crate::{
+  chunk::{
+    recursive::RecursiveChunkerBuilder,
+    simple::SimpleChunkerBuilder,
+    Chunk,
+    Chunker,
+  },
+  describe,
+  dynamic::{
+    DynPipeline,
+    DynProcessor,
+    Erased,
+  },
+  element::Element,
+  error::Error,
+  process::splitter::simple::SimpleSplitterBuilder,
+  traits::Processor,
+};
+use serde::{
+  de::{
+    self,
+    DeserializeOwned,
+    DeserializeSeed,
+    IgnoredAny,
+    MapAccess,
+    SeqAccess,
+    Visitor,
+  },
+  Deserialize,
+  Deserializer,
+  Serialize,
+};
+use serde_json::error::Category;
+use std::{
+  collections::BTreeMap,
+  fmt,
+  path::Path,
+  sync::Arc,
+};
+
+type Factory = Box<
+  dyn for<'de> Fn(
+      &mut dyn erased_serde::Deserializer<'de>,
+    ) -> Result<Box<dyn DynProcessor>, erased_serde::Error>
+    + Send
+    + Sync,
+>;
+
+/// Builds processors by name from their config, so pipelines can be defined
+/// in a file and changed without recompiling.
+///
+/// A definition lists the stages of the pipeline in order, each as a map
+/// from the name of a registered processor to its config, e.g. in TOML:
+///
+/// ```
+/// use ragkit_ai::{
+///   chunk::Chunk,
+///   registry::{
+///     Format,
+///     Registry,
+///   },
+/// };
+///
+/// let definition = r#"
+///   [[stages]]
+///   recursive_chunker = { chunk_size = 12, separators = ["\n\n", " "] }
+/// "#;
+///
+/// let pipeline = Registry::with_builtins().parse(definition, Format::Toml)?;
+/// let chunks: Vec<Chunk> =
+///   pipeline.run_typed("Hello there.\n\nGeneral Kenobi!".to_string())?;
+/// assert_eq!(chunks[0].content(), "Hello there.");
+/// # Ok::<(), ragkit_ai::error::Error>(())
+/// ```
+///
+/// Stages are `DynProcessor`s, so they take and return owned values. The
+/// built-in processors take a `String`, and return `Vec<Element<'static>>`
+/// or `Vec<Chunk<'static>>`.
+pub struct Registry {
+  factories: BTreeMap<&'static str, Factory>,
+}
+
+impl Registry {
+  /// A registry without any processors, for custom ones only.
+  pub fn empty() -> Self {
+    Self {
+      factories: BTreeMap::new(),
+    }
+  }
+
+  /// A registry with the built-in processors: `simple_splitter`,
+  /// `simple_chunker` and `recursive_chunker`.
+  pub fn with_builtins() -> Self {
+    let mut registry = Self::empty();
+    registry
+      .register("simple_splitter", |config: SimpleSplitterConfig| {
+        check_chunk_size(config.chunk_size)?;
+        let splitter = SimpleSplitterBuilder::default()
+          .chunk_size(config.chunk_size)
+          .loc_offset(config.loc_offset)
+          .build()?;
+        let split =
+          move |text: String| -> Result<Vec<Element<'static>>, Error> {
+            let elements: Vec<Element> = splitter.run(text.as_str())?;
+            Ok(elements.into_iter().map(Element::into_owned).collect())
+          };
+        Ok(stage("simple_splitter", describe::config(&config), split))
+      })
+      .register("simple_chunker", |config: SimpleChunkerConfig| {
+        check_chunk_size(config.chunk_size)?;
+        let chunker = SimpleChunkerBuilder::default()
+          .chunk_size(config.chunk_size)
+          .loc_offset(config.loc_offset)
+          .build()?;
+        let chunk = move |text: String| -> Result<Vec<Chunk<'static>>, Error> {
+          let chunks = chunker.chunk(text.as_str())?;
+          Ok(chunks.into_iter().map(Chunk::into_owned).collect())
+        };
+        Ok(stage("simple_chunker", describe::config(&config), chunk))
+      })
+      .register("recursive_chunker", |config: RecursiveChunkerConfig| {
+        check_chunk_size(config.chunk_size)?;
+        let description = describe::config(&config);
+        let chunk = move |text: String| -> Result<Vec<Chunk<'static>>, Error> {
+          // `RecursiveChunker` borrows its separators, so it's rebuilt for
+          // every input.
+          let chunker = RecursiveChunkerBuilder::default()
+            .chunk_size(config.chunk_size)
+            .separators(
+              config
+                .separators
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            )
+            .build()?;
+          let chunks = chunker.chunk(text.as_str())?;
+          Ok(chunks.into_iter().map(Chunk::into_owned).collect())
+        };
+        Ok(stage("recursive_chunker", description, chunk))
+      });
+    registry
+  }
+
+  /// Registers a processor under `name`, replacing any processor with the
+  /// same name. `build` creates the processor from its config, and its
+  /// errors are reported at the location of the config.
+  pub fn register<Config>(
+    &mut self,
+    name: &'static str,
+    build: impl Fn(Config) -> Result<Box<dyn DynProcessor>, Error>
+      + Send
+      + Sync
+      + 'static,
+  ) -> &mut Self
+  where
+    Config: DeserializeOwned,
+  {
+    let factory = move |deserializer: &mut dyn erased_serde::Deserializer| {
+      let config = erased_serde::deserialize::<Config>(deserializer)?;
+      build(config).map_err(de::Error::custom)
+    };
+    self.factories.insert(name, Box::new(factory));
+    self
+  }
+
+  /// The names of the registered processors, sorted.
+  pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+    self.factories.keys().copied()
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.factories.contains_key(name)
+  }
+
+  /// Builds a pipeline from its definition.
+  pub fn parse(
+    &self,
+    text: &str,
+    format: Format,
+  ) -> Result<DynPipeline, Error> {
+    match format {
+      Format::Toml => {
+        let deserializer = toml::Deserializer::parse(text)
+          .map_err(|err| toml_error(text, ".".to_string(), err))?;
+        self
+          .deserialize(deserializer)
+          .map_err(|(path, err)| toml_error(text, path, err))
+      }
+      Format::Yaml => {
+        let deserializer = serde_yaml_ng::Deserializer::from_str(text);
+        self
+          .deserialize(deserializer)
+          .map_err(|(path, err)| yaml_error(path, err))
+      }
+      Format::Json => {
+        let mut deserializer = serde_json::Deserializer::from_str(text);
+        let pipeline = self
+          .deserialize(&mut deserializer)
+          .and_then(|pipeline| {
+            // Trailing characters after the definition.
+            deserializer
+              .end()
+              .map(|()| pipeline)
+              .map_err(|err| (".".to_string(), err))
+          })
+          .map_err(|(path, err)| json_error(path, err))?;
+        Ok(pipeline)
+      }
+    }
+  }
+
+  /// Builds a pipeline from a definition file, in the format given by its
+  /// extension.
+  pub fn load(&self, path: impl AsRef<Path>) -> Result<DynPipeline, Error> {
+    let path = path.as_ref();
+    let format = Format::from_path(path).ok_or_else(|| {
+      Error::InvalidInput(format!(
+        "unknown pipeline definition format of {}, expected a .toml, .yaml, \
+         .yml or .json file",
+        path.display()
+      ))
+    })?;
+    let text =
+      std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    self.parse(&text, format)
+  }
+
+  fn deserialize<'de, D: Deserializer<'de>>(
+    &self,
+    deserializer: D,
+  ) -> Result<DynPipeline, (String, D::Error)> {
+    let mut track = serde_path_to_error::Track::new();
+    let deserializer =
+      serde_path_to_error::Deserializer::new(deserializer, &mut track);
+    PipelineSeed(self)
+      .deserialize(deserializer)
+      .map_err(|err| (track.path().to_string(), err))
+  }
+}
+
+impl Default for Registry {
+  /// The same as `Registry::with_builtins()`.
+  fn default() -> Self {
+    Self::with_builtins()
+  }
+}
+
+/// The format of a pipeline definition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+  Toml,
+  Yaml,
+  Json,
+}
+
+impl Format {
+  /// The format of a file, by its extension.
+  pub fn from_path(path: &Path) -> Option<Self> {
+    match path.extension()?.to_str()? {
+      "toml" => Some(Format::Toml),
+      "yaml" | "yml" => Some(Format::Yaml),
+      "json" => Some(Format::Json),
+      _ => None,
+    }
+  }
+}
+
+/// The config of a `SimpleSplitter` stage.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SimpleSplitterConfig {
+  pub chunk_size: u32,
+  #[serde(default)]
+  pub loc_offset: usize,
+}
+
+/// The config of a `SimpleChunker` stage.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SimpleChunkerConfig {
+  pub chunk_size: u32,
+  #[serde(default)]
+  pub loc_offset: usize,
+}
+
+/// The config of a `RecursiveChunker` stage.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RecursiveChunkerConfig {
+  pub chunk_size: u32,
+  pub separators: Vec<String>,
+}
+
+fn stage<Input, Output>(
+  name: &'static str,
+  config: Option<String>,
+  processor: impl Processor<Input, Output> + Send + Sync + 'static,
+) -> Box<dyn DynProcessor>
+where
+  Input: Send + 'static,
+  Output: Send + 'static,
+{
+  let stage = Erased::new(processor).with_name(name);
+  match config {
+    Some(config) => Box::new(stage.with_config(config)),
+    None => Box::new(stage),
+  }
+}
+
+// The chunkers only check their size when they run, but a definition should
+// be rejected when it's loaded.
+fn check_chunk_size(chunk_size: u32) -> Result<(), Error> {
+  if chunk_size == 0 {
+    return Err(Error::InvalidChunkSize(chunk_size));
+  }
+  Ok(())
+}
+
+fn toml_error(text: &str, path: String, err: toml::de::Error) -> Error {
+  // TOML errors have a span rather than a line and column.
+  let position = err.span().map(|span| {
+    let before = &text[..span.start];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+  });
+  Error::Config {
+    path,
+    position,
+    message: err.message().to_string(),
+    source: Arc::new(err),
+  }
+}
+
+fn yaml_error(path: String, err: serde_yaml_ng::Error) -> Error {
+  // YAML errors only have a message with their location in it, and the path
+  // for data errors. Both are reported separately.
+  let location = err.location();
+  let mut message = err.to_string();
+  if let Some(location) = &location {
+    let at =
+      format!(" at line {} column {}", location.line(), location.column());
+    message = message.replacen(&at, "", 1);
+  }
+  if let Some(rest) = message.strip_prefix(&format!("{path}: ")) {
+    message = rest.to_string();
+  }
+  Error::Config {
+    path,
+    position: location.map(|location| (location.line(), location.column())),
+    message,
+    source: Arc::new(err),
+  }
+}
+
+fn json_error(path: String, err: serde_json::Error) -> Error {
+  // JSON errors end with their line and column, unless they're I/O errors.
+  let position = match err.classify() {
+    Category::Io => None,
+    _ => (err.line() > 0).then(|| (err.line(), err.column())),
+  };
+  let mut message = err.to_string();
+  if let Some((line, column)) = position {
+    let at = format!(" at line {line} column {column}");
+    if let Some(rest) = message.strip_suffix(&at) {
+      message = rest.to_string();
+    }
+  }
+  Error::Config {
+    path,
+    position,
+    message,
+    source: Arc::new(err),
+  }
+}
+
+/// Reads the top level of a definition, building the pipeline as its stages
+/// are read.
+#[derive(Clone, Copy)]
+struct PipelineSeed<'r>(&'r Registry);
+
+impl<'de> DeserializeSeed<'de> for PipelineSeed<'_> {
+  type Value = DynPipeline;
+
+  fn deserialize<D: Deserializer<'de>>(
+    self,
+    deserializer: D,
+  ) -> Result<Self::Value, D::Error> {
+    deserializer.deserialize_map(self)
+  }
+}
+
+impl<'de> Visitor<'de> for PipelineSeed<'_> {
+  type Value = DynPipeline;
+
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("a pipeline definition")
+  }
+
+  fn visit_map<A: MapAccess<'de>>(
+    self,
+    mut map: A,
+  ) -> Result<Self::Value, A::Error> {
+    let mut pipeline = None;
+    while let Some(key) = map.next_key::<String>()? {
+      match key.as_str() {
+        "stages" if pipeline.is_some() => {
+          return Err(de::Error::duplicate_field("stages"));
+        }
+        "stages" => pipeline = Some(map.next_value_seed(StagesSeed(self.0))?),
+        _ => return Err(de::Error::unknown_field(&key, &["stages"])),
+      }
+    }
+    pipeline.ok_or_else(|| de::Error::missing_field("stages"))
+  }
+}
+
+#[derive(Clone, Copy)]
+struct StagesSeed<'r>(&'r Registry);
+
+impl<'de> DeserializeSeed<'de> for StagesSeed<'_> {
+  type Value = DynPipeline;
+
+  fn deserialize<D: Deserializer<'de>>(
+    self,
+    deserializer: D,
+  ) -> Result<Self::Value, D::Error> {
+    deserializer.deserialize_seq(self)
+  }
+}
+
+impl<'de> Visitor<'de> for StagesSeed<'_> {
+  type Value = DynPipeline;
+
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("a list of stages")
+  }
+
+  fn visit_seq<A: SeqAccess<'de>>(
+    self,
+    mut seq: A,
+  ) -> Result<Self::Value, A::Error> {
+    let mut pipeline = None;
+    while seq
+      .next_element_seed(StageSeed {
+        registry: self.0,
+        pipeline: &mut pipeline,
+      })?
+      .is_some()
+    {}
+    pipeline.ok_or_else(|| de::Error::invalid_length(0, &"at least one stage"))
+  }
+}
+
+/// Reads a stage and adds it to the pipeline, so type mismatches between
+/// stages are reported at the stage.
+struct StageSeed<'r, 'p> {
+  registry: &'r Registry,
+  pipeline: &'p mut Option<DynPipeline>,
+}
+
+impl<'de> DeserializeSeed<'de> for StageSeed<'_, '_> {
+  type Value = ();
+
+  fn deserialize<D: Deserializer<'de>>(
+    self,
+    deserializer: D,
+  ) -> Result<Self::Value, D::Error> {
+    deserializer.deserialize_map(self)
+  }
+}
+
+impl<'de> Visitor<'de> for StageSeed<'_, '_> {
+  type Value = ();
+
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("a map from a processor name to its config")
+  }
+
+  fn visit_map<A: MapAccess<'de>>(
+    self,
+    mut map: A,
+  ) -> Result<Self::Value, A::Error> {
+    let Some(name) = map.next_key::<String>()? else {
+      return Err(de::Error::invalid_length(0, &self));
+    };
+    let Some(factory) = self.registry.factories.get(name.as_str()) else {
+      let names = self
+        .registry
+        .names()
+        .map(|name| format!("`{name}`"))
+        .collect::<Vec<_>>();
+      return Err(de::Error::custom(format!(
+        "unknown stage `{name}`, expected one of {}",
+        names.join(", ")
+      )));
+    };
+    let processor = map.next_value_seed(FactorySeed(factory))?;
+    if map.next_key::<IgnoredAny>()?.is_some() {
+      return Err(de::Error::custom("a stage must have exactly one name"));
+    }
+
+    match self.pipeline {
+      Some(pipeline) => pipeline.push(processor).map_err(de::Error::custom)?,
+      None => *self.pipeline = Some(DynPipeline::new(processor)),
+    }
+    Ok(())
+  }
+}
+
+struct FactorySeed<'f>(&'f Factory);
+
+impl<'de> DeserializeSeed<'de> for FactorySeed<'_> {
+  type Value = Box<dyn DynProcessor>;
+
+  fn deserialize<D: Deserializer<'de>>(
+    self,
+    deserializer: D,
+  ) -> Result<Self::Value, D::Error> {
+    let mut deserializer =
+      <dyn erased_serde::Deserializer>::erase(deserializer);
+    (self.0)(&mut deserializer).map_err(de::Error::custom)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn contents(chunks: &[Chunk]) -> Vec<String> {
+    chunks
+      .iter()
+      .map(|chunk| chunk.content().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn formats() {
+    let registry = Registry::with_builtins();
+    let toml = r#"
+      [[stages]]
+      [stages.recursive_chunker]
+      chunk_size = 5
+      separators = [" "]
+    "#;
+    let yaml = "
+      stages:
+        - recursive_chunker:
+            chunk_size: 5
+            separators: [' ']
+    ";
+    let json = r#"
+      {"stages": [{"recursive_chunker": {"chunk_size": 5, "separators": [" "]}}]}
+    "#;
+    for (text, format) in [
+      (toml, Format::Toml),
+      (yaml, Format::Yaml),
+      (json, Format::Json),
+    ] {
+      let pipeline = registry.parse(text, format).unwrap();
+      let chunks: Vec<Chunk> =
+        pipeline.run_typed("hello big world".to_string()).unwrap();
+      assert_eq!(contents(&chunks), ["hello", "big", "world"]);
+    }
+
+    let pipeline = registry
+      .parse(
+        "stages: [{simple_splitter: {chunk_size: 4, loc_offset: 10}}]",
+        Format::Yaml,
+      )
+      .unwrap();
+    let elements: Vec<Element> =
+      pipeline.run_typed("abcdef".to_string()).unwrap();
+    assert_eq!(elements[1].content(), "ef");
+    assert_eq!(elements[1].loc().as_tuple(), (14, 16));
+    assert_eq!(
+      Processor::<String, Vec<Element>>::describe(&pipeline).to_string(),
+      "0: simple_splitter (chunk_size: 4, loc_offset: 10)\n"
+    );
+  }
+
+  #[test]
+  fn error_locations() {
+    let registry = Registry::with_builtins();
+    let error = |text, format| registry.parse(text, format).err().unwrap();
+
+    let toml = "
+[[stages]]
+simple_chunker = { chunk_size = 10 }
+
+[[stages]]
+recursive_chunker = { chunk_size = \"big\", separators = [] }
+";
+    assert!(matches!(
+      error(toml, Format::Toml),
+      Error::Config {
+        path,
+        position: Some((6, 21)),
+        message,
+        ..
+      } if path == "stages[1].recursive_chunker.chunk_size"
+        && message.contains("invalid type: string \"big\"")
+    ));
+
+    let yaml = "
+stages:
+  - simple_chunker:
+      chunk_size: 10
+  - smart_chunker:
+      chunk_size: 10
+";
+    let err = error(yaml, Format::Yaml);
+    assert_eq!(
+      err.to_string(),
+      "Invalid config at stages[1] (line 5, column 5): unknown stage \
+       `smart_chunker`, expected one of `recursive_chunker`, \
+       `simple_chunker`, `simple_splitter`"
+    );
+
+    let json = r#"{"stages": [
+      {"simple_chunker": {"chunk_size": 0}}
+    ]}"#;
+    let Error::Config {
+      path,
+      position,
+      message,
+      ..
+    } = error(json, Format::Json)
+    else {
+      panic!("expected a config error");
+    };
+    assert_eq!(path, "stages[0].simple_chunker");
+    assert_eq!(position.unwrap().0, 2);
+    assert_eq!(message, "Invalid chunk size: 0");
+
+    let json = r#"{"stages": [{"simple_chunker": {"size": 10}}]}"#;
+    assert!(error(json, Format::Json)
+      .to_string()
+      .contains("unknown field `size`, expected `chunk_size` or `loc_offset`"));
+
+    // The second stage doesn't take the elements of the first.
+    let yaml = "
+stages:
+  - simple_splitter: {chunk_size: 10}
+  - simple_chunker: {chunk_size: 10}
+";
+    let err = error(yaml, Format::Yaml);
+    assert!(matches!(&err, Error::Config { path, .. } if path == "stages[1]"));
+    assert!(err.to_string().contains("Type mismatch at stage 1"));
+
+    // Syntax errors don't repeat their position in the message.
+    assert!(matches!(
+      error(r#"{"stages": [}"#, Format::Json),
+      Error::Config { position: Some((1, 13)), message, .. }
+        if message == "expected value"
+    ));
+    assert!(matches!(
+      error("stages: [{simple_chunker: {chunk_size: 10}}", Format::Yaml),
+      Error::Config { position: Some((2, 1)), message, .. }
+        if message.starts_with("did not find expected ',' or ']', ")
+    ));
+
+    assert!(matches!(
+      error("stages = []", Format::Toml),
+      Error::Config { message, .. } if message.contains("at least one stage")
+    ));
+  }
+
+  #[test]
+  fn custom_processors() {
+    #[derive(Deserialize)]
+    struct Repeat {
+      times: usize,
+    }
+
+    let mut registry = Registry::empty();
+    registry.register("repeat", |config: Repeat| {
+      Ok(stage("repeat", None, move |text: String| {
+        Ok(text.repeat(config.times))
+      }))
+    });
+    assert_eq!(registry.names().collect::<Vec<_>>(), ["repeat"]);
+    assert!(!registry.contains("simple_chunker"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("pipeline.json");
+    std::fs::write(
+      &path,
+      r#"{"stages": [{"repeat": {"times": 2}}, {"repeat": {"times": 3}}]}"#,
+    )
+    .unwrap();
+    let pipeline = registry.load(&path).unwrap();
+    assert_eq!(
+      pipeline
+        .run_typed::<_, String>("ab".to_string())
+        .unwrap()
+        .len(),
+      12
+    );
+
+    assert!(matches!(
+      registry.load(dir.path().join("pipeline.ini")),
+      Err(Error::InvalidInput(_))
+    ));
+  }
+}
@@ -1,12 +1,680 @@
-use crate::loc::Loc;
+use crate::{
+  chunk::Chunk,
+  element::Element,
+  loc::Loc,
+};
+use chrono::{
+  DateTime,
+  Utc,
+};
 use serde::{
   Deserialize,
   Serialize,
 };
+use std::{
+  borrow::Cow,
+  cmp::Ordering,
+  fmt,
+  ops::Not,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Tag<'a> {
-  pub key: &'a str,
-  pub value: &'a str,
+  #[serde(borrow)]
+  pub key: Cow<'a, str>,
+  #[serde(borrow)]
+  pub value: TagValue<'a>,
   pub loc: Loc,
 }
+
+impl<'a> Tag<'a> {
+  pub fn new(
+    key: impl Into<Cow<'a, str>>,
+    value: impl Into<TagValue<'a>>,
+    loc: Loc,
+  ) -> Self {
+    Self {
+      key: key.into(),
+      value: value.into(),
+      loc,
+    }
+  }
+
+  pub fn into_owned(self) -> Tag<'static> {
+    Tag {
+      key: Cow::Owned(self.key.into_owned()),
+      value: self.value.into_owned(),
+      loc: self.loc,
+    }
+  }
+}
+
+/// The value of a `Tag`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum TagValue<'a> {
+  #[serde(borrow)]
+  String(Cow<'a, str>),
+  Int(i64),
+  Float(f64),
+  Bool(bool),
+  DateTime(DateTime<Utc>),
+  #[serde(borrow)]
+  List(Vec<TagValue<'a>>),
+}
+
+impl<'a> TagValue<'a> {
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      TagValue::String(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_int(&self) -> Option<i64> {
+    match self {
+      TagValue::Int(value) => Some(*value),
+      _ => None,
+    }
+  }
+
+  /// The value as a float, converting ints.
+  pub fn as_float(&self) -> Option<f64> {
+    match self {
+      TagValue::Float(value) => Some(*value),
+      TagValue::Int(value) => Some(*value as f64),
+      _ => None,
+    }
+  }
+
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      TagValue::Bool(value) => Some(*value),
+      _ => None,
+    }
+  }
+
+  pub fn as_datetime(&self) -> Option<&DateTime<Utc>> {
+    match self {
+      TagValue::DateTime(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_list(&self) -> Option<&[TagValue<'a>]> {
+    match self {
+      TagValue::List(values) => Some(values),
+      _ => None,
+    }
+  }
+
+  /// Orders values of compatible types. Ints and floats compare numerically,
+  /// other values only compare with values of the same type. Lists don't
+  /// compare.
+  pub fn compare(&self, other: &TagValue) -> Option<Ordering> {
+    match (self, other) {
+      (TagValue::String(a), TagValue::String(b)) => Some(a.cmp(b)),
+      (TagValue::Int(a), TagValue::Int(b)) => Some(a.cmp(b)),
+      (TagValue::Bool(a), TagValue::Bool(b)) => Some(a.cmp(b)),
+      (TagValue::DateTime(a), TagValue::DateTime(b)) => Some(a.cmp(b)),
+      (TagValue::Int(_) | TagValue::Float(_), _) => {
+        self.as_float()?.partial_cmp(&other.as_float()?)
+      }
+      _ => None,
+    }
+  }
+
+  pub fn into_owned(self) -> TagValue<'static> {
+    match self {
+      TagValue::String(value) => TagValue::String(value.into_owned().into()),
+      TagValue::Int(value) => TagValue::Int(value),
+      TagValue::Float(value) => TagValue::Float(value),
+      TagValue::Bool(value) => TagValue::Bool(value),
+      TagValue::DateTime(value) => TagValue::DateTime(value),
+      TagValue::List(values) => {
+        TagValue::List(values.into_iter().map(TagValue::into_owned).collect())
+      }
+    }
+  }
+}
+
+/// Strings are written as is, lists as their values separated by `, ` and
+/// datetimes in RFC 3339 format.
+impl fmt::Display for TagValue<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TagValue::String(value) => f.write_str(value),
+      TagValue::Int(value) => write!(f, "{value}"),
+      TagValue::Float(value) => write!(f, "{value}"),
+      TagValue::Bool(value) => write!(f, "{value}"),
+      TagValue::DateTime(value) => f.write_str(&value.to_rfc3339()),
+      TagValue::List(values) => {
+        for (i, value) in values.iter().enumerate() {
+          if i > 0 {
+            f.write_str(", ")?;
+          }
+          write!(f, "{value}")?;
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+impl PartialEq<str> for TagValue<'_> {
+  fn eq(&self, other: &str) -> bool {
+    self.as_str() == Some(other)
+  }
+}
+
+impl PartialEq<&str> for TagValue<'_> {
+  fn eq(&self, other: &&str) -> bool {
+    self.as_str() == Some(*other)
+  }
+}
+
+impl<'a> From<&'a str> for TagValue<'a> {
+  fn from(value: &'a str) -> Self {
+    TagValue::String(value.into())
+  }
+}
+
+impl From<String> for TagValue<'_> {
+  fn from(value: String) -> Self {
+    TagValue::String(value.into())
+  }
+}
+
+impl<'a> From<Cow<'a, str>> for TagValue<'a> {
+  fn from(value: Cow<'a, str>) -> Self {
+    TagValue::String(value)
+  }
+}
+
+macro_rules! int_tag_value {
+  ($($t:ty),*) => {
+    $(
+      impl From<$t> for TagValue<'_> {
+        fn from(value: $t) -> Self {
+          TagValue::Int(value.into())
+        }
+      }
+    )*
+  };
+}
+
+int_tag_value!(i8, i16, i32, i64, u8, u16, u32);
+
+/// Integers too large for an `Int` become a `Float`, which compares with
+/// `Int`s.
+macro_rules! large_int_tag_value {
+  ($($t:ty),*) => {
+    $(
+      impl From<$t> for TagValue<'_> {
+        fn from(value: $t) -> Self {
+          i64::try_from(value)
+            .map_or(TagValue::Float(value as f64), TagValue::Int)
+        }
+      }
+    )*
+  };
+}
+
+large_int_tag_value!(u64, usize);
+
+impl From<f32> for TagValue<'_> {
+  fn from(value: f32) -> Self {
+    TagValue::Float(value as f64)
+  }
+}
+
+impl From<f64> for TagValue<'_> {
+  fn from(value: f64) -> Self {
+    TagValue::Float(value)
+  }
+}
+
+impl From<bool> for TagValue<'_> {
+  fn from(value: bool) -> Self {
+    TagValue::Bool(value)
+  }
+}
+
+impl From<DateTime<Utc>> for TagValue<'_> {
+  fn from(value: DateTime<Utc>) -> Self {
+    TagValue::DateTime(value)
+  }
+}
+
+impl<'a, T: Into<TagValue<'a>>> From<Vec<T>> for TagValue<'a> {
+  fn from(values: Vec<T>) -> Self {
+    TagValue::List(values.into_iter().map(Into::into).collect())
+  }
+}
+
+/// The tags of an element or chunk. A key can have several values, which are
+/// kept in insertion order.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Tags<'a> {
+  #[serde(borrow)]
+  tags: Vec<Tag<'a>>,
+}
+
+impl<'a> Tags<'a> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds `tag`, keeping any other values of its key.
+  pub fn insert(&mut self, tag: Tag<'a>) {
+    self.tags.push(tag);
+  }
+
+  /// Replaces all values of the key of `tag` with `tag`.
+  pub fn set(&mut self, tag: Tag<'a>) {
+    self.remove(&tag.key);
+    self.tags.push(tag);
+  }
+
+  /// Removes all values of `key`, returning them.
+  pub fn remove(&mut self, key: &str) -> Vec<Tag<'a>> {
+    let (removed, kept) = std::mem::take(&mut self.tags)
+      .into_iter()
+      .partition(|tag| tag.key == key);
+    self.tags = kept;
+    removed
+  }
+
+  /// The first tag with `key`.
+  pub fn get(&self, key: &str) -> Option<&Tag<'a>> {
+    self.tags.iter().find(|tag| tag.key == key)
+  }
+
+  /// All tags with `key`.
+  pub fn get_all<'s>(
+    &'s self,
+    key: &'s str,
+  ) -> impl Iterator<Item = &'s Tag<'a>> + 's {
+    self.tags.iter().filter(move |tag| tag.key == key)
+  }
+
+  /// The values of all tags with `key`.
+  pub fn values<'s>(
+    &'s self,
+    key: &'s str,
+  ) -> impl Iterator<Item = &'s TagValue<'a>> + 's {
+    self.get_all(key).map(|tag| &tag.value)
+  }
+
+  pub fn contains_key(&self, key: &str) -> bool {
+    self.get(key).is_some()
+  }
+
+  /// The distinct keys, in order of first insertion.
+  pub fn keys(&self) -> Vec<&str> {
+    let mut keys: Vec<&str> = vec![];
+    for tag in &self.tags {
+      if !keys.contains(&&*tag.key) {
+        keys.push(&tag.key);
+      }
+    }
+    keys
+  }
+
+  pub fn iter(&self) -> std::slice::Iter<'_, Tag<'a>> {
+    self.tags.iter()
+  }
+
+  pub fn len(&self) -> usize {
+    self.tags.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.tags.is_empty()
+  }
+
+  pub fn into_owned(self) -> Tags<'static> {
+    self.tags.into_iter().map(Tag::into_owned).collect()
+  }
+}
+
+impl<'a> FromIterator<Tag<'a>> for Tags<'a> {
+  fn from_iter<I: IntoIterator<Item = Tag<'a>>>(iter: I) -> Self {
+    Self {
+      tags: iter.into_iter().collect(),
+    }
+  }
+}
+
+impl<'a> IntoIterator for Tags<'a> {
+  type Item = Tag<'a>;
+  type IntoIter = std::vec::IntoIter<Tag<'a>>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.tags.into_iter()
+  }
+}
+
+impl<'s, 'a> IntoIterator for &'s Tags<'a> {
+  type Item = &'s Tag<'a>;
+  type IntoIter = std::slice::Iter<'s, Tag<'a>>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.tags.iter()
+  }
+}
+
+/// Anything with tags that can be selected with a `TagQuery`.
+pub trait Tagged<'a> {
+  fn tag_set(&self) -> &Tags<'a>;
+}
+
+impl<'a> Tagged<'a> for Tags<'a> {
+  fn tag_set(&self) -> &Tags<'a> {
+    self
+  }
+}
+
+impl<'a> Tagged<'a> for Element<'a> {
+  fn tag_set(&self) -> &Tags<'a> {
+    self.tags()
+  }
+}
+
+impl<'a> Tagged<'a> for Chunk<'a> {
+  fn tag_set(&self) -> &Tags<'a> {
+    self.tags()
+  }
+}
+
+/// A predicate over tags, e.g. to select the elements of some pages:
+///
+/// ```
+/// use ragkit_ai::tag::TagQuery;
+///
+/// let query = TagQuery::ge("page", 3)
+///   .and(TagQuery::le("page", 5))
+///   .and(!TagQuery::eq("style", "Heading1"));
+/// ```
+///
+/// Predicates on a key hold if any value of the key satisfies them. For list
+/// values, any item of the list counts as a value. Items without the key
+/// only match `Missing`, or negated queries.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagQuery {
+  /// The key has a value.
+  Has(String),
+  /// The key has no value.
+  Missing(String),
+  Eq(String, TagValue<'static>),
+  /// The key has values, none of them equal to the value.
+  Ne(String, TagValue<'static>),
+  Lt(String, TagValue<'static>),
+  Le(String, TagValue<'static>),
+  Gt(String, TagValue<'static>),
+  Ge(String, TagValue<'static>),
+  /// The key has one of the values.
+  In(String, Vec<TagValue<'static>>),
+  /// The key has a string value containing the text.
+  Contains(String, String),
+  And(Vec<TagQuery>),
+  Or(Vec<TagQuery>),
+  Not(Box<TagQuery>),
+}
+
+impl TagQuery {
+  pub fn has(key: impl Into<String>) -> Self {
+    TagQuery::Has(key.into())
+  }
+
+  pub fn missing(key: impl Into<String>) -> Self {
+    TagQuery::Missing(key.into())
+  }
+
+  pub fn eq(
+    key: impl Into<String>,
+    value: impl Into<TagValue<'static>>,
+  ) -> Self {
+    TagQuery::Eq(key.into(), value.into())
+  }
+
+  pub fn ne(
+    key: impl Into<String>,
+    value: impl Into<TagValue<'static>>,
+  ) -> Self {
+    TagQuery::Ne(key.into(), value.into())
+  }
+
+  pub fn lt(
+    key: impl Into<String>,
+    value: impl Into<TagValue<'static>>,
+  ) -> Self {
+    TagQuery::Lt(key.into(), value.into())
+  }
+
+  pub fn le(
+    key: impl Into<String>,
+    value: impl Into<TagValue<'static>>,
+  ) -> Self {
+    TagQuery::Le(key.into(), value.into())
+  }
+
+  pub fn gt(
+    key: impl Into<String>,
+    value: impl Into<TagValue<'static>>,
+  ) -> Self {
+    TagQuery::Gt(key.into(), value.into())
+  }
+
+  pub fn ge(
+    key: impl Into<String>,
+    value: impl Into<TagValue<'static>>,
+  ) -> Self {
+    TagQuery::Ge(key.into(), value.into())
+  }
+
+  pub fn one_of<V: Into<TagValue<'static>>>(
+    key: impl Into<String>,
+    values: impl IntoIterator<Item = V>,
+  ) -> Self {
+    TagQuery::In(key.into(), values.into_iter().map(Into::into).collect())
+  }
+
+  pub fn contains(key: impl Into<String>, text: impl Into<String>) -> Self {
+    TagQuery::Contains(key.into(), text.into())
+  }
+
+  pub fn and(self, other: TagQuery) -> Self {
+    match self {
+      TagQuery::And(mut queries) => {
+        queries.push(other);
+        TagQuery::And(queries)
+      }
+      query => TagQuery::And(vec![query, other]),
+    }
+  }
+
+  pub fn or(self, other: TagQuery) -> Self {
+    match self {
+      TagQuery::Or(mut queries) => {
+        queries.push(other);
+        TagQuery::Or(queries)
+      }
+      query => TagQuery::Or(vec![query, other]),
+    }
+  }
+
+  pub fn matches<'a>(&self, item: &impl Tagged<'a>) -> bool {
+    let tags = item.tag_set();
+    let any = |key: &str, predicate: &dyn Fn(&TagValue) -> bool| {
+      tags.values(key).any(|value| match value {
+        TagValue::List(values) => values.iter().any(predicate),
+        value => predicate(value),
+      })
+    };
+    let ordered =
+      |key: &str, value: &TagValue, accept: fn(Ordering) -> bool| {
+        any(key, &|v| v.compare(value).is_some_and(accept))
+      };
+
+    match self {
+      TagQuery::Has(key) => tags.contains_key(key),
+      TagQuery::Missing(key) => !tags.contains_key(key),
+      TagQuery::Eq(key, value) => {
+        ordered(key, value, |ord| ord == Ordering::Equal)
+      }
+      TagQuery::Ne(key, value) => {
+        tags.contains_key(key)
+          && !ordered(key, value, |ord| ord == Ordering::Equal)
+      }
+      TagQuery::Lt(key, value) => ordered(key, value, Ordering::is_lt),
+      TagQuery::Le(key, value) => ordered(key, value, Ordering::is_le),
+      TagQuery::Gt(key, value) => ordered(key, value, Ordering::is_gt),
+      TagQuery::Ge(key, value) => ordered(key, value, Ordering::is_ge),
+      TagQuery::In(key, values) => any(key, &|v| {
+        values
+          .iter()
+          .any(|value| v.compare(value) == Some(Ordering::Equal))
+      }),
+      TagQuery::Contains(key, text) => any(key, &|v| {
+        v.as_str().is_some_and(|s| s.contains(text.as_str()))
+      }),
+      TagQuery::And(queries) => queries.iter().all(|query| query.matches(item)),
+      TagQuery::Or(queries) => queries.iter().any(|query| query.matches(item)),
+      TagQuery::Not(query) => !query.matches(item),
+    }
+  }
+
+  /// The items matching this query.
+  pub fn select<'q, 'a, T: Tagged<'a> + 'q>(
+    &'q self,
+    items: impl IntoIterator<Item = &'q T>,
+  ) -> impl Iterator<Item = &'q T> {
+    items.into_iter().filter(move |item| self.matches(*item))
+  }
+}
+
+impl Not for TagQuery {
+  type Output = TagQuery;
+
+  fn not(self) -> Self::Output {
+    TagQuery::Not(Box::new(self))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::element::SimpleElement;
+  use chrono::TimeZone;
+
+  fn element(
+    page: i64,
+    tags: &[(&'static str, TagValue<'static>)],
+  ) -> Element<'static> {
+    let mut element = SimpleElement::new("text", Loc::new(0, 4))
+      .as_element()
+      .with_tag("page", page);
+    for (key, value) in tags {
+      element = element.with_tag(key, value.clone());
+    }
+    element
+  }
+
+  #[test]
+  fn multi_valued_tags() {
+    let mut tags = Tags::new();
+    tags.insert(Tag::new("author", "ann", Loc::new(0, 1)));
+    tags.insert(Tag::new("author", "bob", Loc::new(0, 1)));
+    tags.insert(Tag::new("year", 2024, Loc::new(0, 1)));
+
+    assert_eq!(tags.keys(), ["author", "year"]);
+    assert_eq!(tags.get("author").unwrap().value, "ann");
+    let authors = tags
+      .values("author")
+      .map(|v| v.to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(authors, ["ann", "bob"]);
+
+    tags.set(Tag::new("author", "carl", Loc::new(0, 1)));
+    assert_eq!(tags.values("author").count(), 1);
+    assert_eq!(tags.remove("year").len(), 1);
+    assert_eq!(tags.len(), 1);
+  }
+
+  #[test]
+  fn typed_values() {
+    let date = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+    let list = TagValue::from(vec![1.5, 2.0]);
+    assert_eq!(list.to_string(), "1.5, 2");
+    assert_eq!(
+      TagValue::from(date).to_string(),
+      "2024-01-02T03:04:05+00:00"
+    );
+    assert_eq!(TagValue::from(3).as_float(), Some(3.0));
+    assert_eq!(TagValue::from(7_usize), TagValue::Int(7));
+    // Values out of range of an `Int` don't wrap around.
+    let large = TagValue::from(u64::MAX);
+    assert_eq!(large, TagValue::Float(u64::MAX as f64));
+    assert_eq!(
+      large.compare(&TagValue::from(i64::MAX)),
+      Some(Ordering::Greater)
+    );
+    assert_eq!(
+      TagValue::from(3).compare(&TagValue::from(2.5)),
+      Some(Ordering::Greater)
+    );
+    assert_eq!(TagValue::from("3").compare(&TagValue::from(3)), None);
+
+    let tag = Tag::new("when", date, Loc::new(0, 1));
+    let json = serde_json::to_string(&tag).unwrap();
+    let parsed: Tag = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, tag);
+    let tags: Tags = [Tag::new("list", list, Loc::new(0, 1))]
+      .into_iter()
+      .collect();
+    let json = serde_json::to_string(&tags).unwrap();
+    assert_eq!(serde_json::from_str::<Tags>(&json).unwrap(), tags);
+  }
+
+  #[test]
+  fn queries() {
+    let date = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+    let elements = [
+      element(1, &[("lang", "en".into()), ("draft", true.into())]),
+      element(2, &[("lang", "de".into()), ("updated", date.into())]),
+      element(3, &[("lang", vec!["en", "fr"].into())]),
+      element(4, &[("style", "Heading 1".into())]),
+    ];
+    let pages = |query: TagQuery| {
+      query
+        .select(&elements)
+        .map(|el| el.tag("page").unwrap().value.as_int().unwrap())
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(pages(TagQuery::eq("lang", "en")), [1, 3]);
+    assert_eq!(
+      pages(TagQuery::ge("page", 2).and(TagQuery::lt("page", 4))),
+      [2, 3]
+    );
+    assert_eq!(
+      pages(TagQuery::gt("page", 1.5).and(TagQuery::has("lang"))),
+      [2, 3]
+    );
+    assert_eq!(pages(TagQuery::one_of("lang", ["de", "fr"])), [2, 3]);
+    assert_eq!(
+      pages(
+        TagQuery::eq("draft", true).or(TagQuery::contains("style", "Heading"))
+      ),
+      [1, 4]
+    );
+    assert_eq!(pages(!TagQuery::has("lang")), [4]);
+    assert_eq!(pages(TagQuery::missing("lang")), [4]);
+    assert_eq!(pages(TagQuery::ne("lang", "en")), [2]);
+    assert_eq!(pages(!TagQuery::eq("lang", "en")), [2, 4]);
+    let later = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    assert_eq!(pages(TagQuery::gt("updated", later)), [2]);
+  }
+}
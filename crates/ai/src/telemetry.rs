@@ -0,0 +1,650 @@
+//! Tracing for pipelines. Every stage of a `Pipeline`, `AsyncPipeline`,
+//! `DynPipeline` or `Dag` runs in a `stage` span, recording:
+//!
+//! - `index` and `name`: the position of the stage in its pipeline, and the
+//!   name of its processor,
+//! - `duration_us`: how long the call took, in microseconds,
+//! - `error`: the error the call failed with, if any.
+//!
+//! Built-in processors like loaders, splitters and chunkers run in a
+//! `processor` span, which records the same fields except `index`, and:
+//!
+//! - `input_size` and `output_size`: bytes of text or numbers of items, when
+//!   the processor knows them.
+//!
+//! The spans work with any `tracing` subscriber. To diagnose slow pipelines,
+//! add a `Recorder` to the subscriber and read its `Report`. It needs the
+//! `telemetry` feature.
+
+use crate::{
+  document::{
+    BinaryDocument,
+    Document,
+  },
+  error::Error,
+  process::{
+    loader::Loaded,
+    normalize::Normalized,
+  },
+};
+#[cfg(feature = "telemetry")]
+use std::{
+  fmt,
+  sync::{
+    Arc,
+    Mutex,
+  },
+  time::Duration,
+};
+use std::{
+  future::Future,
+  time::Instant,
+};
+use tracing::{
+  field::Empty,
+  Instrument,
+  Span,
+};
+#[cfg(feature = "telemetry")]
+use tracing::{
+  field::{
+    Field,
+    Visit,
+  },
+  span,
+  Subscriber,
+};
+#[cfg(feature = "telemetry")]
+use tracing_subscriber::{
+  layer::Context,
+  registry::LookupSpan,
+  Layer,
+};
+
+// ============================================================================
+// Spans
+// ============================================================================
+
+/// The size of a processor's input or output: bytes for text and data, items
+/// for collections.
+pub(crate) trait Size {
+  fn size(&self) -> usize;
+}
+
+impl Size for str {
+  fn size(&self) -> usize {
+    self.len()
+  }
+}
+
+impl<T> Size for [T] {
+  fn size(&self) -> usize {
+    self.len()
+  }
+}
+
+impl<T> Size for Vec<T> {
+  fn size(&self) -> usize {
+    self.len()
+  }
+}
+
+impl Size for Document<'_> {
+  fn size(&self) -> usize {
+    match self {
+      Document::Binary(binary) => binary.size(),
+      document => document.text().map_or(0, str::len),
+    }
+  }
+}
+
+impl Size for BinaryDocument<'_> {
+  fn size(&self) -> usize {
+    self.data.len()
+  }
+}
+
+impl Size for Loaded<'_> {
+  fn size(&self) -> usize {
+    self.elements.len()
+  }
+}
+
+impl Size for Normalized {
+  fn size(&self) -> usize {
+    self.text.len()
+  }
+}
+
+/// A readable name for `T`, without module paths, e.g. `Map<SimpleSplitter>`.
+pub(crate) fn type_name<T: ?Sized>() -> String {
+  let name = std::any::type_name::<T>();
+  let mut short = String::with_capacity(name.len());
+  // Where the current path segment starts in `short`.
+  let mut segment = 0;
+  let mut rest = name;
+  while let Some(c) = rest.chars().next() {
+    if let Some(after) = rest.strip_prefix("::") {
+      short.truncate(segment);
+      rest = after;
+      continue;
+    }
+    short.push(c);
+    if !(c.is_alphanumeric() || c == '_') {
+      segment = short.len();
+    }
+    rest = &rest[c.len_utf8()..];
+  }
+  short
+}
+
+/// Runs the stage at `index` of a pipeline in a `stage` span. Errors get the
+/// name of the stage, see `Error::stages`.
+pub(crate) fn stage<T>(
+  index: usize,
+  name: &str,
+  f: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+  let span = stage_span(index, name);
+  let start = Instant::now();
+  let result = span.in_scope(f);
+  finish(&span, start, &result);
+  result.map_err(|err| err.in_stage(name))
+}
+
+/// Like `stage`, for async pipelines.
+pub(crate) async fn stage_async<T>(
+  index: usize,
+  name: &str,
+  future: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+  let span = stage_span(index, name);
+  let start = Instant::now();
+  let result = future.instrument(span.clone()).await;
+  finish(&span, start, &result);
+  result.map_err(|err| err.in_stage(name))
+}
+
+/// Runs a built-in processor in a `processor` span, recording the size of
+/// its input when known and of its output.
+pub(crate) fn processor<T: Size>(
+  name: &'static str,
+  input_size: Option<usize>,
+  f: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+  let span = tracing::info_span!(
+    "processor",
+    name,
+    input_size,
+    output_size = Empty,
+    duration_us = Empty,
+    error = Empty,
+  );
+  let start = Instant::now();
+  let result = span.in_scope(f);
+  if let Ok(output) = &result {
+    span.record("output_size", output.size());
+  }
+  finish(&span, start, &result);
+  result
+}
+
+fn stage_span(index: usize, name: &str) -> Span {
+  tracing::info_span!("stage", index, name, duration_us = Empty, error = Empty,)
+}
+
+fn finish<T>(span: &Span, start: Instant, result: &Result<T, Error>) {
+  span.record("duration_us", start.elapsed().as_micros() as u64);
+  if let Err(err) = result {
+    span.record("error", tracing::field::display(err));
+  }
+}
+
+// ============================================================================
+// Reports
+// ============================================================================
+
+/// A `tracing_subscriber` layer collecting the spans of pipelines into a
+/// `Report`. Clones share their report, so keep one to read it after adding
+/// the other to a subscriber.
+///
+/// ```
+/// use ragkit_ai::{
+///   error::Error,
+///   telemetry::Recorder,
+///   traits::Pipeline,
+/// };
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let recorder = Recorder::new();
+/// let subscriber = tracing_subscriber::registry().with(recorder.clone());
+/// let pipeline =
+///   Pipeline::new(|text: String| -> Result<usize, Error> { Ok(text.len()) });
+/// tracing::subscriber::with_default(subscriber, || {
+///   pipeline.run("hello".to_string())
+/// })?;
+///
+/// let report = recorder.report();
+/// assert_eq!(report.stages[0].calls, 1);
+/// println!("{report}");
+/// # Ok::<(), Error>(())
+/// ```
+#[cfg(feature = "telemetry")]
+#[derive(Clone, Debug, Default)]
+pub struct Recorder {
+  report: Arc<Mutex<Report>>,
+}
+
+#[cfg(feature = "telemetry")]
+impl Recorder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// A snapshot of everything recorded so far.
+  pub fn report(&self) -> Report {
+    self.lock().clone()
+  }
+
+  pub fn reset(&self) {
+    *self.lock() = Report::default();
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, Report> {
+    self.report.lock().expect("report is never poisoned")
+  }
+}
+
+// The fields of a span, collected as they're recorded.
+#[cfg(feature = "telemetry")]
+#[derive(Debug, Default)]
+struct Fields {
+  index: Option<u64>,
+  name: String,
+  input_size: Option<u64>,
+  output_size: Option<u64>,
+  duration_us: Option<u64>,
+  error: bool,
+}
+
+#[cfg(feature = "telemetry")]
+impl Visit for Fields {
+  fn record_u64(&mut self, field: &Field, value: u64) {
+    match field.name() {
+      "index" => self.index = Some(value),
+      "input_size" => self.input_size = Some(value),
+      "output_size" => self.output_size = Some(value),
+      "duration_us" => self.duration_us = Some(value),
+      _ => {}
+    }
+  }
+
+  fn record_i64(&mut self, field: &Field, value: i64) {
+    self.record_u64(field, value.max(0) as u64);
+  }
+
+  fn record_str(&mut self, field: &Field, value: &str) {
+    match field.name() {
+      "name" => self.name = value.to_string(),
+      "error" => self.error = true,
+      _ => {}
+    }
+  }
+
+  fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+    match field.name() {
+      "name" => self.name = format!("{value:?}"),
+      "error" => self.error = true,
+      _ => {}
+    }
+  }
+}
+
+#[cfg(feature = "telemetry")]
+impl<S> Layer<S> for Recorder
+where
+  S: Subscriber + for<'a> LookupSpan<'a>,
+{
+  fn on_new_span(
+    &self,
+    attrs: &span::Attributes<'_>,
+    id: &span::Id,
+    ctx: Context<'_, S>,
+  ) {
+    if attrs.metadata().target() != module_path!() {
+      return;
+    }
+    let mut fields = Fields::default();
+    attrs.record(&mut fields);
+    if let Some(span) = ctx.span(id) {
+      span.extensions_mut().insert(fields);
+    }
+  }
+
+  fn on_record(
+    &self,
+    id: &span::Id,
+    values: &span::Record<'_>,
+    ctx: Context<'_, S>,
+  ) {
+    let Some(span) = ctx.span(id) else {
+      return;
+    };
+    let mut extensions = span.extensions_mut();
+    if let Some(fields) = extensions.get_mut::<Fields>() {
+      values.record(fields);
+    }
+  }
+
+  fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+    let Some(span) = ctx.span(&id) else {
+      return;
+    };
+    let Some(fields) = span.extensions_mut().remove::<Fields>() else {
+      return;
+    };
+    let mut report = self.lock();
+    let stats = match span.name() {
+      "stage" => &mut report.stages,
+      "processor" => &mut report.processors,
+      _ => return,
+    };
+    // Stages are told apart by their index too, since unnamed stages of the
+    // same type share a name.
+    let index = fields.index.map(|index| index as usize);
+    let position = stats
+      .iter()
+      .position(|stats| stats.index == index && stats.name == fields.name);
+    let position = match position {
+      Some(position) => position,
+      None => {
+        stats.push(Stats::new(index, fields.name.clone()));
+        stats.len() - 1
+      }
+    };
+    stats[position].add(&fields);
+  }
+}
+
+/// What a `Recorder` has seen, in the order stages and processors first ran.
+#[cfg(feature = "telemetry")]
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+  pub stages: Vec<Stats>,
+  pub processors: Vec<Stats>,
+}
+
+#[cfg(feature = "telemetry")]
+impl Report {
+  /// The first stage named `name`, see `Stats::index` for others.
+  pub fn stage(&self, name: &str) -> Option<&Stats> {
+    self.stages.iter().find(|stats| stats.name == name)
+  }
+
+  pub fn processor(&self, name: &str) -> Option<&Stats> {
+    self.processors.iter().find(|stats| stats.name == name)
+  }
+}
+
+#[cfg(feature = "telemetry")]
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let rows = [("stage", &self.stages), ("processor", &self.processors)];
+    let width = rows
+      .iter()
+      .flat_map(|(_, stats)| stats.iter().map(|stats| stats.label().len()))
+      .chain(["processor".len()])
+      .max()
+      .unwrap_or_default();
+    for (kind, stats) in rows {
+      if stats.is_empty() {
+        continue;
+      }
+      writeln!(
+        f,
+        "{kind:<width$} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "calls", "errors", "items in", "items out", "mean", "p95", "max",
+      )?;
+      for stats in stats.iter() {
+        writeln!(
+          f,
+          "{:<width$} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10}",
+          stats.label(),
+          stats.calls,
+          stats.errors,
+          stats.items_in,
+          stats.items_out,
+          format!("{:.1?}", stats.mean()),
+          format!("{:.1?}", stats.histogram.percentile(0.95)),
+          format!("{:.1?}", stats.max),
+        )?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The calls to one stage or processor.
+#[cfg(feature = "telemetry")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+  /// The position of the stage in its pipeline, `None` for processors.
+  pub index: Option<usize>,
+  pub name: String,
+  pub calls: u64,
+  pub errors: u64,
+  /// The total size of the inputs, where known.
+  pub items_in: u64,
+  /// The total size of the outputs, where known.
+  pub items_out: u64,
+  pub total: Duration,
+  pub max: Duration,
+  pub histogram: Histogram,
+}
+
+#[cfg(feature = "telemetry")]
+impl Stats {
+  fn new(index: Option<usize>, name: String) -> Self {
+    Self {
+      index,
+      name,
+      calls: 0,
+      errors: 0,
+      items_in: 0,
+      items_out: 0,
+      total: Duration::ZERO,
+      max: Duration::ZERO,
+      histogram: Histogram::default(),
+    }
+  }
+
+  fn add(&mut self, fields: &Fields) {
+    let duration = Duration::from_micros(fields.duration_us.unwrap_or(0));
+    self.calls += 1;
+    self.errors += u64::from(fields.error);
+    self.items_in += fields.input_size.unwrap_or(0);
+    self.items_out += fields.output_size.unwrap_or(0);
+    self.total += duration;
+    self.max = self.max.max(duration);
+    self.histogram.add(duration);
+  }
+
+  pub fn mean(&self) -> Duration {
+    match self.calls {
+      0 => Duration::ZERO,
+      calls => self.total / calls as u32,
+    }
+  }
+
+  // The name, after the index for stages, e.g. `1: {{closure}}`.
+  fn label(&self) -> String {
+    match self.index {
+      Some(index) => format!("{index}: {}", self.name),
+      None => self.name.clone(),
+    }
+  }
+}
+
+/// Latencies counted in buckets doubling in size: the first counts calls
+/// under 1µs, the second under 2µs, then 4µs and so on.
+#[cfg(feature = "telemetry")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+  counts: Vec<u64>,
+}
+
+#[cfg(feature = "telemetry")]
+impl Histogram {
+  fn add(&mut self, duration: Duration) {
+    let micros = duration.as_micros().min(u64::MAX as u128) as u64;
+    let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+    if self.counts.len() <= bucket {
+      self.counts.resize(bucket + 1, 0);
+    }
+    self.counts[bucket] += 1;
+  }
+
+  pub fn count(&self) -> u64 {
+    self.counts.iter().sum()
+  }
+
+  /// The non-empty buckets, as the latency calls were under and how many
+  /// calls there were.
+  pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+    self
+      .counts
+      .iter()
+      .enumerate()
+      .filter(|(_, count)| **count > 0)
+      .map(|(bucket, count)| (Self::bound(bucket), *count))
+  }
+
+  /// An upper bound for the latency under which a `quantile` of calls
+  /// finished, e.g. 0.95 for the 95th percentile.
+  pub fn percentile(&self, quantile: f64) -> Duration {
+    let rank = (quantile.clamp(0.0, 1.0) * self.count() as f64).ceil() as u64;
+    let mut seen = 0;
+    for (bucket, count) in self.counts.iter().enumerate() {
+      seen += count;
+      if seen >= rank.max(1) {
+        return Self::bound(bucket);
+      }
+    }
+    Duration::ZERO
+  }
+
+  fn bound(bucket: usize) -> Duration {
+    Duration::from_micros(1u64.checked_shl(bucket as u32).unwrap_or(u64::MAX))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[cfg(feature = "telemetry")]
+  use crate::{
+    chunk::{
+      simple::SimpleChunkerBuilder,
+      Chunker,
+    },
+    process::splitter::simple::SimpleSplitterBuilder,
+    traits::{
+      Pipeline,
+      Processor,
+    },
+  };
+  #[cfg(feature = "telemetry")]
+  use tracing_subscriber::layer::SubscriberExt;
+
+  #[test]
+  fn type_names() {
+    assert_eq!(type_name::<String>(), "String");
+    assert_eq!(type_name::<Vec<Option<&str>>>(), "Vec<Option<&str>>");
+    assert_eq!(
+      type_name::<crate::combinator::Map<crate::combinator::Zip>>(),
+      "Map<Zip>"
+    );
+  }
+
+  #[cfg(feature = "telemetry")]
+  #[test]
+  fn report() {
+    let recorder = Recorder::new();
+    let subscriber = tracing_subscriber::registry().with(recorder.clone());
+    let splitter = SimpleSplitterBuilder::default()
+      .chunk_size(4_u32)
+      .build()
+      .unwrap();
+    let count = |elements: Vec<_>| -> Result<usize, Error> {
+      match elements.len() {
+        0 => Err(Error::InvalidInput("empty".to_string())),
+        len => Ok(len),
+      }
+    };
+    let double = |count: usize| -> Result<usize, Error> { Ok(count * 2) };
+    let pipeline = Pipeline::new(splitter).chain(count).chain(double);
+    let chunker = SimpleChunkerBuilder::default()
+      .chunk_size(4_u32)
+      .build()
+      .unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+      assert_eq!(pipeline.process("hello world").unwrap(), 6);
+      assert_eq!(pipeline.process("hey").unwrap(), 2);
+      assert!(pipeline.process("").is_err());
+      assert_eq!(chunker.chunk("hello").unwrap().len(), 2);
+    });
+
+    // Both closures are named `{{closure}}`, but are told apart by index.
+    let report = recorder.report();
+    let stages = report
+      .stages
+      .iter()
+      .map(|stats| (stats.index.unwrap(), stats.name.as_str(), stats.calls))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      stages,
+      [
+        (0, "SimpleSplitter", 3),
+        (1, "{{closure}}", 3),
+        (2, "{{closure}}", 2)
+      ]
+    );
+    let stage = report.stage("{{closure}}").unwrap();
+    assert_eq!((stage.calls, stage.errors), (3, 1));
+    assert_eq!(stage.histogram.count(), 3);
+    assert!(stage.histogram.percentile(0.5) <= stage.histogram.percentile(1.0));
+
+    let splitter = report.processor("SimpleSplitter").unwrap();
+    assert_eq!(splitter.index, None);
+    assert_eq!((splitter.calls, splitter.errors), (3, 0));
+    assert_eq!((splitter.items_in, splitter.items_out), (14, 4));
+    let chunker = report.processor("SimpleChunker").unwrap();
+    assert_eq!((chunker.items_in, chunker.items_out), (5, 2));
+    let report = report.to_string();
+    assert!(report.contains("0: SimpleSplitter"));
+    assert!(report.contains("2: {{closure}}"));
+
+    recorder.reset();
+    assert!(recorder.report().stages.is_empty());
+  }
+
+  #[cfg(feature = "telemetry")]
+  #[test]
+  fn histogram() {
+    let mut histogram = Histogram::default();
+    for micros in [0, 3, 3, 100] {
+      histogram.add(Duration::from_micros(micros));
+    }
+    assert_eq!(
+      histogram.buckets().collect::<Vec<_>>(),
+      [
+        (Duration::from_micros(1), 1),
+        (Duration::from_micros(4), 2),
+        (Duration::from_micros(128), 1),
+      ]
+    );
+    assert_eq!(histogram.percentile(0.5), Duration::from_micros(4));
+    assert_eq!(histogram.percentile(1.0), Duration::from_micros(128));
+  }
+}
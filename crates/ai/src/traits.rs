@@ -1,25 +1,43 @@
-use crate::error::Error;
-use std::marker::PhantomData;
+use crate::{
+  describe::Graph,
+  error::Error,
+  telemetry,
+};
+use std::{
+  borrow::Cow,
+  future::Future,
+  marker::PhantomData,
+};
 
 // ============================================================================
 // Processor definition
 // ============================================================================
 
+/// A step of a pipeline. Errors are handled by wrapping processors in the
+/// middleware of `combinator::resilience`, e.g. `Retry` or `Fallback`.
 pub trait Processor<Input, Output> {
   fn process(&self, input: Input) -> Result<Output, Error>;
 
-  // TODO: Not sure how helpful this is, can just handle errors in the process
-  // implementation itself.
-  fn on_err(&self, err: Error) -> Result<Output, Error> {
-    Err(err)
+  fn run(&self, input: Input) -> Result<Output, Error> {
+    self.process(input)
   }
 
-  fn run(&self, input: Input) -> Result<Output, Error> {
-    let out = self.process(input);
-    match out {
-      Ok(o) => Ok(o),
-      Err(e) => self.on_err(e),
-    }
+  /// The name of the processor in traces and descriptions. Defaults to its
+  /// type, use `Named` to choose one.
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Owned(telemetry::type_name::<Self>())
+  }
+
+  /// The settings of the processor, shown in descriptions. Processors
+  /// deriving `Serialize` can use `describe::config`.
+  fn config(&self) -> Option<String> {
+    None
+  }
+
+  /// The stages the processor is made of. A single stage by default, while
+  /// pipelines and combinators describe their parts.
+  fn describe(&self) -> Graph {
+    Graph::stage(self.name(), self.config())
   }
 }
 
@@ -29,6 +47,10 @@ impl<Input> Processor<Input, Input> for IdentityProcessor {
   fn process(&self, input: Input) -> Result<Input, Error> {
     Ok(input)
   }
+
+  fn describe(&self) -> Graph {
+    Graph::default()
+  }
 }
 
 impl<Input, Output, T> Processor<Input, Output> for T
@@ -40,6 +62,75 @@ where
   }
 }
 
+/// Gives a processor a name for traces and descriptions, e.g. for closures,
+/// which are otherwise all named `{{closure}}`. If the processor has several
+/// stages, like a pipeline, the stages keep their names.
+pub struct Named<P> {
+  name: String,
+  processor: P,
+}
+
+impl<P> Named<P> {
+  pub fn new(name: impl Into<String>, processor: P) -> Self {
+    Self {
+      name: name.into(),
+      processor,
+    }
+  }
+
+  fn rename(&self, mut graph: Graph) -> Graph {
+    if let [stage] = &mut graph.stages[..] {
+      stage.name.clone_from(&self.name);
+    }
+    graph
+  }
+}
+
+impl<Input, Output, P> Processor<Input, Output> for Named<P>
+where
+  P: Processor<Input, Output>,
+{
+  fn process(&self, input: Input) -> Result<Output, Error> {
+    self.processor.process(input)
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Borrowed(&self.name)
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.rename(self.processor.describe())
+  }
+}
+
+impl<Input, Output, P> AsyncProcessor<Input, Output> for Named<P>
+where
+  P: AsyncProcessor<Input, Output>,
+{
+  fn process(
+    &self,
+    input: Input,
+  ) -> impl Future<Output = Result<Output, Error>> + Send {
+    self.processor.process(input)
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Borrowed(&self.name)
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.rename(self.processor.describe())
+  }
+}
+
 // ============================================================================
 // Pipeline definition
 // ============================================================================
@@ -51,6 +142,8 @@ where
 {
   processor: Curr,
   prev: Prev,
+  // The position of `processor` in the pipeline, counting from 0.
+  index: usize,
   phantom: PhantomData<(Input, Output, IntermediateOut, IntermediateIn)>,
 }
 
@@ -65,6 +158,7 @@ where
     Pipeline {
       processor,
       prev: IdentityProcessor,
+      index: 0,
       phantom: PhantomData,
     }
   }
@@ -88,6 +182,7 @@ where
   {
     Pipeline {
       processor,
+      index: self.index + 1,
       prev: self,
       phantom: PhantomData,
     }
@@ -98,7 +193,8 @@ where
   }
 }
 
-// Pipelines themselves are also processors for their inputs and outputs.
+// Pipelines themselves are also processors for their inputs and outputs. Each
+// stage runs in a span, see `telemetry`.
 impl<Input, Output, IntermediateOut, IntermediateIn, Curr, Prev>
   Processor<Input, Output>
   for Pipeline<Input, Output, IntermediateOut, IntermediateIn, Curr, Prev>
@@ -109,6 +205,354 @@ where
 {
   fn process(&self, input: Input) -> Result<Output, Error> {
     let intermediate = self.prev.process(input)?;
-    self.processor.process(intermediate.into())
+    telemetry::stage(self.index, &self.processor.name(), || {
+      self.processor.process(intermediate.into())
+    })
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Borrowed("Pipeline")
+  }
+
+  fn describe(&self) -> Graph {
+    self.prev.describe().then(self.processor.describe())
+  }
+}
+
+// ============================================================================
+// Async processor definition
+// ============================================================================
+
+/// The async counterpart of `Processor`, for steps that wait on I/O such as
+/// embedding or LLM calls. Closures returning a future of a `Result` are
+/// async processors. Sync processors can be used in async pipelines through
+/// `Inline` or `Blocking`.
+pub trait AsyncProcessor<Input, Output> {
+  fn process(
+    &self,
+    input: Input,
+  ) -> impl Future<Output = Result<Output, Error>> + Send;
+
+  /// See `Processor::name`.
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Owned(telemetry::type_name::<Self>())
+  }
+
+  /// See `Processor::config`.
+  fn config(&self) -> Option<String> {
+    None
+  }
+
+  /// See `Processor::describe`.
+  fn describe(&self) -> Graph {
+    Graph::stage(self.name(), self.config())
+  }
+}
+
+impl<Input: Send> AsyncProcessor<Input, Input> for IdentityProcessor {
+  fn process(
+    &self,
+    input: Input,
+  ) -> impl Future<Output = Result<Input, Error>> + Send {
+    std::future::ready(Ok(input))
+  }
+
+  fn describe(&self) -> Graph {
+    Graph::default()
+  }
+}
+
+impl<Input, Output, T, Fut> AsyncProcessor<Input, Output> for T
+where
+  T: Fn(Input) -> Fut,
+  Fut: Future<Output = Result<Output, Error>> + Send,
+{
+  fn process(
+    &self,
+    input: Input,
+  ) -> impl Future<Output = Result<Output, Error>> + Send {
+    self(input)
+  }
+}
+
+/// Runs a sync processor directly on the async task. Fine for cheap steps;
+/// use `Blocking` for ones that take long enough to stall other tasks.
+pub struct Inline<P>(pub P);
+
+impl<P> Inline<P> {
+  pub fn new(processor: P) -> Self {
+    Self(processor)
+  }
+}
+
+impl<Input, Output, P> AsyncProcessor<Input, Output> for Inline<P>
+where
+  P: Processor<Input, Output>,
+  Output: Send,
+{
+  fn process(
+    &self,
+    input: Input,
+  ) -> impl Future<Output = Result<Output, Error>> + Send {
+    std::future::ready(self.0.run(input))
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.0.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.0.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.0.describe()
+  }
+}
+
+/// Runs a sync processor on tokio's blocking thread pool, e.g. for parsing
+/// large files. Must be used within a tokio runtime. Since the work moves to
+/// another thread, inputs and outputs can't borrow. Needs the `tokio` feature.
+#[cfg(feature = "tokio")]
+pub struct Blocking<P> {
+  processor: std::sync::Arc<P>,
+}
+
+#[cfg(feature = "tokio")]
+impl<P> Blocking<P> {
+  pub fn new(processor: P) -> Self {
+    Self {
+      processor: std::sync::Arc::new(processor),
+    }
+  }
+}
+
+#[cfg(feature = "tokio")]
+impl<Input, Output, P> AsyncProcessor<Input, Output> for Blocking<P>
+where
+  P: Processor<Input, Output> + Send + Sync + 'static,
+  Input: Send + 'static,
+  Output: Send + 'static,
+{
+  fn process(
+    &self,
+    input: Input,
+  ) -> impl Future<Output = Result<Output, Error>> + Send {
+    let processor = std::sync::Arc::clone(&self.processor);
+    async move {
+      tokio::task::spawn_blocking(move || processor.run(input))
+        .await
+        .map_err(|err| Error::Task(err.to_string()))?
+    }
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    self.processor.name()
+  }
+
+  fn config(&self) -> Option<String> {
+    self.processor.config()
+  }
+
+  fn describe(&self) -> Graph {
+    self.processor.describe()
+  }
+}
+
+// ============================================================================
+// Async pipeline definition
+// ============================================================================
+
+/// The async counterpart of `Pipeline`, chaining `AsyncProcessor`s.
+pub struct AsyncPipeline<
+  Input,
+  Output,
+  IntermediateOut,
+  IntermediateIn,
+  Curr,
+  Prev,
+> where
+  Curr: AsyncProcessor<IntermediateIn, Output>,
+  Prev: AsyncProcessor<Input, IntermediateOut>,
+{
+  processor: Curr,
+  prev: Prev,
+  index: usize,
+  // A function pointer so the pipeline is `Send` and `Sync` whatever the
+  // types flowing through it are.
+  #[allow(clippy::type_complexity)]
+  phantom: PhantomData<fn(Input, IntermediateIn) -> (Output, IntermediateOut)>,
+}
+
+impl<Input, Output, Curr>
+  AsyncPipeline<Input, Output, Input, Input, Curr, IdentityProcessor>
+where
+  Curr: AsyncProcessor<Input, Output>,
+  Input: Send,
+{
+  pub fn new(processor: Curr) -> Self {
+    AsyncPipeline {
+      processor,
+      prev: IdentityProcessor,
+      index: 0,
+      phantom: PhantomData,
+    }
+  }
+}
+
+impl<Input, Output, IntermediateOut, IntermediateIn, Curr, Prev>
+  AsyncPipeline<Input, Output, IntermediateOut, IntermediateIn, Curr, Prev>
+where
+  Curr: AsyncProcessor<IntermediateIn, Output> + Sync,
+  Prev: AsyncProcessor<Input, IntermediateOut> + Sync,
+  IntermediateOut: Into<IntermediateIn>,
+  Input: Send,
+{
+  pub fn chain<NextIn, NextOut, Next>(
+    self,
+    processor: Next,
+  ) -> AsyncPipeline<Input, NextOut, Output, NextIn, Next, Self>
+  where
+    Next: AsyncProcessor<NextIn, NextOut>,
+    Output: Into<NextIn>,
+    Self: Sized,
+  {
+    AsyncPipeline {
+      processor,
+      index: self.index + 1,
+      prev: self,
+      phantom: PhantomData,
+    }
+  }
+
+  /// Chains a sync processor, run with `Inline`.
+  pub fn chain_sync<NextIn, NextOut, Next>(
+    self,
+    processor: Next,
+  ) -> AsyncPipeline<Input, NextOut, Output, NextIn, Inline<Next>, Self>
+  where
+    Next: Processor<NextIn, NextOut>,
+    NextOut: Send,
+    Output: Into<NextIn>,
+    Self: Sized,
+  {
+    self.chain(Inline(processor))
+  }
+
+  /// Chains a sync processor, run with `Blocking`.
+  #[cfg(feature = "tokio")]
+  pub fn chain_blocking<NextIn, NextOut, Next>(
+    self,
+    processor: Next,
+  ) -> AsyncPipeline<Input, NextOut, Output, NextIn, Blocking<Next>, Self>
+  where
+    Next: Processor<NextIn, NextOut> + Send + Sync + 'static,
+    NextIn: Send + 'static,
+    NextOut: Send + 'static,
+    Output: Into<NextIn>,
+    Self: Sized,
+  {
+    self.chain(Blocking::new(processor))
+  }
+
+  pub async fn run(&self, input: impl Into<Input>) -> Result<Output, Error> {
+    AsyncProcessor::process(self, input.into()).await
+  }
+}
+
+// Async pipelines are also async processors for their inputs and outputs.
+impl<Input, Output, IntermediateOut, IntermediateIn, Curr, Prev>
+  AsyncProcessor<Input, Output>
+  for AsyncPipeline<Input, Output, IntermediateOut, IntermediateIn, Curr, Prev>
+where
+  Curr: AsyncProcessor<IntermediateIn, Output> + Sync,
+  Prev: AsyncProcessor<Input, IntermediateOut> + Sync,
+  IntermediateOut: Into<IntermediateIn>,
+  Input: Send,
+{
+  async fn process(&self, input: Input) -> Result<Output, Error> {
+    let intermediate = self.prev.process(input).await?;
+    let stage = self.processor.process(intermediate.into());
+    telemetry::stage_async(self.index, &self.processor.name(), stage).await
+  }
+
+  fn name(&self) -> Cow<'_, str> {
+    Cow::Borrowed("AsyncPipeline")
+  }
+
+  fn describe(&self) -> Graph {
+    self.prev.describe().then(self.processor.describe())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct Double;
+
+  impl Processor<u32, u32> for Double {
+    fn process(&self, input: u32) -> Result<u32, Error> {
+      Ok(input * 2)
+    }
+  }
+
+  #[cfg(feature = "tokio")]
+  #[tokio::test]
+  async fn async_pipeline() {
+    let fetch = |id: u32| async move { Ok(format!("doc {id}")) };
+    let pipeline = AsyncPipeline::new(Inline(Double))
+      .chain_blocking(Double)
+      .chain(fetch)
+      .chain_sync(|doc: String| Ok(doc.len()));
+    assert_eq!(pipeline.run(5u32).await.unwrap(), 6);
+
+    // Pipelines are processors too, and can be spawned.
+    let pipeline = std::sync::Arc::new(pipeline);
+    let handle = tokio::spawn({
+      let pipeline = std::sync::Arc::clone(&pipeline);
+      async move { pipeline.run(25u32).await }
+    });
+    assert_eq!(handle.await.unwrap().unwrap(), 7);
+  }
+
+  #[tokio::test]
+  async fn errors() {
+    let failing =
+      |_: u32| -> Result<u32, Error> { Err(Error::InvalidChunkSize(0)) };
+    let pipeline = AsyncPipeline::new(Inline(Double)).chain_sync(failing);
+    let err = pipeline.run(1u32).await.unwrap_err();
+    assert!(matches!(err.root(), Error::InvalidChunkSize(0)));
+
+    // Errors name the stages they happened in, nested pipelines included.
+    let pipeline = Pipeline::new(Double).chain(
+      Pipeline::new(Named::new("double", Double))
+        .chain(Named::new("fail", failing)),
+    );
+    let err = pipeline.run(1u32).unwrap_err();
+    assert_eq!(err.stages(), ["Pipeline", "fail"]);
+    // Messages don't repeat their sources, which are reported separately.
+    let chain =
+      std::iter::successors(Some(&err as &dyn std::error::Error), |err| {
+        err.source()
+      })
+      .map(ToString::to_string)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      chain,
+      [
+        "Stage Pipeline failed",
+        "Stage fail failed",
+        "Invalid chunk size: 0"
+      ]
+    );
+
+    #[cfg(feature = "tokio")]
+    {
+      let panics = |_: u32| -> Result<u32, Error> { panic!("boom") };
+      let pipeline = AsyncPipeline::new(Blocking::new(panics));
+      let err = pipeline.run(1u32).await.unwrap_err();
+      assert!(matches!(err.root(), Error::Task(_)));
+    }
   }
 }
@@ -0,0 +1,399 @@
+use crate::{
+  chunk::Chunk,
+  element::Element,
+  loc::Loc,
+};
+use std::collections::HashMap;
+
+/// Identifies a section of a `DocumentTree`.
+pub type SectionId = usize;
+
+/// A heading and everything up to the next heading of the same or a higher
+/// level. The root section is the whole document and has no title.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+  /// The index of the title element, `None` for the root.
+  pub title: Option<usize>,
+  /// From the end of the element before the title, so the markup of the
+  /// title is included, to the start of the next section that isn't a
+  /// subsection, or the end of the document.
+  pub loc: Loc,
+  pub parent: Option<SectionId>,
+  /// The subsections, in document order.
+  pub children: Vec<SectionId>,
+  /// The indices of the elements directly in this section, not in a
+  /// subsection, excluding the title.
+  pub elements: Vec<usize>,
+}
+
+/// The sections of a document, built from the `section` and `parent` of its
+/// elements, as set by loaders and the `Partitioner` (see
+/// `process::partition::link`). Elements outside of any section, including
+/// headers, footers and page breaks, belong to the root.
+///
+/// Chunks of the same document can be attached with `with_chunks` to find
+/// their sections and neighbours.
+#[derive(Clone, Debug)]
+pub struct DocumentTree<'t, 'a> {
+  elements: &'t [Element<'a>],
+  chunks: &'t [Chunk<'a>],
+  sections: Vec<Section>,
+  /// The section started by each title element.
+  titles: HashMap<usize, SectionId>,
+}
+
+impl<'t, 'a> DocumentTree<'t, 'a> {
+  pub const ROOT: SectionId = 0;
+
+  pub fn new(elements: &'t [Element<'a>]) -> Self {
+    let end = elements.iter().map(|el| el.loc().end).max().unwrap_or(0);
+    let mut sections = vec![Section {
+      title: None,
+      loc: Loc::new(0, end),
+      parent: None,
+      children: vec![],
+      elements: vec![],
+    }];
+    let mut titles = HashMap::new();
+
+    for (index, element) in elements.iter().enumerate() {
+      let section_of = |index: Option<usize>| {
+        index
+          .and_then(|title| titles.get(&title).copied())
+          .unwrap_or(Self::ROOT)
+      };
+      if let Element::Title(_) = element {
+        let parent = section_of(element.parent());
+        let start = index
+          .checked_sub(1)
+          .map_or(0, |prev| elements[prev].loc().end);
+        let id = sections.len();
+        sections.push(Section {
+          title: Some(index),
+          loc: Loc::new(start, end),
+          parent: Some(parent),
+          children: vec![],
+          elements: vec![],
+        });
+        sections[parent].children.push(id);
+        titles.insert(index, id);
+      } else {
+        let section = section_of(element.section());
+        sections[section].elements.push(index);
+      }
+    }
+
+    // Sections end where their next sibling starts, or with their parent.
+    for id in 0..sections.len() {
+      let end = sections[id].loc.end;
+      let children = sections[id].children.clone();
+      for (i, &child) in children.iter().enumerate() {
+        let next = children.get(i + 1).map(|&next| sections[next].loc.start);
+        sections[child].loc.end = next.unwrap_or(end);
+      }
+    }
+
+    Self {
+      elements,
+      chunks: &[],
+      sections,
+      titles,
+    }
+  }
+
+  /// Attaches the chunks of the document, in document order.
+  pub fn with_chunks(self, chunks: &'t [Chunk<'a>]) -> Self {
+    Self { chunks, ..self }
+  }
+
+  pub fn section(&self, id: SectionId) -> &Section {
+    &self.sections[id]
+  }
+
+  /// All sections in document order, starting with the root.
+  pub fn sections(&self) -> &[Section] {
+    &self.sections
+  }
+
+  pub fn title(&self, id: SectionId) -> Option<&'t Element<'a>> {
+    self.sections[id].title.map(|title| &self.elements[title])
+  }
+
+  /// The level of the section's title, `None` for the root.
+  pub fn level(&self, id: SectionId) -> Option<u8> {
+    match self.title(id) {
+      Some(Element::Title(title)) => Some(title.level),
+      _ => None,
+    }
+  }
+
+  /// The subsections of a section.
+  pub fn children(&self, id: SectionId) -> &[SectionId] {
+    &self.sections[id].children
+  }
+
+  /// The other subsections of the section's parent.
+  pub fn siblings(&self, id: SectionId) -> Vec<SectionId> {
+    match self.sections[id].parent {
+      Some(parent) => self.sections[parent]
+        .children
+        .iter()
+        .copied()
+        .filter(|&other| other != id)
+        .collect(),
+      None => vec![],
+    }
+  }
+
+  pub fn prev_sibling(&self, id: SectionId) -> Option<SectionId> {
+    let children = &self.sections[self.sections[id].parent?].children;
+    let position = children.iter().position(|&other| other == id)?;
+    position.checked_sub(1).map(|prev| children[prev])
+  }
+
+  pub fn next_sibling(&self, id: SectionId) -> Option<SectionId> {
+    let children = &self.sections[self.sections[id].parent?].children;
+    let position = children.iter().position(|&other| other == id)?;
+    children.get(position + 1).copied()
+  }
+
+  /// The elements directly in a section, excluding its title and
+  /// subsections.
+  pub fn elements(
+    &self,
+    id: SectionId,
+  ) -> impl Iterator<Item = &'t Element<'a>> + '_ {
+    let elements = self.elements;
+    self.sections[id]
+      .elements
+      .iter()
+      .map(move |&el| &elements[el])
+  }
+
+  /// The sections containing a section, from its parent up to the root.
+  pub fn ancestors(&self, id: SectionId) -> Vec<SectionId> {
+    let mut ancestors = vec![];
+    let mut current = self.sections[id].parent;
+    while let Some(id) = current {
+      ancestors.push(id);
+      current = self.sections[id].parent;
+    }
+    ancestors
+  }
+
+  /// The titles of the sections leading to a section, including its own,
+  /// e.g. `["Guide", "Install", "Linux"]`.
+  pub fn breadcrumbs(&self, id: SectionId) -> Vec<&'t str> {
+    let mut ids = self.ancestors(id);
+    ids.reverse();
+    ids.push(id);
+    ids
+      .into_iter()
+      .filter_map(|id| self.title(id))
+      .map(|title| title.content())
+      .collect()
+  }
+
+  /// The section an element is in. For titles, the section they start.
+  pub fn element_section(&self, index: usize) -> SectionId {
+    if let Some(&id) = self.titles.get(&index) {
+      return id;
+    }
+    self.elements[index]
+      .section()
+      .and_then(|title| self.titles.get(&title).copied())
+      .unwrap_or(Self::ROOT)
+  }
+
+  /// The innermost section containing all of `loc`.
+  pub fn section_at(&self, loc: &Loc) -> SectionId {
+    let mut id = Self::ROOT;
+    loop {
+      let children = &self.sections[id].children;
+      let after = children
+        .partition_point(|&child| self.sections[child].loc.start <= loc.start);
+      match after.checked_sub(1).map(|child| children[child]) {
+        Some(child) if self.sections[child].loc.contains(loc) => id = child,
+        _ => return id,
+      }
+    }
+  }
+
+  pub fn chunks(&self) -> &'t [Chunk<'a>] {
+    self.chunks
+  }
+
+  /// The innermost section containing the chunk at `index`, `None` if there
+  /// is no such chunk.
+  pub fn chunk_section(&self, index: usize) -> Option<SectionId> {
+    Some(self.section_at(self.chunks.get(index)?.loc()))
+  }
+
+  pub fn prev_chunk(&self, index: usize) -> Option<&'t Chunk<'a>> {
+    index.checked_sub(1).and_then(|prev| self.chunks.get(prev))
+  }
+
+  pub fn next_chunk(&self, index: usize) -> Option<&'t Chunk<'a>> {
+    self.chunks.get(index + 1)
+  }
+
+  /// The indices of the chunks within a section, including its subsections.
+  pub fn section_chunks(&self, id: SectionId) -> Vec<usize> {
+    let loc = &self.sections[id].loc;
+    (0..self.chunks.len())
+      .filter(|&index| loc.contains(self.chunks[index].loc()))
+      .collect()
+  }
+
+  /// The chunk at `index` with up to `radius` chunks on either side that are
+  /// in the same section, e.g. to expand the context of a retrieved chunk.
+  /// Empty if there is no chunk at `index`.
+  pub fn chunk_context(&self, index: usize, radius: usize) -> &'t [Chunk<'a>] {
+    let Some(section) = self.chunk_section(index) else {
+      return &[];
+    };
+    let section = &self.sections[section].loc;
+    let in_section =
+      |index: &usize| section.contains(self.chunks[*index].loc());
+    let start = (index.saturating_sub(radius)..index)
+      .rev()
+      .take_while(in_section)
+      .last()
+      .unwrap_or(index);
+    let last = index.saturating_add(radius).min(self.chunks.len() - 1);
+    let end = (index + 1..=last)
+      .take_while(in_section)
+      .last()
+      .unwrap_or(index);
+    &self.chunks[start..=end]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    chunk::{
+      recursive::RecursiveChunkerBuilder,
+      Chunker,
+    },
+    document::MarkdownDocument,
+    process::partition::PartitionerBuilder,
+    traits::Processor,
+  };
+
+  const GUIDE: &str = "Preface.\n\n# Guide\n\nIntro.\n\n## Install\n\n\
+    Get it.\n\n### Linux\n\nUse apt.\n\n### Mac\n\nUse brew.\n\n\
+    ## Usage\n\nRun it.\n\n# Appendix\n\nMore.";
+
+  #[test]
+  fn sections() {
+    let doc = MarkdownDocument::new(GUIDE).as_document();
+    let partitioner = PartitionerBuilder::default().build().unwrap();
+    let elements = partitioner.process(&doc).unwrap();
+    let tree = DocumentTree::new(&elements);
+
+    let titles = |ids: &[SectionId]| {
+      ids
+        .iter()
+        .map(|&id| tree.title(id).map_or("", |title| title.content()))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      titles(tree.children(DocumentTree::ROOT)),
+      ["Guide", "Appendix"]
+    );
+    let guide = tree.children(DocumentTree::ROOT)[0];
+    assert_eq!(titles(tree.children(guide)), ["Install", "Usage"]);
+    let install = tree.children(guide)[0];
+    let linux = tree.children(install)[0];
+    assert_eq!(titles(&tree.siblings(linux)), ["Mac"]);
+    assert_eq!(tree.next_sibling(install), Some(tree.children(guide)[1]));
+    assert_eq!(tree.prev_sibling(install), None);
+    assert_eq!(tree.level(linux), Some(3));
+    assert_eq!(tree.breadcrumbs(linux), ["Guide", "Install", "Linux"]);
+    assert_eq!(
+      tree
+        .elements(DocumentTree::ROOT)
+        .map(|el| el.content())
+        .collect::<Vec<_>>(),
+      ["Preface."]
+    );
+    assert_eq!(
+      tree
+        .elements(install)
+        .map(|el| el.content())
+        .collect::<Vec<_>>(),
+      ["Get it."]
+    );
+
+    // Install runs up to the start of Usage, right after its last element.
+    let brew = GUIDE.find("brew").unwrap();
+    assert_eq!(tree.section(install).loc.end, brew + "brew.".len());
+    let apt = GUIDE.find("apt").unwrap();
+    assert_eq!(tree.section_at(&Loc::new(apt, apt + 3)), linux);
+    // Spanning Linux and Mac.
+    assert_eq!(tree.section_at(&Loc::new(apt, brew)), install);
+    assert_eq!(
+      tree.element_section(tree.section(linux).title.unwrap()),
+      linux
+    );
+  }
+
+  #[test]
+  fn chunks() {
+    let doc = MarkdownDocument::new(GUIDE).as_document();
+    let partitioner = PartitionerBuilder::default().build().unwrap();
+    let elements = partitioner.process(&doc).unwrap();
+    let chunker = RecursiveChunkerBuilder::default()
+      .chunk_size(12u32)
+      .separators(vec!["\n\n"])
+      .build()
+      .unwrap();
+    let chunks = chunker.chunk(GUIDE).unwrap();
+    let tree = DocumentTree::new(&elements).with_chunks(&chunks);
+
+    let apt = chunks
+      .iter()
+      .position(|c| c.content() == "Use apt.")
+      .unwrap();
+    assert_eq!(
+      tree.breadcrumbs(tree.chunk_section(apt).unwrap()),
+      ["Guide", "Install", "Linux"]
+    );
+    assert_eq!(tree.prev_chunk(apt).unwrap().content(), "### Linux");
+    assert_eq!(tree.next_chunk(apt).unwrap().content(), "### Mac");
+    let context = tree
+      .chunk_context(apt, 2)
+      .iter()
+      .map(|chunk| chunk.content())
+      .collect::<Vec<_>>();
+    assert_eq!(context, ["### Linux", "Use apt."]);
+    // The whole section, however large the radius.
+    let context = tree.chunk_context(apt, usize::MAX);
+    assert_eq!(context.len(), 2);
+
+    // Indices past the last chunk.
+    assert_eq!(tree.chunk_section(chunks.len()), None);
+    assert!(tree.chunk_context(chunks.len(), 1).is_empty());
+
+    let install = tree.section_at(chunks[apt].loc());
+    let install = tree.section(install).parent.unwrap();
+    let contents = tree
+      .section_chunks(install)
+      .into_iter()
+      .map(|index| chunks[index].content())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      contents,
+      [
+        "## Install",
+        "Get it.",
+        "### Linux",
+        "Use apt.",
+        "### Mac",
+        "Use brew."
+      ]
+    );
+  }
+}
@@ -3,19 +3,25 @@ pub mod processors;
 use processors::Named;
 use ragkit_ai::{
   error::Error,
-  traits::Pipeline,
+  traits::{
+    AsyncPipeline,
+    Inline,
+  },
 };
 
 #[tokio::main]
 async fn main() {
   println!("== Demo ==");
-  run_pipeline("input").expect("Shouldn't fail");
+  run_pipeline("input").await.expect("Shouldn't fail");
   println!("==========");
 }
 
-fn run_pipeline(s: &str) -> Result<&str, Error> {
-  Pipeline::new(Named::new("From prompt, find potentialy relevant wikis"))
-    .chain(Named::new("Fetch wikipedia data"))
-    .chain(Named::new(""))
-    .run(s)
+async fn run_pipeline(s: &str) -> Result<&str, Error> {
+  AsyncPipeline::new(Inline(Named::new(
+    "From prompt, find potentialy relevant wikis",
+  )))
+  .chain_sync(Named::new("Fetch wikipedia data"))
+  .chain_sync(Named::new(""))
+  .run(s)
+  .await
 }